@@ -151,9 +151,9 @@ pub extern "C" fn regorus_engine_add_policy(
     rego: *const c_char,
 ) -> RegorusResult {
     to_regorus_string_result(|| -> Result<String> {
-        to_ref(&engine)?
+        Ok(to_ref(&engine)?
             .engine
-            .add_policy(from_c_str("path", path)?, from_c_str("rego", rego)?)
+            .add_policy(from_c_str("path", path)?, from_c_str("rego", rego)?)?)
     }())
 }
 
@@ -164,9 +164,9 @@ pub extern "C" fn regorus_engine_add_policy_from_file(
     path: *const c_char,
 ) -> RegorusResult {
     to_regorus_string_result(|| -> Result<String> {
-        to_ref(&engine)?
+        Ok(to_ref(&engine)?
             .engine
-            .add_policy_from_file(from_c_str("path", path)?)
+            .add_policy_from_file(from_c_str("path", path)?)?)
     }())
 }
 
@@ -180,9 +180,9 @@ pub extern "C" fn regorus_engine_add_data_json(
     data: *const c_char,
 ) -> RegorusResult {
     to_regorus_result(|| -> Result<()> {
-        to_ref(&engine)?
+        Ok(to_ref(&engine)?
             .engine
-            .add_data(regorus::Value::from_json_str(&from_c_str("data", data)?)?)
+            .add_data(regorus::Value::from_json_str(&from_c_str("data", data)?)?)?)
     }())
 }
 
@@ -203,7 +203,7 @@ pub extern "C" fn regorus_engine_get_packages(engine: *mut RegorusEngine) -> Reg
 #[no_mangle]
 pub extern "C" fn regorus_engine_get_policies(engine: *mut RegorusEngine) -> RegorusResult {
     to_regorus_string_result(|| -> Result<String> {
-        to_ref(&engine)?.engine.get_policies_as_json()
+        Ok(to_ref(&engine)?.engine.get_policies_as_json()?)
     }())
 }
 
@@ -214,9 +214,9 @@ pub extern "C" fn regorus_engine_add_data_from_json_file(
     path: *const c_char,
 ) -> RegorusResult {
     to_regorus_result(|| -> Result<()> {
-        to_ref(&engine)?
+        Ok(to_ref(&engine)?
             .engine
-            .add_data(regorus::Value::from_json_file(from_c_str("path", path)?)?)
+            .add_data(regorus::Value::from_json_file(from_c_str("path", path)?)?)?)
     }())
 }
 
@@ -426,7 +426,7 @@ pub extern "C" fn regorus_engine_take_prints(engine: *mut RegorusEngine) -> Rego
 #[no_mangle]
 #[cfg(feature = "ast")]
 pub extern "C" fn regorus_engine_get_ast_as_json(engine: *mut RegorusEngine) -> RegorusResult {
-    let output = || -> Result<String> { to_ref(&engine)?.engine.get_ast_as_json() }();
+    let output = || -> Result<String> { Ok(to_ref(&engine)?.engine.get_ast_as_json()?) }();
     match output {
         Ok(out) => RegorusResult {
             status: RegorusStatus::RegorusStatusOk,
@@ -170,7 +170,7 @@ impl Engine {
     /// * `path`: A filename to be associated with the policy.
     /// * `rego`: Rego policy.
     pub fn add_policy(&mut self, path: String, rego: String) -> Result<String> {
-        self.engine.add_policy(path, rego)
+        Ok(self.engine.add_policy(path, rego)?)
     }
 
     /// Add a policy from given file.
@@ -179,13 +179,13 @@ impl Engine {
     ///
     /// * `path`: Path to the policy file.
     pub fn add_policy_from_file(&mut self, path: String) -> Result<String> {
-        self.engine.add_policy_from_file(path)
+        Ok(self.engine.add_policy_from_file(path)?)
     }
 
     /// Get the list of packages defined by loaded policies.
     ///
     pub fn get_packages(&self) -> Result<Vec<String>> {
-        self.engine.get_packages()
+        Ok(self.engine.get_packages()?)
     }
 
     /// Get the list of policies.
@@ -202,7 +202,7 @@ impl Engine {
     ///           or a list/set/map whose items themselves are Rego values.
     pub fn add_data(&mut self, data: &Bound<'_, PyAny>) -> Result<()> {
         let data = from(data)?;
-        self.engine.add_data(data)
+        Ok(self.engine.add_data(data)?)
     }
 
     /// Add policy data.
@@ -210,7 +210,7 @@ impl Engine {
     /// * `data`: JSON encoded value to be used as policy data.
     pub fn add_data_json(&mut self, data: String) -> Result<()> {
         let data = Value::from_json_str(&data)?;
-        self.engine.add_data(data)
+        Ok(self.engine.add_data(data)?)
     }
 
     /// Add policy data from file.
@@ -218,7 +218,7 @@ impl Engine {
     /// * `path`: Path to JSON policy data.
     pub fn add_data_from_json_file(&mut self, path: String) -> Result<()> {
         let data = Value::from_json_file(path)?;
-        self.engine.add_data(data)
+        Ok(self.engine.add_data(data)?)
     }
 
     /// Clear policy data.
@@ -349,7 +349,7 @@ impl Engine {
     /// Take gathered prints.
     ///
     pub fn take_prints(&mut self) -> Result<Vec<String>> {
-        self.engine.take_prints()
+        Ok(self.engine.take_prints()?)
     }
 
     /// Clone a [`Engine`]
@@ -366,7 +366,7 @@ impl Engine {
     ///
     #[cfg(feature = "ast")]
     pub fn get_ast_as_json(&self) -> Result<String> {
-        self.engine.get_ast_as_json()
+        Ok(self.engine.get_ast_as_json()?)
     }
 }
 
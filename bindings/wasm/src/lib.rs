@@ -119,6 +119,35 @@ impl Engine {
         v.to_json_str().map_err(error_to_jsvalue)
     }
 
+    /// Get the paths of the rules defined by loaded policies.
+    ///
+    /// See https://docs.rs/regorus/latest/regorus/struct.Engine.html#method.get_rule_paths
+    pub fn getRulePaths(&mut self) -> Result<Vec<String>, JsValue> {
+        self.engine.get_rule_paths().map_err(error_to_jsvalue)
+    }
+
+    /// Evaluate multiple rules.
+    ///
+    /// Returns a JSON object mapping each path to the value of the rule.
+    /// Paths of rules that evaluate to undefined are omitted.
+    /// See https://docs.rs/regorus/latest/regorus/struct.Engine.html#method.eval_rule
+    ///
+    /// * `paths`: The full paths to the rules.
+    pub fn evalRules(&mut self, paths: Vec<String>) -> Result<String, JsValue> {
+        let mut results = regorus::Value::new_object();
+        let map = results.as_object_mut().map_err(error_to_jsvalue)?;
+        for path in paths {
+            let v = self
+                .engine
+                .eval_rule(path.clone())
+                .map_err(error_to_jsvalue)?;
+            if v != regorus::Value::Undefined {
+                map.insert(regorus::Value::from(path), v);
+            }
+        }
+        results.to_json_str().map_err(error_to_jsvalue)
+    }
+
     /// Gather output from print statements instead of emiting to stderr.
     ///
     /// See https://docs.rs/regorus/latest/regorus/struct.Engine.html#method.set_gather_prints
@@ -242,6 +271,13 @@ mod tests {
         // Ensure that input and policy were evaluated.
         assert_eq!(v, regorus::Value::from("Hello"));
 
+        let paths = engine.getRulePaths()?;
+        assert_eq!(paths, vec!["data.test.message"]);
+
+        let v = engine.evalRules(paths)?;
+        let v = regorus::Value::from_json_str(&v).map_err(error_to_jsvalue)?;
+        assert_eq!(v["data.test.message"], regorus::Value::from("Hello"));
+
         let pkgs = engine.getPackages()?;
         assert_eq!(pkgs, vec!["data.test"]);
 
@@ -27,10 +27,10 @@ fn read_value_from_json_file(path: &String) -> Result<regorus::Value> {
 
 fn add_policy_from_file(engine: &mut regorus::Engine, path: String) -> Result<String> {
     #[cfg(feature = "std")]
-    return engine.add_policy_from_file(path);
+    return Ok(engine.add_policy_from_file(path)?);
 
     #[cfg(not(feature = "std"))]
-    engine.add_policy(path.clone(), read_file(&path)?)
+    Ok(engine.add_policy(path.clone(), read_file(&path)?)?)
 }
 
 #[allow(clippy::too_many_arguments)]
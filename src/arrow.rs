@@ -0,0 +1,186 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use crate::value::Value;
+use crate::*;
+
+use alloc::collections::BTreeMap;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Result};
+use arrow_array::builder::{BooleanBuilder, Float64Builder, Int64Builder, StringBuilder};
+use arrow_array::{ArrayRef, NullArray, RecordBatch, RecordBatchOptions};
+use arrow_schema::{DataType, Field, Schema};
+
+// Number of rows used to infer the schema.
+pub const SCHEMA_INFERENCE_ROWS: usize = 100;
+
+fn data_type_of(v: &Value) -> DataType {
+    match v {
+        Value::Null | Value::Undefined => DataType::Null,
+        Value::Bool(_) => DataType::Boolean,
+        Value::Number(n) if n.as_i64().is_some() => DataType::Int64,
+        Value::Number(_) => DataType::Float64,
+        Value::String(_) => DataType::Utf8,
+        // Nested values are stored as JSON.
+        Value::Array(_) | Value::Set(_) | Value::Object(_) => DataType::Utf8,
+    }
+}
+
+// Type of a column that holds values of both given types.
+fn unify(a: &DataType, b: &DataType) -> Option<DataType> {
+    match (a, b) {
+        (a, b) if a == b => Some(a.clone()),
+        (DataType::Null, t) | (t, DataType::Null) => Some(t.clone()),
+        (DataType::Int64, DataType::Float64) | (DataType::Float64, DataType::Int64) => {
+            Some(DataType::Float64)
+        }
+        _ => None,
+    }
+}
+
+fn get_fields(idx: usize, row: &Value) -> Result<Option<&BTreeMap<Value, Value>>> {
+    match row {
+        Value::Object(fields) => Ok(Some(fields)),
+        // Undefined results produce rows of nulls.
+        Value::Undefined => Ok(None),
+        _ => bail!("result {idx}: expected an object, got {row}"),
+    }
+}
+
+fn infer_schema(rows: &[Value]) -> Result<Schema> {
+    let mut types: BTreeMap<&str, DataType> = BTreeMap::new();
+    for (idx, row) in rows.iter().take(SCHEMA_INFERENCE_ROWS).enumerate() {
+        let Some(fields) = get_fields(idx, row)? else {
+            continue;
+        };
+        for (k, v) in fields.iter() {
+            let Value::String(name) = k else {
+                bail!("result {idx}: field names must be strings, got {k}");
+            };
+            let t = data_type_of(v);
+            let t = match types.get(name.as_ref()) {
+                Some(prev) => unify(prev, &t).ok_or_else(|| {
+                    anyhow!("result {idx}: field `{name}` is {t} but previous results had {prev}")
+                })?,
+                None => t,
+            };
+            types.insert(name.as_ref(), t);
+        }
+    }
+
+    Ok(Schema::new(
+        types
+            .into_iter()
+            .map(|(name, t)| Field::new(name, t, true))
+            .collect::<Vec<_>>(),
+    ))
+}
+
+fn mismatch(idx: usize, name: &str, t: &DataType, v: &Value) -> anyhow::Error {
+    anyhow!("result {idx}: field `{name}` has value {v} that does not match inferred type {t}")
+}
+
+fn build_column(rows: &[Value], name: &str, t: &DataType) -> Result<ArrayRef> {
+    let key = Value::from(name);
+    let values = rows.iter().enumerate().map(|(idx, row)| {
+        let v = match row {
+            Value::Object(fields) => fields.get(&key).unwrap_or(&Value::Null),
+            _ => &Value::Null,
+        };
+        (idx, v)
+    });
+
+    Ok(match t {
+        DataType::Null => {
+            if let Some((idx, v)) = values.clone().find(|(_, v)| **v != Value::Null) {
+                return Err(mismatch(idx, name, t, v));
+            }
+            Arc::new(NullArray::new(rows.len()))
+        }
+        DataType::Boolean => {
+            let mut builder = BooleanBuilder::with_capacity(rows.len());
+            for (idx, v) in values {
+                match v {
+                    Value::Null => builder.append_null(),
+                    Value::Bool(b) => builder.append_value(*b),
+                    _ => return Err(mismatch(idx, name, t, v)),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+        DataType::Int64 => {
+            let mut builder = Int64Builder::with_capacity(rows.len());
+            for (idx, v) in values {
+                match v {
+                    Value::Null => builder.append_null(),
+                    Value::Number(n) => match n.as_i64() {
+                        Some(n) => builder.append_value(n),
+                        None => return Err(mismatch(idx, name, t, v)),
+                    },
+                    _ => return Err(mismatch(idx, name, t, v)),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+        DataType::Float64 => {
+            let mut builder = Float64Builder::with_capacity(rows.len());
+            for (idx, v) in values {
+                match v {
+                    Value::Null => builder.append_null(),
+                    Value::Number(n) => builder.append_value(
+                        n.format_decimal()
+                            .parse()
+                            .map_err(|_| mismatch(idx, name, t, v))?,
+                    ),
+                    _ => return Err(mismatch(idx, name, t, v)),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+        _ => {
+            let mut builder = StringBuilder::with_capacity(rows.len(), rows.len() * 16);
+            for (idx, v) in values {
+                match v {
+                    Value::Null => builder.append_null(),
+                    Value::String(s) => builder.append_value(s.as_ref()),
+                    Value::Array(_) | Value::Set(_) | Value::Object(_) => {
+                        builder.append_value(v.to_string())
+                    }
+                    _ => return Err(mismatch(idx, name, t, v)),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+    })
+}
+
+// Convert an array of result objects to a record batch with a column per field.
+pub fn to_record_batch(rows: &[Value]) -> Result<RecordBatch> {
+    let schema = infer_schema(rows)?;
+
+    // Fields not seen while inferring the schema are a shape mismatch.
+    for (idx, row) in rows.iter().enumerate() {
+        if let Some(fields) = get_fields(idx, row)? {
+            for k in fields.keys() {
+                let known = match k {
+                    Value::String(name) => schema.field_with_name(name.as_ref()).is_ok(),
+                    _ => false,
+                };
+                if !known {
+                    bail!("result {idx}: field {k} is not present in inferred schema");
+                }
+            }
+        }
+    }
+
+    let columns = schema
+        .fields()
+        .iter()
+        .map(|f| build_column(rows, f.name(), f.data_type()))
+        .collect::<Result<Vec<_>>>()?;
+
+    let options = RecordBatchOptions::new().with_row_count(Some(rows.len()));
+    RecordBatch::try_new_with_options(Arc::new(schema), columns, &options)
+        .map_err(anyhow::Error::msg)
+}
@@ -0,0 +1,887 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+// Compact binary encoding of parsed modules.
+//
+// The encoding is only meant to be read back by the same version of regorus; a header with
+// the format version and the crate version guards against loading stale caches.
+
+use crate::ast::*;
+use crate::lexer::*;
+use crate::number::Number;
+use crate::value::Value;
+use crate::*;
+
+use alloc::collections::BTreeMap;
+use anyhow::{anyhow, bail, Result};
+use core::str::FromStr;
+
+const MAGIC: &[u8] = b"RGAST";
+const FORMAT_VERSION: u32 = 1;
+
+pub fn encode_modules(modules: &[Ref<Module>]) -> Result<Vec<u8>> {
+    let mut body = Encoder::default();
+    body.write_len(modules.len());
+    for module in modules {
+        body.module(module)?;
+    }
+
+    let mut out = Encoder::default();
+    out.buf.extend_from_slice(MAGIC);
+    out.write_u32(FORMAT_VERSION);
+    out.write_str(env!("CARGO_PKG_VERSION"));
+    out.write_len(body.sources.len());
+    for source in &body.sources {
+        out.write_str(source.file());
+        out.write_str(source.contents());
+        out.write_u32(source.reported_line(0));
+        out.write_u32(source.reported_col(0));
+    }
+    out.buf.extend_from_slice(&body.buf);
+    Ok(out.buf)
+}
+
+pub fn decode_modules(bytes: &[u8]) -> Result<Vec<Ref<Module>>> {
+    let mut d = Decoder {
+        data: bytes,
+        pos: 0,
+        sources: vec![],
+    };
+    if d.read_bytes(MAGIC.len()).ok() != Some(MAGIC) {
+        bail!("invalid AST cache: missing header");
+    }
+    let format_version = d.read_u32()?;
+    let crate_version = d.read_str()?;
+    if format_version != FORMAT_VERSION || crate_version != env!("CARGO_PKG_VERSION") {
+        bail!(
+            "AST cache version mismatch: cache was produced by regorus {crate_version} (format {format_version}), expected regorus {} (format {FORMAT_VERSION})",
+            env!("CARGO_PKG_VERSION")
+        );
+    }
+
+    for _ in 0..d.read_len()? {
+        let file = d.read_str()?.to_string();
+        let contents = d.read_str()?.to_string();
+        let line_offset = d.read_u32()?;
+        let col_offset = d.read_u32()?;
+        d.sources.push(Source::from_contents_with_offset(
+            file,
+            contents,
+            line_offset,
+            col_offset,
+        )?);
+    }
+
+    let mut modules = vec![];
+    for _ in 0..d.read_len()? {
+        modules.push(Ref::new(d.module()?));
+    }
+    if d.pos != d.data.len() {
+        bail!("invalid AST cache: trailing data");
+    }
+    Ok(modules)
+}
+
+#[derive(Default)]
+struct Encoder {
+    buf: Vec<u8>,
+    sources: Vec<Source>,
+    source_indices: BTreeMap<Source, usize>,
+}
+
+impl Encoder {
+    // Unsigned LEB128.
+    fn write_u64(&mut self, mut v: u64) {
+        loop {
+            let byte = (v & 0x7f) as u8;
+            v >>= 7;
+            if v == 0 {
+                self.buf.push(byte);
+                return;
+            }
+            self.buf.push(byte | 0x80);
+        }
+    }
+
+    fn write_u32(&mut self, v: u32) {
+        self.write_u64(v as u64)
+    }
+
+    fn write_len(&mut self, len: usize) {
+        self.write_u64(len as u64)
+    }
+
+    fn write_bool(&mut self, b: bool) {
+        self.buf.push(b as u8)
+    }
+
+    fn write_str(&mut self, s: &str) {
+        self.write_len(s.len());
+        self.buf.extend_from_slice(s.as_bytes());
+    }
+
+    fn span(&mut self, span: &Span) {
+        let index = match self.source_indices.get(&span.source) {
+            Some(index) => *index,
+            None => {
+                self.sources.push(span.source.clone());
+                self.source_indices
+                    .insert(span.source.clone(), self.sources.len() - 1);
+                self.sources.len() - 1
+            }
+        };
+        self.write_len(index);
+        self.write_u32(span.line);
+        self.write_u32(span.col);
+        self.write_u32(span.start);
+        self.write_u32(span.end);
+    }
+
+    fn spans(&mut self, spans: &[Span]) {
+        self.write_len(spans.len());
+        for span in spans {
+            self.span(span);
+        }
+    }
+
+    fn opt_span(&mut self, span: &Option<Span>) {
+        self.write_bool(span.is_some());
+        if let Some(span) = span {
+            self.span(span);
+        }
+    }
+
+    // Only strings and numbers occur in the parsed AST.
+    fn value(&mut self, value: &Value) -> Result<()> {
+        match value {
+            Value::String(s) => {
+                self.buf.push(0);
+                self.write_str(s);
+            }
+            Value::Number(n) => {
+                self.buf.push(1);
+                self.write_str(&n.format_decimal());
+            }
+            _ => bail!("internal error: unexpected value {value} in AST"),
+        }
+        Ok(())
+    }
+
+    fn spanned_value(&mut self, (span, value): &(Span, Value)) -> Result<()> {
+        self.span(span);
+        self.value(value)
+    }
+
+    fn exprs(&mut self, exprs: &[Ref<Expr>]) -> Result<()> {
+        self.write_len(exprs.len());
+        for e in exprs {
+            self.expr(e)?;
+        }
+        Ok(())
+    }
+
+    fn opt_expr(&mut self, expr: &Option<Ref<Expr>>) -> Result<()> {
+        self.write_bool(expr.is_some());
+        if let Some(expr) = expr {
+            self.expr(expr)?;
+        }
+        Ok(())
+    }
+
+    fn binary(&mut self, tag: u8, span: &Span, op: u8, lhs: &ExprRef, rhs: &ExprRef) -> Result<()> {
+        self.buf.push(tag);
+        self.span(span);
+        self.buf.push(op);
+        self.expr(lhs)?;
+        self.expr(rhs)
+    }
+
+    fn expr(&mut self, expr: &ExprRef) -> Result<()> {
+        use Expr::*;
+        match expr.as_ref() {
+            String(v) => {
+                self.buf.push(0);
+                self.spanned_value(v)?;
+            }
+            RawString(v) => {
+                self.buf.push(1);
+                self.spanned_value(v)?;
+            }
+            Number(v) => {
+                self.buf.push(2);
+                self.spanned_value(v)?;
+            }
+            True(span) => {
+                self.buf.push(3);
+                self.span(span);
+            }
+            False(span) => {
+                self.buf.push(4);
+                self.span(span);
+            }
+            Null(span) => {
+                self.buf.push(5);
+                self.span(span);
+            }
+            Var(v) => {
+                self.buf.push(6);
+                self.spanned_value(v)?;
+            }
+            Array { span, items } => {
+                self.buf.push(7);
+                self.span(span);
+                self.exprs(items)?;
+            }
+            Set { span, items } => {
+                self.buf.push(8);
+                self.span(span);
+                self.exprs(items)?;
+            }
+            Object { span, fields } => {
+                self.buf.push(9);
+                self.span(span);
+                self.write_len(fields.len());
+                for (span, key, value) in fields {
+                    self.span(span);
+                    self.expr(key)?;
+                    self.expr(value)?;
+                }
+            }
+            ArrayCompr { span, term, query } => {
+                self.buf.push(10);
+                self.span(span);
+                self.expr(term)?;
+                self.query(query)?;
+            }
+            SetCompr { span, term, query } => {
+                self.buf.push(11);
+                self.span(span);
+                self.expr(term)?;
+                self.query(query)?;
+            }
+            ObjectCompr {
+                span,
+                key,
+                value,
+                query,
+            } => {
+                self.buf.push(12);
+                self.span(span);
+                self.expr(key)?;
+                self.expr(value)?;
+                self.query(query)?;
+            }
+            Call { span, fcn, params } => {
+                self.buf.push(13);
+                self.span(span);
+                self.expr(fcn)?;
+                self.exprs(params)?;
+            }
+            UnaryExpr { span, expr } => {
+                self.buf.push(14);
+                self.span(span);
+                self.expr(expr)?;
+            }
+            RefDot { span, refr, field } => {
+                self.buf.push(15);
+                self.span(span);
+                self.expr(refr)?;
+                self.spanned_value(field)?;
+            }
+            RefBrack { span, refr, index } => {
+                self.buf.push(16);
+                self.span(span);
+                self.expr(refr)?;
+                self.expr(index)?;
+            }
+            BinExpr { span, op, lhs, rhs } => {
+                let op = match op {
+                    BinOp::Intersection => 0,
+                    BinOp::Union => 1,
+                };
+                self.binary(17, span, op, lhs, rhs)?;
+            }
+            BoolExpr { span, op, lhs, rhs } => {
+                let op = match op {
+                    BoolOp::Lt => 0,
+                    BoolOp::Le => 1,
+                    BoolOp::Eq => 2,
+                    BoolOp::Ge => 3,
+                    BoolOp::Gt => 4,
+                    BoolOp::Ne => 5,
+                };
+                self.binary(18, span, op, lhs, rhs)?;
+            }
+            ArithExpr { span, op, lhs, rhs } => {
+                let op = match op {
+                    ArithOp::Add => 0,
+                    ArithOp::Sub => 1,
+                    ArithOp::Mul => 2,
+                    ArithOp::Div => 3,
+                    ArithOp::Mod => 4,
+                };
+                self.binary(19, span, op, lhs, rhs)?;
+            }
+            AssignExpr { span, op, lhs, rhs } => {
+                self.binary(20, span, Self::assign_op(op), lhs, rhs)?;
+            }
+            Membership {
+                span,
+                key,
+                value,
+                collection,
+            } => {
+                self.buf.push(21);
+                self.span(span);
+                self.opt_expr(key)?;
+                self.expr(value)?;
+                self.expr(collection)?;
+            }
+            #[cfg(feature = "rego-extensions")]
+            OrExpr { span, lhs, rhs } => {
+                self.binary(22, span, 0, lhs, rhs)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn assign_op(op: &AssignOp) -> u8 {
+        match op {
+            AssignOp::Eq => 0,
+            AssignOp::ColEq => 1,
+        }
+    }
+
+    fn query(&mut self, query: &Ref<Query>) -> Result<()> {
+        self.span(&query.span);
+        self.write_len(query.stmts.len());
+        for stmt in &query.stmts {
+            self.span(&stmt.span);
+            match &stmt.literal {
+                Literal::SomeVars { span, vars } => {
+                    self.buf.push(0);
+                    self.span(span);
+                    self.spans(vars);
+                }
+                Literal::SomeIn {
+                    span,
+                    key,
+                    value,
+                    collection,
+                } => {
+                    self.buf.push(1);
+                    self.span(span);
+                    self.opt_expr(key)?;
+                    self.expr(value)?;
+                    self.expr(collection)?;
+                }
+                Literal::Expr { span, expr } => {
+                    self.buf.push(2);
+                    self.span(span);
+                    self.expr(expr)?;
+                }
+                Literal::NotExpr { span, expr } => {
+                    self.buf.push(3);
+                    self.span(span);
+                    self.expr(expr)?;
+                }
+                Literal::Every {
+                    span,
+                    key,
+                    value,
+                    domain,
+                    query,
+                } => {
+                    self.buf.push(4);
+                    self.span(span);
+                    self.opt_span(key);
+                    self.span(value);
+                    self.expr(domain)?;
+                    self.query(query)?;
+                }
+            }
+            self.write_len(stmt.with_mods.len());
+            for wm in &stmt.with_mods {
+                self.span(&wm.span);
+                self.expr(&wm.refr)?;
+                self.expr(&wm.r#as)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn rule_assign(&mut self, assign: &Option<RuleAssign>) -> Result<()> {
+        self.write_bool(assign.is_some());
+        if let Some(assign) = assign {
+            self.span(&assign.span);
+            self.buf.push(Self::assign_op(&assign.op));
+            self.expr(&assign.value)?;
+        }
+        Ok(())
+    }
+
+    fn rule(&mut self, rule: &Ref<Rule>) -> Result<()> {
+        match rule.as_ref() {
+            Rule::Spec { span, head, bodies } => {
+                self.buf.push(0);
+                self.span(span);
+                match head {
+                    RuleHead::Compr { span, refr, assign } => {
+                        self.buf.push(0);
+                        self.span(span);
+                        self.expr(refr)?;
+                        self.rule_assign(assign)?;
+                    }
+                    RuleHead::Set { span, refr, key } => {
+                        self.buf.push(1);
+                        self.span(span);
+                        self.expr(refr)?;
+                        self.opt_expr(key)?;
+                    }
+                    RuleHead::Func {
+                        span,
+                        refr,
+                        args,
+                        assign,
+                    } => {
+                        self.buf.push(2);
+                        self.span(span);
+                        self.expr(refr)?;
+                        self.exprs(args)?;
+                        self.rule_assign(assign)?;
+                    }
+                }
+                self.write_len(bodies.len());
+                for body in bodies {
+                    self.span(&body.span);
+                    self.rule_assign(&body.assign)?;
+                    self.query(&body.query)?;
+                }
+            }
+            Rule::Default {
+                span,
+                refr,
+                args,
+                op,
+                value,
+            } => {
+                self.buf.push(1);
+                self.span(span);
+                self.expr(refr)?;
+                self.exprs(args)?;
+                self.buf.push(Self::assign_op(op));
+                self.expr(value)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn module(&mut self, module: &Module) -> Result<()> {
+        self.span(&module.package.span);
+        self.expr(&module.package.refr)?;
+        self.write_len(module.imports.len());
+        for import in &module.imports {
+            self.span(&import.span);
+            self.expr(&import.refr)?;
+            self.opt_span(&import.r#as);
+        }
+        self.write_len(module.policy.len());
+        for rule in &module.policy {
+            self.rule(rule)?;
+        }
+        self.write_bool(module.rego_v1);
+        Ok(())
+    }
+}
+
+struct Decoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+    sources: Vec<Source>,
+}
+
+impl<'a> Decoder<'a> {
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        match self.pos.checked_add(len) {
+            Some(end) if end <= self.data.len() => {
+                let bytes = &self.data[self.pos..end];
+                self.pos = end;
+                Ok(bytes)
+            }
+            _ => bail!("invalid AST cache: unexpected end of data"),
+        }
+    }
+
+    fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_u64(&mut self) -> Result<u64> {
+        let mut v = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.read_u8()?;
+            v |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(v);
+            }
+        }
+        bail!("invalid AST cache: malformed integer")
+    }
+
+    fn read_u32(&mut self) -> Result<u32> {
+        u32::try_from(self.read_u64()?).map_err(|_| anyhow!("invalid AST cache: integer overflow"))
+    }
+
+    // Every counted item occupies at least one byte, which bounds lengths by the remaining data.
+    fn read_len(&mut self) -> Result<usize> {
+        match usize::try_from(self.read_u64()?) {
+            Ok(len) if len <= self.data.len() - self.pos => Ok(len),
+            _ => bail!("invalid AST cache: length out of bounds"),
+        }
+    }
+
+    fn read_bool(&mut self) -> Result<bool> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => bail!("invalid AST cache: malformed boolean"),
+        }
+    }
+
+    fn read_str(&mut self) -> Result<&'a str> {
+        let len = self.read_len()?;
+        core::str::from_utf8(self.read_bytes(len)?)
+            .map_err(|_| anyhow!("invalid AST cache: malformed string"))
+    }
+
+    fn invalid_tag<T>(&self, kind: &str) -> Result<T> {
+        bail!(
+            "invalid AST cache: unknown {kind} at offset {}",
+            self.pos - 1
+        )
+    }
+
+    fn span(&mut self) -> Result<Span> {
+        let index = self.read_len()?;
+        let source = match self.sources.get(index) {
+            Some(source) => source.clone(),
+            None => bail!("invalid AST cache: unknown source {index}"),
+        };
+        let span = Span {
+            source,
+            line: self.read_u32()?,
+            col: self.read_u32()?,
+            start: self.read_u32()?,
+            end: self.read_u32()?,
+        };
+        if span.start > span.end || span.end as usize > span.source.contents().len() {
+            bail!("invalid AST cache: span out of bounds");
+        }
+        Ok(span)
+    }
+
+    fn spans(&mut self) -> Result<Vec<Span>> {
+        (0..self.read_len()?).map(|_| self.span()).collect()
+    }
+
+    fn opt_span(&mut self) -> Result<Option<Span>> {
+        Ok(match self.read_bool()? {
+            true => Some(self.span()?),
+            false => None,
+        })
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        match self.read_u8()? {
+            0 => Ok(Value::from(self.read_str()?)),
+            1 => {
+                let s = self.read_str()?;
+                match Number::from_str(s) {
+                    Ok(n) => Ok(Value::Number(n)),
+                    Err(_) => bail!("invalid AST cache: malformed number {s}"),
+                }
+            }
+            _ => self.invalid_tag("value"),
+        }
+    }
+
+    fn spanned_value(&mut self) -> Result<(Span, Value)> {
+        Ok((self.span()?, self.value()?))
+    }
+
+    fn exprs(&mut self) -> Result<Vec<Ref<Expr>>> {
+        (0..self.read_len()?).map(|_| self.expr()).collect()
+    }
+
+    fn opt_expr(&mut self) -> Result<Option<Ref<Expr>>> {
+        Ok(match self.read_bool()? {
+            true => Some(self.expr()?),
+            false => None,
+        })
+    }
+
+    fn assign_op(&mut self) -> Result<AssignOp> {
+        match self.read_u8()? {
+            0 => Ok(AssignOp::Eq),
+            1 => Ok(AssignOp::ColEq),
+            _ => self.invalid_tag("assignment operator"),
+        }
+    }
+
+    fn expr(&mut self) -> Result<Ref<Expr>> {
+        use Expr::*;
+        let expr = match self.read_u8()? {
+            0 => String(self.spanned_value()?),
+            1 => RawString(self.spanned_value()?),
+            2 => Number(self.spanned_value()?),
+            3 => True(self.span()?),
+            4 => False(self.span()?),
+            5 => Null(self.span()?),
+            6 => Var(self.spanned_value()?),
+            7 => Array {
+                span: self.span()?,
+                items: self.exprs()?,
+            },
+            8 => Set {
+                span: self.span()?,
+                items: self.exprs()?,
+            },
+            9 => {
+                let span = self.span()?;
+                let fields = (0..self.read_len()?)
+                    .map(|_| Ok((self.span()?, self.expr()?, self.expr()?)))
+                    .collect::<Result<_>>()?;
+                Object { span, fields }
+            }
+            10 => ArrayCompr {
+                span: self.span()?,
+                term: self.expr()?,
+                query: self.query()?,
+            },
+            11 => SetCompr {
+                span: self.span()?,
+                term: self.expr()?,
+                query: self.query()?,
+            },
+            12 => ObjectCompr {
+                span: self.span()?,
+                key: self.expr()?,
+                value: self.expr()?,
+                query: self.query()?,
+            },
+            13 => Call {
+                span: self.span()?,
+                fcn: self.expr()?,
+                params: self.exprs()?,
+            },
+            14 => UnaryExpr {
+                span: self.span()?,
+                expr: self.expr()?,
+            },
+            15 => RefDot {
+                span: self.span()?,
+                refr: self.expr()?,
+                field: self.spanned_value()?,
+            },
+            16 => RefBrack {
+                span: self.span()?,
+                refr: self.expr()?,
+                index: self.expr()?,
+            },
+            17 => BinExpr {
+                span: self.span()?,
+                op: match self.read_u8()? {
+                    0 => BinOp::Intersection,
+                    1 => BinOp::Union,
+                    _ => return self.invalid_tag("set operator"),
+                },
+                lhs: self.expr()?,
+                rhs: self.expr()?,
+            },
+            18 => BoolExpr {
+                span: self.span()?,
+                op: match self.read_u8()? {
+                    0 => BoolOp::Lt,
+                    1 => BoolOp::Le,
+                    2 => BoolOp::Eq,
+                    3 => BoolOp::Ge,
+                    4 => BoolOp::Gt,
+                    5 => BoolOp::Ne,
+                    _ => return self.invalid_tag("comparison operator"),
+                },
+                lhs: self.expr()?,
+                rhs: self.expr()?,
+            },
+            19 => ArithExpr {
+                span: self.span()?,
+                op: match self.read_u8()? {
+                    0 => ArithOp::Add,
+                    1 => ArithOp::Sub,
+                    2 => ArithOp::Mul,
+                    3 => ArithOp::Div,
+                    4 => ArithOp::Mod,
+                    _ => return self.invalid_tag("arithmetic operator"),
+                },
+                lhs: self.expr()?,
+                rhs: self.expr()?,
+            },
+            20 => AssignExpr {
+                span: self.span()?,
+                op: self.assign_op()?,
+                lhs: self.expr()?,
+                rhs: self.expr()?,
+            },
+            21 => Membership {
+                span: self.span()?,
+                key: self.opt_expr()?,
+                value: self.expr()?,
+                collection: self.expr()?,
+            },
+            #[cfg(feature = "rego-extensions")]
+            22 => {
+                let span = self.span()?;
+                self.read_u8()?;
+                OrExpr {
+                    span,
+                    lhs: self.expr()?,
+                    rhs: self.expr()?,
+                }
+            }
+            _ => return self.invalid_tag("expression"),
+        };
+        Ok(Ref::new(expr))
+    }
+
+    fn query(&mut self) -> Result<Ref<Query>> {
+        let span = self.span()?;
+        let mut stmts = vec![];
+        for _ in 0..self.read_len()? {
+            let span = self.span()?;
+            let literal = match self.read_u8()? {
+                0 => Literal::SomeVars {
+                    span: self.span()?,
+                    vars: self.spans()?,
+                },
+                1 => Literal::SomeIn {
+                    span: self.span()?,
+                    key: self.opt_expr()?,
+                    value: self.expr()?,
+                    collection: self.expr()?,
+                },
+                2 => Literal::Expr {
+                    span: self.span()?,
+                    expr: self.expr()?,
+                },
+                3 => Literal::NotExpr {
+                    span: self.span()?,
+                    expr: self.expr()?,
+                },
+                4 => Literal::Every {
+                    span: self.span()?,
+                    key: self.opt_span()?,
+                    value: self.span()?,
+                    domain: self.expr()?,
+                    query: self.query()?,
+                },
+                _ => return self.invalid_tag("literal"),
+            };
+            let with_mods = (0..self.read_len()?)
+                .map(|_| {
+                    Ok(WithModifier {
+                        span: self.span()?,
+                        refr: self.expr()?,
+                        r#as: self.expr()?,
+                    })
+                })
+                .collect::<Result<_>>()?;
+            stmts.push(LiteralStmt {
+                span,
+                literal,
+                with_mods,
+            });
+        }
+        Ok(Ref::new(Query { span, stmts }))
+    }
+
+    fn rule_assign(&mut self) -> Result<Option<RuleAssign>> {
+        Ok(match self.read_bool()? {
+            true => Some(RuleAssign {
+                span: self.span()?,
+                op: self.assign_op()?,
+                value: self.expr()?,
+            }),
+            false => None,
+        })
+    }
+
+    fn rule(&mut self) -> Result<Ref<Rule>> {
+        let rule = match self.read_u8()? {
+            0 => {
+                let span = self.span()?;
+                let head = match self.read_u8()? {
+                    0 => RuleHead::Compr {
+                        span: self.span()?,
+                        refr: self.expr()?,
+                        assign: self.rule_assign()?,
+                    },
+                    1 => RuleHead::Set {
+                        span: self.span()?,
+                        refr: self.expr()?,
+                        key: self.opt_expr()?,
+                    },
+                    2 => RuleHead::Func {
+                        span: self.span()?,
+                        refr: self.expr()?,
+                        args: self.exprs()?,
+                        assign: self.rule_assign()?,
+                    },
+                    _ => return self.invalid_tag("rule head"),
+                };
+                let bodies = (0..self.read_len()?)
+                    .map(|_| {
+                        Ok(RuleBody {
+                            span: self.span()?,
+                            assign: self.rule_assign()?,
+                            query: self.query()?,
+                        })
+                    })
+                    .collect::<Result<_>>()?;
+                Rule::Spec { span, head, bodies }
+            }
+            1 => Rule::Default {
+                span: self.span()?,
+                refr: self.expr()?,
+                args: self.exprs()?,
+                op: self.assign_op()?,
+                value: self.expr()?,
+            },
+            _ => return self.invalid_tag("rule"),
+        };
+        Ok(Ref::new(rule))
+    }
+
+    fn module(&mut self) -> Result<Module> {
+        let package = Package {
+            span: self.span()?,
+            refr: self.expr()?,
+        };
+        let imports = (0..self.read_len()?)
+            .map(|_| {
+                Ok(Import {
+                    span: self.span()?,
+                    refr: self.expr()?,
+                    r#as: self.opt_span()?,
+                })
+            })
+            .collect::<Result<_>>()?;
+        let policy = (0..self.read_len()?)
+            .map(|_| self.rule())
+            .collect::<Result<_>>()?;
+        Ok(Module {
+            package,
+            imports,
+            policy,
+            rego_v1: self.read_bool()?,
+        })
+    }
+}
@@ -4,6 +4,7 @@
 use crate::ast::{Expr, Ref};
 use crate::builtins;
 use crate::builtins::utils::{ensure_args_count, ensure_numeric};
+use crate::builtins::Builtin;
 use crate::lexer::Span;
 use crate::number::Number;
 use crate::value::Value;
@@ -12,12 +13,12 @@ use crate::*;
 use anyhow::{bail, Result};
 
 pub fn register(m: &mut builtins::BuiltinsMap<&'static str, builtins::BuiltinFcn>) {
-    m.insert("count", (count, 1));
-    m.insert("max", (max, 1));
-    m.insert("min", (min, 1));
-    m.insert("product", (product, 1));
-    m.insert("sort", (sort, 1));
-    m.insert("sum", (sum, 1));
+    m.insert("count", (Builtin::Pure(count), 1));
+    m.insert("max", (Builtin::Pure(max), 1));
+    m.insert("min", (Builtin::Pure(min), 1));
+    m.insert("product", (Builtin::Pure(product), 1));
+    m.insert("sort", (Builtin::Pure(sort), 1));
+    m.insert("sum", (Builtin::Pure(sum), 1));
 }
 
 fn count(span: &Span, params: &[Ref<Expr>], args: &[Value], strict: bool) -> Result<Value> {
@@ -4,6 +4,7 @@
 use crate::ast::{Expr, Ref};
 use crate::builtins;
 use crate::builtins::utils::{ensure_args_count, ensure_array, ensure_numeric};
+use crate::builtins::Builtin;
 use crate::lexer::Span;
 use crate::Rc;
 use crate::Value;
@@ -11,9 +12,12 @@ use crate::Value;
 use anyhow::Result;
 
 pub fn register(m: &mut builtins::BuiltinsMap<&'static str, builtins::BuiltinFcn>) {
-    m.insert("array.concat", (concat, 2));
-    m.insert("array.reverse", (reverse, 1));
-    m.insert("array.slice", (slice, 3));
+    m.insert("array.concat", (Builtin::Pure(concat), 2));
+    m.insert("array.reverse", (Builtin::Pure(reverse), 1));
+    m.insert("array.slice", (Builtin::Pure(slice), 3));
+
+    #[cfg(feature = "rego-extensions")]
+    m.insert("array.indexof", (Builtin::Pure(indexof), 2));
 }
 
 fn concat(span: &Span, params: &[Ref<Expr>], args: &[Value], _strict: bool) -> Result<Value> {
@@ -26,6 +30,18 @@ fn concat(span: &Span, params: &[Ref<Expr>], args: &[Value], _strict: bool) -> R
     Ok(Value::Array(v1))
 }
 
+#[cfg(feature = "rego-extensions")]
+fn indexof(span: &Span, params: &[Ref<Expr>], args: &[Value], _strict: bool) -> Result<Value> {
+    let name = "array.indexof";
+    ensure_args_count(span, name, params, args, 2)?;
+
+    let array = ensure_array(name, &params[0], args[0].clone())?;
+    Ok(match array.iter().position(|v| v == &args[1]) {
+        Some(idx) => Value::from(idx),
+        None => Value::from(-1i64),
+    })
+}
+
 fn reverse(span: &Span, params: &[Ref<Expr>], args: &[Value], _strict: bool) -> Result<Value> {
     let name = "array.reverse";
     ensure_args_count(span, name, params, args, 1)?;
@@ -4,6 +4,7 @@
 use crate::ast::{Expr, Ref};
 use crate::builtins;
 use crate::builtins::utils::{ensure_args_count, ensure_numeric};
+use crate::builtins::Builtin;
 
 use crate::lexer::Span;
 use crate::value::Value;
@@ -11,12 +12,12 @@ use crate::value::Value;
 use anyhow::Result;
 
 pub fn register(m: &mut builtins::BuiltinsMap<&'static str, builtins::BuiltinFcn>) {
-    m.insert("bits.and", (and, 2));
-    m.insert("bits.lsh", (lsh, 2));
-    m.insert("bits.negate", (negate, 1));
-    m.insert("bits.or", (or, 2));
-    m.insert("bits.rsh", (rsh, 2));
-    m.insert("bits.xor", (xor, 2));
+    m.insert("bits.and", (Builtin::Pure(and), 2));
+    m.insert("bits.lsh", (Builtin::Pure(lsh), 2));
+    m.insert("bits.negate", (Builtin::Pure(negate), 1));
+    m.insert("bits.or", (Builtin::Pure(or), 2));
+    m.insert("bits.rsh", (Builtin::Pure(rsh), 2));
+    m.insert("bits.xor", (Builtin::Pure(xor), 2));
 }
 
 fn and(span: &Span, params: &[Ref<Expr>], args: &[Value], _strict: bool) -> Result<Value> {
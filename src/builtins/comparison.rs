@@ -3,6 +3,9 @@
 
 use crate::ast::BoolOp;
 use crate::value::Value;
+use crate::Collation;
+
+use core::cmp::Ordering;
 
 use anyhow::Result;
 
@@ -53,3 +56,32 @@ pub fn compare(op: &BoolOp, v1: &Value, v2: &Value) -> Result<Value> {
         BoolOp::Ge => v1 >= v2,
     }))
 }
+
+/// Compare two strings using the given collation.
+///
+/// [`Collation::Exact`] compares strings byte-wise like [`compare`].
+/// [`Collation::CaseInsensitiveAscii`] ignores the case of ASCII letters.
+///
+/// # Arguments
+/// * `op` - The comparison operation to perform.
+/// * `s1` - The first string.
+/// * `s2` - The second string.
+/// * `collation` - The collation to use.
+pub fn compare_strings(op: &BoolOp, s1: &str, s2: &str, collation: Collation) -> Value {
+    let ordering = match collation {
+        Collation::Exact => s1.cmp(s2),
+        Collation::CaseInsensitiveAscii => s1
+            .bytes()
+            .map(|b| b.to_ascii_lowercase())
+            .cmp(s2.bytes().map(|b| b.to_ascii_lowercase())),
+    };
+
+    Value::Bool(match op {
+        BoolOp::Eq => ordering == Ordering::Equal,
+        BoolOp::Ne => ordering != Ordering::Equal,
+        BoolOp::Lt => ordering == Ordering::Less,
+        BoolOp::Le => ordering != Ordering::Greater,
+        BoolOp::Gt => ordering == Ordering::Greater,
+        BoolOp::Ge => ordering != Ordering::Less,
+    })
+}
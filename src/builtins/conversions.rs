@@ -4,6 +4,7 @@
 use crate::ast::{Expr, Ref};
 use crate::builtins;
 use crate::builtins::utils::ensure_args_count;
+use crate::builtins::Builtin;
 use crate::lexer::Span;
 use crate::value::Value;
 use crate::*;
@@ -11,7 +12,7 @@ use crate::*;
 use anyhow::{bail, Result};
 
 pub fn register(m: &mut builtins::BuiltinsMap<&'static str, builtins::BuiltinFcn>) {
-    m.insert("to_number", (to_number, 1));
+    m.insert("to_number", (Builtin::Pure(to_number), 1));
 }
 
 fn to_number(span: &Span, params: &[Ref<Expr>], args: &[Value], _strict: bool) -> Result<Value> {
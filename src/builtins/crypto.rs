@@ -4,6 +4,7 @@
 use crate::ast::{Expr, Ref};
 use crate::builtins;
 use crate::builtins::utils::{ensure_args_count, ensure_string};
+use crate::builtins::Builtin;
 use crate::lexer::Span;
 use crate::value::Value;
 
@@ -15,15 +16,18 @@ use sha1::Sha1;
 use sha2::{Sha256, Sha512};
 
 pub fn register(m: &mut builtins::BuiltinsMap<&'static str, builtins::BuiltinFcn>) {
-    m.insert("crypto.hmac.equal", (hmac_equal_fixed_time, 2));
-    m.insert("crypto.hmac.md5", (hmac_md5, 2));
-    m.insert("crypto.hmac.sha1", (hmac_sha1, 2));
-    m.insert("crypto.hmac.sha256", (hmac_sha256, 2));
-    m.insert("crypto.hmac.sha512", (hmac_sha512, 2));
-
-    m.insert("crypto.md5", (crypto_md5, 1));
-    m.insert("crypto.sha1", (crypto_sha1, 1));
-    m.insert("crypto.sha256", (crypto_sha256, 1));
+    m.insert(
+        "crypto.hmac.equal",
+        (Builtin::Pure(hmac_equal_fixed_time), 2),
+    );
+    m.insert("crypto.hmac.md5", (Builtin::Pure(hmac_md5), 2));
+    m.insert("crypto.hmac.sha1", (Builtin::Pure(hmac_sha1), 2));
+    m.insert("crypto.hmac.sha256", (Builtin::Pure(hmac_sha256), 2));
+    m.insert("crypto.hmac.sha512", (Builtin::Pure(hmac_sha512), 2));
+
+    m.insert("crypto.md5", (Builtin::Pure(crypto_md5), 1));
+    m.insert("crypto.sha1", (Builtin::Pure(crypto_sha1), 1));
+    m.insert("crypto.sha256", (Builtin::Pure(crypto_sha256), 1));
 }
 
 fn hmac_equal_fixed_time(
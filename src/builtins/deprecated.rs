@@ -4,7 +4,7 @@
 use crate::ast::{Expr, Ref};
 use crate::builtins;
 use crate::builtins::utils::{ensure_args_count, ensure_set};
-use crate::builtins::BuiltinFcn;
+use crate::builtins::{Builtin, BuiltinFcn};
 use crate::lexer::Span;
 use crate::value::Value;
 use crate::*;
@@ -20,18 +20,18 @@ lazy_static! {
     pub static ref DEPRECATED: builtins::BuiltinsMap<&'static str, BuiltinFcn> = {
 	let mut m : builtins::BuiltinsMap<&'static str, BuiltinFcn>  = builtins::BuiltinsMap::new();
 	
-	m.insert("all", (all, 1));
-	m.insert("any", (any, 1));
-	m.insert("cast_array", (cast_array, 1));
-	m.insert("cast_boolean", (cast_boolean, 1));
-	m.insert("cast_null", (cast_null, 1));
-	m.insert("cast_object", (cast_object, 1));
-	m.insert("cast_set", (cast_set, 1));
-	m.insert("cast_string", (cast_string, 1));
-	m.insert("set_diff", (set_diff, 2));
+	m.insert("all", (Builtin::Pure(all), 1));
+	m.insert("any", (Builtin::Pure(any), 1));
+	m.insert("cast_array", (Builtin::Pure(cast_array), 1));
+	m.insert("cast_boolean", (Builtin::Pure(cast_boolean), 1));
+	m.insert("cast_null", (Builtin::Pure(cast_null), 1));
+	m.insert("cast_object", (Builtin::Pure(cast_object), 1));
+	m.insert("cast_set", (Builtin::Pure(cast_set), 1));
+	m.insert("cast_string", (Builtin::Pure(cast_string), 1));
+	m.insert("set_diff", (Builtin::Pure(set_diff), 2));
 
 	#[cfg(feature = "regex")]
-	m.insert("re_match", (regex_match, 2));
+	m.insert("re_match", (Builtin::Regex(regex_match), 2));
 	m
     };
 }
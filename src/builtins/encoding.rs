@@ -7,6 +7,7 @@ use crate::builtins;
 use crate::builtins::utils::{
     ensure_args_count, ensure_object, ensure_string, ensure_string_collection,
 };
+use crate::builtins::Builtin;
 use crate::lexer::Span;
 use crate::value::Value;
 use crate::*;
@@ -17,38 +18,50 @@ use anyhow::{anyhow, bail, Context, Result};
 pub fn register(m: &mut builtins::BuiltinsMap<&'static str, builtins::BuiltinFcn>) {
     #[cfg(feature = "base64")]
     {
-        m.insert("base64.decode", (base64_decode, 1));
-        m.insert("base64.encode", (base64_encode, 1));
-        m.insert("base64.is_valid", (base64_is_valid, 1));
+        m.insert("base64.decode", (Builtin::Pure(base64_decode), 1));
+        m.insert("base64.encode", (Builtin::Pure(base64_encode), 1));
+        m.insert("base64.is_valid", (Builtin::Pure(base64_is_valid), 1));
     }
     #[cfg(feature = "base64url")]
     {
-        m.insert("base64url.decode", (base64url_decode, 1));
-        m.insert("base64url.encode", (base64url_encode, 1));
-        m.insert("base64url.encode_no_pad", (base64url_encode_no_pad, 1));
+        m.insert("base64url.decode", (Builtin::Pure(base64url_decode), 1));
+        m.insert("base64url.encode", (Builtin::Pure(base64url_encode), 1));
+        m.insert(
+            "base64url.encode_no_pad",
+            (Builtin::Pure(base64url_encode_no_pad), 1),
+        );
     }
     #[cfg(feature = "hex")]
     {
-        m.insert("hex.decode", (hex_decode, 1));
-        m.insert("hex.encode", (hex_encode, 1));
+        m.insert("hex.decode", (Builtin::Pure(hex_decode), 1));
+        m.insert("hex.encode", (Builtin::Pure(hex_encode), 1));
     }
     #[cfg(feature = "urlquery")]
     {
-        m.insert("urlquery.decode", (urlquery_decode, 1));
-        m.insert("urlquery.decode_object", (urlquery_decode_object, 1));
-        m.insert("urlquery.encode", (urlquery_encode, 1));
-        m.insert("urlquery.encode_object", (urlquery_encode_object, 1));
+        m.insert("urlquery.decode", (Builtin::Pure(urlquery_decode), 1));
+        m.insert(
+            "urlquery.decode_object",
+            (Builtin::Pure(urlquery_decode_object), 1),
+        );
+        m.insert("urlquery.encode", (Builtin::Pure(urlquery_encode), 1));
+        m.insert(
+            "urlquery.encode_object",
+            (Builtin::Pure(urlquery_encode_object), 1),
+        );
     }
-    m.insert("json.is_valid", (json_is_valid, 1));
-    m.insert("json.marshal", (json_marshal, 1));
-    m.insert("json.marshal_with_options", (json_marshal_with_options, 2));
-    m.insert("json.unmarshal", (json_unmarshal, 1));
+    m.insert("json.is_valid", (Builtin::Pure(json_is_valid), 1));
+    m.insert("json.marshal", (Builtin::Pure(json_marshal), 1));
+    m.insert(
+        "json.marshal_with_options",
+        (Builtin::Pure(json_marshal_with_options), 2),
+    );
+    m.insert("json.unmarshal", (Builtin::Pure(json_unmarshal), 1));
 
     #[cfg(feature = "yaml")]
     {
-        m.insert("yaml.is_valid", (yaml_is_valid, 1));
-        m.insert("yaml.marshal", (yaml_marshal, 1));
-        m.insert("yaml.unmarshal", (yaml_unmarshal, 1));
+        m.insert("yaml.is_valid", (Builtin::Pure(yaml_is_valid), 1));
+        m.insert("yaml.marshal", (Builtin::Pure(yaml_marshal), 1));
+        m.insert("yaml.unmarshal", (Builtin::Pure(yaml_unmarshal), 1));
     }
 }
 
@@ -4,100 +4,272 @@
 use crate::ast::{Expr, Ref};
 use crate::builtins;
 use crate::builtins::utils::{ensure_args_count, ensure_string, ensure_string_collection};
+use crate::builtins::Builtin;
 use crate::lexer::Span;
 use crate::value::Value;
 use crate::*;
 
 use anyhow::{bail, Result};
-//use glob::{Pattern, MatchOptions};
-use wax::{Glob, Pattern};
 
 pub fn register(m: &mut builtins::BuiltinsMap<&'static str, builtins::BuiltinFcn>) {
-    m.insert("glob.match", (glob_match, 3));
-    m.insert("glob.quote_meta", (quote_meta, 1));
+    m.insert("glob.match", (Builtin::Pure(glob_match), 3));
+    m.insert("glob.quote_meta", (Builtin::Pure(quote_meta), 1));
 }
 
-const PLACE_HOLDER: &str = "\0";
-
-fn suppress_unix_style_delimiter(s: &str) -> Result<String> {
-    // Replace unix-style delimiter with placeholder so that no delimiter is
-    // encountered during glob matching.
-    Ok(s.replace('/', PLACE_HOLDER))
+// Glob syntax follows OPA (github.com/gobwas/glob):
+//   *      any sequence of characters other than delimiters
+//   **     any sequence of characters, including delimiters
+//   ?      any single character other than a delimiter
+//   [abc]  any character in the list; [a-z] ranges and [!...] negation are allowed
+//   {a,b}  any of the comma separated patterns
+//   \c     the character c literally
+enum Token {
+    Char(char),
+    Any,
+    Super,
+    Single,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+    Alternatives(Vec<Vec<Token>>),
 }
 
-fn make_delimiters_unix_style(s: &str, delimiters: &[char]) -> Result<String> {
-    if s.contains(PLACE_HOLDER) {
-        bail!("string contains internal glob placeholder");
-    }
+const SPECIAL_CHARS: &[char] = &['*', '?', '\\', '[', ']', '{', '}'];
 
-    let has_unix_style = delimiters.contains(&'/');
+struct Parser<'a> {
+    chars: core::iter::Peekable<core::str::Chars<'a>>,
+}
 
-    let mut s = if !has_unix_style {
-        suppress_unix_style_delimiter(s)?
-    } else {
-        s.to_string()
-    };
+impl Parser<'_> {
+    // Parse a sequence of tokens, stopping at `,` or `}` if within alternatives.
+    fn parse_sequence(&mut self, in_alternatives: bool) -> Result<Vec<Token>> {
+        let mut tokens = vec![];
+        while let Some(&c) = self.chars.peek() {
+            if in_alternatives && (c == ',' || c == '}') {
+                break;
+            }
+            self.chars.next();
+            tokens.push(match c {
+                '*' if self.chars.next_if_eq(&'*').is_some() => Token::Super,
+                '*' => Token::Any,
+                '?' => Token::Single,
+                '[' => self.parse_class()?,
+                '{' => self.parse_alternatives()?,
+                '\\' => match self.chars.next() {
+                    Some(c) => Token::Char(c),
+                    None => bail!("unterminated escape"),
+                },
+                _ => Token::Char(c),
+            });
+        }
+        Ok(tokens)
+    }
 
-    for d in delimiters {
-        if *d == ':' {
-            s = s.replace(*d, PLACE_HOLDER);
-        } else if *d != '/' {
-            // Insert / before occurances of delimiter.
-            s = s.replace(*d, format!("/{}/", *d).as_str());
+    fn parse_class(&mut self) -> Result<Token> {
+        let negated = self.chars.next_if_eq(&'!').is_some();
+        let mut ranges = vec![];
+        loop {
+            let lo = match self.chars.next() {
+                Some(']') if !ranges.is_empty() => break,
+                Some(c) => c,
+                None => bail!("unterminated character class"),
+            };
+            let hi = match self.chars.next_if_eq(&'-') {
+                Some(_) => match self.chars.next() {
+                    Some(c) if c >= lo => c,
+                    _ => bail!("invalid character range"),
+                },
+                None => lo,
+            };
+            ranges.push((lo, hi));
         }
+        Ok(Token::Class { negated, ranges })
     }
 
-    Ok(s)
+    fn parse_alternatives(&mut self) -> Result<Token> {
+        let mut alternatives = vec![];
+        loop {
+            alternatives.push(self.parse_sequence(true)?);
+            match self.chars.next() {
+                Some(',') => continue,
+                Some('}') => break,
+                _ => bail!("unterminated alternatives"),
+            }
+        }
+        Ok(Token::Alternatives(alternatives))
+    }
 }
 
-fn make_glob<'a>(pattern: &'a str, span: &'a Span) -> Result<Glob<'a>> {
-    Glob::new(pattern).or_else(|_| bail!(span.error("invalid glob")))
+fn parse(pattern: &str) -> Result<Vec<Token>> {
+    Parser {
+        chars: pattern.chars().peekable(),
+    }
+    .parse_sequence(false)
 }
 
-fn glob_match(span: &Span, params: &[Ref<Expr>], args: &[Value], _strict: bool) -> Result<Value> {
-    let name = "glob.match";
-    ensure_args_count(span, name, params, args, 3)?;
+// A pattern compiled to a nondeterministic automaton. Simulating the automaton over the input
+// takes time proportional to the product of the lengths of the pattern and the input.
+enum State {
+    Char(char, usize),
+    Single(usize),
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+        next: usize,
+    },
+    // Consume any number of characters, other than delimiters if `delimited` is set.
+    Repeat {
+        delimited: bool,
+        next: usize,
+    },
+    Split(Vec<usize>),
+    Match,
+}
 
-    let pattern = ensure_string(name, &params[0], &args[0])?;
-    let value = ensure_string(name, &params[2], &args[2])?;
+struct Automaton {
+    states: Vec<State>,
+    start: usize,
+}
 
-    let pattern = pattern.as_ref();
-    let value = value.as_ref();
+impl Automaton {
+    fn new(tokens: Vec<Token>) -> Self {
+        let mut automaton = Automaton {
+            states: vec![State::Match],
+            start: 0,
+        };
+        automaton.start = automaton.compile(tokens, 0);
+        automaton
+    }
 
-    if let Value::Null = &args[1] {
-        // Ensure that / is not treated as a delimiter.
-        let value = suppress_unix_style_delimiter(value)?;
-        let pattern = suppress_unix_style_delimiter(pattern)?;
+    fn add(&mut self, state: State) -> usize {
+        self.states.push(state);
+        self.states.len() - 1
+    }
 
-        let glob = make_glob(&pattern, params[0].span())?;
-        return Ok(Value::Bool(glob.is_match(&value[..])));
+    // Add states matching the tokens followed by `next` and return the initial state.
+    fn compile(&mut self, tokens: Vec<Token>, mut next: usize) -> usize {
+        for token in tokens.into_iter().rev() {
+            next = match token {
+                Token::Char(c) => self.add(State::Char(c, next)),
+                Token::Single => self.add(State::Single(next)),
+                Token::Class { negated, ranges } => self.add(State::Class {
+                    negated,
+                    ranges,
+                    next,
+                }),
+                Token::Any => self.add(State::Repeat {
+                    delimited: true,
+                    next,
+                }),
+                Token::Super => self.add(State::Repeat {
+                    delimited: false,
+                    next,
+                }),
+                Token::Alternatives(alternatives) => {
+                    let starts = alternatives
+                        .into_iter()
+                        .map(|alternative| self.compile(alternative, next))
+                        .collect();
+                    self.add(State::Split(starts))
+                }
+            };
+        }
+        next
     }
 
-    let delimiters = if let Value::Array(_) = &args[1] {
-        ensure_string_collection(name, &params[1], &args[1])?
-    } else {
-        bail!(params[1]
-            .span()
-            .error(format!("{name} requires string array").as_str()));
-    };
+    // Add a state and the states reachable from it without consuming characters.
+    fn add_closure(&self, state: usize, added: &mut [bool], states: &mut Vec<usize>) {
+        if added[state] {
+            return;
+        }
+        added[state] = true;
+        states.push(state);
+        match &self.states[state] {
+            State::Repeat { next, .. } => self.add_closure(*next, added, states),
+            State::Split(nexts) => {
+                for next in nexts {
+                    self.add_closure(*next, added, states);
+                }
+            }
+            _ => (),
+        }
+    }
 
-    if delimiters.iter().any(|d| d.len() > 1) {
-        bail!(params[1]
-            .span()
-            .error("delimiters must be single character"));
+    fn matches(&self, s: &str, delimiters: &[char]) -> bool {
+        let mut current = vec![];
+        self.add_closure(
+            self.start,
+            &mut vec![false; self.states.len()],
+            &mut current,
+        );
+        for c in s.chars() {
+            let mut added = vec![false; self.states.len()];
+            let mut next = vec![];
+            for &state in &current {
+                let target = match &self.states[state] {
+                    State::Char(d, next) if *d == c => *next,
+                    State::Single(next) if !delimiters.contains(&c) => *next,
+                    State::Class {
+                        negated,
+                        ranges,
+                        next,
+                    } if ranges.iter().any(|(lo, hi)| *lo <= c && c <= *hi) != *negated => *next,
+                    State::Repeat { delimited, .. } if !*delimited || !delimiters.contains(&c) => {
+                        state
+                    }
+                    _ => continue,
+                };
+                self.add_closure(target, &mut added, &mut next);
+            }
+            if next.is_empty() {
+                return false;
+            }
+            current = next;
+        }
+        current
+            .iter()
+            .any(|state| matches!(self.states[*state], State::Match))
     }
+}
 
-    let mut delimiters: Vec<char> = delimiters.iter().filter_map(|d| d.chars().next()).collect();
+fn glob_match(span: &Span, params: &[Ref<Expr>], args: &[Value], _strict: bool) -> Result<Value> {
+    let name = "glob.match";
+    ensure_args_count(span, name, params, args, 3)?;
 
-    if delimiters.is_empty() {
-        delimiters.push('.');
-    }
+    let pattern = ensure_string(name, &params[0], &args[0])?;
+    let value = ensure_string(name, &params[2], &args[2])?;
 
-    let pattern = make_delimiters_unix_style(pattern, &delimiters)?;
-    let value = make_delimiters_unix_style(value, &delimiters)?;
+    // A null delimiters list means that no delimiters are used.
+    // An empty delimiters list means that `.` is used as delimiter.
+    let delimiters: Vec<char> = match &args[1] {
+        Value::Null => vec![],
+        Value::Array(_) => {
+            let delimiters = ensure_string_collection(name, &params[1], &args[1])?;
+            if delimiters.iter().any(|d| d.chars().count() != 1) {
+                bail!(params[1]
+                    .span()
+                    .error("delimiters must be single character"));
+            }
+            let mut delimiters: Vec<char> =
+                delimiters.iter().filter_map(|d| d.chars().next()).collect();
+            if delimiters.is_empty() {
+                delimiters.push('.');
+            }
+            delimiters
+        }
+        _ => bail!(params[1]
+            .span()
+            .error(format!("{name} requires string array").as_str())),
+    };
 
-    let glob = make_glob(&pattern, params[0].span())?;
-    Ok(Value::Bool(glob.is_match(&value[..])))
+    let tokens = match parse(pattern.as_ref()) {
+        Ok(tokens) => tokens,
+        Err(e) => bail!(params[0].span().error(&format!("invalid glob: {e}"))),
+    };
+    Ok(Value::Bool(
+        Automaton::new(tokens).matches(value.as_ref(), &delimiters),
+    ))
 }
 
 fn quote_meta(span: &Span, params: &[Ref<Expr>], args: &[Value], _strict: bool) -> Result<Value> {
@@ -105,7 +277,12 @@ fn quote_meta(span: &Span, params: &[Ref<Expr>], args: &[Value], _strict: bool)
     ensure_args_count(span, name, params, args, 1)?;
 
     let pattern = ensure_string(name, &params[0], &args[0])?;
-    // Ensure that the glob is valid.
-    let _ = make_glob(&pattern, params[0].span())?;
-    Ok(Value::String(pattern.as_ref().replace('*', "\\*").into()))
+    let mut quoted = String::with_capacity(pattern.len());
+    for c in pattern.chars() {
+        if SPECIAL_CHARS.contains(&c) {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    Ok(Value::String(quoted.into()))
 }
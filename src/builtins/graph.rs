@@ -4,6 +4,7 @@
 use crate::ast::{Expr, Ref};
 use crate::builtins;
 use crate::builtins::utils::{ensure_args_count, ensure_object};
+use crate::builtins::Builtin;
 use crate::lexer::Span;
 use crate::value::Value;
 use crate::*;
@@ -13,9 +14,9 @@ use alloc::collections::{BTreeMap, BTreeSet};
 use anyhow::{bail, Result};
 
 pub fn register(m: &mut builtins::BuiltinsMap<&'static str, builtins::BuiltinFcn>) {
-    m.insert("graph.reachable", (reachable, 2));
-    m.insert("graph.reachable_paths", (reachable_paths, 2));
-    m.insert("walk", (walk, 1));
+    m.insert("graph.reachable", (Builtin::Pure(reachable), 2));
+    m.insert("graph.reachable_paths", (Builtin::Pure(reachable_paths), 2));
+    m.insert("walk", (Builtin::Pure(walk), 1));
 }
 
 fn reachable(span: &Span, params: &[Ref<Expr>], args: &[Value], strict: bool) -> Result<Value> {
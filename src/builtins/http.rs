@@ -4,6 +4,7 @@
 use crate::ast::{Expr, Ref};
 use crate::builtins;
 use crate::builtins::utils::ensure_args_count;
+use crate::builtins::Builtin;
 
 use crate::lexer::Span;
 use crate::value::Value;
@@ -11,7 +12,7 @@ use crate::value::Value;
 use anyhow::Result;
 
 pub fn register(m: &mut builtins::BuiltinsMap<&'static str, builtins::BuiltinFcn>) {
-    m.insert("http.send", (send, 1));
+    m.insert("http.send", (Builtin::Impure(send), 1));
 }
 
 fn send(span: &Span, params: &[Ref<Expr>], args: &[Value], _strict: bool) -> Result<Value> {
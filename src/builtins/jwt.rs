@@ -4,6 +4,7 @@
 use crate::ast::{Expr, Ref};
 use crate::builtins;
 use crate::builtins::utils::{ensure_args_count, ensure_string};
+use crate::builtins::Builtin;
 use crate::*;
 
 use crate::lexer::Span;
@@ -14,8 +15,11 @@ use itertools::Itertools;
 use anyhow::{bail, Result};
 
 pub fn register(m: &mut builtins::BuiltinsMap<&'static str, builtins::BuiltinFcn>) {
-    m.insert("io.jwt.decode", (jwt_decode, 1));
-    m.insert("io.jwt.decode_verify", (jwt_decode_verify, 2));
+    m.insert("io.jwt.decode", (Builtin::Pure(jwt_decode), 1));
+    m.insert(
+        "io.jwt.decode_verify",
+        (Builtin::Impure(jwt_decode_verify), 2),
+    );
 }
 
 fn decode(span: &Span, jwt: String, strict: bool) -> Result<Value> {
@@ -51,11 +51,35 @@ use crate::Map as BuiltinsMap;
 use anyhow::Result;
 use lazy_static::lazy_static;
 
-pub type BuiltinFcn = (fn(&Span, &[Ref<Expr>], &[Value], bool) -> Result<Value>, u8);
+/// Function implementing a builtin.
+pub type PlainBuiltinFcn = fn(&Span, &[Ref<Expr>], &[Value], bool) -> Result<Value>;
+
+/// Implementation of a builtin.
+#[derive(Clone, Copy)]
+pub enum Builtin {
+    /// Result depends only on the arguments and hence may be memoized across evaluations.
+    Pure(PlainBuiltinFcn),
+    /// Result may differ between calls with the same arguments, or the call has side effects.
+    Impure(PlainBuiltinFcn),
+    /// Pure builtin that compiles regexes using the interpreter's [`RegexCache`].
+    #[cfg(feature = "regex")]
+    Regex(RegexBuiltinFcn),
+}
+
+impl Builtin {
+    pub fn is_pure(&self) -> bool {
+        !matches!(self, Self::Impure(_))
+    }
+}
+
+pub type BuiltinFcn = (Builtin, u8);
 
 #[cfg(feature = "deprecated")]
 pub use deprecated::DEPRECATED;
 
+#[cfg(feature = "regex")]
+pub use regex::{new_regex_cache, RegexBuiltinFcn, RegexCache};
+
 #[rustfmt::skip]
 lazy_static! {
     pub static ref BUILTINS: BuiltinsMap<&'static str, BuiltinFcn> = {
@@ -120,3 +144,8 @@ pub fn must_cache(path: &str) -> Option<&'static str> {
         _ => None,
     }
 }
+
+// Whether the builtin is registered only when the std feature is enabled.
+pub fn requires_std(path: &str) -> bool {
+    matches!(path, "rand.intn")
+}
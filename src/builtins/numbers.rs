@@ -4,6 +4,7 @@
 use crate::ast::{ArithOp, Expr, Ref};
 use crate::builtins;
 use crate::builtins::utils::{ensure_args_count, ensure_numeric};
+use crate::builtins::Builtin;
 use crate::lexer::Span;
 use crate::number::Number;
 use crate::value::Value;
@@ -15,16 +16,17 @@ use anyhow::{bail, Result};
 use rand::{thread_rng, Rng};
 
 pub fn register(m: &mut builtins::BuiltinsMap<&'static str, builtins::BuiltinFcn>) {
-    m.insert("abs", (abs, 1));
-    m.insert("ceil", (ceil, 1));
-    m.insert("floor", (floor, 1));
-    m.insert("numbers.range", (range, 2));
-    m.insert("numbers.range_step", (range_step, 3));
+    m.insert("abs", (Builtin::Pure(abs), 1));
+    m.insert("ceil", (Builtin::Pure(ceil), 1));
+    m.insert("floor", (Builtin::Pure(floor), 1));
+    m.insert("numbers.range", (Builtin::Pure(range), 2));
+    m.insert("numbers.range_step", (Builtin::Pure(range_step), 3));
     #[cfg(feature = "std")]
-    m.insert("rand.intn", (intn, 2));
-    m.insert("round", (round, 1));
+    m.insert("rand.intn", (Builtin::Impure(intn), 2));
+    m.insert("round", (Builtin::Pure(round), 1));
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn arithmetic_operation(
     span: &Span,
     op: &ArithOp,
@@ -33,6 +35,7 @@ pub fn arithmetic_operation(
     v1: Value,
     v2: Value,
     strict: bool,
+    exact: bool,
 ) -> Result<Value> {
     let op_name = format!("{:?}", op).to_lowercase();
     let v1 = ensure_numeric(op_name.as_str(), expr1, &v1)?;
@@ -44,6 +47,7 @@ pub fn arithmetic_operation(
         ArithOp::Mul => v1.mul(&v2)?,
         ArithOp::Div if strict && v2 == Number::from(0u64) => bail!(span.error("divide by zero")),
         ArithOp::Div if v2 == Number::from(0u64) => return Ok(Value::Undefined),
+        ArithOp::Div if exact => v1.divide_exact(&v2)?,
         ArithOp::Div => v1.divide(&v2)?,
         ArithOp::Mod if strict && v2 == Number::from(0u64) => bail!(span.error("modulo by zero")),
         ArithOp::Mod if v2 == Number::from(0u64) => return Ok(Value::Undefined),
@@ -4,6 +4,7 @@
 use crate::ast::{Expr, Ref};
 use crate::builtins;
 use crate::builtins::utils::{ensure_args_count, ensure_array, ensure_object};
+use crate::builtins::Builtin;
 use crate::lexer::Span;
 use crate::Rc;
 use crate::Value;
@@ -15,20 +16,20 @@ use core::iter::Iterator;
 use anyhow::{bail, Result};
 
 pub fn register(m: &mut builtins::BuiltinsMap<&'static str, builtins::BuiltinFcn>) {
-    m.insert("json.filter", (json_filter, 2));
-    m.insert("json.remove", (json_remove, 2));
-    m.insert("object.filter", (filter, 2));
-    m.insert("object.get", (get, 3));
-    m.insert("object.keys", (keys, 1));
-    m.insert("object.remove", (remove, 2));
-    m.insert("object.subset", (subset, 2));
-    m.insert("object.union", (object_union, 2));
-    m.insert("object.union_n", (object_union_n, 1));
+    m.insert("json.filter", (Builtin::Pure(json_filter), 2));
+    m.insert("json.remove", (Builtin::Pure(json_remove), 2));
+    m.insert("object.filter", (Builtin::Pure(filter), 2));
+    m.insert("object.get", (Builtin::Pure(get), 3));
+    m.insert("object.keys", (Builtin::Pure(keys), 1));
+    m.insert("object.remove", (Builtin::Pure(remove), 2));
+    m.insert("object.subset", (Builtin::Pure(subset), 2));
+    m.insert("object.union", (Builtin::Pure(object_union), 2));
+    m.insert("object.union_n", (Builtin::Pure(object_union_n), 1));
 
     #[cfg(feature = "jsonschema")]
     {
-        m.insert("json.match_schema", (json_match_schema, 2));
-        m.insert("json.verify_schema", (json_verify_schema, 1));
+        m.insert("json.match_schema", (Builtin::Pure(json_match_schema), 2));
+        m.insert("json.verify_schema", (Builtin::Pure(json_verify_schema), 1));
     }
 }
 
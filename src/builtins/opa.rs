@@ -4,6 +4,7 @@
 use crate::ast::{Expr, Ref};
 use crate::builtins;
 use crate::builtins::utils::ensure_args_count;
+use crate::builtins::Builtin;
 use crate::*;
 
 use crate::lexer::Span;
@@ -14,7 +15,7 @@ use alloc::collections::BTreeMap;
 use anyhow::Result;
 
 pub fn register(m: &mut builtins::BuiltinsMap<&'static str, builtins::BuiltinFcn>) {
-    m.insert("opa.runtime", (opa_runtime, 0));
+    m.insert("opa.runtime", (Builtin::Impure(opa_runtime), 0));
 }
 
 fn opa_runtime(span: &Span, params: &[Ref<Expr>], args: &[Value], _strict: bool) -> Result<Value> {
@@ -4,28 +4,61 @@
 use crate::ast::{Expr, Ref};
 use crate::builtins;
 use crate::builtins::utils::{ensure_args_count, ensure_numeric, ensure_string};
+use crate::builtins::Builtin;
 use crate::lexer::Span;
 use crate::value::Value;
 use crate::*;
 
-use anyhow::{bail, Result};
+use crate::lru::LruCache;
+
+use alloc::collections::BTreeSet;
+use anyhow::{anyhow, bail, Result};
 use regex::Regex;
 
+// Maximum number of compiled regexes retained by an engine.
+const REGEX_CACHE_CAPACITY: usize = 256;
+
+/// Compiled regexes retained across evaluations.
+/// The least recently used regex is evicted when the cache is full.
+pub type RegexCache = LruCache<String, Regex>;
+
+/// Builtin that compiles regexes using a [`RegexCache`].
+pub type RegexBuiltinFcn =
+    fn(&mut RegexCache, &Span, &[Ref<Expr>], &[Value], bool) -> Result<Value>;
+
+pub fn new_regex_cache() -> RegexCache {
+    LruCache::new(REGEX_CACHE_CAPACITY)
+}
+
+// Compile a regex, reusing previously compiled regexes where possible.
+fn compile_regex(cache: &mut RegexCache, pattern: &str) -> Result<Regex, regex::Error> {
+    if let Some(regex) = cache.get(pattern) {
+        return Ok(regex.clone());
+    }
+    let regex = Regex::new(pattern)?;
+    cache.insert(pattern.to_string(), regex.clone());
+    Ok(regex)
+}
+
 pub fn register(m: &mut builtins::BuiltinsMap<&'static str, builtins::BuiltinFcn>) {
     m.insert(
         "regex.find_all_string_submatch_n",
-        (find_all_string_submatch_n, 3),
+        (Builtin::Regex(find_all_string_submatch_n), 3),
+    );
+    m.insert("regex.find_n", (Builtin::Regex(find_n), 3));
+    m.insert("regex.globs_match", (Builtin::Pure(globs_match), 2));
+    m.insert("regex.is_valid", (Builtin::Regex(is_valid), 1));
+    m.insert("regex.match", (Builtin::Regex(regex_match), 2));
+    m.insert("regex.replace", (Builtin::Regex(regex_replace), 3));
+    m.insert("regex.split", (Builtin::Regex(regex_split), 2));
+    m.insert(
+        "regex.template_match",
+        (Builtin::Regex(regex_template_match), 4),
     );
-    m.insert("regex.find_n", (find_n, 3));
-    // TODO: m.insert("regex.globs_match", (globs_match, 2));
-    m.insert("regex.is_valid", (is_valid, 1));
-    m.insert("regex.match", (regex_match, 2));
-    m.insert("regex.replace", (regex_replace, 3));
-    m.insert("regex.split", (regex_split, 2));
-    m.insert("regex.template_match", (regex_template_match, 4));
 }
 
 fn find_all_string_submatch_n(
+    cache: &mut RegexCache,
     span: &Span,
     params: &[Ref<Expr>],
     args: &[Value],
@@ -38,8 +71,8 @@ fn find_all_string_submatch_n(
     let value = ensure_string(name, &params[1], &args[1])?;
     let n = ensure_numeric(name, &params[2], &args[2])?;
 
-    let pattern =
-        Regex::new(&pattern).or_else(|_| bail!(params[0].span().error("invalid regex")))?;
+    let pattern = compile_regex(cache, &pattern)
+        .or_else(|_| bail!(params[0].span().error("invalid regex")))?;
 
     if !n.is_integer() {
         bail!(params[2].span().error("n must be an integer"));
@@ -72,7 +105,13 @@ fn find_all_string_submatch_n(
     ))
 }
 
-fn find_n(span: &Span, params: &[Ref<Expr>], args: &[Value], _strict: bool) -> Result<Value> {
+fn find_n(
+    cache: &mut RegexCache,
+    span: &Span,
+    params: &[Ref<Expr>],
+    args: &[Value],
+    _strict: bool,
+) -> Result<Value> {
     let name = "regex.find_n";
     ensure_args_count(span, name, params, args, 3)?;
 
@@ -80,8 +119,8 @@ fn find_n(span: &Span, params: &[Ref<Expr>], args: &[Value], _strict: bool) -> R
     let value = ensure_string(name, &params[1], &args[1])?;
     let n = ensure_numeric(name, &params[2], &args[2])?;
 
-    let pattern =
-        Regex::new(&pattern).or_else(|_| bail!(params[0].span().error("invalid regex")))?;
+    let pattern = compile_regex(cache, &pattern)
+        .or_else(|_| bail!(params[0].span().error("invalid regex")))?;
 
     if !n.is_integer() {
         bail!(params[2].span().error("n must be an integer"));
@@ -102,14 +141,24 @@ fn find_n(span: &Span, params: &[Ref<Expr>], args: &[Value], _strict: bool) -> R
     ))
 }
 
-fn is_valid(span: &Span, params: &[Ref<Expr>], args: &[Value], _strict: bool) -> Result<Value> {
+fn is_valid(
+    cache: &mut RegexCache,
+    span: &Span,
+    params: &[Ref<Expr>],
+    args: &[Value],
+    _strict: bool,
+) -> Result<Value> {
     let name = "regex.is_valid";
     ensure_args_count(span, name, params, args, 1)?;
-    Ok(ensure_string(name, &params[0], &args[0])
-        .map_or(Value::Bool(false), |p| Value::Bool(Regex::new(&p).is_ok())))
+    Ok(
+        ensure_string(name, &params[0], &args[0]).map_or(Value::Bool(false), |p| {
+            Value::Bool(compile_regex(cache, &p).is_ok())
+        }),
+    )
 }
 
 pub fn regex_match(
+    cache: &mut RegexCache,
     span: &Span,
     params: &[Ref<Expr>],
     args: &[Value],
@@ -120,12 +169,13 @@ pub fn regex_match(
     let pattern = ensure_string(name, &params[0], &args[0])?;
     let value = ensure_string(name, &params[1], &args[1])?;
 
-    let pattern =
-        Regex::new(&pattern).or_else(|_| bail!(params[0].span().error("invalid regex")))?;
+    let pattern = compile_regex(cache, &pattern)
+        .or_else(|_| bail!(params[0].span().error("invalid regex")))?;
     Ok(Value::Bool(pattern.is_match(&value)))
 }
 
 fn regex_replace(
+    cache: &mut RegexCache,
     span: &Span,
     params: &[Ref<Expr>],
     args: &[Value],
@@ -138,7 +188,7 @@ fn regex_replace(
     let pattern = ensure_string(name, &params[1], &args[1])?;
     let value = ensure_string(name, &params[2], &args[2])?;
 
-    let pattern = match Regex::new(&pattern) {
+    let pattern = match compile_regex(cache, &pattern) {
         Ok(p) => p,
         // TODO: This behavior is due to OPA test not raising error. Should we raise error?
         _ => return Ok(Value::Undefined),
@@ -149,14 +199,20 @@ fn regex_replace(
     ))
 }
 
-fn regex_split(span: &Span, params: &[Ref<Expr>], args: &[Value], _strict: bool) -> Result<Value> {
+fn regex_split(
+    cache: &mut RegexCache,
+    span: &Span,
+    params: &[Ref<Expr>],
+    args: &[Value],
+    _strict: bool,
+) -> Result<Value> {
     let name = "regex.split";
     ensure_args_count(span, name, params, args, 2)?;
     let pattern = ensure_string(name, &params[0], &args[0])?;
     let value = ensure_string(name, &params[1], &args[1])?;
 
-    let pattern =
-        Regex::new(&pattern).or_else(|_| bail!(params[0].span().error("invalid regex")))?;
+    let pattern = compile_regex(cache, &pattern)
+        .or_else(|_| bail!(params[0].span().error("invalid regex")))?;
     Ok(Value::from_array(
         pattern
             .split(&value)
@@ -166,6 +222,7 @@ fn regex_split(span: &Span, params: &[Ref<Expr>], args: &[Value], _strict: bool)
 }
 
 fn regex_template_match(
+    cache: &mut RegexCache,
     span: &Span,
     params: &[Ref<Expr>],
     args: &[Value],
@@ -195,7 +252,7 @@ fn regex_template_match(
         }
 
         // Fetch pattern, excluding delimiters.
-        let pattern = Regex::new(&template[start + delimiter_start.len()..end])
+        let pattern = compile_regex(cache, &template[start + delimiter_start.len()..end])
             .or_else(|_| bail!(params[0].span().error("invalid regex")))?;
 
         // Skip preceding literal in value.
@@ -215,3 +272,190 @@ fn regex_template_match(
     // Ensure that ending literal matches.
     Ok(Value::Bool(template == value))
 }
+
+// A set of characters matched by a glob token.
+// Negated sets match all characters other than those in the ranges.
+#[derive(Debug, Clone)]
+struct GlobCharSet {
+    ranges: Vec<(char, char)>,
+    negated: bool,
+}
+
+impl GlobCharSet {
+    // Whether some character in the given ranges is not in the set.
+    fn excludes_some(&self, ranges: &[(char, char)]) -> bool {
+        ranges.iter().any(|(lo, hi)| {
+            let mut c = *lo;
+            loop {
+                match self.ranges.iter().find(|(l, h)| *l <= c && c <= *h) {
+                    None => return true,
+                    Some((_, h)) if h >= hi => return false,
+                    Some((_, h)) => match char::from_u32(*h as u32 + 1) {
+                        Some(next) => c = next,
+                        // Skip surrogates.
+                        None => c = '\u{E000}',
+                    },
+                }
+                if c > *hi {
+                    return false;
+                }
+            }
+        })
+    }
+
+    fn intersects(&self, other: &GlobCharSet) -> bool {
+        match (self.negated, other.negated) {
+            (true, true) => true,
+            (false, true) => other.excludes_some(&self.ranges),
+            (true, false) => self.excludes_some(&other.ranges),
+            (false, false) => self
+                .ranges
+                .iter()
+                .any(|(lo, hi)| other.ranges.iter().any(|(l, h)| lo.max(l) <= hi.min(h))),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct GlobToken {
+    chars: GlobCharSet,
+    // Whether the token may be repeated zero or more times.
+    star: bool,
+}
+
+// Parse a glob-style regex consisting of literals, escapes, `.`, character classes and
+// the `*` and `+` repetition operators.
+fn parse_glob(glob: &str) -> Option<Vec<GlobToken>> {
+    let chars: Vec<char> = glob.chars().collect();
+    let mut tokens: Vec<GlobToken> = vec![];
+    let mut idx = 0;
+    let mut next = || {
+        idx += 1;
+        chars.get(idx - 1).copied()
+    };
+    while let Some(c) = next() {
+        let set = match c {
+            '*' | '+' => {
+                let last = tokens.last_mut().filter(|t| !t.star)?;
+                if c == '*' {
+                    last.star = true;
+                } else {
+                    let mut repeat = last.clone();
+                    repeat.star = true;
+                    tokens.push(repeat);
+                }
+                continue;
+            }
+            '.' => GlobCharSet {
+                ranges: vec![],
+                negated: true,
+            },
+            '\\' => {
+                let c = next()?;
+                GlobCharSet {
+                    ranges: vec![(c, c)],
+                    negated: false,
+                }
+            }
+            '[' => {
+                let mut set = GlobCharSet {
+                    ranges: vec![],
+                    negated: false,
+                };
+                let mut c = next()?;
+                if c == '^' {
+                    set.negated = true;
+                    c = next()?;
+                }
+                // A leading ']' is a literal.
+                let mut first = true;
+                while c != ']' || first {
+                    first = false;
+                    let lo = if c == '\\' { next()? } else { c };
+                    c = next()?;
+                    if c != '-' {
+                        set.ranges.push((lo, lo));
+                        continue;
+                    }
+                    c = next()?;
+                    if c == ']' {
+                        // Trailing '-' is a literal.
+                        set.ranges.push((lo, lo));
+                        set.ranges.push(('-', '-'));
+                        break;
+                    }
+                    let hi = if c == '\\' { next()? } else { c };
+                    if hi < lo {
+                        return None;
+                    }
+                    set.ranges.push((lo, hi));
+                    c = next()?;
+                }
+                set
+            }
+            c => GlobCharSet {
+                ranges: vec![(c, c)],
+                negated: false,
+            },
+        };
+        tokens.push(GlobToken {
+            chars: set,
+            star: false,
+        });
+    }
+    Some(tokens)
+}
+
+// Advance past tokens that can match the empty string, returning all reachable positions.
+fn glob_closure(tokens: &[GlobToken], mut pos: usize) -> Vec<usize> {
+    let mut positions = vec![pos];
+    while pos < tokens.len() && tokens[pos].star {
+        pos += 1;
+        positions.push(pos);
+    }
+    positions
+}
+
+// Determine whether some non-empty string is matched by both globs, by exploring
+// the product of the two globs' automata.
+fn globs_intersect(glob1: &[GlobToken], glob2: &[GlobToken]) -> bool {
+    let mut visited = BTreeSet::new();
+    let mut pending = vec![(0, 0, false)];
+    while let Some(state @ (pos1, pos2, nonempty)) = pending.pop() {
+        if !visited.insert(state) {
+            continue;
+        }
+        for p1 in glob_closure(glob1, pos1) {
+            for p2 in glob_closure(glob2, pos2) {
+                if p1 == glob1.len() && p2 == glob2.len() {
+                    if nonempty {
+                        return true;
+                    }
+                    continue;
+                }
+                let (Some(t1), Some(t2)) = (glob1.get(p1), glob2.get(p2)) else {
+                    continue;
+                };
+                if t1.chars.intersects(&t2.chars) {
+                    let next1 = if t1.star { p1 } else { p1 + 1 };
+                    let next2 = if t2.star { p2 } else { p2 + 1 };
+                    pending.push((next1, next2, true));
+                }
+            }
+        }
+    }
+    false
+}
+
+fn globs_match(span: &Span, params: &[Ref<Expr>], args: &[Value], _strict: bool) -> Result<Value> {
+    let name = "regex.globs_match";
+    ensure_args_count(span, name, params, args, 2)?;
+    let glob1 = ensure_string(name, &params[0], &args[0])?;
+    let glob2 = ensure_string(name, &params[1], &args[1])?;
+
+    let glob1 =
+        parse_glob(&glob1).ok_or_else(|| anyhow!(params[0].span().error("invalid glob")))?;
+    let glob2 =
+        parse_glob(&glob2).ok_or_else(|| anyhow!(params[1].span().error("invalid glob")))?;
+    Ok(Value::Bool(globs_intersect(&glob1, &glob2)))
+}
@@ -4,6 +4,7 @@
 use crate::ast::{Expr, Ref};
 use crate::builtins;
 use crate::builtins::utils::{ensure_args_count, ensure_string};
+use crate::builtins::Builtin;
 use crate::lexer::Span;
 use crate::value::Value;
 
@@ -14,8 +15,8 @@ use core::cmp::Ordering;
 use anyhow::Result;
 
 pub fn register(m: &mut builtins::BuiltinsMap<&'static str, builtins::BuiltinFcn>) {
-    m.insert("semver.compare", (compare, 2));
-    m.insert("semver.is_valid", (is_valid, 1));
+    m.insert("semver.compare", (Builtin::Pure(compare), 2));
+    m.insert("semver.is_valid", (Builtin::Pure(is_valid), 1));
 }
 
 fn compare(span: &Span, params: &[Ref<Expr>], args: &[Value], _strict: bool) -> Result<Value> {
@@ -25,7 +26,7 @@ fn compare(span: &Span, params: &[Ref<Expr>], args: &[Value], _strict: bool) ->
     let v1 = ensure_string(name, &params[0], &args[0])?;
     let v2 = ensure_string(name, &params[1], &args[1])?;
     let version1 = Version::parse(&v1).map_err(|_| params[0].span().error("invalid semver"))?;
-    let version2 = Version::parse(&v2).map_err(|_| params[0].span().error("invalid semver"))?;
+    let version2 = Version::parse(&v2).map_err(|_| params[1].span().error("invalid semver"))?;
     let result = match version1.cmp_precedence(&version2) {
         Ordering::Less => -1,
         Ordering::Equal => 0,
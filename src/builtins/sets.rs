@@ -4,6 +4,7 @@
 use crate::ast::{Expr, Ref};
 use crate::builtins;
 use crate::builtins::utils::{ensure_args_count, ensure_set};
+use crate::builtins::Builtin;
 use crate::lexer::Span;
 use crate::value::Value;
 use crate::*;
@@ -13,8 +14,11 @@ use alloc::collections::BTreeSet;
 use anyhow::{bail, Result};
 
 pub fn register(m: &mut builtins::BuiltinsMap<&'static str, builtins::BuiltinFcn>) {
-    m.insert("intersection", (intersection_of_set_of_sets, 1));
-    m.insert("union", (union_of_set_of_sets, 1));
+    m.insert(
+        "intersection",
+        (Builtin::Pure(intersection_of_set_of_sets), 1),
+    );
+    m.insert("union", (Builtin::Pure(union_of_set_of_sets), 1));
 }
 
 pub fn intersection(expr1: &Expr, expr2: &Expr, v1: Value, v2: Value) -> Result<Value> {
@@ -7,6 +7,7 @@ use crate::builtins::utils::{
     ensure_args_count, ensure_array, ensure_numeric, ensure_object, ensure_string,
     ensure_string_collection,
 };
+use crate::builtins::Builtin;
 use crate::lexer::Span;
 use crate::number::Number;
 use crate::value::Value;
@@ -15,30 +16,40 @@ use crate::*;
 use anyhow::{bail, Result};
 
 pub fn register(m: &mut builtins::BuiltinsMap<&'static str, builtins::BuiltinFcn>) {
-    m.insert("concat", (concat, 2));
-    m.insert("contains", (contains, 2));
-    m.insert("endswith", (endswith, 2));
-    m.insert("format_int", (format_int, 2));
-    m.insert("indexof", (indexof, 2));
-    m.insert("indexof_n", (indexof_n, 2));
-    m.insert("lower", (lower, 1));
-    m.insert("replace", (replace, 3));
-    m.insert("split", (split, 2));
-    m.insert("sprintf", (sprintf, 2));
-    m.insert("startswith", (startswith, 2));
-    m.insert("strings.any_prefix_match", (any_prefix_match, 2));
-    m.insert("strings.any_suffix_match", (any_suffix_match, 2));
-    m.insert("strings.count", (strings_count, 2));
-    m.insert("strings.replace_n", (replace_n, 2));
-    m.insert("strings.reverse", (reverse, 1));
-    m.insert("substring", (substring, 3));
-    m.insert("trim", (trim, 2));
-    m.insert("trim_left", (trim_left, 2));
-    m.insert("trim_prefix", (trim_prefix, 2));
-    m.insert("trim_right", (trim_right, 2));
-    m.insert("trim_space", (trim_space, 1));
-    m.insert("trim_suffix", (trim_suffix, 2));
-    m.insert("upper", (upper, 1));
+    m.insert("concat", (Builtin::Pure(concat), 2));
+    m.insert("contains", (Builtin::Pure(contains), 2));
+    m.insert("endswith", (Builtin::Pure(endswith), 2));
+    m.insert("format_int", (Builtin::Pure(format_int), 2));
+    m.insert("indexof", (Builtin::Pure(indexof), 2));
+    m.insert("indexof_n", (Builtin::Pure(indexof_n), 2));
+    m.insert("lower", (Builtin::Pure(lower), 1));
+    m.insert("replace", (Builtin::Pure(replace), 3));
+    m.insert("split", (Builtin::Pure(split), 2));
+    m.insert("sprintf", (Builtin::Pure(sprintf), 2));
+    m.insert("startswith", (Builtin::Pure(startswith), 2));
+    m.insert(
+        "strings.any_prefix_match",
+        (Builtin::Pure(any_prefix_match), 2),
+    );
+    m.insert(
+        "strings.any_suffix_match",
+        (Builtin::Pure(any_suffix_match), 2),
+    );
+    m.insert("strings.count", (Builtin::Pure(strings_count), 2));
+    m.insert(
+        "strings.render_template",
+        (Builtin::Pure(render_template), 2),
+    );
+    m.insert("strings.replace_n", (Builtin::Pure(replace_n), 2));
+    m.insert("strings.reverse", (Builtin::Pure(reverse), 1));
+    m.insert("substring", (Builtin::Pure(substring), 3));
+    m.insert("trim", (Builtin::Pure(trim), 2));
+    m.insert("trim_left", (Builtin::Pure(trim_left), 2));
+    m.insert("trim_prefix", (Builtin::Pure(trim_prefix), 2));
+    m.insert("trim_right", (Builtin::Pure(trim_right), 2));
+    m.insert("trim_space", (Builtin::Pure(trim_space), 1));
+    m.insert("trim_suffix", (Builtin::Pure(trim_suffix), 2));
+    m.insert("upper", (Builtin::Pure(upper), 1));
 }
 
 fn concat(span: &Span, params: &[Ref<Expr>], args: &[Value], _strict: bool) -> Result<Value> {
@@ -141,7 +152,7 @@ fn replace(span: &Span, params: &[Ref<Expr>], args: &[Value], _strict: bool) ->
 }
 
 fn split(span: &Span, params: &[Ref<Expr>], args: &[Value], _strict: bool) -> Result<Value> {
-    let name = "replace";
+    let name = "split";
     ensure_args_count(span, name, params, args, 2)?;
     let s = ensure_string(name, &params[0], &args[0])?;
     let delimiter = ensure_string(name, &params[1], &args[1])?;
@@ -177,6 +188,8 @@ fn to_string(v: &Value, unescape: bool) -> String {
                     .join(", ")
                 + "]"
         }
+        // Empty sets are displayed as set() to distinguish them from empty objects.
+        Value::Set(s) if s.is_empty() => "set()".to_owned(),
         Value::Set(s) => {
             "{".to_owned()
                 + &s.iter()
@@ -197,6 +210,27 @@ fn to_string(v: &Value, unescape: bool) -> String {
     }
 }
 
+// Format a value the way Go's fmt package prints the corresponding Go value.
+// OPA passes arrays and sets as slices and objects as maps to text/template.
+fn to_go_string(v: &Value) -> String {
+    let join = |items: Vec<String>| items.join(" ");
+    match v {
+        Value::Null => "<nil>".to_owned(),
+        Value::Array(a) => "[".to_owned() + &join(a.iter().map(to_go_string).collect()) + "]",
+        Value::Set(s) => "[".to_owned() + &join(s.iter().map(to_go_string).collect()) + "]",
+        Value::Object(o) => {
+            "map[".to_owned()
+                + &join(
+                    o.iter()
+                        .map(|(k, v)| to_go_string(k) + ":" + &to_go_string(v))
+                        .collect(),
+                )
+                + "]"
+        }
+        _ => to_string(v, false),
+    }
+}
+
 enum Width {
     None,
     LeadingZeros(usize),
@@ -223,48 +257,58 @@ fn sprintf(span: &Span, params: &[Ref<Expr>], args: &[Value], _strict: bool) ->
     let mut chars = fmt.chars().peekable();
     let args_span = params[1].span();
     loop {
+        let mut emit_sign = false;
+        let mut leave_space_for_elided_sign = false;
         let (verb, width) = match chars.next() {
-            Some('%') => match chars.next() {
-                Some('%') => {
-                    s.push('%');
-                    continue;
+            Some('%') => {
+                // Handle Golang flags.
+                while let Some(flag @ ('+' | ' ')) = chars.peek() {
+                    emit_sign |= *flag == '+';
+                    leave_space_for_elided_sign |= *flag == ' ';
+                    chars.next();
                 }
-                Some(c) if c == '.' || c.is_numeric() => {
-                    let first_char = c;
-                    let mut w = 0;
-                    if c != '.' {
-                        w = c.to_digit(10).expect("could not get digit from char");
+                match chars.next() {
+                    Some('%') => {
+                        s.push('%');
+                        continue;
                     }
+                    Some(c) if c == '.' || c.is_numeric() => {
+                        let first_char = c;
+                        let mut w = 0;
+                        if c != '.' {
+                            w = c.to_digit(10).expect("could not get digit from char");
+                        }
 
-                    while chars.peek().map(|c| c.is_numeric()) == Some(true) {
-                        w = w * 10
-                            + chars
-                                .next()
-                                .expect("could not get next digit")
-                                .to_digit(10)
-                                .expect("could not get digit from char");
-                    }
-                    let width = match first_char {
-                        '0' => Width::LeadingZeros(w as usize),
-                        '.' => Width::Decimals(w as usize),
-                        _ => Width::Cell(w as usize),
-                    };
-                    match chars.next() {
-                        Some(c) => (c, width),
-                        _ => {
-                            let span = params[0].span();
-                            bail!(span.error(
-                                "missing format verb after `%width` at end of format string"
-                            ));
+                        while chars.peek().map(|c| c.is_numeric()) == Some(true) {
+                            w = w * 10
+                                + chars
+                                    .next()
+                                    .expect("could not get next digit")
+                                    .to_digit(10)
+                                    .expect("could not get digit from char");
+                        }
+                        let width = match first_char {
+                            '0' => Width::LeadingZeros(w as usize),
+                            '.' => Width::Decimals(w as usize),
+                            _ => Width::Cell(w as usize),
+                        };
+                        match chars.next() {
+                            Some(c) => (c, width),
+                            _ => {
+                                let span = params[0].span();
+                                bail!(span.error(
+                                    "missing format verb after `%width` at end of format string"
+                                ));
+                            }
                         }
                     }
+                    Some(c) => (c, Width::None),
+                    None => {
+                        let span = params[0].span();
+                        bail!(span.error("missing format verb after `%` at end of format string"));
+                    }
                 }
-                Some(c) => (c, Width::None),
-                None => {
-                    let span = params[0].span();
-                    bail!(span.error("missing format verb after `%` at end of format string"));
-                }
-            },
+            }
             Some(c) => {
                 s.push(c);
                 continue;
@@ -279,23 +323,8 @@ fn sprintf(span: &Span, params: &[Ref<Expr>], args: &[Value], _strict: bool) ->
         let arg = &args[args_idx];
         args_idx += 1;
 
-        // Handle Golang flags.
-        let mut emit_sign = false;
-        let mut leave_space_for_elided_sign = false;
-        match chars.peek() {
-            Some('+') => {
-                emit_sign = true;
-                chars.next();
-            }
-            Some(' ') => {
-                leave_space_for_elided_sign = true;
-                chars.next();
-            }
-            _ => (),
-        }
-
         let get_sign_value = |f: &Number| match (emit_sign, f) {
-            (_, v) if v < &Number::from(0.0) => ("-", v.clone()),
+            (_, v) if v < &Number::from(0.0) => ("-", v.abs()),
             (true, v) => ("+", v.clone()),
             (false, v) if leave_space_for_elided_sign => (" ", v.clone()),
             (false, v) => ("", v.clone()),
@@ -307,6 +336,12 @@ fn sprintf(span: &Span, params: &[Ref<Expr>], args: &[Value], _strict: bool) ->
             ('s', Value::String(sv)) => s += sv.as_ref(),
             ('s', v) => s += &to_string(v, false),
 
+            // Go only adds field names for structs with %+v, so flags affect just numbers.
+            ('v', Value::Number(f)) => {
+                let (sign, v) = get_sign_value(f);
+                s += sign;
+                s += v.format_decimal().as_str()
+            }
             ('v', _) => s += &to_string(arg, false),
             ('b', Value::Number(f)) if f.is_integer() => {
                 let (sign, v) = get_sign_value(f);
@@ -395,9 +430,6 @@ fn sprintf(span: &Span, params: &[Ref<Expr>], args: &[Value], _strict: bool) ->
                 bail!(args_span.error(&format!("number specified for format verb {verb}.")));
             }
 
-            ('+', _) if chars.next() == Some('v') => {
-                bail!(args_span.error("Go-syntax fields names format verm %#v is not supported."));
-            }
             ('T', _) | ('#', _) | ('q', _) | ('p', _) => {
                 bail!(
                     args_span.error("Go-syntax format verbs %#v. %q, %p and %T are not supported.")
@@ -541,6 +573,62 @@ fn strings_count(
     ))
 }
 
+// Supports `{{.key}}` and `{{.key.nested}}` substitutions of Go's text/template.
+// As in OPA, referring to a missing key is an error.
+fn render_template(
+    span: &Span,
+    params: &[Ref<Expr>],
+    args: &[Value],
+    _strict: bool,
+) -> Result<Value> {
+    let name = "strings.render_template";
+    ensure_args_count(span, name, params, args, 2)?;
+    let template = ensure_string(name, &params[0], &args[0])?;
+    ensure_object(name, &params[1], args[1].clone())?;
+
+    let span = params[0].span();
+    let mut rendered = String::new();
+    let mut rest = template.as_ref();
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+        rest = &rest[start + 2..];
+        let Some(end) = rest.find("}}") else {
+            bail!(span.error(format!("`{name}` unclosed action in template").as_str()));
+        };
+
+        let action = rest[..end].trim();
+        let Some(path) = action.strip_prefix('.') else {
+            bail!(
+                span.error(format!("`{name}` unsupported action `{action}` in template").as_str())
+            );
+        };
+        let mut value = &args[1];
+        // `{{.}}` renders the vars object itself.
+        for key in path.split_terminator('.') {
+            value = match value {
+                Value::Object(o) => match o.get(&Value::from(key)) {
+                    Some(v) => v,
+                    None => {
+                        bail!(span
+                            .error(format!("`{name}` map has no entry for key \"{key}\"").as_str()))
+                    }
+                },
+                _ => bail!(span
+                    .error(format!("`{name}` cannot lookup key \"{key}\" in `{value}`").as_str())),
+            };
+        }
+        match value {
+            // text/template prints nil values this way.
+            Value::Null => rendered.push_str("<no value>"),
+            _ => rendered.push_str(&to_go_string(value)),
+        }
+        rest = &rest[end + 2..];
+    }
+    rendered.push_str(rest);
+
+    Ok(Value::String(rendered.into()))
+}
+
 fn startswith(span: &Span, params: &[Ref<Expr>], args: &[Value], _strict: bool) -> Result<Value> {
     let name = "startswith";
     ensure_args_count(span, name, params, args, 2)?;
@@ -586,17 +674,24 @@ fn substring(span: &Span, params: &[Ref<Expr>], args: &[Value], strict: bool) ->
     let offset = ensure_numeric(name, &params[1], &args[1])?;
     let length = ensure_numeric(name, &params[2], &args[2])?;
 
-    match (offset.as_i64(), length.as_i64()) {
-        (Some(offset), _) if offset < 0 && strict => {
-            bail!(params[1].span().error("negative offset"))
-        }
-        (Some(offset), _) if offset < 0 => Ok(Value::Undefined),
-        (Some(offset), Some(length)) => {
-            let start = s.chars().skip(offset as usize);
-            let length = if length < 0 { s.len() } else { length as usize };
-            Ok(Value::String(start.take(length).collect::<String>().into()))
-        }
-        _ => Ok(Value::String("".into())),
+    // Offsets and lengths are in runes, as in OPA.
+    let offset = match offset.as_i64() {
+        Some(offset) if offset >= s.chars().count() as i64 => return Ok(Value::from("")),
+        Some(offset) if offset < 0 && strict => bail!(params[1].span().error("negative offset")),
+        Some(offset) if offset < 0 => return Ok(Value::Undefined),
+        Some(offset) => offset as usize,
+        None if strict => bail!(params[1].span().error("operand must be integer")),
+        None => return Ok(Value::Undefined),
+    };
+
+    let chars = s.chars().skip(offset);
+    match length.as_i64() {
+        Some(length) if length < 0 => Ok(Value::String(chars.collect::<String>().into())),
+        Some(length) => Ok(Value::String(
+            chars.take(length as usize).collect::<String>().into(),
+        )),
+        None if strict => bail!(params[2].span().error("operand must be integer")),
+        None => Ok(Value::Undefined),
     }
 }
 
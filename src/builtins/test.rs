@@ -5,6 +5,7 @@ use crate::ast::{Expr, Ref};
 use crate::builtins;
 use crate::builtins::time;
 use crate::builtins::utils::{ensure_args_count, ensure_string};
+use crate::builtins::Builtin;
 use crate::lexer::Span;
 use crate::value::Value;
 use crate::*;
@@ -14,7 +15,7 @@ use std::thread;
 use anyhow::{Ok, Result};
 
 pub fn register(m: &mut builtins::BuiltinsMap<&'static str, builtins::BuiltinFcn>) {
-    m.insert("test.sleep", (sleep, 1));
+    m.insert("test.sleep", (Builtin::Impure(sleep), 1));
 }
 
 fn sleep(span: &Span, params: &[Ref<Expr>], args: &[Value], _strict: bool) -> Result<Value> {
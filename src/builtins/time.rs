@@ -4,6 +4,7 @@
 use crate::ast::{Expr, Ref};
 use crate::builtins;
 use crate::builtins::utils::{ensure_args_count, ensure_numeric, ensure_string};
+use crate::builtins::Builtin;
 use crate::lexer::Span;
 use crate::value::Value;
 use crate::*;
@@ -20,16 +21,22 @@ pub(in crate::builtins) mod compat;
 mod diff;
 
 pub fn register(m: &mut builtins::BuiltinsMap<&'static str, builtins::BuiltinFcn>) {
-    m.insert("time.add_date", (add_date, 4));
-    m.insert("time.clock", (clock, 1));
-    m.insert("time.date", (date, 1));
-    m.insert("time.diff", (diff, 2));
-    m.insert("time.format", (format, 1));
-    m.insert("time.now_ns", (now_ns, 0));
-    m.insert("time.parse_duration_ns", (parse_duration_ns, 1));
-    m.insert("time.parse_ns", (parse_ns, 2));
-    m.insert("time.parse_rfc3339_ns", (parse_rfc3339_ns, 1));
-    m.insert("time.weekday", (weekday, 1));
+    m.insert("time.add_date", (Builtin::Pure(add_date), 4));
+    m.insert("time.clock", (Builtin::Pure(clock), 1));
+    m.insert("time.date", (Builtin::Pure(date), 1));
+    m.insert("time.diff", (Builtin::Pure(diff), 2));
+    m.insert("time.format", (Builtin::Pure(format), 1));
+    m.insert("time.now_ns", (Builtin::Impure(now_ns), 0));
+    m.insert(
+        "time.parse_duration_ns",
+        (Builtin::Pure(parse_duration_ns), 1),
+    );
+    m.insert("time.parse_ns", (Builtin::Pure(parse_ns), 2));
+    m.insert(
+        "time.parse_rfc3339_ns",
+        (Builtin::Pure(parse_rfc3339_ns), 1),
+    );
+    m.insert("time.weekday", (Builtin::Pure(weekday), 1));
 }
 
 fn add_date(span: &Span, params: &[Ref<Expr>], args: &[Value], strict: bool) -> Result<Value> {
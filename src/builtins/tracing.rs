@@ -4,13 +4,14 @@
 use crate::ast::{Expr, Ref};
 use crate::builtins;
 use crate::builtins::utils::{ensure_args_count, ensure_string};
+use crate::builtins::Builtin;
 use crate::lexer::Span;
 use crate::value::Value;
 
 use anyhow::Result;
 
 pub fn register(m: &mut builtins::BuiltinsMap<&'static str, builtins::BuiltinFcn>) {
-    m.insert("trace", (trace, 1));
+    m.insert("trace", (Builtin::Impure(trace), 1));
 }
 
 // Symbol analyzer must ensure that vars used by trace are defined before
@@ -4,20 +4,21 @@
 use crate::ast::{Expr, Ref};
 use crate::builtins;
 use crate::builtins::utils::ensure_args_count;
+use crate::builtins::Builtin;
 use crate::lexer::Span;
 use crate::value::Value;
 
 use anyhow::Result;
 
 pub fn register(m: &mut builtins::BuiltinsMap<&'static str, builtins::BuiltinFcn>) {
-    m.insert("is_array", (is_array, 1));
-    m.insert("is_boolean", (is_boolean, 1));
-    m.insert("is_null", (is_null, 1));
-    m.insert("is_number", (is_number, 1));
-    m.insert("is_object", (is_object, 1));
-    m.insert("is_set", (is_set, 1));
-    m.insert("is_string", (is_string, 1));
-    m.insert("type_name", (type_name, 1));
+    m.insert("is_array", (Builtin::Pure(is_array), 1));
+    m.insert("is_boolean", (Builtin::Pure(is_boolean), 1));
+    m.insert("is_null", (Builtin::Pure(is_null), 1));
+    m.insert("is_number", (Builtin::Pure(is_number), 1));
+    m.insert("is_object", (Builtin::Pure(is_object), 1));
+    m.insert("is_set", (Builtin::Pure(is_set), 1));
+    m.insert("is_string", (Builtin::Pure(is_string), 1));
+    m.insert("type_name", (Builtin::Pure(type_name), 1));
 }
 
 fn is_array(span: &Span, params: &[Ref<Expr>], args: &[Value], _strict: bool) -> Result<Value> {
@@ -4,6 +4,7 @@
 use crate::ast::{Expr, Ref};
 use crate::builtins;
 use crate::builtins::utils::{ensure_args_count, ensure_string};
+use crate::builtins::Builtin;
 use crate::lexer::Span;
 use crate::number::Number;
 use crate::value::Value;
@@ -12,8 +13,8 @@ use crate::*;
 use anyhow::{bail, Result};
 
 pub fn register(m: &mut builtins::BuiltinsMap<&'static str, builtins::BuiltinFcn>) {
-    m.insert("units.parse", (parse, 1));
-    m.insert("units.parse_bytes", (parse_bytes, 1));
+    m.insert("units.parse", (Builtin::Pure(parse), 1));
+    m.insert("units.parse_bytes", (Builtin::Pure(parse_bytes), 1));
 }
 
 fn ten_exp(suffix: &str) -> Option<i32> {
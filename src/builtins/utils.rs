@@ -2,6 +2,7 @@
 // Licensed under the MIT License.
 
 use crate::ast::{Expr, Ref};
+use crate::error::ErrorKind;
 use crate::lexer::Span;
 use crate::number::Number;
 use crate::Rc;
@@ -25,9 +26,15 @@ pub fn ensure_args_count(
             true => params[args.len() - 1].span(),
         };
         if expected == 1 {
-            bail!(span.error(format!("`{fcn}` expects 1 argument").as_str()))
+            bail!(span.error_of_kind(
+                ErrorKind::Type,
+                format!("`{fcn}` expects 1 argument").as_str()
+            ))
         } else {
-            bail!(span.error(format!("`{fcn}` expects {expected} arguments").as_str()))
+            bail!(span.error_of_kind(
+                ErrorKind::Type,
+                format!("`{fcn}` expects {expected} arguments").as_str()
+            ))
         }
     }
     Ok(())
@@ -38,9 +45,10 @@ pub fn ensure_numeric(fcn: &str, arg: &Expr, v: &Value) -> Result<Number> {
         Value::Number(n) => n.clone(),
         _ => {
             let span = arg.span();
-            bail!(
-                span.error(format!("`{fcn}` expects numeric argument. Got `{v}` instead").as_str())
-            )
+            bail!(span.error_of_kind(
+                ErrorKind::Type,
+                format!("`{fcn}` expects numeric argument. Got `{v}` instead").as_str()
+            ))
         }
     })
 }
@@ -50,7 +58,10 @@ pub fn ensure_string(fcn: &str, arg: &Expr, v: &Value) -> Result<Rc<str>> {
         Value::String(s) => s.clone(),
         _ => {
             let span = arg.span();
-            bail!(span.error(format!("`{fcn}` expects string argument. Got `{v}` instead").as_str()))
+            bail!(span.error_of_kind(
+                ErrorKind::Type,
+                format!("`{fcn}` expects string argument. Got `{v}` instead").as_str()
+            ))
         }
     })
 }
@@ -65,7 +76,8 @@ pub fn ensure_string_element<'a>(
         Value::String(s) => s.as_ref(),
         _ => {
             let span = arg.span();
-            bail!(span.error(
+            bail!(span.error_of_kind(
+                ErrorKind::Type,
                 format!("`{fcn}` expects string collection. Element {idx} is not a string.")
                     .as_str()
             ))
@@ -88,7 +100,10 @@ pub fn ensure_string_collection<'a>(fcn: &str, arg: &Expr, v: &'a Value) -> Resu
         }
         _ => {
             let span = arg.span();
-            bail!(span.error(format!("`{fcn}` expects array/set of strings.").as_str()))
+            bail!(span.error_of_kind(
+                ErrorKind::Type,
+                format!("`{fcn}` expects array/set of strings.").as_str()
+            ))
         }
     }
     Ok(collection)
@@ -99,7 +114,10 @@ pub fn ensure_array(fcn: &str, arg: &Expr, v: Value) -> Result<Rc<Vec<Value>>> {
         Value::Array(a) => a,
         _ => {
             let span = arg.span();
-            bail!(span.error(format!("`{fcn}` expects array argument. Got `{v}` instead").as_str()))
+            bail!(span.error_of_kind(
+                ErrorKind::Type,
+                format!("`{fcn}` expects array argument. Got `{v}` instead").as_str()
+            ))
         }
     })
 }
@@ -109,7 +127,10 @@ pub fn ensure_set(fcn: &str, arg: &Expr, v: Value) -> Result<Rc<BTreeSet<Value>>
         Value::Set(s) => s,
         _ => {
             let span = arg.span();
-            bail!(span.error(format!("`{fcn}` expects set argument. Got `{v}` instead").as_str()))
+            bail!(span.error_of_kind(
+                ErrorKind::Type,
+                format!("`{fcn}` expects set argument. Got `{v}` instead").as_str()
+            ))
         }
     })
 }
@@ -119,7 +140,10 @@ pub fn ensure_object(fcn: &str, arg: &Expr, v: Value) -> Result<Rc<BTreeMap<Valu
         Value::Object(o) => o,
         _ => {
             let span = arg.span();
-            bail!(span.error(format!("`{fcn}` expects object argument. Got `{v}` instead").as_str()))
+            bail!(span.error_of_kind(
+                ErrorKind::Type,
+                format!("`{fcn}` expects object argument. Got `{v}` instead").as_str()
+            ))
         }
     })
 }
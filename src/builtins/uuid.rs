@@ -4,6 +4,7 @@
 use crate::ast::{Expr, Ref};
 use crate::builtins;
 use crate::builtins::utils::{ensure_args_count, ensure_string};
+use crate::builtins::Builtin;
 use crate::lexer::Span;
 use crate::value::Value;
 use crate::*;
@@ -14,8 +15,8 @@ use anyhow::Result;
 use uuid::{Timestamp, Uuid};
 
 pub fn register(m: &mut builtins::BuiltinsMap<&'static str, builtins::BuiltinFcn>) {
-    m.insert("uuid.parse", (parse, 1));
-    m.insert("uuid.rfc4122", (rfc4122, 1));
+    m.insert("uuid.parse", (Builtin::Pure(parse), 1));
+    m.insert("uuid.rfc4122", (Builtin::Impure(rfc4122), 1));
 }
 
 fn parse(span: &Span, params: &[Ref<Expr>], args: &[Value], _strict: bool) -> Result<Value> {
@@ -2,16 +2,25 @@
 // Licensed under the MIT License.
 
 use crate::ast::*;
+use crate::error::{classify, ErrorKind};
 use crate::interpreter::*;
 use crate::lexer::*;
 use crate::parser::*;
 use crate::scheduler::*;
-use crate::utils::gather_functions;
+use crate::utils::{gather_functions, get_path_string, visit_calls_in_rule};
 use crate::value::*;
 use crate::*;
-use crate::{Extension, QueryResults};
+use crate::{
+    BuiltinCallLogger, BuiltinSignature, Collation, DataSnapshot, DecisionLog, DependencyEdge,
+    DependencyGraph, Extension, Metrics, ModuleInfo, NumberOutput, PartialResult,
+    PrintValueFormatter, QueryResults, RuleOutcome, ScheduleInfo, StatementSchedule,
+};
 
-use anyhow::{bail, Result};
+use alloc::collections::{BTreeMap, BTreeSet};
+use anyhow::{anyhow, Result};
+
+// Names of rules that are considered entrypoints by [`Engine::unused_rules`].
+const ENTRYPOINT_RULES: [&str; 3] = ["allow", "deny", "main"];
 
 /// The Rego evaluation engine.
 ///
@@ -21,6 +30,16 @@ pub struct Engine {
     interpreter: Interpreter,
     prepared: bool,
     rego_v1: bool,
+    max_literal_elements: usize,
+    sort_results: bool,
+    data_merge_overwrite: bool,
+    data_snapshots: BTreeMap<String, Value>,
+    number_output: NumberOutput,
+    error_format: ErrorFormat,
+    input_schema: Option<Value>,
+    apply_input_defaults: bool,
+    #[cfg(feature = "coverage")]
+    coverage_snapshot: BTreeMap<String, BTreeSet<u32>>,
 }
 
 /// Create a default engine.
@@ -38,6 +57,16 @@ impl Engine {
             interpreter: Interpreter::new(),
             prepared: false,
             rego_v1: false,
+            max_literal_elements: usize::MAX,
+            sort_results: false,
+            data_merge_overwrite: false,
+            data_snapshots: BTreeMap::new(),
+            number_output: NumberOutput::Natural,
+            error_format: ErrorFormat::Annotated,
+            input_schema: None,
+            apply_input_defaults: false,
+            #[cfg(feature = "coverage")]
+            coverage_snapshot: BTreeMap::new(),
         }
     }
 
@@ -92,14 +121,177 @@ impl Engine {
     /// # }
     /// ```
     ///
-    pub fn add_policy(&mut self, path: String, rego: String) -> Result<String> {
+    pub fn add_policy(&mut self, path: String, rego: String) -> Result<String, RegorusError> {
+        self.add_policy_with_offset(path, rego, 0, 0)
+    }
+
+    /// Add a policy and return information about it.
+    ///
+    /// Behaves like [`Engine::add_policy`], but in addition to the package, the imports
+    /// and the paths of the rules defined by the policy are returned.
+    /// This is useful for building a manifest of dependencies as policies are loaded.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    ///
+    /// let info = engine.add_policy_with_info(
+    ///    "test.rego".to_string(),
+    ///    r#"
+    ///    package test
+    ///    import rego.v1
+    ///    import data.lib.users
+    ///
+    ///    default allow := false
+    ///    allow if users.is_admin(input.user)
+    ///    admins contains u if some u in users.all
+    ///    "#.to_string())?;
+    ///
+    /// assert_eq!(info.package, "data.test");
+    /// assert_eq!(info.imports, vec!["rego.v1", "data.lib.users"]);
+    /// assert_eq!(info.rule_paths, vec!["data.test.admins", "data.test.allow"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn add_policy_with_info(
+        &mut self,
+        path: String,
+        rego: String,
+    ) -> Result<ModuleInfo, RegorusError> {
         let source = Source::from_contents(path, rego)?;
-        let mut parser = self.make_parser(&source)?;
-        let module = Ref::new(parser.parse()?);
-        self.modules.push(module.clone());
+        let module = self.add_source_module(source)?;
+        Ok(ModuleInfo {
+            package: Interpreter::get_path_string(&module.package.refr, Some("data"))?,
+            imports: module
+                .imports
+                .iter()
+                .map(|i| get_path_string(&i.refr, None))
+                .collect::<Result<Vec<String>>>()?,
+            rule_paths: Self::gather_rule_paths(&[module])?.into_keys().collect(),
+        })
+    }
+
+    /// Add a policy that is embedded within another file.
+    ///
+    /// Behaves like [`Engine::add_policy`], except that line and column numbers in diagnostics
+    /// are reported relative to the enclosing file instead of the policy.
+    ///
+    /// * `path`: The enclosing file.
+    /// * `rego`: The rego policy code.
+    /// * `line_offset`: The number of lines in the enclosing file before the policy.
+    /// * `col_offset`: The number of columns each line of the policy is indented by.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    ///
+    /// // Policy embedded in a markdown file at line 11, indented by 4 columns.
+    /// let r = engine.add_policy_with_offset(
+    ///    "README.md".to_string(),
+    ///    "package test\nx := [1, 2".to_string(),
+    ///    10,
+    ///    4);
+    ///
+    /// assert!(r.unwrap_err().to_string().contains("README.md:12:"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn add_policy_with_offset(
+        &mut self,
+        path: String,
+        rego: String,
+        line_offset: u32,
+        col_offset: u32,
+    ) -> Result<String, RegorusError> {
+        let source = Source::from_contents_with_offset(path, rego, line_offset, col_offset)?;
+        Ok(self.add_source(source)?)
+    }
+
+    /// Add multiple policies concatenated in a single source.
+    ///
+    /// The source is split at each line that begins with a `package` declaration, and each part
+    /// is added as a separate module. Comments before the first package declaration belong to the
+    /// first module. Line numbers in diagnostics are reported relative to the combined source.
+    /// Either all the modules are added or, in case of errors, none are.
+    /// Returns the Rego package names declared in the policies, in order.
+    ///
+    /// * `path`: A filename to be associated with the combined source.
+    /// * `rego`: The concatenated rego policies.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    ///
+    /// let packages = engine.add_policies_from_combined(
+    ///    "bundle.rego".to_string(),
+    ///    r#"package lib
+    /// limit := 10
+    ///
+    /// package test
+    /// allow := input.x < data.lib.limit
+    /// "#.to_string())?;
+    ///
+    /// assert_eq!(packages, vec!["data.lib".to_string(), "data.test".to_string()]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn add_policies_from_combined(
+        &mut self,
+        path: String,
+        rego: String,
+    ) -> Result<Vec<String>, RegorusError> {
+        // Determine the (line, byte offset) at which each module starts.
+        let mut starts = vec![];
+        let mut offset = 0;
+        for (idx, line) in rego.split_inclusive('\n').enumerate() {
+            let is_package = line
+                .strip_prefix("package")
+                .is_some_and(|rest| rest.starts_with(char::is_whitespace));
+            if is_package {
+                starts.push((idx as u32, if starts.is_empty() { 0 } else { offset }));
+            } else if starts.is_empty() {
+                let trimmed = line.trim();
+                if !trimmed.is_empty() && !trimmed.starts_with('#') {
+                    let col = (line.len() - line.trim_start().len() + 1) as u32;
+                    let source = Source::from_contents(path, rego.clone())?;
+                    return Err(source
+                        .error(idx as u32 + 1, col, "expecting package declaration")
+                        .into());
+                }
+            }
+            offset += line.len();
+        }
+        if starts.is_empty() {
+            return Err(anyhow!("{path}: no package declaration found").into());
+        }
+
+        // Only the first module includes the lines preceding its package declaration.
+        let mut modules = vec![];
+        for (i, (line, start)) in starts.iter().enumerate() {
+            let end = starts.get(i + 1).map_or(rego.len(), |(_, end)| *end);
+            let line_offset = if i == 0 { 0 } else { *line };
+            let source = Source::from_contents_with_offset(
+                path.clone(),
+                rego[*start..end].to_string(),
+                line_offset,
+                0,
+            )?;
+            let mut parser = self.make_parser(&source)?;
+            let module = parser.parse().map_err(|e| self.format_error(e))?;
+            modules.push(Ref::new(module));
+        }
+
+        let packages = modules
+            .iter()
+            .map(|m| Interpreter::get_path_string(&m.package.refr, Some("data")))
+            .collect::<Result<Vec<String>>>()?;
+        self.modules.extend(modules);
         // if policies change, interpreter needs to be prepared again
         self.prepared = false;
-        Interpreter::get_path_string(&module.package.refr, Some("data"))
+        Ok(packages)
     }
 
     /// Add a policy from a given file.
@@ -123,14 +315,77 @@ impl Engine {
     /// ```
     #[cfg(feature = "std")]
     #[cfg_attr(docsrs, doc(cfg(feature = "std")))]
-    pub fn add_policy_from_file<P: AsRef<std::path::Path>>(&mut self, path: P) -> Result<String> {
+    pub fn add_policy_from_file<P: AsRef<std::path::Path>>(
+        &mut self,
+        path: P,
+    ) -> Result<String, RegorusError> {
         let source = Source::from_file(path)?;
-        let mut parser = self.make_parser(&source)?;
-        let module = Ref::new(parser.parse()?);
-        self.modules.push(module.clone());
-        // if policies change, interpreter needs to be prepared again
-        self.prepared = false;
-        Interpreter::get_path_string(&module.package.refr, Some("data"))
+        Ok(self.add_source(source)?)
+    }
+
+    /// Remove the policies that were added with the given path.
+    ///
+    /// Returns whether any policy was removed. All the modules added from the path are
+    /// removed, e.g. every package added via [`Engine::add_policies_from_combined`].
+    /// Data and other settings of the engine are retained.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.add_policy("a.rego".to_string(), "package a\nx := 1".to_string())?;
+    /// engine.add_policy("b.rego".to_string(), "package b\ny := 2".to_string())?;
+    ///
+    /// assert!(engine.remove_policy("a.rego")?);
+    /// assert!(!engine.remove_policy("a.rego")?);
+    /// assert_eq!(engine.get_packages()?, vec!["data.b"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn remove_policy(&mut self, path: &str) -> Result<bool, RegorusError> {
+        let count = self.modules.len();
+        self.modules
+            .retain(|m| m.package.refr.span().source.get_path() != path);
+        Ok(self.modules_removed(count) > 0)
+    }
+
+    /// Remove the policies that define the given package.
+    ///
+    /// The package is specified as returned by [`Engine::add_policy`], e.g. `data.test`.
+    /// Returns the number of policies removed.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.add_policy("a1.rego".to_string(), "package a\nx := 1".to_string())?;
+    /// engine.add_policy("a2.rego".to_string(), "package a\ny := 2".to_string())?;
+    /// engine.add_policy("b.rego".to_string(), "package b\nz := 3".to_string())?;
+    ///
+    /// assert_eq!(engine.remove_policies_in_package("data.a")?, 2);
+    /// assert_eq!(engine.get_packages()?, vec!["data.b"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn remove_policies_in_package(&mut self, package: &str) -> Result<usize, RegorusError> {
+        let packages = self.get_packages()?;
+        let count = self.modules.len();
+        self.modules = core::mem::take(&mut self.modules)
+            .into_iter()
+            .zip(packages)
+            .filter(|(_, p)| p != package)
+            .map(|(m, _)| m)
+            .collect();
+        Ok(self.modules_removed(count))
+    }
+
+    fn modules_removed(&mut self, count: usize) -> usize {
+        let removed = count - self.modules.len();
+        if removed > 0 {
+            // if policies change, interpreter needs to be prepared again
+            self.prepared = false;
+        }
+        removed
     }
 
     /// Get the list of packages defined by loaded policies.
@@ -140,7 +395,7 @@ impl Engine {
     /// # fn main() -> anyhow::Result<()> {
     /// let mut engine = Engine::new();
     ///
-    /// let _ = engine.add_policy_from_file("tests/aci/framework.rego")?;
+    /// let _ = engine.add_policy("framework.rego".into(), "package framework".into())?;
     ///
     /// // Package names can be different from file names.
     /// let _ = engine.add_policy("policy.rego".into(), "package hello.world".into())?;
@@ -149,11 +404,12 @@ impl Engine {
     /// # Ok(())
     /// # }
     /// ```
-    pub fn get_packages(&self) -> Result<Vec<String>> {
-        self.modules
+    pub fn get_packages(&self) -> Result<Vec<String>, RegorusError> {
+        Ok(self
+            .modules
             .iter()
             .map(|m| Interpreter::get_path_string(&m.package.refr, Some("data")))
-            .collect()
+            .collect::<Result<_>>()?)
     }
 
     /// Get the list of policy files.
@@ -172,7 +428,7 @@ impl Engine {
     /// # Ok(())
     /// # }
     /// ```
-    pub fn get_policies(&self) -> Result<Vec<Source>> {
+    pub fn get_policies(&self) -> Result<Vec<Source>, RegorusError> {
         Ok(self
             .modules
             .iter()
@@ -197,7 +453,7 @@ impl Engine {
     /// # Ok(())
     /// # }
     /// ```
-    pub fn get_policies_as_json(&self) -> Result<String> {
+    pub fn get_policies_as_json(&self) -> Result<String, RegorusError> {
         #[derive(Serialize)]
         struct Source<'a> {
             path: &'a String,
@@ -213,7 +469,44 @@ impl Engine {
             });
         }
 
-        serde_json::to_string_pretty(&sources).map_err(anyhow::Error::msg)
+        Ok(serde_json::to_string_pretty(&sources).map_err(anyhow::Error::msg)?)
+    }
+
+    /// Export the parsed policies in a compact binary form.
+    ///
+    /// The bytes can be passed to [`Engine::import_parsed`] to restore the policies without
+    /// lexing and parsing them again, e.g. to speed up startup with large policy sets.
+    /// The policy sources are included so that diagnostics continue to work.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.add_policy("test.rego".to_string(), "package test\nx := 1".to_string())?;
+    ///
+    /// let bytes = engine.export_parsed()?;
+    ///
+    /// let mut restored = Engine::new();
+    /// restored.import_parsed(&bytes)?;
+    /// assert_eq!(restored.eval_rule("data.test.x".to_string())?, Value::from(1));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn export_parsed(&self) -> Result<Vec<u8>, RegorusError> {
+        Ok(ast_cache::encode_modules(&self.modules)?)
+    }
+
+    /// Add policies previously exported via [`Engine::export_parsed`].
+    ///
+    /// The policies are added to those already in the engine.
+    /// The bytes must have been produced by the same version of regorus; otherwise an error
+    /// is returned and no policies are added.
+    pub fn import_parsed(&mut self, bytes: &[u8]) -> Result<(), RegorusError> {
+        let modules = ast_cache::decode_modules(bytes)?;
+        self.modules.extend(modules);
+        // if policies change, interpreter needs to be prepared again
+        self.prepared = false;
+        Ok(())
     }
 
     /// Set the input document.
@@ -237,13 +530,209 @@ impl Engine {
     /// ```
     pub fn set_input(&mut self, input: Value) {
         self.interpreter.set_input(input);
+        self.interpreter.start_print_group();
+        self.fill_input_defaults();
     }
 
-    pub fn set_input_json(&mut self, input_json: &str) -> Result<()> {
+    pub fn set_input_json(&mut self, input_json: &str) -> Result<(), RegorusError> {
         self.set_input(Value::from_json_str(input_json)?);
         Ok(())
     }
 
+    /// Set the input document from a reader producing JSON.
+    ///
+    /// Unlike [`Engine::set_input_json`], the JSON is parsed as it is read, avoiding an
+    /// intermediate string for large inputs. See [`Value::from_json_reader`].
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.set_input_from_json_reader(std::fs::File::open("tests/aci/input.json")?)?;
+    ///
+    /// let results = engine.eval_query("input.containerID".to_string(), false)?;
+    /// assert_eq!(results.result[0].expressions[0].value, Value::from("container0"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "std")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+    pub fn set_input_from_json_reader<R: std::io::Read>(
+        &mut self,
+        reader: R,
+    ) -> Result<(), RegorusError> {
+        self.set_input(Value::from_json_reader(reader)?);
+        Ok(())
+    }
+
+    /// Set the input document from YAML.
+    ///
+    /// See [`Value::from_yaml_str`] for the YAML that is accepted.
+    #[cfg(feature = "yaml")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "yaml")))]
+    pub fn set_input_yaml(&mut self, input_yaml: &str) -> Result<(), RegorusError> {
+        self.set_input(Value::from_yaml_str(input_yaml)?);
+        Ok(())
+    }
+
+    /// Update part of the input document in place.
+    ///
+    /// * `pointer`: A JSON pointer (RFC 6901) such as `/user/roles/0`. The empty pointer refers
+    ///   to the whole input.
+    /// * `value`: The new value. Object fields are added or replaced. Array elements are
+    ///   replaced, or appended if the index is the length of the array or `-`.
+    ///
+    /// This avoids rebuilding a large input document when only a few fields change between
+    /// evaluations. Returns an error, leaving the input unmodified, if the parent of the
+    /// pointer does not exist.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.set_input(Value::from_json_str(r#"{ "user": { "name": "alice", "roles": ["dev"] } }"#)?);
+    ///
+    /// engine.patch_input("/user/name", Value::from("bob"))?;
+    /// engine.patch_input("/user/roles/-", Value::from("admin"))?;
+    ///
+    /// let r = engine.eval_query("input.user".to_string(), false)?;
+    /// assert_eq!(
+    ///   r.result[0].expressions[0].value,
+    ///   Value::from_json_str(r#"{ "name": "bob", "roles": ["dev", "admin"] }"#)?
+    /// );
+    ///
+    /// // The parent must exist.
+    /// assert!(engine.patch_input("/group/name", Value::from("ops")).is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn patch_input(&mut self, pointer: &str, value: Value) -> Result<(), RegorusError> {
+        self.interpreter
+            .update_input(|input| input.set_at_json_pointer(pointer, value))?;
+        self.interpreter.start_print_group();
+        self.fill_input_defaults();
+        Ok(())
+    }
+
+    /// Remove part of the input document in place.
+    ///
+    /// * `pointer`: A JSON pointer (RFC 6901) to an object field or array element. The empty
+    ///   pointer removes the whole input.
+    ///
+    /// Returns an error, leaving the input unmodified, if nothing exists at the pointer.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.set_input(Value::from_json_str(r#"{ "user": "alice", "token": "xyz" }"#)?);
+    ///
+    /// engine.remove_input_at("/token")?;
+    /// let r = engine.eval_query("input".to_string(), false)?;
+    /// assert_eq!(r.result[0].expressions[0].value, Value::from_json_str(r#"{ "user": "alice" }"#)?);
+    ///
+    /// assert!(engine.remove_input_at("/token").is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn remove_input_at(&mut self, pointer: &str) -> Result<(), RegorusError> {
+        self.interpreter
+            .update_input(|input| input.remove_at_json_pointer(pointer).map(|_| ()))?;
+        self.interpreter.start_print_group();
+        self.fill_input_defaults();
+        Ok(())
+    }
+
+    /// Register a JSON schema describing the input document.
+    ///
+    /// The schema is currently only used to populate defaults.
+    /// See [`Engine::set_apply_input_defaults`].
+    pub fn set_input_schema(&mut self, schema: Value) {
+        self.input_schema = Some(schema);
+        self.fill_input_defaults();
+    }
+
+    /// Populate missing input fields using the `default` keywords of the input schema.
+    ///
+    /// Defaults are applied to fields listed under `properties` of objects that are present
+    /// in the input, and to the elements of arrays via `items`.
+    /// Existing values are never overwritten.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.set_input_schema(Value::from_json_str(r#"{
+    ///   "type": "object",
+    ///   "properties": {
+    ///     "role": { "type": "string" },
+    ///     "mfa": { "type": "boolean", "default": false }
+    ///   }
+    /// }"#)?);
+    /// engine.set_apply_input_defaults(true);
+    ///
+    /// engine.set_input(Value::from_json_str(r#"{ "role": "admin" }"#)?);
+    /// let results = engine.eval_query("input.mfa".to_string(), false)?;
+    /// assert_eq!(results.result[0].expressions[0].value, Value::from(false));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_apply_input_defaults(&mut self, apply: bool) {
+        self.apply_input_defaults = apply;
+        self.fill_input_defaults();
+    }
+
+    fn fill_input_defaults(&mut self) {
+        if !self.apply_input_defaults {
+            return;
+        }
+        if let Some(schema) = &self.input_schema {
+            let mut input = self.interpreter.get_input().clone();
+            if input != Value::Undefined {
+                Self::fill_schema_defaults(schema, &mut input);
+                self.interpreter.set_input(input);
+            }
+        }
+    }
+
+    fn fill_schema_defaults(schema: &Value, value: &mut Value) {
+        match value {
+            Value::Object(_) => {
+                let Ok(properties) = schema["properties"].as_object() else {
+                    return;
+                };
+                let Ok(fields) = value.as_object_mut() else {
+                    return;
+                };
+                for (key, field_schema) in properties {
+                    match fields.get_mut(key) {
+                        Some(field) => Self::fill_schema_defaults(field_schema, field),
+                        None => {
+                            let default = &field_schema["default"];
+                            if default != &Value::Undefined {
+                                let mut field = default.clone();
+                                Self::fill_schema_defaults(field_schema, &mut field);
+                                fields.insert(key.clone(), field);
+                            }
+                        }
+                    }
+                }
+            }
+            Value::Array(_) => {
+                let items = &schema["items"];
+                if items == &Value::Undefined {
+                    return;
+                }
+                if let Ok(elements) = value.as_array_mut() {
+                    for element in elements.iter_mut() {
+                        Self::fill_schema_defaults(items, element);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
     /// Clear the data document.
     ///
     /// The data document will be reset to an empty object.
@@ -291,19 +780,52 @@ impl Engine {
     /// // Merge { "z" : 3 }. Conflict error.
     /// assert!(engine.add_data(Value::from_json_str(r#"{ "z" : 3 }"#)?).is_err());
     ///
+    /// // Objects are merged recursively.
+    /// assert!(engine.add_data(Value::from_json_str(r#"{ "y" : { "a" : 4 } }"#)?).is_ok());
+    ///
     /// assert_eq!(
     ///   engine.eval_query("data".to_string(), false)?.result[0].expressions[0].value,
-    ///   Value::from_json_str(r#"{ "x": 1, "y": {}, "z": 2}"#)?
+    ///   Value::from_json_str(r#"{ "x": 1, "y": { "a" : 4 }, "z": 2}"#)?
     /// );
     /// # Ok(())
     /// # }
     /// ```
-    pub fn add_data(&mut self, data: Value) -> Result<()> {
+    ///
+    /// See [`Engine::set_data_merge_overwrite`] for overwriting conflicting values instead.
+    pub fn add_data(&mut self, data: Value) -> Result<(), RegorusError> {
         if data.as_object().is_err() {
-            bail!("data must be object");
+            return Err(anyhow!("data must be object").into());
         }
+        let mut merged = self.interpreter.get_init_data().clone();
+        merged.merge_data(data, self.data_merge_overwrite, "data")?;
+        self.interpreter.set_init_data(merged);
         self.prepared = false;
-        self.interpreter.get_init_data_mut().merge(data)
+        Ok(())
+    }
+
+    /// Overwrite conflicting values when adding data documents.
+    ///
+    /// By default, [`Engine::add_data`] raises an error naming the path and both values if
+    /// a document sets a value that conflicts with an existing value. If set, the new value
+    /// replaces the existing one instead.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.add_data(Value::from_json_str(r#"{ "a" : { "b" : 1 } }"#)?)?;
+    ///
+    /// let err = engine.add_data(Value::from_json_str(r#"{ "a" : { "b" : 2 } }"#)?).unwrap_err();
+    /// assert_eq!(err.to_string(), "conflicting values for `data.a.b`: `1` and `2`");
+    ///
+    /// engine.set_data_merge_overwrite(true);
+    /// engine.add_data(Value::from_json_str(r#"{ "a" : { "b" : 2 } }"#)?)?;
+    /// assert_eq!(engine.get_data()["a"]["b"], Value::from(2));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_data_merge_overwrite(&mut self, overwrite: bool) {
+        self.data_merge_overwrite = overwrite;
     }
 
     /// Get the data document.
@@ -339,134 +861,2461 @@ impl Engine {
         self.interpreter.get_init_data().clone()
     }
 
-    pub fn add_data_json(&mut self, data_json: &str) -> Result<()> {
-        self.add_data(Value::from_json_str(data_json)?)
-    }
-
-    /// Set whether builtins should raise errors strictly or not.
+    /// Get the virtual data document.
     ///
-    /// Regorus differs from OPA in that by default builtins will
-    /// raise errors instead of returning Undefined.
+    /// Unlike [`Engine::get_data`], which returns only the data added via [`Engine::add_data`],
+    /// the virtual document is the base data merged with the values of all the rules defined by
+    /// loaded policies. Each package is present, even if it does not define any rules.
+    /// This is equivalent to evaluating the query `data`, and hence evaluates all the rules.
     ///
-    /// ----
-    /// **_NOTE:_** Currently not all builtins honor this flag and will always strictly raise errors.
-    /// ----
-    pub fn set_strict_builtin_errors(&mut self, b: bool) {
-        self.interpreter.set_strict_builtin_errors(b)
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    ///
+    /// engine.add_data(Value::from_json_str(r#"{ "tenants": { "t1": {}, "t2": {} } }"#)?)?;
+    /// engine.add_policy(
+    ///    "test.rego".to_string(),
+    ///    r#"
+    ///    package test
+    ///    count_tenants := count(data.tenants)
+    ///    "#.to_string())?;
+    ///
+    /// let data = engine.get_virtual_data()?;
+    /// assert_eq!(data["tenants"], engine.get_data()["tenants"]);
+    /// assert_eq!(data["test"]["count_tenants"], Value::from(2));
+    ///
+    /// // The base data does not contain rule values.
+    /// assert_eq!(engine.get_data()["test"], Value::Undefined);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn get_virtual_data(&mut self) -> Result<Value, RegorusError> {
+        let results = self.eval_query("data".to_string(), false)?;
+        match results.result.first() {
+            Some(r) if r.expressions.len() == 1 => Ok(r.expressions[0].value.clone()),
+            _ => Err(anyhow!("could not evaluate virtual data document").into()),
+        }
     }
 
-    #[doc(hidden)]
-    pub fn get_modules(&mut self) -> &Vec<Ref<Module>> {
-        &self.modules
+    pub fn add_data_json(&mut self, data_json: &str) -> Result<(), RegorusError> {
+        self.add_data(Value::from_json_str(data_json)?)
     }
 
-    /// Evaluate specified rule(s).
+    /// Add data document from a reader producing JSON.
     ///
-    /// [`Engine::eval_rule`] is often faster than [`Engine::eval_query`] and should be preferred if
-    /// OPA style [`QueryResults`] are not needed.
+    /// Unlike [`Engine::add_data_json`], the JSON is parsed as it is read, avoiding an
+    /// intermediate string for large data bundles. See [`Engine::add_data`] for how the data
+    /// is merged.
     ///
     /// ```
     /// # use regorus::*;
     /// # fn main() -> anyhow::Result<()> {
     /// let mut engine = Engine::new();
-    ///
+    /// engine.add_data_from_json_reader(std::fs::File::open("tests/aci/data.json")?)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "std")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+    pub fn add_data_from_json_reader<R: std::io::Read>(
+        &mut self,
+        reader: R,
+    ) -> Result<(), RegorusError> {
+        self.add_data(Value::from_json_reader(reader)?)
+    }
+
+    /// Add data document from YAML.
+    ///
+    /// See [`Engine::add_data`] for how the data is merged and [`Value::from_yaml_str`] for
+    /// the YAML that is accepted.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.add_data_yaml("limits:\n  cpu: 2\n  memory: 4Gi")?;
+    ///
+    /// let results = engine.eval_query("data.limits.cpu".to_string(), false)?;
+    /// assert_eq!(results.result[0].expressions[0].value, Value::from(2));
+    ///
+    /// // Duplicate keys are errors.
+    /// assert!(engine.add_data_yaml("a: 1\na: 2").is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "yaml")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "yaml")))]
+    pub fn add_data_yaml(&mut self, data_yaml: &str) -> Result<(), RegorusError> {
+        self.add_data(Value::from_yaml_str(data_yaml)?)
+    }
+
+    /// Load data from a directory of JSON and YAML files.
+    ///
+    /// Every `*.json` file and, with the `yaml` feature, every `*.yaml`/`*.yml` file under `dir`
+    /// is added to data at the path given by its directory relative to `dir`. Files named
+    /// `data.json` (or `data.yaml`) contribute at the directory path itself, while other files
+    /// are nested under their stem. Hence `dir/users/data.json` is loaded at `data.users` and
+    /// `dir/users/roles.json` at `data.users.roles`. Files are merged as per
+    /// [`Engine::add_data`]. In case of error, no data is added.
+    ///
+    /// ```no_run
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.add_data_from_dir("data")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "std")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+    pub fn add_data_from_dir<P: AsRef<std::path::Path>>(
+        &mut self,
+        dir: P,
+    ) -> Result<(), RegorusError> {
+        let mut files = vec![];
+        Self::find_data_files(dir.as_ref(), &mut vec![], &mut files)?;
+
+        let mut data = Value::new_object();
+        for (comps, file) in files {
+            let is_yaml = matches!(
+                file.extension().and_then(|e| e.to_str()),
+                Some("yaml" | "yml")
+            );
+            let mut value = if is_yaml {
+                #[cfg(feature = "yaml")]
+                {
+                    let contents = std::fs::read_to_string(&file)
+                        .map_err(|e| anyhow!("Failed to read {}. {e}", file.display()))?;
+                    Value::from_yaml_str(&contents)
+                        .map_err(|e| anyhow!("Failed to load {}. {e}", file.display()))?
+                }
+                #[cfg(not(feature = "yaml"))]
+                bail!(
+                    "Failed to load {}. yaml feature is not enabled",
+                    file.display()
+                );
+            } else {
+                Value::from_json_file(&file)?
+            };
+
+            for c in comps.iter().rev() {
+                let mut obj = Value::new_object();
+                obj.as_object_mut()?.insert(Value::from(c.as_str()), value);
+                value = obj;
+            }
+            if value.as_object().is_err() {
+                return Err(anyhow!("{} must contain an object", file.display()).into());
+            }
+            data.merge_data(value, self.data_merge_overwrite, "data")?;
+        }
+
+        self.add_data(data)
+    }
+
+    // Gather data files under dir along with the data path components of each file.
+    #[cfg(feature = "std")]
+    fn find_data_files(
+        dir: &std::path::Path,
+        comps: &mut Vec<String>,
+        files: &mut Vec<(Vec<String>, std::path::PathBuf)>,
+    ) -> Result<()> {
+        let entries =
+            std::fs::read_dir(dir).map_err(|e| anyhow!("Failed to read {}. {e}", dir.display()))?;
+        let mut paths = entries
+            .map(|e| e.map(|e| e.path()))
+            .collect::<std::io::Result<Vec<_>>>()
+            .map_err(|e| anyhow!("Failed to read {}. {e}", dir.display()))?;
+        // Load files in a deterministic order.
+        paths.sort();
+
+        for path in paths {
+            let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            if path.is_dir() {
+                comps.push(name.to_string());
+                Self::find_data_files(&path, comps, files)?;
+                comps.pop();
+                continue;
+            }
+
+            let (Some(stem), Some("json" | "yaml" | "yml")) = (
+                path.file_stem().and_then(|s| s.to_str()),
+                path.extension().and_then(|e| e.to_str()),
+            ) else {
+                continue;
+            };
+            let mut file_comps = comps.clone();
+            if stem != "data" {
+                file_comps.push(stem.to_string());
+            }
+            files.push((file_comps, path));
+        }
+        Ok(())
+    }
+
+    /// Import the policies and data of another engine.
+    ///
+    /// Policies are shared with `other` and are not parsed again. Policies that are already
+    /// shared between the two engines are skipped; hence merging the same engine more than once
+    /// is a no-op. Packages and rules may span both engines; as with multiple policies added to
+    /// the same engine, conflicting rule definitions are reported during evaluation. It is an
+    /// error if data of either engine overlaps a rule of the other or if the data of the engines
+    /// conflict. In case of error, this engine is left unmodified.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.add_policy("a.rego".to_string(), "package a\nx = data.y\nz contains 1".to_string())?;
+    ///
+    /// let mut plugin = Engine::new();
+    /// plugin.add_policy("b.rego".to_string(), "package a\nz contains 2".to_string())?;
+    /// plugin.add_data(Value::from_json_str(r#"{ "y" : 1 }"#)?)?;
+    ///
+    /// engine.merge_from(&plugin)?;
+    /// assert_eq!(engine.eval_rule("data.a.x".to_string())?, Value::from(1));
+    ///
+    /// // Partial set `data.a.z` is defined by both engines.
+    /// let z = engine.eval_rule("data.a.z".to_string())?;
+    /// assert_eq!(z.as_set()?.len(), 2);
+    ///
+    /// // Merging the same engine again is a no-op.
+    /// engine.merge_from(&plugin)?;
+    ///
+    /// // Data `data.y` cannot be redefined.
+    /// let mut other = Engine::new();
+    /// other.add_data(Value::from_json_str(r#"{ "y" : 2 }"#)?)?;
+    /// assert!(engine.merge_from(&other).is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn merge_from(&mut self, other: &Engine) -> Result<(), RegorusError> {
+        let modules: Vec<Ref<Module>> = other
+            .modules
+            .iter()
+            .filter(|m| !self.modules.contains(m))
+            .cloned()
+            .collect();
+
+        let rule_paths = Self::gather_rule_paths(&self.modules)?;
+        let other_rule_paths = Self::gather_rule_paths(&modules)?;
+        let data = self.interpreter.get_init_data();
+        let other_data = other.interpreter.get_init_data();
+        for (path, span) in &other_rule_paths {
+            if Self::data_overlaps_path(data, path) {
+                return Err(span
+                    .error(&format!("rule `{path}` overlaps existing data"))
+                    .into());
+            }
+        }
+        for path in rule_paths.keys() {
+            if Self::data_overlaps_path(other_data, path) {
+                return Err(anyhow!("could not merge data: data overlaps rule `{path}`").into());
+            }
+        }
+
+        let mut data = data.clone();
+        data.merge_data(other_data.clone(), self.data_merge_overwrite, "data")
+            .map_err(|e| anyhow!("could not merge data: {e}"))?;
+
+        self.modules.extend(modules);
+        self.interpreter.set_init_data(data);
+        self.prepared = false;
+        Ok(())
+    }
+
+    // Gather the paths of the rules defined in given modules along with the span of the first
+    // definition of each rule.
+    fn gather_rule_paths(modules: &[Ref<Module>]) -> Result<BTreeMap<String, Span>> {
+        let mut paths = BTreeMap::new();
+        for m in modules {
+            let package = Interpreter::get_path_string(&m.package.refr, Some("data"))?;
+            for rule in &m.policy {
+                let refr = match rule.as_ref() {
+                    Rule::Spec { head, .. } => match head {
+                        RuleHead::Compr { refr, .. }
+                        | RuleHead::Set { refr, .. }
+                        | RuleHead::Func { refr, .. } => refr,
+                    },
+                    Rule::Default { refr, .. } => refr,
+                };
+                let path = get_path_string(refr, Some(&package))?;
+                paths.entry(path).or_insert_with(|| refr.span().clone());
+            }
+        }
+        Ok(paths)
+    }
+
+    // Check whether data has a value at given rule path or a non-object value at a prefix of it.
+    fn data_overlaps_path(data: &Value, path: &str) -> bool {
+        let mut value = data;
+        for part in path.split('.').skip(1) {
+            match value {
+                Value::Object(fields) => match fields.get(&Value::from(part)) {
+                    Some(v) => value = v,
+                    None => return false,
+                },
+                _ => return true,
+            }
+        }
+        true
+    }
+
+    /// Set whether builtins should raise errors strictly or not.
+    ///
+    /// Regorus differs from OPA in that by default builtins will
+    /// raise errors instead of returning Undefined.
+    ///
+    /// ----
+    /// **_NOTE:_** Currently not all builtins honor this flag and will always strictly raise errors.
+    /// ----
+    pub fn set_strict_builtin_errors(&mut self, b: bool) {
+        self.interpreter.set_strict_builtin_errors(b)
+    }
+
+    /// Set whether division should be carried out using exact rational arithmetic.
+    ///
+    /// Addition, subtraction and multiplication of numbers are always exact. Division however
+    /// truncates quotients that have no finite decimal form, e.g. `1 / 3`. When exact arithmetic
+    /// is enabled, these quotients are tracked as fractions so that further arithmetic on them
+    /// stays exact, and they are rendered as floats. Numbers that could not be computed exactly,
+    /// e.g. due to the fraction overflowing `i128`, are marked inexact.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    ///
+    /// let r = engine.eval_query("1 / 3 * 3 == 1".to_string(), false)?;
+    /// assert_eq!(r.result[0].expressions[0].value, Value::from(false));
+    ///
+    /// engine.set_exact_arithmetic(true);
+    /// let r = engine.eval_query("1 / 3 * 3 == 1".to_string(), false)?;
+    /// assert_eq!(r.result[0].expressions[0].value, Value::from(true));
+    ///
+    /// // Numbers expose their exact value as a fraction.
+    /// let r = engine.eval_query("x := 10 / 4".to_string(), false)?;
+    /// if let Value::Number(n) = &r.result[0].bindings["x"] {
+    ///   assert_eq!(n.to_rational(), Some((5, 2)));
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_exact_arithmetic(&mut self, b: bool) {
+        self.interpreter.set_exact_arithmetic(b)
+    }
+
+    /// Include results of query evaluations that failed.
+    ///
+    /// By default, [`Engine::eval_query`] drops results whose expressions are false or undefined.
+    /// If set, a result is produced for each such evaluation (including each failing loop iteration),
+    /// along with the bindings at that point. The expression that failed has its value (`false`
+    /// or [`Value::Undefined`]) and expressions that were not evaluated are [`Value::Undefined`].
+    /// This is useful for determining why a query did not produce results.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.set_input(Value::from_json_str(r#"{ "x": [1, 2, 3] }"#)?);
+    ///
+    /// let query = "some i; v := input.x[i]; v > 1; input.y".to_string();
+    /// let results = engine.eval_query(query.clone(), false)?;
+    /// assert!(results.result.is_empty());
+    ///
+    /// engine.set_include_undefined_results(true);
+    /// let results = engine.eval_query(query, false)?;
+    /// assert_eq!(results.result.len(), 3);
+    ///
+    /// // The first iteration fails at `v > 1`.
+    /// let first = &results.result[0];
+    /// assert_eq!(first.bindings["v"], Value::from(1));
+    /// assert_eq!(first.expressions[2].value, Value::from(false));
+    /// assert_eq!(first.expressions[3].text.as_ref(), "input.y");
+    /// assert_eq!(first.expressions[3].value, Value::Undefined);
+    ///
+    /// // The remaining iterations fail at `input.y`.
+    /// assert_eq!(results.result[1].expressions[2].value, Value::from(true));
+    /// assert_eq!(results.result[1].expressions[3].value, Value::Undefined);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_include_undefined_results(&mut self, b: bool) {
+        self.interpreter.set_include_undefined_results(b)
+    }
+
+    /// Set the collation used by comparison operators (`==`, `!=`, `<`, `<=`, `>`, `>=`)
+    /// when both operands are strings.
+    ///
+    /// The default is [`Collation::Exact`]. Other collations deviate from OPA.
+    /// Only comparisons between two strings are affected; strings nested within collections,
+    /// object keys, set membership and builtins such as `startswith` always use exact comparison.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// let results = engine.eval_query(r#"x := "Admin" == "admin""#.to_string(), false)?;
+    /// assert_eq!(results.result[0].bindings["x"], Value::from(false));
+    ///
+    /// engine.set_string_collation(Collation::CaseInsensitiveAscii);
+    /// let results = engine.eval_query(r#"x := "Admin" == "admin""#.to_string(), false)?;
+    /// assert_eq!(results.result[0].bindings["x"], Value::from(true));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_string_collation(&mut self, collation: Collation) {
+        self.interpreter.set_string_collation(collation)
+    }
+
+    /// Limit the number of results a query may produce.
+    ///
+    /// Query evaluation fails as soon as the limit would be exceeded. This protects against
+    /// queries that accidentally produce an explosive number of results, e.g. via nested loops.
+    /// By default there is no limit.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.set_max_results(100);
+    ///
+    /// let query = "x := numbers.range(1, 10)[_]; y := numbers.range(1, 10)[_]";
+    /// assert_eq!(engine.eval_query(query.to_string(), false)?.result.len(), 100);
+    ///
+    /// let query = "x := numbers.range(1, 11)[_]; y := numbers.range(1, 10)[_]";
+    /// assert!(engine.eval_query(query.to_string(), false).is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_max_results(&mut self, n: usize) {
+        self.interpreter.set_max_results(n)
+    }
+
+    /// Set the environment variables exposed to policies via `opa.runtime().env`.
+    ///
+    /// Evaluation never reads the process environment; only the variables injected here are
+    /// visible, which keeps evaluation deterministic. Unset variables are undefined.
+    /// By default `opa.runtime()` does not include `env`.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # use std::collections::BTreeMap;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.set_env(BTreeMap::from([("REGION".to_string(), "westus".to_string())]));
+    ///
+    /// let results = engine.eval_query("x := opa.runtime().env.REGION".to_string(), false)?;
+    /// assert_eq!(results.result[0].bindings["x"], Value::from("westus"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "opa-runtime")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "opa-runtime")))]
+    pub fn set_env(&mut self, env: BTreeMap<String, String>) {
+        let env = env
+            .into_iter()
+            .map(|(k, v)| (Value::from(k), Value::from(v)))
+            .collect::<BTreeMap<Value, Value>>();
+        self.interpreter.set_runtime_env(Some(Value::from(env)));
+    }
+
+    /// Fix the current time seen by policies.
+    ///
+    /// When set, `time.now_ns()` returns `fixed_ns`, the number of nanoseconds since the Unix
+    /// epoch, instead of reading the system clock. Builtins such as `time.clock`, `time.date`
+    /// and `time.weekday` that are applied to `time.now_ns()` therefore also become
+    /// deterministic, which is useful for golden-file tests. Pass `None` to use the system
+    /// clock again.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    ///
+    /// // 2024-02-29T13:45:30Z
+    /// engine.set_clock(Some(1_709_214_330_000_000_000));
+    ///
+    /// let results = engine.eval_query("x := time.weekday(time.now_ns())".to_string(), false)?;
+    /// assert_eq!(results.result[0].bindings["x"], Value::from("Thursday"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "time")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "time")))]
+    pub fn set_clock(&mut self, fixed_ns: Option<i64>) {
+        self.interpreter.set_clock(fixed_ns);
+    }
+
+    /// Limit the number of elements a single comprehension may produce.
+    ///
+    /// Evaluation fails as soon as an array, set or object comprehension would exceed the
+    /// limit. Unlike [`Engine::set_max_results`], this applies to each comprehension
+    /// individually, including those inside rules. By default there is no limit.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.set_max_comprehension_size(100);
+    ///
+    /// let query = "count({x | x := numbers.range(1, 100)[_]})";
+    /// assert_eq!(engine.eval_query(query.to_string(), false)?.result.len(), 1);
+    ///
+    /// let query = "count({x | x := numbers.range(1, 101)[_]})";
+    /// assert!(engine.eval_query(query.to_string(), false).is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_max_comprehension_size(&mut self, n: usize) {
+        self.interpreter.set_max_comprehension_size(n)
+    }
+
+    /// Limit how deeply the evaluation of rules and expressions may nest.
+    ///
+    /// Each rule evaluation and each nested expression counts towards the depth. Evaluation
+    /// fails with an `eval_recursion_limit` [`RegorusError`] instead of overflowing the native
+    /// stack when the limit is exceeded. The default is 1000.
+    ///
+    /// With the opt-in `stacker` feature, the native stack is extended on the heap when it runs
+    /// low, so evaluation up to the limit is safe regardless of the thread's stack size or build
+    /// profile. Otherwise the default fits within 2 MiB of stack in optimized builds only; lower
+    /// the limit when evaluating with smaller stacks.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.set_max_eval_depth(5);
+    ///
+    /// assert!(engine.eval_query("x := [[1]]".to_string(), false).is_ok());
+    ///
+    /// let err = engine
+    ///     .eval_query("x := [[[[[[1]]]]]]".to_string(), false)
+    ///     .unwrap_err();
+    /// let err = RegorusError::from(err);
+    /// assert_eq!(err.code(), "eval_recursion_limit");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_max_eval_depth(&mut self, depth: usize) {
+        self.interpreter.set_max_eval_depth(depth)
+    }
+
+    /// Limit the number of elements of a single array, set or object literal.
+    ///
+    /// Subsequently added policies and queries fail to parse if any literal in them has more
+    /// than `n` elements, and the error points to the oversized literal. This guards against
+    /// policies with enormous literals. Comprehensions are not affected; see
+    /// [`Engine::set_max_comprehension_size`]. By default there is no limit.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.set_max_literal_elements(3);
+    ///
+    /// engine.add_policy("a.rego".to_string(), "package a\nx := [1, 2, 3]".to_string())?;
+    /// assert!(engine
+    ///     .add_policy("b.rego".to_string(), "package b\nx := {1, 2, 3, 4}".to_string())
+    ///     .is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_max_literal_elements(&mut self, n: usize) {
+        self.max_literal_elements = n;
+    }
+
+    /// Set how numbers are rendered by [`Engine::to_json_str`].
+    ///
+    /// Evaluation is not affected; only serialization of values and results is.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// let results = engine.eval_query("x := [10 / 2, 10 / 4]".to_string(), false)?;
+    /// let x = &results.result[0].bindings["x"];
+    /// assert_eq!(engine.to_json_str(x)?, "[\n  5,\n  2.5\n]");
+    ///
+    /// engine.set_number_output(NumberOutput::AlwaysFloat);
+    /// assert_eq!(engine.to_json_str(x)?, "[\n  5.0,\n  2.5\n]");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_number_output(&mut self, number_output: NumberOutput) {
+        self.number_output = number_output;
+    }
+
+    /// Set how errors are rendered.
+    ///
+    /// By default errors are annotated with the offending source line and a caret
+    /// spanning multiple lines. [`ErrorFormat::Terse`] renders each location on a
+    /// single line as `file:line:col: message`, which is convenient for logging.
+    ///
+    /// Applies to errors from adding policies and from [`Engine::eval_rule`],
+    /// [`Engine::eval_function`], [`Engine::eval_query`], [`Engine::eval_modules`]
+    /// and the methods built on them.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.set_error_format(ErrorFormat::Terse);
+    ///
+    /// let err = engine.add_policy("test.rego".to_string(), "package test\nx := ".to_string());
+    /// assert_eq!(err.unwrap_err().to_string(), "test.rego:2:6: expecting expression");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_error_format(&mut self, error_format: ErrorFormat) {
+        self.error_format = error_format;
+    }
+
+    fn format_error(&self, e: anyhow::Error) -> RegorusError {
+        let e = RegorusError::from(e);
+        match self.error_format {
+            ErrorFormat::Annotated => e,
+            ErrorFormat::Terse => {
+                let Some(location) = e.location() else {
+                    return e;
+                };
+                let reason = e
+                    .reason()
+                    .lines()
+                    .map(str::trim)
+                    .filter(|l| !l.is_empty())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                let message = format!("{location}: {reason}");
+                e.with_message(message)
+            }
+        }
+    }
+
+    /// Serialize a value or query results to pretty printed JSON.
+    ///
+    /// Numbers are rendered as per [`Engine::set_number_output`].
+    pub fn to_json_str<T: Serialize>(&self, value: &T) -> Result<String, RegorusError> {
+        let mut json = serde_json::to_value(value).map_err(anyhow::Error::msg)?;
+        if self.number_output == NumberOutput::IntegerWhenWhole {
+            let mut s = String::new();
+            Self::write_integers_when_whole(&json, 0, &mut s)?;
+            return Ok(s);
+        }
+        Self::apply_number_output(&mut json, self.number_output);
+        Ok(serde_json::to_string_pretty(&json).map_err(anyhow::Error::msg)?)
+    }
+
+    // Pretty print like serde_json, but render whole floats as integers.
+    // serde_json cannot represent integers that do not fit in 64 bits and hence they are
+    // written directly.
+    fn write_integers_when_whole(
+        json: &serde_json::Value,
+        indent: usize,
+        s: &mut String,
+    ) -> anyhow::Result<()> {
+        let newline = |s: &mut String, indent: usize| {
+            s.push('\n');
+            (0..indent).for_each(|_| s.push_str("  "));
+        };
+        match json {
+            serde_json::Value::Number(n) => match n.as_f64() {
+                Some(f) if n.is_f64() && f.is_finite() && f.fract() == 0.0 => {
+                    s.push_str(&format!("{f:.0}"))
+                }
+                _ => s.push_str(&n.to_string()),
+            },
+            serde_json::Value::Array(a) if !a.is_empty() => {
+                s.push('[');
+                for (idx, v) in a.iter().enumerate() {
+                    if idx > 0 {
+                        s.push(',');
+                    }
+                    newline(s, indent + 1);
+                    Self::write_integers_when_whole(v, indent + 1, s)?;
+                }
+                newline(s, indent);
+                s.push(']');
+            }
+            serde_json::Value::Object(o) if !o.is_empty() => {
+                s.push('{');
+                for (idx, (k, v)) in o.iter().enumerate() {
+                    if idx > 0 {
+                        s.push(',');
+                    }
+                    newline(s, indent + 1);
+                    s.push_str(&serde_json::to_string(k).map_err(anyhow::Error::msg)?);
+                    s.push_str(": ");
+                    Self::write_integers_when_whole(v, indent + 1, s)?;
+                }
+                newline(s, indent);
+                s.push('}');
+            }
+            _ => s.push_str(&serde_json::to_string(json).map_err(anyhow::Error::msg)?),
+        }
+        Ok(())
+    }
+
+    fn apply_number_output(json: &mut serde_json::Value, number_output: NumberOutput) {
+        match json {
+            serde_json::Value::Number(n) => match number_output {
+                NumberOutput::Natural | NumberOutput::IntegerWhenWhole => (),
+                NumberOutput::AlwaysFloat => {
+                    if !n.is_f64() {
+                        if let Some(f) = n.as_f64().and_then(serde_json::Number::from_f64) {
+                            *n = f;
+                        }
+                    }
+                }
+            },
+            serde_json::Value::Array(a) => a
+                .iter_mut()
+                .for_each(|v| Self::apply_number_output(v, number_output)),
+            serde_json::Value::Object(o) => o
+                .values_mut()
+                .for_each(|v| Self::apply_number_output(v, number_output)),
+            _ => (),
+        }
+    }
+
+    /// Restrict the available builtins to those listed in an OPA `capabilities.json`.
+    ///
+    /// Only the `builtins` field is used; each entry must have a `name`.
+    /// Policies that call builtins outside the set are rejected before evaluation,
+    /// and queries that call them fail.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.set_capabilities_from_json(r#"{ "builtins": [ { "name": "count" } ] }"#)?;
+    ///
+    /// engine.add_policy(
+    ///    "test.rego".to_string(),
+    ///    r#"
+    ///    package test
+    ///    x := count([1, 2])
+    ///    y := upper("a")
+    ///    "#.to_string())?;
+    ///
+    /// let err = engine.eval_rule("data.test.x".to_string()).unwrap_err();
+    /// assert!(err.to_string().contains("upper is not allowed by capabilities"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_capabilities_from_json(&mut self, json: &str) -> Result<(), RegorusError> {
+        let capabilities = Value::from_json_str(json)?;
+        let mut allowed = BTreeSet::new();
+        let builtins = capabilities["builtins"]
+            .as_array()
+            .map_err(|_| anyhow!("capabilities must contain a builtins array"))?;
+        for builtin in builtins {
+            let name = builtin["name"]
+                .as_string()
+                .map_err(|_| anyhow!("capabilities builtin must have a name"))?;
+            allowed.insert(name.to_string());
+        }
+        self.interpreter.set_allowed_builtins(Some(allowed));
+        self.prepared = false;
+        Ok(())
+    }
+
+    #[doc(hidden)]
+    pub fn get_modules(&mut self) -> &Vec<Ref<Module>> {
+        &self.modules
+    }
+
+    /// Evaluate specified rule(s).
+    ///
+    /// [`Engine::eval_rule`] is often faster than [`Engine::eval_query`] and should be preferred if
+    /// OPA style [`QueryResults`] are not needed.
+    ///
+    /// Rules are evaluated on demand: only the specified rule and the rules it transitively
+    /// depends on are evaluated. Rules that are not reachable from it are skipped, and hence
+    /// errors in them are not reported.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    ///
     /// // Add policy
     /// engine.add_policy(
     ///   "policy.rego".to_string(),
     ///   r#"
-    ///   package example
+    ///   package example
+    ///   import rego.v1
+    ///
+    ///   x = [1, 2]
+    ///
+    ///   y := 5 if input.a > 2
+    ///   "#.to_string())?;
+    ///
+    /// // Evaluate rule.
+    /// let v = engine.eval_rule("data.example.x".to_string())?;
+    /// assert_eq!(v, Value::from(vec![Value::from(1), Value::from(2)]));
+    ///
+    /// // y evaluates to undefined.
+    /// let v = engine.eval_rule("data.example.y".to_string())?;
+    /// assert_eq!(v, Value::Undefined);
+    ///
+    /// // Evaluating a non-existent rule is an error.
+    /// let r = engine.eval_rule("data.exaample.x".to_string());
+    /// assert!(r.is_err());
+    ///
+    /// // Path must be valid rule paths.
+    /// assert!( engine.eval_rule("data".to_string()).is_err());
+    /// assert!( engine.eval_rule("data.example".to_string()).is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn eval_rule(&mut self, rule: String) -> Result<Value, RegorusError> {
+        let result = self.prepare_for_eval(false).and_then(|_| {
+            self.interpreter.clean_internal_evaluation_state();
+            self.interpreter.eval_rule_in_path(rule)
+        });
+        result.map_err(|e| self.format_error(e))
+    }
+
+    /// Evaluate a rule for the given input and return a decision log record.
+    ///
+    /// The input is used only for this decision; the input in effect before the call is restored afterwards.
+    /// Input defaults are applied as per [`Engine::set_apply_input_defaults`] and the logged input
+    /// includes them.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    ///
+    /// engine.add_policy(
+    ///   "policy.rego".to_string(),
+    ///   r#"
+    ///   package example
+    ///   import rego.v1
+    ///
+    ///   default allow := false
+    ///   allow if input.role == "admin"
+    ///   "#.to_string())?;
+    ///
+    /// let input = Value::from_json_str(r#"{"role": "admin"}"#)?;
+    /// let log = engine.decide("data.example.allow".to_string(), input.clone())?;
+    /// assert_eq!(log.path, "data.example.allow");
+    /// assert_eq!(log.input, input);
+    /// assert_eq!(log.result, Value::from(true));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn decide(&mut self, path: String, input: Value) -> Result<DecisionLog, RegorusError> {
+        #[cfg(feature = "std")]
+        let timestamp_ns = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .ok()
+            .and_then(|d| i64::try_from(d.as_nanos()).ok());
+        #[cfg(not(feature = "std"))]
+        let timestamp_ns = None;
+
+        self.prepare_for_eval(false)
+            .map_err(|e| self.format_error(e))?;
+        let prev_input = self.interpreter.get_input().clone();
+        self.set_input(input);
+        let input = self.interpreter.get_input().clone();
+
+        #[cfg(feature = "std")]
+        let start = std::time::Instant::now();
+        self.interpreter.clean_internal_evaluation_state();
+        let result = self.interpreter.eval_rule_in_path(path.clone());
+        #[cfg(feature = "std")]
+        let eval_ns = u64::try_from(start.elapsed().as_nanos()).ok();
+        #[cfg(not(feature = "std"))]
+        let eval_ns = None;
+
+        self.interpreter.set_input(prev_input);
+        Ok(DecisionLog {
+            path,
+            input,
+            result: result.map_err(|e| self.format_error(e))?,
+            timestamp_ns,
+            metrics: Metrics {
+                timer_rego_query_eval_ns: eval_ns,
+            },
+        })
+    }
+
+    /// Evaluate an allow rule and, if it does not allow, gather the reasons from a deny rule.
+    ///
+    /// The decision is allowed only if `allow_path` evaluates to `true`; an undefined allow
+    /// rule is treated as a denial. When denied, the elements of the set (or array) produced
+    /// by `deny_path` become the reasons. Any other defined deny value is used as the single
+    /// reason and an undefined deny rule yields no reasons.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.add_policy(
+    ///   "policy.rego".to_string(),
+    ///   r#"
+    ///   package example
+    ///   import rego.v1
+    ///
+    ///   allow if count(deny) == 0
+    ///
+    ///   deny contains sprintf("user %s is not an admin", [input.user]) if not input.admin
+    ///   deny contains "request is too large" if input.size > 100
+    ///   "#.to_string())?;
+    ///
+    /// engine.set_input(Value::from_json_str(r#"{"user": "bob", "admin": false, "size": 200}"#)?);
+    /// let decision = engine.evaluate_decision("data.example.allow", "data.example.deny")?;
+    /// assert!(!decision.allowed);
+    /// assert_eq!(decision.reasons, vec![
+    ///   Value::from("request is too large"),
+    ///   Value::from("user bob is not an admin"),
+    /// ]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn evaluate_decision(
+        &mut self,
+        allow_path: &str,
+        deny_path: &str,
+    ) -> Result<Decision, RegorusError> {
+        let allowed = self.eval_rule(allow_path.to_string())? == Value::from(true);
+        if allowed {
+            return Ok(Decision {
+                allowed,
+                reasons: vec![],
+            });
+        }
+
+        let reasons = match self.eval_rule(deny_path.to_string())? {
+            Value::Undefined => vec![],
+            v @ (Value::Set(_) | Value::Array(_)) => v.elements().cloned().collect(),
+            v => vec![v],
+        };
+        Ok(Decision { allowed, reasons })
+    }
+
+    /// Evaluate a rule and pass its value to a callback piecewise.
+    ///
+    /// For partial set rules (`p contains x`), the callback is invoked once per element.
+    /// For partial object rules (`p[k] := v`), it is invoked once per entry with a `[key, value]` array.
+    /// For other rules, it is invoked once with the rule's value, unless the rule is undefined.
+    /// Evaluation stops at the first error returned by the callback.
+    ///
+    /// Elements and entries are passed as they are produced, in evaluation order, so that they
+    /// can be processed before the evaluation of the rule completes. Each element or entry is
+    /// passed once even if it is produced more than once. Conflicting values for an object key
+    /// are reported as errors, as by [`Engine::eval_rule`]; entries passed before the conflict
+    /// is detected are not withdrawn.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    ///
+    /// engine.add_policy(
+    ///   "policy.rego".to_string(),
+    ///   r#"
+    ///   package example
+    ///   import rego.v1
+    ///
+    ///   evens contains x if { some x in numbers.range(1, 10); x % 2 == 0 }
+    ///   squares[x] := x * x if some x in [1, 2, 3]
+    ///   "#.to_string())?;
+    ///
+    /// let mut evens = vec![];
+    /// engine.eval_rule_stream("data.example.evens".to_string(), &mut |v| {
+    ///   evens.push(v);
+    ///   Ok(())
+    /// })?;
+    /// assert_eq!(evens.len(), 5);
+    ///
+    /// let mut squares = 0;
+    /// engine.eval_rule_stream("data.example.squares".to_string(), &mut |_| {
+    ///   squares += 1;
+    ///   Ok(())
+    /// })?;
+    /// assert_eq!(squares, 3);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn eval_rule_stream(
+        &mut self,
+        path: String,
+        out: &mut dyn FnMut(Value) -> Result<()>,
+    ) -> Result<(), RegorusError> {
+        self.prepare_for_eval(false)
+            .map_err(|e| self.format_error(e))?;
+        self.interpreter.clean_internal_evaluation_state();
+        let is_partial = self
+            .interpreter
+            .is_partial_rule_path(&path)
+            .map_err(|e| self.format_error(e))?;
+
+        if !is_partial {
+            let value = self.eval_rule(path)?;
+            self.interpreter.clean_internal_evaluation_state();
+            if value != Value::Undefined {
+                out(value).map_err(|e| self.format_error(e))?;
+            }
+            return Ok(());
+        }
+
+        let result = self.interpreter.eval_rule_stream_in_path(path, out);
+        self.interpreter.clean_internal_evaluation_state();
+        result.map_err(|e| self.format_error(e))
+    }
+
+    /// Evaluate a rule and report why it is undefined, if it is.
+    ///
+    /// [`Engine::eval_rule`] returns [`Value::Undefined`] both when no rule is defined at the path
+    /// and when none of the rule's bodies succeed, and raises errors for invalid paths.
+    /// This function distinguishes these cases via [`RuleOutcome`].
+    /// Errors raised while evaluating the rule are returned as [`RuleOutcome::Error`];
+    /// errors in preparing the policies for evaluation are returned as is.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    ///
+    /// engine.add_policy(
+    ///   "policy.rego".to_string(),
+    ///   r#"
+    ///   package example
+    ///   import rego.v1
+    ///
+    ///   allow if input.admin
+    ///   x := 1 / input.n
+    ///   "#.to_string())?;
+    ///
+    /// engine.set_input(Value::from_json_str(r#"{"admin": false, "n": 0}"#)?);
+    /// assert_eq!(
+    ///   engine.eval_rule_explained("data.example.allow".to_string())?,
+    ///   RuleOutcome::UndefinedAllBodiesFalse
+    /// );
+    /// assert_eq!(
+    ///   engine.eval_rule_explained("data.example.deny".to_string())?,
+    ///   RuleOutcome::UndefinedNoMatch
+    /// );
+    /// assert!(matches!(
+    ///   engine.eval_rule_explained("data.example.x".to_string())?,
+    ///   RuleOutcome::Error(_)
+    /// ));
+    ///
+    /// engine.set_input(Value::from_json_str(r#"{"admin": true, "n": 2}"#)?);
+    /// assert_eq!(
+    ///   engine.eval_rule_explained("data.example.allow".to_string())?,
+    ///   RuleOutcome::Defined(Value::from(true))
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn eval_rule_explained(&mut self, rule: String) -> Result<RuleOutcome, RegorusError> {
+        self.prepare_for_eval(false)?;
+        if !self.interpreter.is_rule_path(&rule) {
+            return Ok(RuleOutcome::UndefinedNoMatch);
+        }
+
+        self.interpreter.clean_internal_evaluation_state();
+        Ok(match self.interpreter.eval_rule_in_path(rule) {
+            Ok(Value::Undefined) => RuleOutcome::UndefinedAllBodiesFalse,
+            Ok(v) => RuleOutcome::Defined(v),
+            Err(e) => RuleOutcome::Error(e.to_string()),
+        })
+    }
+
+    /// Evaluate a rule once for each element of an array of inputs.
+    ///
+    /// Each element is set as the input in turn and the results are returned in the same order.
+    /// Prepared state is reused across elements. The input in effect before the call is restored afterwards.
+    /// Evaluation stops at the first element that fails; use [`Engine::eval_rule_over_array_results`]
+    /// to evaluate all elements regardless of failures.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    ///
+    /// engine.add_policy(
+    ///   "policy.rego".to_string(),
+    ///   r#"
+    ///   package example
+    ///   import rego.v1
+    ///
+    ///   default allow := false
+    ///   allow if input.role == "admin"
+    ///   "#.to_string())?;
+    ///
+    /// let inputs = Value::from_json_str(r#"[{"role": "admin"}, {"role": "guest"}]"#)?;
+    /// let results = engine.eval_rule_over_array("data.example.allow".to_string(), inputs)?;
+    /// assert_eq!(results, vec![Value::from(true), Value::from(false)]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn eval_rule_over_array(
+        &mut self,
+        rule: String,
+        inputs: Value,
+    ) -> Result<Vec<Value>, RegorusError> {
+        self.eval_rule_over_array_results(rule, inputs)?
+            .into_iter()
+            .enumerate()
+            .map(|(idx, r)| {
+                r.map_err(|e| {
+                    let message = format!("input {idx}: {e}");
+                    e.with_message(message)
+                })
+            })
+            .collect()
+    }
+
+    /// Evaluate a rule once for each element of an array of inputs and return the results as an
+    /// Arrow record batch.
+    ///
+    /// Each result must be an object with string keys and becomes a row of the batch, with a
+    /// nullable column per key. Undefined results become rows of nulls, as do missing keys.
+    /// The schema is inferred from the first 100 results: booleans map to `Boolean`, integers
+    /// to `Int64`, other numbers to `Float64` and strings to `Utf8`. Arrays, sets and objects are
+    /// stored as JSON in `Utf8` columns. Columns with only nulls in the inferred rows have type `Null`.
+    /// An error is raised if a result does not match the inferred schema.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    ///
+    /// engine.add_policy(
+    ///   "policy.rego".to_string(),
+    ///   r#"
+    ///   package example
+    ///
+    ///   decision := {"allow": input.a > 1, "score": input.a * 10}
+    ///   "#.to_string())?;
+    ///
+    /// let inputs = Value::from_json_str(r#"[{"a": 1}, {"a": 2}]"#)?;
+    /// let batch = engine.eval_rule_over_array_arrow("data.example.decision".to_string(), inputs)?;
+    /// assert_eq!(batch.num_rows(), 2);
+    /// assert_eq!(batch.schema().field(1).name(), "score");
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "arrow")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "arrow")))]
+    pub fn eval_rule_over_array_arrow(
+        &mut self,
+        rule: String,
+        inputs: Value,
+    ) -> Result<arrow_array::RecordBatch, RegorusError> {
+        let results = self.eval_rule_over_array(rule, inputs)?;
+        Ok(crate::arrow::to_record_batch(&results)?)
+    }
+
+    /// Evaluate a rule once for each element of an array of inputs, collecting per-element errors.
+    ///
+    /// Behaves like [`Engine::eval_rule_over_array`], except that a failing element does not
+    /// abort the batch. An error is returned only if `inputs` is not an array.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    ///
+    /// engine.add_policy(
+    ///   "policy.rego".to_string(),
+    ///   r#"
+    ///   package example
+    ///
+    ///   x := input.a / input.b
+    ///   "#.to_string())?;
+    ///
+    /// let inputs = Value::from_json_str(r#"[{"a": 4, "b": 2}, {"a": 1, "b": 0}, {"a": 9, "b": 3}]"#)?;
+    /// let results = engine.eval_rule_over_array_results("data.example.x".to_string(), inputs)?;
+    /// assert_eq!(results[0].as_ref().unwrap(), &Value::from(2));
+    /// assert!(results[1].is_err());
+    /// assert_eq!(results[2].as_ref().unwrap(), &Value::from(3));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn eval_rule_over_array_results(
+        &mut self,
+        rule: String,
+        inputs: Value,
+    ) -> Result<Vec<Result<Value, RegorusError>>, RegorusError> {
+        let inputs = match inputs {
+            Value::Array(a) => a,
+            _ => return Err(anyhow!("inputs must be an array").into()),
+        };
+
+        self.prepare_for_eval(false)?;
+        let prev_input = self.interpreter.get_input().clone();
+        let results = inputs
+            .iter()
+            .map(|input| {
+                self.interpreter.set_input(input.clone());
+                self.interpreter.start_print_group();
+                self.interpreter.clean_internal_evaluation_state();
+                self.interpreter
+                    .eval_rule_in_path(rule.clone())
+                    .map_err(|e| self.format_error(e))
+            })
+            .collect();
+        self.interpreter.set_input(prev_input);
+        Ok(results)
+    }
+
+    /// Get the paths of the rules defined by loaded policies.
+    ///
+    /// The paths are sorted and can be evaluated using [`Engine::eval_rule`].
+    /// Functions are not included.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    ///
+    /// engine.add_policy(
+    ///   "policy.rego".to_string(),
+    ///   r#"
+    ///   package example
+    ///   import rego.v1
+    ///
+    ///   default allow := false
+    ///   allow if input.admin
+    ///
+    ///   users.admins contains "alice"
+    ///
+    ///   f(x) := x + 1
+    ///   "#.to_string())?;
+    ///
+    /// assert_eq!(
+    ///   engine.get_rule_paths()?,
+    ///   vec!["data.example.allow", "data.example.users.admins"]
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn get_rule_paths(&mut self) -> Result<Vec<String>, RegorusError> {
+        self.prepare_for_eval(false)?;
+        Ok(self.interpreter.get_rule_paths())
+    }
+
+    /// Evaluate a user defined function with the given arguments.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    ///
+    /// engine.add_policy(
+    ///   "lib.rego".to_string(),
+    ///   r#"
+    ///   package lib
+    ///   import rego.v1
+    ///
+    ///   f(x, y) := x + y
+    ///
+    ///   g(x) := x if x > 2
+    ///   "#.to_string())?;
+    ///
+    /// let v = engine.eval_function("data.lib.f", vec![Value::from(1), Value::from(2)])?;
+    /// assert_eq!(v, Value::from(3));
+    ///
+    /// // g evaluates to undefined.
+    /// let v = engine.eval_function("data.lib.g", vec![Value::from(1)])?;
+    /// assert_eq!(v, Value::Undefined);
+    ///
+    /// // The number of arguments must match.
+    /// assert!(engine.eval_function("data.lib.f", vec![Value::from(1)]).is_err());
+    ///
+    /// // Path must be a valid function path.
+    /// assert!(engine.eval_function("data.lib.h", vec![]).is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn eval_function(&mut self, path: &str, args: Vec<Value>) -> Result<Value, RegorusError> {
+        let result = self.prepare_for_eval(false).and_then(|_| {
+            self.interpreter.clean_internal_evaluation_state();
+            self.interpreter
+                .eval_function_in_path(path.to_string(), args)
+        });
+        result.map_err(|e| self.format_error(e))
+    }
+
+    /// Evaluate a Rego query.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// # #[cfg(feature = "std")] {
+    /// let mut engine = Engine::new();
+    ///
+    /// // Add policies
+    /// engine.add_policy_from_file("tests/aci/framework.rego")?;
+    /// engine.add_policy_from_file("tests/aci/api.rego")?;
+    /// engine.add_policy_from_file("tests/aci/policy.rego")?;
+    ///
+    /// // Add data document (if any).
+    /// // If multiple data documents can be added, they will be merged together.
+    /// engine.add_data(Value::from_json_file("tests/aci/data.json")?)?;
+    ///
+    /// // At this point the policies and data have been loaded.
+    /// // Either the same engine can be used to make multiple queries or the engine
+    /// // can be cloned to avoid having the reload the policies and data.
+    /// let _clone = engine.clone();
+    ///
+    /// // Evaluate a query.
+    /// // Load input and make query.
+    /// engine.set_input(Value::new_object());
+    /// let results = engine.eval_query("data.framework.mount_overlay.allowed".to_string(), false)?;
+    /// assert_eq!(results.result[0].expressions[0].value, Value::from(false));
+    ///
+    /// // Evaluate query with different inputs.
+    /// engine.set_input(Value::from_json_file("tests/aci/input.json")?);
+    /// let results = engine.eval_query("data.framework.mount_overlay.allowed".to_string(), false)?;
+    /// assert_eq!(results.result[0].expressions[0].value, Value::from(true));
+    /// # }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn eval_query(
+        &mut self,
+        query: String,
+        enable_tracing: bool,
+    ) -> Result<QueryResults, RegorusError> {
+        self.eval_query_impl(query, enable_tracing, "__internal_query_module", false)
+            .map_err(|e| self.format_error(e))
+    }
+
+    /// Evaluate a Rego query and write the results as JSON to `w`.
+    ///
+    /// The output is the same as serializing the [`QueryResults`] returned by
+    /// [`Engine::eval_query`] using `serde_json::to_string`, except that numbers are rendered
+    /// as per [`Engine::set_number_output`]. Each [`QueryResult`] is written to `w` as soon as
+    /// the query produces it and is then released; hence neither the results nor the JSON output
+    /// are held in memory in full. If evaluation fails, the output written so far is incomplete.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    ///
+    /// let mut json = String::new();
+    /// engine.eval_query_to_writer("x := [1, 2][_]".to_string(), false, &mut json)?;
+    /// assert_eq!(json, r#"{"result":[{"expressions":[{"value":true,"text":"x := [1, 2][_]","location":{"row":1,"col":1}}],"bindings":{"x":1}},{"expressions":[{"value":true,"text":"x := [1, 2][_]","location":{"row":1,"col":1}}],"bindings":{"x":2}}]}"#);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "std")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+    pub fn eval_query_to_writer(
+        &mut self,
+        query: String,
+        enable_tracing: bool,
+        w: &mut dyn core::fmt::Write,
+    ) -> Result<(), RegorusError> {
+        let mut writer = QueryResultsWriter {
+            w,
+            number_output: self.number_output,
+            count: 0,
+        };
+        let result = {
+            let mut out = |result| writer.write_result(result);
+            let guard = QueryResultStreamGuard::new(self, &mut out);
+            guard
+                .0
+                .eval_query_impl(query, enable_tracing, "__internal_query_module", false)
+        };
+        self.interpreter.clean_internal_evaluation_state();
+        result.map_err(|e| self.format_error(e))?;
+        Ok(writer.finish()?)
+    }
+
+    /// Get the names of the builtins available to policies.
+    ///
+    /// The list reflects the features regorus was built with and any restrictions set via
+    /// [`Engine::set_capabilities_from_json`]. Deprecated builtins are included unless
+    /// rego.v1 is enabled via [`Engine::set_rego_v1`].
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let engine = Engine::new();
+    /// let names = engine.builtin_names();
+    ///
+    /// assert!(names.contains(&"count".to_string()));
+    /// assert!(names.contains(&"strings.replace_n".to_string()));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn builtin_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .interpreter
+            .builtins(!self.rego_v1)
+            .map(|(name, _)| name.to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Get the signature of an available builtin.
+    ///
+    /// Returns `None` if no builtin by that name is available; see [`Engine::builtin_names`].
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let engine = Engine::new();
+    /// let signature = engine.builtin_signature("substring").unwrap();
+    ///
+    /// assert_eq!(signature.arity, 3);
+    /// assert_eq!(signature.arg_names, ["arg1", "arg2", "arg3"]);
+    /// assert!(!signature.requires_std);
+    /// assert!(engine.builtin_signature("no.such.builtin").is_none());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn builtin_signature(&self, name: &str) -> Option<BuiltinSignature> {
+        let (name, (_, arity)) = self
+            .interpreter
+            .builtins(!self.rego_v1)
+            .find(|(n, _)| *n == name)?;
+        Some(BuiltinSignature {
+            arity: *arity,
+            arg_names: (1..=*arity).map(|i| format!("arg{i}")).collect(),
+            requires_std: builtins::requires_std(name),
+        })
+    }
+
+    /// Partially evaluate a query, treating parts of the input as unknown.
+    ///
+    /// Expressions that depend on unknowns are retained in the residual queries with all
+    /// known values substituted; everything else is evaluated using the current data and
+    /// input. Rules that depend on unknowns are inlined when used as conditions, producing a
+    /// residual query per rule body. This allows policy decisions to be translated into,
+    /// e.g., database filters.
+    ///
+    /// * `query`: The query to evaluate.
+    /// * `unknowns`: Paths such as `input` or `input.user` whose values are unknown.
+    ///
+    /// Iteration (`some`, `every`), `with` modifiers, comprehensions, `else` and negation or
+    /// value use of rules that depend on unknowns are not supported and result in errors.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.add_policy(
+    ///    "test.rego".to_string(),
+    ///    r#"
+    ///    package test
+    ///    import rego.v1
+    ///
+    ///    default allow := false
+    ///    allow if input.user.role == "admin"
+    ///    allow if {
+    ///      input.user.name == input.resource.owner
+    ///      input.action == "read"
+    ///    }
+    ///    "#.to_string())?;
+    /// engine.set_input(Value::from_json_str(r#"{"action": "read", "resource": {"owner": "alice"}}"#)?);
+    ///
+    /// let result = engine.partial_eval("data.test.allow".to_string(), vec!["input.user".to_string()])?;
+    ///
+    /// assert_eq!(result.queries.len(), 2);
+    /// assert!(result.rego.contains(r#"input.user.name == "alice""#));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn partial_eval(
+        &mut self,
+        query: String,
+        unknowns: Vec<String>,
+    ) -> Result<PartialResult, RegorusError> {
+        let source = Source::from_contents("<query.rego>".to_string(), query)?;
+        let mut parser = self.make_parser(&source)?;
+        let query = parser.parse_user_query()?;
+        let modules = self.modules.clone();
+        let queries = partial::PartialEvaluator::new(self, modules, unknowns)?.eval(&query)?;
+        Ok(partial::make_result(queries)?)
+    }
+
+    /// Evaluate several Rego queries against the same policies, data and input.
+    ///
+    /// The evaluation state is prepared once and shared by the queries. In particular, each
+    /// rule is evaluated at most once across all the queries; hence prints within rules are
+    /// gathered only for the first query that evaluates the rule. The results are in the order
+    /// of `queries`. A query that fails does not affect the remaining queries; its error is
+    /// returned in place of its results and rules are evaluated afresh for subsequent
+    /// queries. Traces are produced for each query if
+    /// `enable_tracing` is set, and when gathering prints, each query starts a new group
+    /// for [`Engine::take_prints_grouped`].
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.add_policy(
+    ///   "policy.rego".to_string(),
+    ///   r#"
+    ///   package test
+    ///   import rego.v1
+    ///
+    ///   is_admin if input.role == "admin"
+    ///   allow if is_admin
+    ///   "#.to_string())?;
+    /// engine.set_input(Value::from_json_str(r#"{ "role": "admin" }"#)?);
+    ///
+    /// let results = engine.eval_queries(
+    ///   vec!["data.test.allow".to_string(), "1 +".to_string(), "data.test.is_admin".to_string()],
+    ///   false,
+    /// )?;
+    /// assert_eq!(results[0].as_ref().unwrap().result[0].expressions[0].value, Value::from(true));
+    /// assert!(results[1].is_err());
+    /// assert_eq!(results[2].as_ref().unwrap().result[0].expressions[0].value, Value::from(true));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn eval_queries(
+        &mut self,
+        queries: Vec<String>,
+        enable_tracing: bool,
+    ) -> Result<Vec<Result<QueryResults, RegorusError>>, RegorusError> {
+        self.prepare_for_eval(enable_tracing)
+            .map_err(|e| self.format_error(e))?;
+        self.interpreter.clean_internal_evaluation_state();
+        self.interpreter.create_rule_prefixes()?;
+        let query_module = Self::make_query_module("__internal_query_module")?;
+
+        let mut results = vec![];
+        for query in queries {
+            self.interpreter.start_print_group();
+            let r = self
+                .eval_prepared_query(&query_module, query, enable_tracing, false)
+                .map_err(|e| self.format_error(e));
+            if r.is_err() {
+                // A failed evaluation may leave the state partially updated.
+                self.interpreter.clean_internal_evaluation_state();
+                self.interpreter.create_rule_prefixes()?;
+            }
+            results.push(r);
+        }
+        Ok(results)
+    }
+
+    // Evaluate a query without resetting the evaluation state.
+    // Unqualified references in the query resolve to rules of the package of `query_module`.
+    // If `scoped` is set, references to `data` in the query only see that package.
+    fn eval_prepared_query(
+        &mut self,
+        query_module: &Ref<Module>,
+        query: String,
+        enable_tracing: bool,
+        scoped: bool,
+    ) -> Result<QueryResults> {
+        let query_source = Source::from_contents("<query.rego>".to_string(), query)?;
+        let mut parser = self.make_parser(&query_source)?;
+        let query_node = parser.parse_user_query()?;
+        if query_node.span.text() == "data" && !scoped {
+            self.eval_modules_impl(enable_tracing)?;
+        }
+        let query_package = get_path_string(&query_module.package.refr, Some("data"))?;
+        let query_schedule = Analyzer::new()
+            .analyze_query_snippet_in_package(&self.modules, &query_package, &query_node)
+            .map_err(classify(ErrorKind::Compile))?;
+        if scoped {
+            let scope = Parser::get_path_ref_components(&query_module.package.refr)?
+                .iter()
+                .map(|s| s.text().to_string())
+                .collect();
+            self.interpreter
+                .set_query_scope(Some((query_source.clone(), scope)));
+        }
+        let results = self.interpreter.eval_user_query(
+            query_module,
+            &query_node,
+            &query_schedule,
+            enable_tracing,
+        );
+        self.interpreter.set_query_scope(None);
+        let mut results = results?;
+        if self.sort_results {
+            results.result.sort_by(|a, b| a.bindings.cmp(&b.bindings));
+        }
+        Ok(results)
+    }
+
+    /// Evaluate a Rego query and project the values of the given binding variables.
+    ///
+    /// Returns a row per result containing the values of `vars` in order.
+    /// Variables that are not bound in a result yield [`Value::Undefined`].
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    ///
+    /// let rows = engine.eval_query_project(
+    ///   r#"x := ["a", "b"][i]; y := upper(x)"#.to_string(),
+    ///   &["i", "y"],
+    /// )?;
+    /// assert_eq!(rows, vec![
+    ///   vec![Value::from(0), Value::from("A")],
+    ///   vec![Value::from(1), Value::from("B")],
+    /// ]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn eval_query_project(
+        &mut self,
+        query: String,
+        vars: &[&str],
+    ) -> Result<Vec<Vec<Value>>, RegorusError> {
+        let results = self.eval_query(query, false)?;
+        Ok(results
+            .result
+            .iter()
+            .map(|r| vars.iter().map(|v| r.bindings[*v].clone()).collect())
+            .collect())
+    }
+
+    /// Evaluate a Rego query against the rules of a single package.
+    ///
+    /// References to `data` in the query only see the given package and its subpackages.
+    /// Unqualified references in the query resolve to rules of the package and to names
+    /// imported by its policies. Rules of the package may still refer to other packages.
+    ///
+    /// * `package`: The package, e.g. `data.tenant1` or `tenant1`.
+    /// * `query`: The query.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.add_policy("a.rego".to_string(), "package tenant_a\nlimit := 10".to_string())?;
+    /// engine.add_policy("b.rego".to_string(), "package tenant_b\nlimit := 20".to_string())?;
+    ///
+    /// let results = engine.eval_query_in_package("tenant_b", "limit".to_string())?;
+    /// assert_eq!(results.result[0].expressions[0].value, Value::from(20));
+    ///
+    /// let results = engine.eval_query_in_package("data.tenant_a", "data".to_string())?;
+    /// assert_eq!(
+    ///   results.result[0].expressions[0].value,
+    ///   Value::from_json_str(r#"{"tenant_a": {"limit": 10}}"#)?
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn eval_query_in_package(
+        &mut self,
+        package: &str,
+        query: String,
+    ) -> Result<QueryResults, RegorusError> {
+        let package = package.strip_prefix("data.").unwrap_or(package);
+        let package_path = format!("data.{package}");
+        let package_prefix = format!("{package_path}.");
+
+        let mut found = false;
+        for m in &self.modules {
+            let path = get_path_string(&m.package.refr, Some("data"))?;
+            found |= path == package_path || path.starts_with(&package_prefix);
+        }
+        if !found {
+            return Err(anyhow!("no policies found in package {package_path}").into());
+        }
+
+        self.eval_query_impl(query, false, package, true)
+            .map_err(|e| self.format_error(e))
+    }
+
+    fn eval_query_impl(
+        &mut self,
+        query: String,
+        enable_tracing: bool,
+        query_package: &str,
+        scoped: bool,
+    ) -> Result<QueryResults> {
+        self.prepare_for_eval(enable_tracing)?;
+        self.interpreter.clean_internal_evaluation_state();
+
+        self.interpreter.create_rule_prefixes()?;
+        let query_module = Self::make_query_module(query_package)?;
+        self.eval_prepared_query(&query_module, query, enable_tracing, scoped)
+    }
+
+    // Create the module in whose context queries are evaluated.
+    fn make_query_module(query_package: &str) -> Result<Ref<Module>> {
+        let source = Source::from_contents(
+            "<query_module.rego>".to_owned(),
+            format!("package {query_package}"),
+        )?;
+        Ok(Ref::new(Parser::new(&source)?.parse()?))
+    }
+
+    /// Get the order in which the statements of a query would be evaluated.
+    ///
+    /// Statements are reordered so that variables are defined before they are used.
+    /// For each statement, the position in the evaluation order and the variables it
+    /// defines and uses are returned. This is useful for understanding errors about unsafe variables.
+    /// The query is not evaluated.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    ///
+    /// let info = engine.get_query_schedule("y = x + 1; x = 5".to_string())?;
+    /// assert_eq!(info.statements[0].text, "y = x + 1");
+    /// assert_eq!(info.statements[0].order, 1);
+    /// assert_eq!(info.statements[0].defines, vec!["y".to_string()]);
+    /// assert_eq!(info.statements[0].uses, vec!["x".to_string()]);
+    /// assert_eq!(info.statements[1].order, 0);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn get_query_schedule(&mut self, query: String) -> Result<ScheduleInfo, RegorusError> {
+        let query_source = Source::from_contents("<query.rego>".to_string(), query)?;
+        let mut parser = self.make_parser(&query_source)?;
+        let query_node = parser.parse_user_query()?;
+        let (schedule, infos) = Analyzer::new()
+            .analyze_query_snippet_with_infos(&self.modules, &query_node)
+            .map_err(classify(ErrorKind::Compile))?;
+
+        let mut statements: Vec<StatementSchedule> = query_node
+            .stmts
+            .iter()
+            .zip(infos.iter())
+            .map(|(stmt, info)| {
+                let mut defines: Vec<String> = vec![];
+                let mut uses: Vec<String> = vec![];
+                for defn in &info.definitions {
+                    let var = defn.var.to_string();
+                    if !var.is_empty() && !defines.contains(&var) {
+                        defines.push(var);
+                    }
+                }
+                for defn in &info.definitions {
+                    for var in &defn.used_vars {
+                        let var = var.to_string();
+                        if !var.is_empty() && !defines.contains(&var) && !uses.contains(&var) {
+                            uses.push(var);
+                        }
+                    }
+                }
+                StatementSchedule {
+                    text: stmt.span.text().to_string(),
+                    order: 0,
+                    defines,
+                    uses,
+                }
+            })
+            .collect();
+
+        match schedule.order.get(&query_node) {
+            Some(order) => {
+                for (pos, idx) in order.iter().enumerate() {
+                    statements[*idx as usize].order = pos;
+                }
+            }
+            None => statements
+                .iter_mut()
+                .enumerate()
+                .for_each(|(pos, s)| s.order = pos),
+        }
+
+        Ok(ScheduleInfo { statements })
+    }
+
+    /// Evaluate a Rego query with parts of the data document replaced.
+    ///
+    /// Each override is a `data.*` path and the value that replaces the subtree at that path,
+    /// similar to `with data.<path> as <value>` in Rego. Unlike [`Engine::add_data`], the value
+    /// is not merged with existing data, and the overrides apply only to this evaluation.
+    /// The original data is restored afterward.
+    ///
+    /// Overrides cannot target paths that are defined or nested within rules.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.add_data(Value::from_json_str(r#"{ "config": { "max": 5, "min": 1 } }"#)?)?;
+    /// engine.add_policy(
+    ///   "policy.rego".to_string(),
+    ///   r#"
+    ///   package test
+    ///   import rego.v1
+    ///
+    ///   allow if input.x <= data.config.max
+    ///   "#.to_string())?;
+    /// engine.set_input(Value::from_json_str(r#"{ "x": 10 }"#)?);
+    ///
+    /// let results = engine.eval_query_with_overrides(
+    ///   "data.config".to_string(),
+    ///   vec![("data.config".to_string(), Value::from_json_str(r#"{ "max": 20 }"#)?)],
+    ///   false,
+    /// )?;
+    /// // The subtree is replaced, not merged.
+    /// assert_eq!(
+    ///   results.result[0].expressions[0].value,
+    ///   Value::from_json_str(r#"{ "max": 20 }"#)?
+    /// );
+    ///
+    /// let allow = engine.eval_query_with_overrides(
+    ///   "data.test.allow".to_string(),
+    ///   vec![("data.config.max".to_string(), Value::from(20))],
+    ///   false,
+    /// )?;
+    /// assert_eq!(allow.result[0].expressions[0].value, Value::from(true));
+    ///
+    /// // The original data is restored.
+    /// assert_eq!(engine.eval_rule("data.test.allow".to_string())?, Value::Undefined);
+    ///
+    /// // Rules cannot be overridden.
+    /// assert!(engine.eval_query_with_overrides(
+    ///   "data.test.allow".to_string(),
+    ///   vec![("data.test.allow".to_string(), Value::from(true))],
+    ///   false,
+    /// ).is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn eval_query_with_overrides(
+        &mut self,
+        query: String,
+        overrides: Vec<(String, Value)>,
+        enable_tracing: bool,
+    ) -> Result<QueryResults, RegorusError> {
+        self.prepare_for_eval(enable_tracing)?;
+
+        let mut data = self.interpreter.get_init_data().clone();
+        let rule_paths = self.interpreter.get_rule_paths();
+        for (path, value) in overrides {
+            let parts: Vec<&str> = path.split('.').collect();
+            if parts[0] != "data" || parts[1..].iter().any(|p| p.is_empty()) {
+                return Err(
+                    anyhow!("invalid override path `{path}`. expecting `data.*` path").into(),
+                );
+            }
+            if let Some(rule) = rule_paths.iter().find(|r| {
+                matches!(r.strip_prefix(&path), Some(s) if s.is_empty() || s.starts_with('.'))
+                    || matches!(path.strip_prefix(r.as_str()), Some(s) if s.starts_with('.'))
+            }) {
+                return Err(
+                    anyhow!("cannot override `{path}` since it overlaps rule `{rule}`").into(),
+                );
+            }
+            *data.make_or_get_value_mut(&parts[1..]).map_err(|_| {
+                anyhow!("cannot override `{path}` since a parent is not an object")
+            })? = value;
+        }
+
+        self.eval_query_with_data(query, data, enable_tracing)
+    }
+
+    /// Freeze the current data document into a snapshot that can be shared by other engines.
+    ///
+    /// The snapshot refers to the data of this engine without copying it. Data added to this
+    /// engine afterwards is not reflected in the snapshot.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.add_data(Value::from_json_str(r#"{ "limit": 5 }"#)?)?;
+    /// let snapshot = engine.freeze_data();
+    ///
+    /// engine.add_data(Value::from_json_str(r#"{ "extra": 1 }"#)?)?;
+    /// assert_eq!(snapshot.data()["limit"], Value::from(5));
+    /// assert_eq!(snapshot.data()["extra"], Value::Undefined);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn freeze_data(&mut self) -> DataSnapshot {
+        DataSnapshot {
+            data: self.interpreter.get_init_data().clone(),
+        }
+    }
+
+    /// Replace the data document with a snapshot created by [`Engine::freeze_data`].
+    ///
+    /// The data is shared with the snapshot and with all other engines using it; it is
+    /// copied only when data is subsequently added to this engine, and then only the modified
+    /// parts. Policies are not affected.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.add_policy(
+    ///   "policy.rego".to_string(),
+    ///   "package test\nallow := input.x <= data.limit".to_string())?;
+    /// engine.add_data(Value::from_json_str(r#"{ "limit": 5 }"#)?)?;
+    /// let snapshot = engine.freeze_data();
+    ///
+    /// let mut worker = Engine::new();
+    /// worker.add_policy(
+    ///   "policy.rego".to_string(),
+    ///   "package test\nallow := input.x <= data.limit".to_string())?;
+    /// worker.use_data_snapshot(snapshot);
+    /// worker.set_input(Value::from_json_str(r#"{ "x": 3 }"#)?);
+    /// assert_eq!(worker.eval_rule("data.test.allow".to_string())?, Value::from(true));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn use_data_snapshot(&mut self, snapshot: DataSnapshot) {
+        self.interpreter.set_init_data(snapshot.data);
+        self.prepared = false;
+    }
+
+    /// Add a named data snapshot.
+    ///
+    /// Snapshots are data documents that can be evaluated against using
+    /// [`Engine::eval_query_against`] without reloading policies. They are independent of
+    /// the data document built using [`Engine::add_data`]. Adding a snapshot with an
+    /// existing name replaces it.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    ///
+    /// // Only objects can be added.
+    /// assert!(engine.add_data_snapshot("v1".to_string(), Value::from_json_str("[]")?).is_err());
+    ///
+    /// engine.add_data_snapshot("v1".to_string(), Value::from_json_str(r#"{ "limit": 5 }"#)?)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn add_data_snapshot(&mut self, name: String, data: Value) -> Result<(), RegorusError> {
+        if data.as_object().is_err() {
+            return Err(anyhow!("data must be object").into());
+        }
+        self.data_snapshots.insert(name, data);
+        Ok(())
+    }
+
+    /// Evaluate a Rego query using a data snapshot added via [`Engine::add_data_snapshot`].
+    ///
+    /// The snapshot replaces the data document for this evaluation only.
+    /// Parsed policies are shared across snapshots.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.add_policy(
+    ///   "policy.rego".to_string(),
+    ///   r#"
+    ///   package test
     ///   import rego.v1
     ///
-    ///   x = [1, 2]
+    ///   allow if input.x <= data.limit
+    ///   "#.to_string())?;
+    /// engine.set_input(Value::from_json_str(r#"{ "x": 10 }"#)?);
     ///
-    ///   y := 5 if input.a > 2
+    /// engine.add_data_snapshot("v1".to_string(), Value::from_json_str(r#"{ "limit": 5 }"#)?)?;
+    /// engine.add_data_snapshot("v2".to_string(), Value::from_json_str(r#"{ "limit": 20 }"#)?)?;
+    ///
+    /// let results = engine.eval_query_against("data.test.allow".to_string(), "v1", false)?;
+    /// assert!(results.result.is_empty());
+    ///
+    /// let results = engine.eval_query_against("data.test.allow".to_string(), "v2", false)?;
+    /// assert_eq!(results.result[0].expressions[0].value, Value::from(true));
+    ///
+    /// // Unknown snapshots are errors.
+    /// assert!(engine.eval_query_against("data.test.allow".to_string(), "v3", false).is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn eval_query_against(
+        &mut self,
+        query: String,
+        snapshot_name: &str,
+        enable_tracing: bool,
+    ) -> Result<QueryResults, RegorusError> {
+        let data = match self.data_snapshots.get(snapshot_name) {
+            Some(data) => data.clone(),
+            _ => return Err(anyhow!("unknown data snapshot `{snapshot_name}`").into()),
+        };
+        self.eval_query_with_data(query, data, enable_tracing)
+    }
+
+    // Evaluate a query using given data document and then restore the original data document.
+    fn eval_query_with_data(
+        &mut self,
+        query: String,
+        data: Value,
+        enable_tracing: bool,
+    ) -> Result<QueryResults, RegorusError> {
+        let init_data = self.interpreter.get_init_data().clone();
+        self.interpreter.set_init_data(data);
+        let results = self
+            .interpreter
+            .init_with_document()
+            .map_err(RegorusError::from)
+            .and_then(|_| self.eval_query(query, enable_tracing));
+
+        // Restore the original data even if the override could not be applied.
+        self.interpreter.set_init_data(init_data);
+        let restored = self.interpreter.init_with_document();
+        let results = results?;
+        restored?;
+        Ok(results)
+    }
+
+    /// Evaluate a Rego query and produce the JSON emitted by `opa eval --format json`.
+    ///
+    /// [`QueryResults`] serializes to the same shape as `opa eval`: a `result` array (omitted if
+    /// there are no results) of objects with `expressions` (each with `value`, `text` and
+    /// `location`) and `bindings` (omitted if empty). Sets are serialized as arrays.
+    /// This is useful for diffing results against OPA in conformance tests.
+    /// Serializing [`QueryResults`] using `serde_json::to_string_pretty` preserves OPA's field order.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    ///
+    /// let json = engine.eval_query_opa_format("x := 1 + 2".to_string())?;
+    /// assert_eq!(json["result"][0]["expressions"][0]["value"], true);
+    /// assert_eq!(json["result"][0]["expressions"][0]["text"], "x := 1 + 2");
+    /// assert_eq!(json["result"][0]["expressions"][0]["location"]["col"], 1);
+    /// assert_eq!(json["result"][0]["bindings"]["x"], 3);
+    ///
+    /// // Queries without results produce an empty object.
+    /// assert_eq!(engine.eval_query_opa_format("x := input.missing".to_string())?, serde_json::json!({}));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn eval_query_opa_format(
+        &mut self,
+        query: String,
+    ) -> Result<serde_json::Value, RegorusError> {
+        let results = self.eval_query(query, false)?;
+        Ok(serde_json::to_value(&results).map_err(anyhow::Error::msg)?)
+    }
+
+    /// Evaluate a Rego query and produce an explanation of the evaluation.
+    ///
+    /// The explanation is a JSON array of events. The events are modelled after the events of
+    /// `opa eval --explain`, but they are not in OPA's format and cannot be consumed by OPA
+    /// tooling. Each event has the following fields:
+    /// - `op`: `Enter`, `Eval`, `Exit` or `Fail`. When the value of a `default` rule or function
+    ///   is used because none of the other definitions produced a value, an `Eval` and an `Exit`
+    ///   event are recorded for the `default` rule. These events have an additional `path` field
+    ///   naming the rule, e.g. `data.test.allow`.
+    /// - `query_id` and `parent_id`: the query (body) that the event belongs to and its parent.
+    ///   The user query has id 0.
+    /// - `type`: `body`, `expr` or `rule`.
+    /// - `node`: an object containing the source `text` and `location` of the node.
+    /// - `locals`: bindings of local variables in OPA's term format.
+    /// - `location`: `file`, `row` and `col` of the node.
+    ///
+    /// Every `Enter` event is followed by an `Exit` event if the body or rule succeeds or a
+    /// `Fail` event otherwise. Regorus does not backtrack like OPA, hence there are no `Redo`
+    /// events.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.add_policy(
+    ///   "policy.rego".to_string(),
+    ///   r#"
+    ///   package test
+    ///   import rego.v1
+    ///
+    ///   allow if input.x > 1
     ///   "#.to_string())?;
+    /// engine.set_input(Value::from_json_str(r#"{ "x": 0 }"#)?);
     ///
-    /// // Evaluate rule.
-    /// let v = engine.eval_rule("data.example.x".to_string())?;
-    /// assert_eq!(v, Value::from(vec![Value::from(1), Value::from(2)]));
+    /// let (results, explanation) = engine.eval_query_explain("data.test.allow".to_string())?;
+    /// assert!(results.result.is_empty());
     ///
-    /// // y evaluates to undefined.
-    /// let v = engine.eval_rule("data.example.y".to_string())?;
-    /// assert_eq!(v, Value::Undefined);
+    /// let events = explanation.as_array().expect("events");
+    /// assert_eq!(events[0]["op"], "Enter");
+    /// assert_eq!(events[0]["type"], "body");
     ///
-    /// // Evaluating a non-existent rule is an error.
-    /// let r = engine.eval_rule("data.exaample.x".to_string());
-    /// assert!(r.is_err());
+    /// // The rule's body failed at `input.x > 1`.
+    /// assert!(events.iter().any(|e| e["op"] == "Fail" && e["node"]["text"] == "input.x > 1"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn eval_query_explain(
+        &mut self,
+        query: String,
+    ) -> Result<(QueryResults, serde_json::Value), RegorusError> {
+        self.interpreter.set_explain(true);
+        let results = self.eval_query(query, false);
+        let explanation = self.interpreter.take_explanation();
+        self.interpreter.set_explain(false);
+        Ok((results?, serde_json::Value::Array(explanation)))
+    }
+
+    /// Only record trace and explanation events within the given rules.
     ///
-    /// // Path must be valid rule paths.
-    /// assert!( engine.eval_rule("data".to_string()).is_err());
-    /// assert!( engine.eval_rule("data.example".to_string()).is_err());
+    /// Events are recorded while evaluating any of the listed rules, including the rules and
+    /// functions they call, and omitted elsewhere. A path may also name a package, e.g.
+    /// `data.test`, to select all the rules within it. An empty list removes the filter.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.add_policy(
+    ///   "policy.rego".to_string(),
+    ///   r#"
+    ///   package test
+    ///   import rego.v1
+    ///
+    ///   allow if is_admin
+    ///   is_admin if input.role == "admin"
+    ///   audit if input.log
+    ///   "#.to_string())?;
+    /// engine.set_input(Value::from_json_str(r#"{ "role": "admin" }"#)?);
+    /// engine.set_trace_filter(vec!["data.test.is_admin".to_string()]);
+    ///
+    /// let (_, explanation) = engine.eval_query_explain("data.test".to_string())?;
+    /// let events = explanation.as_array().expect("events");
+    /// assert_eq!(events[0]["op"], "Enter");
+    /// assert_eq!(events[0]["type"], "rule");
+    /// assert!(events.iter().all(|e| e["location"]["row"] == 6));
     /// # Ok(())
     /// # }
     /// ```
-    pub fn eval_rule(&mut self, rule: String) -> Result<Value> {
-        self.prepare_for_eval(false)?;
-        self.interpreter.clean_internal_evaluation_state();
-        self.interpreter.eval_rule_in_path(rule)
+    pub fn set_trace_filter(&mut self, paths: Vec<String>) {
+        let paths = (!paths.is_empty()).then(|| paths.into_iter().collect());
+        self.interpreter.set_trace_filter(paths);
     }
 
-    /// Evaluate a Rego query.
+    /// Sort the results produced by [`Engine::eval_query`].
+    ///
+    /// By default, results are produced in evaluation order.
+    /// If set, results are sorted by their bindings. Bindings are objects mapping variable names
+    /// to values, and are compared using the ordering of [`Value`]: by the first differing
+    /// variable name, and then by the value of that variable. Results with equal bindings
+    /// retain their evaluation order.
     ///
     /// ```
     /// # use regorus::*;
     /// # fn main() -> anyhow::Result<()> {
     /// let mut engine = Engine::new();
+    /// engine.set_sort_results(true);
     ///
-    /// // Add policies
-    /// engine.add_policy_from_file("tests/aci/framework.rego")?;
-    /// engine.add_policy_from_file("tests/aci/api.rego")?;
-    /// engine.add_policy_from_file("tests/aci/policy.rego")?;
+    /// let results = engine.eval_query("x = [3, 1, 2][_]".to_string(), false)?;
+    /// assert_eq!(results.result[0].bindings[&Value::from("x")], Value::from(1));
+    /// assert_eq!(results.result[1].bindings[&Value::from("x")], Value::from(2));
+    /// assert_eq!(results.result[2].bindings[&Value::from("x")], Value::from(3));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_sort_results(&mut self, sort_results: bool) {
+        self.sort_results = sort_results;
+    }
+
+    /// Evaluate a Rego query that can be cancelled by another thread.
     ///
-    /// // Add data document (if any).
-    /// // If multiple data documents can be added, they will be merged together.
-    /// engine.add_data(Value::from_json_file("tests/aci/data.json")?)?;
+    /// Behaves like [`Engine::eval_query`] without tracing, except that evaluation is aborted
+    /// with an error once `cancel` is set. The flag is checked before evaluating each rule and
+    /// each statement, including each iteration of a loop.
     ///
-    /// // At this point the policies and data have been loaded.
-    /// // Either the same engine can be used to make multiple queries or the engine
-    /// // can be cloned to avoid having the reload the policies and data.
-    /// let _clone = engine.clone();
+    /// ```
+    /// # use regorus::*;
+    /// # use std::sync::{atomic::{AtomicBool, Ordering}, Arc};
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// let cancel = Arc::new(AtomicBool::new(false));
     ///
-    /// // Evaluate a query.
-    /// // Load input and make query.
-    /// engine.set_input(Value::new_object());
-    /// let results = engine.eval_query("data.framework.mount_overlay.allowed".to_string(), false)?;
-    /// assert_eq!(results.result[0].expressions[0].value, Value::from(false));
+    /// let results = engine.eval_query_cancellable("x := 1 + 1".to_string(), cancel.clone())?;
+    /// assert_eq!(results.result[0].bindings["x"], Value::from(2));
     ///
-    /// // Evaluate query with different inputs.
-    /// engine.set_input(Value::from_json_file("tests/aci/input.json")?);
-    /// let results = engine.eval_query("data.framework.mount_overlay.allowed".to_string(), false)?;
-    /// assert_eq!(results.result[0].expressions[0].value, Value::from(true));
+    /// // E.g. set by a request handler when the client disconnects.
+    /// cancel.store(true, Ordering::Relaxed);
+    /// let r = engine.eval_query_cancellable("x := 1 + 1".to_string(), cancel);
+    /// assert!(r.unwrap_err().to_string().contains("evaluation cancelled"));
     /// # Ok(())
     /// # }
     /// ```
-    pub fn eval_query(&mut self, query: String, enable_tracing: bool) -> Result<QueryResults> {
-        self.prepare_for_eval(enable_tracing)?;
-        self.interpreter.clean_internal_evaluation_state();
+    #[cfg(feature = "arc")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "arc")))]
+    pub fn eval_query_cancellable(
+        &mut self,
+        query: String,
+        cancel: alloc::sync::Arc<core::sync::atomic::AtomicBool>,
+    ) -> Result<QueryResults, RegorusError> {
+        self.interpreter.set_cancel(Some(cancel));
+        let results = self.eval_query(query, false);
+        self.interpreter.set_cancel(None);
+        results
+    }
 
-        self.interpreter.create_rule_prefixes()?;
-        let query_module = {
-            let source = Source::from_contents(
-                "<query_module.rego>".to_owned(),
-                "package __internal_query_module".to_owned(),
-            )?;
-            Ref::new(Parser::new(&source)?.parse()?)
-        };
+    /// Evaluate a Rego query that produces at most one result and return its bindings.
+    ///
+    /// Returns `None` if the query produces no results and an error if it produces more than one.
+    /// This is a shorthand for fetching `results.result[0].bindings` from [`Engine::eval_query`].
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let mut engine = Engine::new();
+    ///
+    /// // bindings contains the value for each named expession.
+    /// let bindings = engine.eval_bindings("x = 1; y = x > 0".to_string())?.unwrap();
+    /// assert_eq!(bindings[&Value::from("x")], Value::from(1u64));
+    /// assert_eq!(bindings[&Value::from("y")], Value::from(true));
+    ///
+    /// // If any expression evaluates to false, then there are no bindings.
+    /// assert_eq!(engine.eval_bindings("x = 1; false".to_string())?, None);
+    ///
+    /// // Queries producing multiple results will raise an error.
+    /// assert!(engine.eval_bindings("x = [1, 2][_]".to_string()).is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn eval_bindings(&mut self, query: String) -> Result<Option<Value>, RegorusError> {
+        let mut results = self.eval_query(query, false)?;
+        match results.result.len() {
+            0 => Ok(None),
+            1 => Ok(results.result.pop().map(|r| r.bindings)),
+            _ => Err(anyhow!("query produced more than one result").into()),
+        }
+    }
 
-        // Parse the query.
-        let query_source = Source::from_contents("<query.rego>".to_string(), query)?;
-        let mut parser = self.make_parser(&query_source)?;
-        let query_node = parser.parse_user_query()?;
-        if query_node.span.text() == "data" {
-            self.eval_modules(enable_tracing)?;
+    /// Get the builtins called by the loaded policies.
+    ///
+    /// The policies are walked statically without being evaluated, so the result includes
+    /// builtins in rules that an evaluation may never reach. Calls to functions defined in the
+    /// policies are not included, while calls to extensions added via [`Engine::add_extension`]
+    /// are. Useful for checking that policies only use an approved set of builtins.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.add_policy(
+    ///   "policy.rego".to_string(),
+    ///   r#"
+    ///   package test
+    ///   import rego.v1
+    ///
+    ///   msg := sprintf("%d users", [count(input.users)])
+    ///   is_short(s) if count(s) < 5
+    ///   short := [u | some u in input.users; is_short(trim_space(u))]
+    ///   "#.to_string())?;
+    ///
+    /// let builtins: Vec<String> = engine.used_builtins()?.into_iter().collect();
+    /// assert_eq!(builtins, ["count", "sprintf", "trim_space"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn used_builtins(&self) -> Result<BTreeSet<String>, RegorusError> {
+        let mut used = BTreeSet::new();
+        for module in &self.modules {
+            for rule in &module.policy {
+                visit_calls_in_rule(rule, &mut |_, fcn| {
+                    // Calls via non-path expressions cannot be resolved statically.
+                    if let Ok(path) = get_path_string(fcn, None) {
+                        if self.interpreter.is_builtin_or_extension(&path) {
+                            used.insert(path);
+                        }
+                    }
+                    Ok(())
+                })?;
+            }
         }
-        let query_schedule = Analyzer::new().analyze_query_snippet(&self.modules, &query_node)?;
-        self.interpreter.eval_user_query(
-            &query_module,
-            &query_node,
-            &query_schedule,
-            enable_tracing,
-        )
+        Ok(used)
+    }
+
+    /// Get the static dependencies between the rules of the loaded policies.
+    ///
+    /// An edge is reported for each rule that refers to another rule or function, along with
+    /// how it does so. The rules are listed in an order in which they can be evaluated, with
+    /// dependencies first. See also [`Engine::dependencies_to_dot`].
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.add_policy(
+    ///   "policy.rego".to_string(),
+    ///   r#"
+    ///   package test
+    ///   import rego.v1
+    ///
+    ///   allow if is_admin(input.user)
+    ///   is_admin(user) if user in data.test.admins
+    ///   admins := {"alice"}
+    ///   "#.to_string())?;
+    ///
+    /// let graph = engine.rule_dependency_graph()?;
+    /// assert_eq!(graph.rules, ["data.test.admins", "data.test.is_admin", "data.test.allow"]);
+    /// assert_eq!(
+    ///   graph.edges[0],
+    ///   DependencyEdge {
+    ///     source: "data.test.allow".to_string(),
+    ///     target: "data.test.is_admin".to_string(),
+    ///     kind: DependencyKind::Call,
+    ///   }
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn rule_dependency_graph(&self) -> Result<DependencyGraph, RegorusError> {
+        let mut engine = self.clone();
+        engine.prepare_for_eval(false)?;
+        let graph = engine.interpreter.get_rule_dependency_kinds()?;
+
+        // Order the rules depth first so that dependencies precede their dependents.
+        // Recursion has been ruled out while preparing for evaluation.
+        fn visit<'a>(
+            path: &'a String,
+            graph: &'a RuleDependencies,
+            visited: &mut BTreeSet<&'a String>,
+            rules: &mut Vec<String>,
+        ) {
+            if !visited.insert(path) {
+                return;
+            }
+            if let Some((_, deps)) = graph.get(path) {
+                for (dep, _) in deps {
+                    visit(dep, graph, visited, rules);
+                }
+            }
+            rules.push(path.clone());
+        }
+        let mut rules = vec![];
+        let mut visited = BTreeSet::new();
+        for path in graph.keys() {
+            visit(path, &graph, &mut visited, &mut rules);
+        }
+
+        let edges = graph
+            .iter()
+            .flat_map(|(source, (_, deps))| {
+                deps.iter().map(|(target, kind)| DependencyEdge {
+                    source: source.clone(),
+                    target: target.clone(),
+                    kind: *kind,
+                })
+            })
+            .collect();
+        Ok(DependencyGraph { rules, edges })
+    }
+
+    /// Render the dependencies between rules as a Graphviz DOT graph.
+    ///
+    /// Each rule path is a node and each package is a cluster. An edge `a -> b` means that
+    /// a rule at path `a` refers to a rule or function at path `b`, either directly or via a
+    /// reference into `data`.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.add_policy(
+    ///   "policy.rego".to_string(),
+    ///   r#"
+    ///   package test
+    ///   import rego.v1
+    ///
+    ///   is_admin if input.role == "admin"
+    ///   allow if is_admin
+    ///   "#.to_string())?;
+    ///
+    /// let dot = engine.dependencies_to_dot()?;
+    /// assert!(dot.contains(r#""data.test.allow" -> "data.test.is_admin";"#));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn dependencies_to_dot(&self) -> Result<String, RegorusError> {
+        let mut engine = self.clone();
+        engine.prepare_for_eval(false)?;
+        let graph = engine.interpreter.get_rule_dependency_graph()?;
+
+        let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+        let mut packages: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for (path, (package, _)) in &graph {
+            packages.entry(package).or_default().push(path);
+        }
+
+        let mut dot = "digraph dependencies {\n".to_string();
+        for (package, paths) in &packages {
+            dot += &format!("  subgraph {} {{\n", quote(&format!("cluster_{package}")));
+            dot += &format!("    label = {};\n", quote(package));
+            for path in paths {
+                dot += &format!("    {};\n", quote(path));
+            }
+            dot += "  }\n";
+        }
+        for (path, (_, deps)) in &graph {
+            for dep in deps {
+                dot += &format!("  {} -> {};\n", quote(path), quote(dep));
+            }
+        }
+        dot += "}\n";
+        Ok(dot)
+    }
+
+    /// Get the rules that no entrypoint depends on and that no other rule refers to.
+    ///
+    /// Rules named `allow`, `deny` or `main` are considered entrypoints and are never
+    /// reported, nor is any rule they depend on directly or indirectly. The remaining rules are
+    /// reported unless referred to by some other rule. Dependencies are determined statically as
+    /// in [`Engine::dependencies_to_dot`]. Rules that are only queried directly by the
+    /// application are reported too and may be kept deliberately.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.add_policy(
+    ///   "policy.rego".to_string(),
+    ///   r#"
+    ///   package test
+    ///   import rego.v1
+    ///
+    ///   allow if is_admin
+    ///   is_admin if input.role == "admin"
+    ///   is_guest if input.role == "guest"
+    ///   "#.to_string())?;
+    ///
+    /// assert_eq!(engine.unused_rules()?, ["data.test.is_guest"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn unused_rules(&self) -> Result<Vec<String>, RegorusError> {
+        let mut engine = self.clone();
+        engine.prepare_for_eval(false)?;
+        let graph = engine.interpreter.get_rule_dependency_graph()?;
+
+        // Rules reachable from entrypoints.
+        let mut reachable = BTreeSet::new();
+        let mut pending: Vec<&String> = graph
+            .keys()
+            .filter(|path| {
+                let name = path.rsplit('.').next().unwrap_or_default();
+                ENTRYPOINT_RULES.contains(&name)
+            })
+            .collect();
+        while let Some(path) = pending.pop() {
+            if reachable.insert(path) {
+                if let Some((_, deps)) = graph.get(path) {
+                    pending.extend(deps.iter());
+                }
+            }
+        }
+
+        let referenced: BTreeSet<&String> = graph.values().flat_map(|(_, deps)| deps).collect();
+        Ok(graph
+            .keys()
+            .filter(|path| !reachable.contains(path) && !referenced.contains(path))
+            .cloned()
+            .collect())
     }
 
     /// Evaluate a Rego query that produces a boolean value.
@@ -495,14 +3344,18 @@ impl Engine {
     /// # Ok(())
     /// # }
     /// ```
-    pub fn eval_bool_query(&mut self, query: String, enable_tracing: bool) -> Result<bool> {
+    pub fn eval_bool_query(
+        &mut self,
+        query: String,
+        enable_tracing: bool,
+    ) -> Result<bool, RegorusError> {
         let results = self.eval_query(query, enable_tracing)?;
         match results.result.len() {
-            0 => bail!("query did not produce any values"),
+            0 => Err(anyhow!("query did not produce any values").into()),
             1 if results.result[0].expressions.len() == 1 => {
-                results.result[0].expressions[0].value.as_bool().copied()
+                Ok(results.result[0].expressions[0].value.as_bool().copied()?)
             }
-            _ => bail!("query produced more than one value"),
+            _ => Err(anyhow!("query produced more than one value").into()),
         }
     }
 
@@ -559,7 +3412,7 @@ impl Engine {
         &mut self,
         query: String,
         enable_tracing: bool,
-    ) -> Result<QueryResults> {
+    ) -> Result<QueryResults, RegorusError> {
         self.eval_modules(enable_tracing)?;
 
         let query_module = {
@@ -574,13 +3427,15 @@ impl Engine {
         let query_source = Source::from_contents("<query.rego>".to_string(), query)?;
         let mut parser = self.make_parser(&query_source)?;
         let query_node = parser.parse_user_query()?;
-        let query_schedule = Analyzer::new().analyze_query_snippet(&self.modules, &query_node)?;
-        self.interpreter.eval_user_query(
+        let query_schedule = Analyzer::new()
+            .analyze_query_snippet(&self.modules, &query_node)
+            .map_err(classify(ErrorKind::Compile))?;
+        Ok(self.interpreter.eval_user_query(
             &query_module,
             &query_node,
             &query_schedule,
             enable_tracing,
-        )
+        )?)
     }
 
     #[doc(hidden)]
@@ -591,21 +3446,29 @@ impl Engine {
         if !self.prepared {
             // Analyze the modules and determine how statements must be scheduled.
             let analyzer = Analyzer::new();
-            let schedule = analyzer.analyze(&self.modules)?;
+            let schedule = analyzer
+                .analyze(&self.modules)
+                .map_err(classify(ErrorKind::Compile))?;
 
             self.interpreter.set_schedule(Some(schedule));
             self.interpreter.set_modules(&self.modules);
 
             self.interpreter.clear_builtins_cache();
+            self.interpreter.clear_input_independent_rules_cache();
             // clean_internal_evaluation_state will set data to an efficient clont of use supplied init_data
             // Initialize the with-document with initial data values.
             // with-modifiers will be applied to this document.
             self.interpreter.init_with_document()?;
 
+            let compile_error = classify(ErrorKind::Compile);
+            self.interpreter
+                .set_functions(gather_functions(&self.modules).map_err(&compile_error)?);
+            self.interpreter.gather_rules().map_err(&compile_error)?;
+            self.interpreter.process_imports().map_err(&compile_error)?;
             self.interpreter
-                .set_functions(gather_functions(&self.modules)?);
-            self.interpreter.gather_rules()?;
-            self.interpreter.process_imports()?;
+                .check_function_calls()
+                .map_err(&compile_error)?;
+            self.interpreter.check_rule_recursion()?;
             self.prepared = true;
         }
 
@@ -618,7 +3481,7 @@ impl Engine {
         module: &Ref<Module>,
         rule: &Ref<Rule>,
         enable_tracing: bool,
-    ) -> Result<Value> {
+    ) -> Result<Value, RegorusError> {
         self.prepare_for_eval(enable_tracing)?;
         self.interpreter.clean_internal_evaluation_state();
 
@@ -628,7 +3491,12 @@ impl Engine {
     }
 
     #[doc(hidden)]
-    pub fn eval_modules(&mut self, enable_tracing: bool) -> Result<Value> {
+    pub fn eval_modules(&mut self, enable_tracing: bool) -> Result<Value, RegorusError> {
+        self.eval_modules_impl(enable_tracing)
+            .map_err(|e| self.format_error(e))
+    }
+
+    fn eval_modules_impl(&mut self, enable_tracing: bool) -> Result<Value> {
         self.prepare_for_eval(enable_tracing)?;
         self.interpreter.clean_internal_evaluation_state();
 
@@ -675,7 +3543,8 @@ impl Engine {
     /// Add a custom builtin (extension).
     ///
     /// * `path`: The fully qualified path of the builtin.
-    /// * `nargs`: The number of arguments the builtin takes.
+    /// * `nargs`: The number of arguments the builtin takes, or `None` if it is variadic.
+    ///   Calls with a different number of arguments fail before the extension is invoked.
     /// * `extension`: The [`Extension`] instance.
     ///
     /// ```rust
@@ -697,7 +3566,7 @@ impl Engine {
     ///
     /// // Add extension to implement `do_magic`. The extension can be stateful.
     /// let mut magic = 8;
-    /// engine.add_extension("do_magic".to_string(), 1 , Box::new(move | mut params: Vec<Value> | {
+    /// engine.add_extension("do_magic".to_string(), Some(1), Box::new(move | mut params: Vec<Value> | {
     ///   // params is mut and therefore individual values can be removed from it and modified.
     ///   // The number of parameters (1) has already been validated.
     ///
@@ -731,10 +3600,31 @@ impl Engine {
     /// assert_eq!(r.result[0].expressions[0].value.as_i64()?, 10);
     ///
     /// // Once added, the extension cannot be replaced or removed.
-    /// assert!(engine.add_extension("do_magic".to_string(), 1, Box::new(|_:Vec<Value>| {
+    /// assert!(engine.add_extension("do_magic".to_string(), Some(1), Box::new(|_:Vec<Value>| {
     ///   Ok(Value::Undefined)
     /// })).is_err());
     ///
+    /// // Calls with the wrong number of arguments are rejected.
+    /// engine.add_policy(
+    ///   "arity.rego".to_string(),
+    ///   r#"package arity
+    ///      x = do_magic(1, 2)
+    ///   "#.to_string()
+    /// )?;
+    /// let err = engine.eval_query("data.arity.x".to_string(), false).unwrap_err();
+    /// assert!(err.to_string().contains("do_magic: expected 1 argument, got 2"));
+    ///
+    /// // Variadic extensions skip the check.
+    /// engine.add_extension("add_all".to_string(), None, Box::new(|params: Vec<Value>| {
+    ///   let mut total = 0;
+    ///   for p in &params {
+    ///     total += p.as_i64()?;
+    ///   }
+    ///   Ok(Value::from(total))
+    /// }))?;
+    /// let r = engine.eval_query("add_all(1, 2, 3)".to_string(), false)?;
+    /// assert_eq!(r.result[0].expressions[0].value.as_i64()?, 6);
+    ///
     /// // Extensions don't support out-parameter syntax.
     /// engine.add_policy(
     ///   "policy.rego".to_string(),
@@ -754,10 +3644,154 @@ impl Engine {
     pub fn add_extension(
         &mut self,
         path: String,
-        nargs: u8,
+        nargs: Option<u8>,
         extension: Box<dyn Extension>,
-    ) -> Result<()> {
-        self.interpreter.add_extension(path, nargs, extension)
+    ) -> Result<(), RegorusError> {
+        Ok(self.interpreter.add_extension(path, nargs, extension)?)
+    }
+
+    /// Set a callback that is invoked before each builtin call.
+    ///
+    /// The callback receives the name of the builtin and its arguments.
+    /// Calls with undefined arguments are not evaluated and hence not logged.
+    /// Extensions and user defined functions are not logged.
+    ///
+    /// ```rust
+    /// # use regorus::*;
+    /// # use std::sync::{Arc, Mutex};
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    ///
+    /// let calls = Arc::new(Mutex::new(vec![]));
+    /// let log = calls.clone();
+    /// engine.set_builtin_call_logger(Box::new(move |name: &str, args: &[Value]| {
+    ///   let args: Vec<String> = args.iter().map(Value::to_string).collect();
+    ///   log.lock().unwrap().push(format!("{name}({})", args.join(", ")));
+    /// }));
+    ///
+    /// engine.eval_query("upper(\"hello\")".to_string(), false)?;
+    /// assert_eq!(calls.lock().unwrap()[0], r#"upper("hello")"#);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_builtin_call_logger(&mut self, logger: Box<dyn BuiltinCallLogger>) {
+        self.interpreter.set_builtin_call_logger(Some(logger))
+    }
+
+    /// Remove the callback set via [`Engine::set_builtin_call_logger`].
+    pub fn clear_builtin_call_logger(&mut self) {
+        self.interpreter.set_builtin_call_logger(None)
+    }
+
+    /// Set a callback that renders the values passed to `print`.
+    ///
+    /// The callback is used for all values other than strings, which are printed as is, and
+    /// undefined values, which are printed as `<undefined>`. By default, arrays, sets and objects
+    /// are printed in Rego notation.
+    ///
+    /// ```rust
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.set_gather_prints(true);
+    /// engine.set_print_value_formatter(Box::new(|v: &Value| {
+    ///   serde_json::to_string(v).unwrap_or_default()
+    /// }));
+    ///
+    /// engine.eval_query(r#"print("value:", {"a": {1}})"#.to_string(), false)?;
+    /// assert_eq!(engine.take_prints()?, vec![r#"<query.rego>:1: value: {"a":[1]}"#]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_print_value_formatter(&mut self, formatter: Box<dyn PrintValueFormatter>) {
+        self.interpreter.set_print_value_formatter(Some(formatter))
+    }
+
+    /// Remove the callback set via [`Engine::set_print_value_formatter`].
+    pub fn clear_print_value_formatter(&mut self) {
+        self.interpreter.set_print_value_formatter(None)
+    }
+
+    /// Memoize the results of builtins across calls and evaluations.
+    ///
+    /// When enabled, the result of a builtin is cached by the builtin's name and arguments and
+    /// reused by subsequent calls with the same arguments, avoiding repeated work such as regex compilation.
+    /// Builtins whose results do not depend only on their arguments (e.g. `time.now_ns`, `rand.intn`,
+    /// `http.send`) are never memoized. The cache retains the results of up to 10000 calls, evicting
+    /// the least recently used results beyond that. It is cleared when policies change or
+    /// memoization is disabled.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.set_memoize_builtins(true);
+    ///
+    /// for _ in 0..3 {
+    ///   engine.eval_query(r#"upper("abc")"#.to_string(), false)?;
+    /// }
+    /// assert_eq!(engine.get_memoized_builtin_hits(), 2);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_memoize_builtins(&mut self, memoize: bool) {
+        self.interpreter.set_memoize_builtins(memoize)
+    }
+
+    /// Clear the cache of compiled regexes.
+    ///
+    /// Regexes used by the `regex.*` builtins are compiled once and reused across evaluations.
+    /// Each engine has its own cache, which is bounded and evicts the least recently used
+    /// regexes when full.
+    #[cfg(feature = "regex")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "regex")))]
+    pub fn clear_regex_cache(&mut self) {
+        self.interpreter.clear_regex_cache()
+    }
+
+    /// Get the number of builtin calls served from the memoization cache.
+    ///
+    /// See [`Engine::set_memoize_builtins`].
+    pub fn get_memoized_builtin_hits(&self) -> u64 {
+        self.interpreter.get_memoized_builtin_hits()
+    }
+
+    /// Cache the values of rules that do not depend on input across evaluations.
+    ///
+    /// A rule is input independent if it does not refer to `input`, does not call extensions or
+    /// builtins whose results vary across calls (e.g. `time.now_ns`, `print`), and every rule
+    /// and function it refers to is input independent. When enabled, such rules are evaluated
+    /// once and their values reused by subsequent evaluations with different inputs, which
+    /// avoids repeating expensive preprocessing of `data` when evaluating a batch of inputs.
+    /// Rules evaluated within `with` modifiers are not cached.
+    /// The cache is cleared when policies or data change or caching is disabled.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.set_cache_input_independent_rules(true);
+    ///
+    /// engine.add_data(Value::from_json_str(r#"{"users": ["alice", "bob"]}"#)?)?;
+    /// engine.add_policy(
+    ///   "policy.rego".to_string(),
+    ///   r#"
+    ///   package test
+    ///   import rego.v1
+    ///
+    ///   names := {upper(u) | some u in data.users}
+    ///   allow if upper(input.user) in names
+    ///   "#.to_string())?;
+    ///
+    /// // names is evaluated only for the first input.
+    /// let inputs = Value::from_json_str(r#"[{"user": "bob"}, {"user": "eve"}]"#)?;
+    /// let results = engine.eval_rule_over_array("data.test.allow".to_string(), inputs)?;
+    /// assert_eq!(results, vec![Value::from(true), Value::Undefined]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_cache_input_independent_rules(&mut self, cache: bool) {
+        self.interpreter.set_cache_input_independent_rules(cache)
     }
 
     #[cfg(feature = "coverage")]
@@ -800,8 +3834,8 @@ impl Engine {
     /// ```
     ///
     /// See also [`crate::coverage::Report::to_colored_string`].
-    pub fn get_coverage_report(&self) -> Result<crate::coverage::Report> {
-        self.interpreter.get_coverage_report()
+    pub fn get_coverage_report(&self) -> Result<crate::coverage::Report, RegorusError> {
+        Ok(self.interpreter.get_coverage_report()?)
     }
 
     #[cfg(feature = "coverage")]
@@ -818,9 +3852,112 @@ impl Engine {
     #[cfg_attr(docsrs, doc(cfg(feature = "coverage")))]
     /// Clear the gathered policy coverage data.
     pub fn clear_coverage_data(&mut self) {
+        self.coverage_snapshot.clear();
         self.interpreter.clear_coverage_data()
     }
 
+    #[cfg(feature = "coverage")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "coverage")))]
+    /// Merge the coverage gathered by another engine into this engine.
+    ///
+    /// Covered lines of policies loaded in both engines are unioned. Lines that are not covered
+    /// are recomputed from the merged data when [`Engine::get_coverage_report`] is called.
+    /// Coverage of policies that are not loaded in this engine is ignored.
+    ///
+    /// Useful for gathering coverage from clones of an engine that evaluate shards of a batch.
+    ///
+    /// ```rust
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.add_policy(
+    ///    "policy.rego".to_string(),
+    ///    r#"
+    /// package test      # Line 2
+    /// import rego.v1
+    ///
+    /// small if input.n < 10    # Line 5
+    /// large if input.n >= 10   # Line 6
+    ///    "#.to_string()
+    /// )?;
+    /// engine.set_enable_coverage(true);
+    ///
+    /// let mut shard = engine.clone();
+    /// shard.set_input(Value::from_json_str(r#"{"n": 50}"#)?);
+    /// shard.eval_rule("data.test.large".to_string())?;
+    ///
+    /// engine.set_input(Value::from_json_str(r#"{"n": 5}"#)?);
+    /// engine.eval_rule("data.test.small".to_string())?;
+    /// engine.merge_coverage_from(&shard)?;
+    ///
+    /// let report = engine.get_coverage_report()?;
+    /// assert_eq!(report.files[0].covered, [5, 6].into());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn merge_coverage_from(&mut self, other: &Engine) -> Result<(), RegorusError> {
+        // Ensure that the interpreter knows about the policies, even if nothing has been evaluated yet.
+        self.prepare_for_eval(false)?;
+        self.interpreter.merge_coverage(&other.interpreter);
+        Ok(())
+    }
+
+    #[cfg(feature = "coverage")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "coverage")))]
+    /// Get the lines newly covered since the previous call.
+    ///
+    /// The returned report has the same shape as [`Engine::get_coverage_report`],
+    /// except that `covered` holds only the lines that were first covered after the
+    /// previous call to this method (or after [`Engine::clear_coverage_data`]).
+    /// `not_covered` holds the lines that are still not covered.
+    ///
+    /// Useful for finding the inputs that contribute new coverage in a batch.
+    ///
+    /// ```rust
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.add_policy(
+    ///    "policy.rego".to_string(),
+    ///    r#"
+    /// package test      # Line 2
+    /// import rego.v1
+    ///
+    /// small if {        # Line 5
+    ///   input.n < 10    # Line 6
+    /// }
+    ///
+    /// large if {        # Line 9
+    ///   input.n >= 10   # Line 10
+    ///   input.n > 100   # Line 11
+    /// }
+    ///    "#.to_string()
+    /// )?;
+    /// engine.set_enable_coverage(true);
+    ///
+    /// engine.set_input(Value::from_json_str(r#"{"n": 50}"#)?);
+    /// engine.eval_query("data".to_string(), false)?;
+    /// let delta = engine.coverage_delta_since_last()?;
+    /// assert_eq!(delta.files[0].covered.iter().cloned().collect::<Vec<u32>>(), vec![6, 10, 11]);
+    ///
+    /// // This input only covers lines that have already been covered.
+    /// engine.set_input(Value::from_json_str(r#"{"n": 70}"#)?);
+    /// engine.eval_query("data".to_string(), false)?;
+    /// assert!(engine.coverage_delta_since_last()?.files[0].covered.is_empty());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn coverage_delta_since_last(&mut self) -> Result<crate::coverage::Report, RegorusError> {
+        let mut report = self.interpreter.get_coverage_report()?;
+        for file in report.files.iter_mut() {
+            let covered = core::mem::take(&mut file.covered);
+            let previous = self.coverage_snapshot.entry(file.path.clone()).or_default();
+            file.covered = covered.difference(previous).cloned().collect();
+            *previous = covered;
+        }
+        Ok(report)
+    }
+
     /// Gather output from print statements instead of emiting to stderr.
     ///
     /// See [`Engine::take_prints`].    
@@ -856,8 +3993,79 @@ impl Engine {
     /// # Ok(())
     /// # }
     /// ```
-    pub fn take_prints(&mut self) -> Result<Vec<String>> {
-        self.interpreter.take_prints()
+    pub fn take_prints(&mut self) -> Result<Vec<String>, RegorusError> {
+        Ok(self.interpreter.take_prints()?)
+    }
+
+    /// Take the gathered output of print statements, grouped by input.
+    ///
+    /// A new group is started each time the input is set via [`Engine::set_input`]
+    /// and for each element evaluated by [`Engine::eval_rule_over_array`].
+    /// Inputs whose evaluations did not print anything have empty groups.
+    /// Prints gathered before any input was set form a leading group.
+    /// The gathered prints are cleared.
+    ///
+    /// ```rust
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.set_gather_prints(true);
+    ///
+    /// for x in [1, 2, 3] {
+    ///   engine.set_input(Value::from(x));
+    ///   engine.eval_query("input != 2; print(input)".to_string(), false)?;
+    /// }
+    ///
+    /// let prints = engine.take_prints_grouped()?;
+    /// assert_eq!(prints.len(), 3);
+    /// assert!(prints[0][0].contains("1"));
+    /// assert!(prints[1].is_empty());
+    /// assert!(prints[2][0].contains("3"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn take_prints_grouped(&mut self) -> Result<Vec<Vec<String>>, RegorusError> {
+        Ok(self.interpreter.take_prints_grouped()?)
+    }
+
+    /// Gather the argument values of print statements.
+    ///
+    /// This is independent of [`Engine::set_gather_prints`]; formatted output continues to be
+    /// gathered or emitted to stderr as configured. If `b` is different from the current value,
+    /// any gathered values are cleared.
+    ///
+    /// See [`Engine::take_print_values`].
+    pub fn set_gather_print_values(&mut self, b: bool) {
+        self.interpreter.set_gather_print_values(b);
+    }
+
+    /// Take the gathered argument values of print statements.
+    ///
+    /// Each element holds the values of the arguments of one print statement, in order.
+    /// Undefined arguments are [`Value::Undefined`]. The gathered values are cleared.
+    ///
+    /// ```rust
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.set_gather_prints(true);
+    /// engine.set_gather_print_values(true);
+    ///
+    /// engine.eval_query(r#"x := 5; print("latency", x * 2, {"ok": true})"#.to_string(), false)?;
+    ///
+    /// let values = engine.take_print_values();
+    /// assert_eq!(values.len(), 1);
+    /// assert_eq!(values[0][0], Value::from("latency"));
+    /// assert_eq!(values[0][1], Value::from(10));
+    /// assert_eq!(values[0][2]["ok"], Value::from(true));
+    ///
+    /// // Formatted output is still available.
+    /// assert!(engine.take_prints()?[0].ends_with("latency 10 {\"ok\": true}"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn take_print_values(&mut self) -> Vec<Vec<Value>> {
+        self.interpreter.take_print_values()
     }
 
     /// Get the policies and corresponding AST.
@@ -879,7 +4087,7 @@ impl Engine {
     /// ```
     #[cfg(feature = "ast")]
     #[cfg_attr(docsrs, doc(cfg(feature = "ast")))]
-    pub fn get_ast_as_json(&self) -> Result<String> {
+    pub fn get_ast_as_json(&self) -> Result<String, RegorusError> {
         #[derive(Serialize)]
         struct Policy<'a> {
             source: &'a Source,
@@ -895,7 +4103,90 @@ impl Engine {
             });
         }
 
-        serde_json::to_string_pretty(&ast).map_err(anyhow::Error::msg)
+        Ok(serde_json::to_string_pretty(&ast).map_err(anyhow::Error::msg)?)
+    }
+
+    /// Parse a query and get its AST.
+    ///
+    /// The query is only parsed, not evaluated; syntax errors are returned as errors.
+    /// The AST uses the same representation as [`Engine::get_ast_as_json`].
+    ///
+    /// ```rust
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let engine = Engine::new();
+    ///
+    /// let ast = engine.parse_query_ast("x = [1, 2][_]".to_string())?;
+    /// let expr = &ast["stmts"][0]["literal"]["Expr"]["expr"]["AssignExpr"];
+    /// assert_eq!(expr["op"], Value::from("Eq"));
+    /// assert_eq!(expr["lhs"]["Var"][1], Value::from("x"));
+    ///
+    /// assert!(engine.parse_query_ast("x = [1, 2".to_string()).is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "ast")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "ast")))]
+    pub fn parse_query_ast(&self, query: String) -> Result<Value, RegorusError> {
+        let query_source = Source::from_contents("<query.rego>".to_string(), query)?;
+        let mut parser = self.make_parser(&query_source)?;
+        let query_node = parser.parse_user_query()?;
+        Ok(Value::from(
+            serde_json::to_value(&*query_node).map_err(anyhow::Error::msg)?,
+        ))
+    }
+
+    /// Get a snapshot of the internal state of the engine for debugging.
+    ///
+    /// The snapshot includes the loaded policies, the top-level keys of the data document,
+    /// configuration flags and the sizes of internal caches.
+    ///
+    /// ----
+    /// **_NOTE:_** The output is a diagnostic aid meant to be included in bug reports.
+    /// Its format is not stable and must not be parsed.
+    /// ----
+    ///
+    /// ```rust
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// engine.add_policy("test.rego".to_string(), "package test\n x := 1".to_string())?;
+    ///
+    /// let state = engine.dump_state();
+    /// assert!(state.contains("test.rego: data.test"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn dump_state(&self) -> String {
+        let mut s = String::default();
+        s.push_str("ENGINE STATE:\n");
+        s.push_str("modules:\n");
+        for m in &self.modules {
+            let package = Interpreter::get_path_string(&m.package.refr, Some("data"))
+                .unwrap_or_else(|e| format!("<{e}>"));
+            s.push_str(&format!(
+                "  {}: {package}\n",
+                m.package.refr.span().source.get_path()
+            ));
+        }
+        s.push_str(&format!("prepared: {}\n", self.prepared));
+        s.push_str(&format!("rego.v1: {}\n", self.rego_v1));
+        self.interpreter.dump_state(&mut s);
+        s
+    }
+
+    fn add_source(&mut self, source: Source) -> Result<String> {
+        let module = self.add_source_module(source)?;
+        Interpreter::get_path_string(&module.package.refr, Some("data"))
+    }
+
+    fn add_source_module(&mut self, source: Source) -> Result<Ref<Module>> {
+        let mut parser = self.make_parser(&source)?;
+        let module = Ref::new(parser.parse().map_err(|e| self.format_error(e))?);
+        self.modules.push(module.clone());
+        // if policies change, interpreter needs to be prepared again
+        self.prepared = false;
+        Ok(module)
     }
 
     fn make_parser<'a>(&self, source: &'a Source) -> Result<Parser<'a>> {
@@ -903,6 +4194,175 @@ impl Engine {
         if self.rego_v1 {
             parser.enable_rego_v1()?;
         }
+        parser.set_max_literal_elements(self.max_literal_elements);
         Ok(parser)
     }
 }
+
+// Adapts a `core::fmt::Write` for use with serializers that write bytes.
+#[cfg(feature = "std")]
+struct FmtWriter<'a>(&'a mut dyn core::fmt::Write);
+
+// Passes the results of user queries to a callback while alive.
+#[cfg(feature = "std")]
+struct QueryResultStreamGuard<'a>(&'a mut Engine);
+
+#[cfg(feature = "std")]
+impl<'a> QueryResultStreamGuard<'a> {
+    fn new(
+        engine: &'a mut Engine,
+        out: &'a mut (dyn FnMut(QueryResult) -> Result<()> + 'a),
+    ) -> Self {
+        // SAFETY: the callback is removed when the guard is dropped, which happens before `out`
+        // is released since the guard does not outlive it.
+        unsafe { engine.interpreter.set_query_result_stream(out) };
+        Self(engine)
+    }
+}
+
+#[cfg(feature = "std")]
+impl Drop for QueryResultStreamGuard<'_> {
+    fn drop(&mut self) {
+        self.0.interpreter.clear_query_result_stream();
+    }
+}
+
+// Writes query results as they are produced.
+#[cfg(feature = "std")]
+struct QueryResultsWriter<'a> {
+    w: &'a mut dyn core::fmt::Write,
+    number_output: NumberOutput,
+    count: usize,
+}
+
+#[cfg(feature = "std")]
+impl QueryResultsWriter<'_> {
+    fn write_result(&mut self, result: QueryResult) -> Result<()> {
+        use std::io::Write;
+
+        let mut writer = FmtWriter(self.w);
+        writer.write_all(match self.count {
+            0 => b"{\"result\":[",
+            _ => b",",
+        })?;
+        self.count += 1;
+        let mut serializer = serde_json::Serializer::with_formatter(
+            &mut writer,
+            NumberFormatter(self.number_output),
+        );
+        Ok(result.serialize(&mut serializer)?)
+    }
+
+    fn finish(self) -> Result<()> {
+        Ok(self.w.write_str(match self.count {
+            0 => "{}",
+            _ => "]}",
+        })?)
+    }
+}
+
+// Renders numbers as per the number output setting while serializing.
+#[cfg(feature = "std")]
+struct NumberFormatter(NumberOutput);
+
+#[cfg(feature = "std")]
+impl serde_json::ser::Formatter for NumberFormatter {
+    fn write_i64<W: ?Sized + std::io::Write>(
+        &mut self,
+        w: &mut W,
+        value: i64,
+    ) -> std::io::Result<()> {
+        match self.0 {
+            NumberOutput::AlwaysFloat => self.write_f64(w, value as f64),
+            _ => serde_json::ser::CompactFormatter.write_i64(w, value),
+        }
+    }
+
+    fn write_u64<W: ?Sized + std::io::Write>(
+        &mut self,
+        w: &mut W,
+        value: u64,
+    ) -> std::io::Result<()> {
+        match self.0 {
+            NumberOutput::AlwaysFloat => self.write_f64(w, value as f64),
+            _ => serde_json::ser::CompactFormatter.write_u64(w, value),
+        }
+    }
+
+    fn write_f64<W: ?Sized + std::io::Write>(
+        &mut self,
+        w: &mut W,
+        value: f64,
+    ) -> std::io::Result<()> {
+        match self.0 {
+            NumberOutput::IntegerWhenWhole if value.is_finite() && value.fract() == 0.0 => {
+                w.write_all(format!("{value:.0}").as_bytes())
+            }
+            _ => serde_json::ser::CompactFormatter.write_f64(w, value),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::io::Write for FmtWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let s = core::str::from_utf8(buf)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        self.0.write_str(s).map_err(std::io::Error::other)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Evaluate a rule in two engines for each input and report where their values differ.
+///
+/// Intended for shadow-testing a new version of a policy against the current one before
+/// promoting it. Each engine is prepared once and reused across all inputs, as in
+/// [`Engine::eval_rule_over_array`]. The input of each engine is restored afterwards.
+///
+/// ```
+/// # use regorus::*;
+/// # fn main() -> anyhow::Result<()> {
+/// let mut old = Engine::new();
+/// old.add_policy(
+///   "policy.rego".to_string(),
+///   "package example\nallow := input.role == \"admin\"".to_string())?;
+///
+/// let mut new = Engine::new();
+/// new.add_policy(
+///   "policy.rego".to_string(),
+///   "package example\nimport rego.v1\nallow := input.role in {\"admin\", \"owner\"}".to_string())?;
+///
+/// let inputs = Value::from_json_str(r#"[{"role": "admin"}, {"role": "owner"}]"#)?;
+/// let divergences = compare_policies(&mut old, &mut new, "data.example.allow", inputs)?;
+/// assert_eq!(divergences, vec![Divergence {
+///   input_index: 1,
+///   old: Value::from(false),
+///   new: Value::from(true),
+/// }]);
+/// # Ok(())
+/// # }
+/// ```
+pub fn compare_policies(
+    old: &mut Engine,
+    new: &mut Engine,
+    path: &str,
+    inputs: Value,
+) -> Result<Vec<Divergence>, RegorusError> {
+    let old_values = old.eval_rule_over_array(path.to_string(), inputs.clone())?;
+    let new_values = new.eval_rule_over_array(path.to_string(), inputs)?;
+    Ok(old_values
+        .into_iter()
+        .zip(new_values)
+        .enumerate()
+        .filter(|(_, (old, new))| old != new)
+        .map(|(input_index, (old, new))| Divergence {
+            input_index,
+            old,
+            new,
+        })
+        .collect())
+}
@@ -0,0 +1,361 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use crate::*;
+
+/// Location in a policy or query at which an error was detected.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ErrorLocation {
+    /// Name of the policy file or query.
+    pub file: String,
+    /// Line number. Starts at 1.
+    pub line: u32,
+    /// Column number. Starts at 1.
+    pub col: u32,
+}
+
+impl fmt::Display for ErrorLocation {
+    /// Renders the location as `file:line:col`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.col)
+    }
+}
+
+/// Structured error produced by [`crate::Engine`].
+///
+/// Engine methods return `RegorusError`. It implements [`core::error::Error`] and hence
+/// converts into [`anyhow::Error`], from which it can be retrieved via
+/// [`anyhow::Error::downcast_ref`].
+/// Each variant carries the rendered message, the message without the annotated source
+/// snippet and the location of the error, if known.
+/// [`RegorusError::code`] returns a stable identifier for the kind of error.
+///
+/// ```
+/// # use regorus::*;
+/// # fn main() -> anyhow::Result<()> {
+/// let mut engine = Engine::new();
+/// let err = engine
+///     .add_policy("test.rego".to_string(), "package test\nx := ".to_string())
+///     .unwrap_err();
+///
+/// assert_eq!(err.code(), "rego_parse_error");
+/// assert_eq!(err.location().map(|l| l.line), Some(2));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum RegorusError {
+    /// Syntax error in a policy or query.
+    Parse {
+        message: String,
+        reason: String,
+        location: Option<ErrorLocation>,
+    },
+    /// Policy or query that is syntactically valid but cannot be compiled.
+    Compile {
+        message: String,
+        reason: String,
+        location: Option<ErrorLocation>,
+    },
+    /// Rules that depend on themselves.
+    Recursion {
+        message: String,
+        reason: String,
+        location: Option<ErrorLocation>,
+    },
+    /// Rules or functions producing conflicting values.
+    Conflict {
+        message: String,
+        reason: String,
+        location: Option<ErrorLocation>,
+    },
+    /// Builtin called with arguments of the wrong type or number.
+    Type {
+        message: String,
+        reason: String,
+        location: Option<ErrorLocation>,
+    },
+    /// Builtin that failed during evaluation.
+    Builtin {
+        message: String,
+        reason: String,
+        location: Option<ErrorLocation>,
+    },
+    /// Evaluation cancelled via [`crate::Engine::eval_query_cancellable`].
+    Cancelled {
+        message: String,
+        reason: String,
+        location: Option<ErrorLocation>,
+    },
+    /// Evaluation nested deeper than allowed by [`crate::Engine::set_max_eval_depth`].
+    RecursionLimit {
+        message: String,
+        reason: String,
+        location: Option<ErrorLocation>,
+    },
+    /// Any other evaluation error.
+    Eval {
+        message: String,
+        reason: String,
+        location: Option<ErrorLocation>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum ErrorKind {
+    Parse,
+    Compile,
+    Recursion,
+    Conflict,
+    Type,
+    Builtin,
+    Cancelled,
+    RecursionLimit,
+    Eval,
+}
+
+impl RegorusError {
+    pub(crate) fn new(
+        kind: ErrorKind,
+        message: String,
+        reason: String,
+        location: Option<ErrorLocation>,
+    ) -> Self {
+        match kind {
+            ErrorKind::Parse => Self::Parse {
+                message,
+                reason,
+                location,
+            },
+            ErrorKind::Compile => Self::Compile {
+                message,
+                reason,
+                location,
+            },
+            ErrorKind::Recursion => Self::Recursion {
+                message,
+                reason,
+                location,
+            },
+            ErrorKind::Conflict => Self::Conflict {
+                message,
+                reason,
+                location,
+            },
+            ErrorKind::Type => Self::Type {
+                message,
+                reason,
+                location,
+            },
+            ErrorKind::Builtin => Self::Builtin {
+                message,
+                reason,
+                location,
+            },
+            ErrorKind::Cancelled => Self::Cancelled {
+                message,
+                reason,
+                location,
+            },
+            ErrorKind::RecursionLimit => Self::RecursionLimit {
+                message,
+                reason,
+                location,
+            },
+            ErrorKind::Eval => Self::Eval {
+                message,
+                reason,
+                location,
+            },
+        }
+    }
+
+    pub(crate) fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Parse { .. } => ErrorKind::Parse,
+            Self::Compile { .. } => ErrorKind::Compile,
+            Self::Recursion { .. } => ErrorKind::Recursion,
+            Self::Conflict { .. } => ErrorKind::Conflict,
+            Self::Type { .. } => ErrorKind::Type,
+            Self::Builtin { .. } => ErrorKind::Builtin,
+            Self::Cancelled { .. } => ErrorKind::Cancelled,
+            Self::RecursionLimit { .. } => ErrorKind::RecursionLimit,
+            Self::Eval { .. } => ErrorKind::Eval,
+        }
+    }
+
+    /// Stable identifier of the kind of error, e.g. `rego_parse_error`.
+    pub fn code(&self) -> &'static str {
+        match self.kind() {
+            ErrorKind::Parse => "rego_parse_error",
+            ErrorKind::Compile => "rego_compile_error",
+            ErrorKind::Recursion => "rego_recursion_error",
+            ErrorKind::Conflict => "eval_conflict_error",
+            ErrorKind::Type => "eval_type_error",
+            ErrorKind::Builtin => "eval_builtin_error",
+            ErrorKind::Cancelled => "eval_cancel_error",
+            ErrorKind::RecursionLimit => "eval_recursion_limit",
+            ErrorKind::Eval => "eval_error",
+        }
+    }
+
+    /// The error message, including the annotated source snippet if any.
+    pub fn message(&self) -> &str {
+        match self {
+            Self::Parse { message, .. }
+            | Self::Compile { message, .. }
+            | Self::Recursion { message, .. }
+            | Self::Conflict { message, .. }
+            | Self::Type { message, .. }
+            | Self::Builtin { message, .. }
+            | Self::Cancelled { message, .. }
+            | Self::RecursionLimit { message, .. }
+            | Self::Eval { message, .. } => message,
+        }
+    }
+
+    /// The error message without the annotated source snippet.
+    ///
+    /// Related locations mentioned by the message are rendered as `file:line:col`.
+    pub fn reason(&self) -> &str {
+        match self {
+            Self::Parse { reason, .. }
+            | Self::Compile { reason, .. }
+            | Self::Recursion { reason, .. }
+            | Self::Conflict { reason, .. }
+            | Self::Type { reason, .. }
+            | Self::Builtin { reason, .. }
+            | Self::Cancelled { reason, .. }
+            | Self::RecursionLimit { reason, .. }
+            | Self::Eval { reason, .. } => reason,
+        }
+    }
+
+    /// Location at which the error was detected.
+    pub fn location(&self) -> Option<&ErrorLocation> {
+        match self {
+            Self::Parse { location, .. }
+            | Self::Compile { location, .. }
+            | Self::Recursion { location, .. }
+            | Self::Conflict { location, .. }
+            | Self::Type { location, .. }
+            | Self::Builtin { location, .. }
+            | Self::Cancelled { location, .. }
+            | Self::RecursionLimit { location, .. }
+            | Self::Eval { location, .. } => location.as_ref(),
+        }
+    }
+
+    fn into_parts(self) -> (String, String, Option<ErrorLocation>) {
+        match self {
+            Self::Parse {
+                message,
+                reason,
+                location,
+            }
+            | Self::Compile {
+                message,
+                reason,
+                location,
+            }
+            | Self::Recursion {
+                message,
+                reason,
+                location,
+            }
+            | Self::Conflict {
+                message,
+                reason,
+                location,
+            }
+            | Self::Type {
+                message,
+                reason,
+                location,
+            }
+            | Self::Builtin {
+                message,
+                reason,
+                location,
+            }
+            | Self::Cancelled {
+                message,
+                reason,
+                location,
+            }
+            | Self::RecursionLimit {
+                message,
+                reason,
+                location,
+            }
+            | Self::Eval {
+                message,
+                reason,
+                location,
+            } => (message, reason, location),
+        }
+    }
+}
+
+impl fmt::Display for RegorusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl core::error::Error for RegorusError {}
+
+impl From<anyhow::Error> for RegorusError {
+    /// Errors not raised by regorus, e.g. by extensions, become [`RegorusError::Eval`].
+    /// Context attached to an error is retained in the message.
+    fn from(e: anyhow::Error) -> Self {
+        let message = format!("{e:#}");
+        // Same as the message, but with regorus errors in the chain contributing their reason.
+        let reason = e
+            .chain()
+            .map(|c| match c.downcast_ref::<RegorusError>() {
+                Some(err) => err.reason().to_string(),
+                None => c.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(": ");
+        match e.downcast::<RegorusError>() {
+            Ok(err) if err.message() == message => err,
+            Ok(err) => {
+                let kind = err.kind();
+                let (_, _, location) = err.into_parts();
+                Self::new(kind, message, reason, location)
+            }
+            Err(_) => Self::Eval {
+                message,
+                reason,
+                location: None,
+            },
+        }
+    }
+}
+
+// Returns a function that assigns the given kind to generic evaluation errors.
+// Errors that have already been classified are left unchanged.
+pub(crate) fn classify(kind: ErrorKind) -> impl Fn(anyhow::Error) -> anyhow::Error {
+    move |e| {
+        let err = RegorusError::from(e);
+        match err.kind() {
+            ErrorKind::Eval => {
+                let (message, reason, location) = err.into_parts();
+                anyhow::Error::new(RegorusError::new(kind, message, reason, location))
+            }
+            _ => anyhow::Error::new(err),
+        }
+    }
+}
+
+impl RegorusError {
+    // Replace the message of an error while retaining its kind and location.
+    pub(crate) fn with_message(self, message: String) -> Self {
+        let kind = self.kind();
+        let (_, reason, location) = self.into_parts();
+        Self::new(kind, message, reason, location)
+    }
+}
@@ -2,14 +2,19 @@
 // Licensed under the MIT License.
 
 use crate::ast::*;
-use crate::builtins::{self, BuiltinFcn};
+use crate::builtins::{self, Builtin, BuiltinFcn};
+use crate::error::{classify, ErrorKind};
 use crate::lexer::*;
+use crate::lru::LruCache;
 use crate::parser::Parser;
 use crate::scheduler::*;
 use crate::utils::*;
 use crate::value::*;
 use crate::*;
-use crate::{Expression, Extension, Location, QueryResult, QueryResults};
+use crate::{
+    BuiltinCallLogger, Collation, Expression, Extension, Location, PrintValueFormatter,
+    QueryResult, QueryResults,
+};
 
 use alloc::collections::btree_map::Entry as BTreeMapEntry;
 use alloc::collections::{BTreeMap, BTreeSet};
@@ -20,6 +25,10 @@ type Scope = BTreeMap<SourceStr, Value>;
 
 type DefaultRuleInfo = (Ref<Rule>, Option<String>);
 type ContextExprs = (Option<Ref<Expr>>, Option<Ref<Expr>>);
+type RuleValues = BTreeMap<Vec<Value>, (Value, Ref<Expr>)>;
+type ExtensionEntry = (Option<u8>, Rc<Box<dyn Extension>>);
+type RuleDependencyKinds = BTreeSet<(Ref<Rule>, DependencyKind)>;
+pub type RuleDependencies = BTreeMap<String, (String, BTreeSet<(String, DependencyKind)>)>;
 type State = (
     Value,
     Value,
@@ -30,6 +39,18 @@ type State = (
     BTreeMap<Vec<Value>, (Value, Ref<Expr>)>,
 );
 
+pub const DEFAULT_MAX_EVAL_DEPTH: usize = 1000;
+
+// Remaining native stack below which rule and function evaluation continues on a new stack
+// segment, and the size of such segments.
+#[cfg(feature = "stacker")]
+const STACK_RED_ZONE: usize = 256 * 1024;
+#[cfg(feature = "stacker")]
+const STACK_SEGMENT_SIZE: usize = 2 * 1024 * 1024;
+
+// Maximum number of builtin results retained by memoization.
+const MAX_MEMOIZED_BUILTINS: usize = 10_000;
+
 #[derive(Debug, Clone)]
 enum FunctionModifier {
     Function(String),
@@ -56,16 +77,51 @@ pub struct Interpreter {
     default_rules: Map<String, Vec<DefaultRuleInfo>>,
     processed: BTreeSet<Ref<Rule>>,
     processed_paths: Value,
-    rule_values: BTreeMap<Vec<Value>, (Value, Ref<Expr>)>,
+    rule_values: RuleValues,
     active_rules: Vec<Ref<Rule>>,
     builtins_cache: BTreeMap<(&'static str, Vec<Value>), Value>,
+    memoize_builtins: bool,
+    memoized_builtins: LruCache<(String, Vec<Value>), Value>,
+    memoized_builtin_hits: u64,
+    #[cfg(feature = "regex")]
+    regex_cache: builtins::RegexCache,
+    cache_input_independent_rules: bool,
+    input_independent_paths: Option<BTreeSet<String>>,
+    input_independent_values: BTreeMap<String, (Value, RuleValues)>,
+    with_depth: usize,
     no_rules_lookup: bool,
     traces: Option<Vec<Rc<str>>>,
+    explanation: Option<Vec<serde_json::Value>>,
+    trace_filter: Option<BTreeSet<String>>,
+    trace_filter_depth: usize,
+    explain_query_ids: Vec<u64>,
+    next_explain_query_id: u64,
     #[cfg(feature = "deprecated")]
     allow_deprecated: bool,
     strict_builtin_errors: bool,
+    exact_arithmetic: bool,
+    include_undefined_results: bool,
+    string_collation: Collation,
+    allowed_builtins: Option<BTreeSet<String>>,
+    max_results: usize,
+    max_comprehension_size: usize,
+    max_eval_depth: usize,
+    eval_depth: usize,
+    #[cfg(feature = "opa-runtime")]
+    runtime_env: Option<Value>,
+    #[cfg(feature = "time")]
+    clock_ns: Option<i64>,
+    #[cfg(feature = "arc")]
+    cancel: Option<alloc::sync::Arc<core::sync::atomic::AtomicBool>>,
     imports: BTreeMap<String, Ref<Expr>>,
-    extensions: Map<String, (u8, Rc<Box<dyn Extension>>)>,
+    // Query whose references to `data` are restricted to the given package.
+    query_scope: Option<(Source, Vec<String>)>,
+    // Partial rule whose members are passed to a callback instead of being added to its value.
+    rule_stream: Stream<RuleStream>,
+    query_result_stream: Stream<QueryResultStream>,
+    extensions: Map<String, ExtensionEntry>,
+    builtin_call_logger: Option<Rc<Box<dyn BuiltinCallLogger>>>,
+    print_value_formatter: Option<Rc<Box<dyn PrintValueFormatter>>>,
 
     #[cfg(feature = "coverage")]
     coverage: Map<Source, Vec<bool>>,
@@ -74,6 +130,9 @@ pub struct Interpreter {
 
     gather_prints: bool,
     prints: Vec<String>,
+    print_groups: Vec<usize>,
+    gather_print_values: bool,
+    print_values: Vec<Vec<Value>>,
     rule_paths: Set<String>,
 }
 
@@ -83,6 +142,85 @@ impl Default for Interpreter {
     }
 }
 
+// Callback that values are passed to as they are produced, instead of being gathered.
+// Callbacks are installed only for the duration of an evaluation, so clones of the interpreter
+// do not carry them.
+struct Stream<T>(Option<T>);
+
+impl<T> Default for Stream<T> {
+    fn default() -> Self {
+        Self(None)
+    }
+}
+
+impl<T> Clone for Stream<T> {
+    fn clone(&self) -> Self {
+        Self(None)
+    }
+}
+
+impl<T> core::fmt::Debug for Stream<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match &self.0 {
+            Some(_) => f.write_str("<stream>"),
+            None => f.write_str("<no stream>"),
+        }
+    }
+}
+
+// Callback borrowed from the caller of an evaluation.
+// Callbacks are removed from the interpreter before the borrow ends.
+struct BorrowedFn<T: 'static>(core::ptr::NonNull<dyn FnMut(T) -> Result<()>>);
+
+impl<T> BorrowedFn<T> {
+    // Safety: the callback must not be called after `f` is released.
+    unsafe fn new<'a>(f: &'a mut (dyn FnMut(T) -> Result<()> + 'a)) -> Self {
+        let f = core::ptr::NonNull::from(f);
+        // SAFETY: only the lifetime of the trait object is erased.
+        Self(unsafe {
+            core::mem::transmute::<
+                core::ptr::NonNull<dyn FnMut(T) -> Result<()> + 'a>,
+                core::ptr::NonNull<dyn FnMut(T) -> Result<()>>,
+            >(f)
+        })
+    }
+
+    fn call(&mut self, v: T) -> Result<()> {
+        // SAFETY: the callback is alive while it is installed and is called only through
+        // `&mut Interpreter`, hence never concurrently.
+        unsafe { (self.0.as_mut())(v) }
+    }
+}
+
+// SAFETY: the callback is only called during the evaluation that installed it, on the thread
+// that installed it.
+unsafe impl<T> Send for BorrowedFn<T> {}
+unsafe impl<T> Sync for BorrowedFn<T> {}
+
+// Members of the partial rule at `path`, along with the keys of the members already passed to
+// the callback. Keys are set elements or object keys.
+struct RuleStream {
+    path: String,
+    out: BorrowedFn<Value>,
+    streamed: BTreeSet<Value>,
+}
+
+// Removes the callbacks installed in the interpreter when dropped, including during unwinding.
+struct StreamGuard<'a>(&'a mut Interpreter);
+
+impl Drop for StreamGuard<'_> {
+    fn drop(&mut self) {
+        self.0.rule_stream = Stream::default();
+    }
+}
+
+// Results of the user query, along with the order of the query's expressions.
+struct QueryResultStream {
+    out: BorrowedFn<QueryResult>,
+    count: usize,
+    order: Option<(Ref<Query>, Vec<u16>)>,
+}
+
 #[derive(Debug, Clone)]
 struct Context {
     key_expr: Option<ExprRef>,
@@ -97,6 +235,8 @@ struct Context {
     is_old_style_set: bool,
     output_constness_determined: bool,
     early_return: bool,
+    // Whether the rule's members are passed to the rule stream.
+    streamed: bool,
 }
 
 impl Default for Context {
@@ -114,6 +254,7 @@ impl Default for Context {
             is_old_style_set: false,
             output_constness_determined: false,
             early_return: false,
+            streamed: false,
         }
     }
 }
@@ -185,13 +326,46 @@ impl Interpreter {
             rule_values: BTreeMap::new(),
             active_rules: vec![],
             builtins_cache: BTreeMap::new(),
+            memoize_builtins: false,
+            memoized_builtins: LruCache::new(MAX_MEMOIZED_BUILTINS),
+            memoized_builtin_hits: 0,
+            #[cfg(feature = "regex")]
+            regex_cache: builtins::new_regex_cache(),
+            cache_input_independent_rules: false,
+            input_independent_paths: None,
+            input_independent_values: BTreeMap::new(),
+            with_depth: 0,
             no_rules_lookup: false,
             traces: None,
+            explanation: None,
+            trace_filter: None,
+            trace_filter_depth: 0,
+            explain_query_ids: vec![],
+            next_explain_query_id: 0,
             #[cfg(feature = "deprecated")]
             allow_deprecated: true,
             strict_builtin_errors: true,
+            exact_arithmetic: false,
+            include_undefined_results: false,
+            string_collation: Collation::Exact,
+            allowed_builtins: None,
+            max_results: usize::MAX,
+            max_comprehension_size: usize::MAX,
+            max_eval_depth: DEFAULT_MAX_EVAL_DEPTH,
+            eval_depth: 0,
+            #[cfg(feature = "opa-runtime")]
+            runtime_env: None,
+            #[cfg(feature = "time")]
+            clock_ns: None,
+            #[cfg(feature = "arc")]
+            cancel: None,
             imports: BTreeMap::default(),
+            query_scope: None,
+            rule_stream: Stream::default(),
+            query_result_stream: Stream::default(),
             extensions: Map::new(),
+            builtin_call_logger: None,
+            print_value_formatter: None,
 
             #[cfg(feature = "coverage")]
             coverage: Map::new(),
@@ -200,6 +374,9 @@ impl Interpreter {
 
             gather_prints: false,
             prints: Vec::default(),
+            print_groups: Vec::default(),
+            gather_print_values: false,
+            print_values: Vec::default(),
             rule_paths: Set::new(),
         }
     }
@@ -222,16 +399,13 @@ impl Interpreter {
 
     pub fn set_init_data(&mut self, data: Value) {
         self.init_data = data;
+        self.input_independent_values.clear();
     }
 
     pub fn get_init_data(&self) -> &Value {
         &self.init_data
     }
 
-    pub fn get_init_data_mut(&mut self) -> &mut Value {
-        &mut self.init_data
-    }
-
     pub fn set_traces(&mut self, enable_tracing: bool) {
         self.traces = match enable_tracing {
             true => Some(vec![]),
@@ -243,8 +417,223 @@ impl Interpreter {
         self.strict_builtin_errors = b;
     }
 
+    pub fn set_exact_arithmetic(&mut self, b: bool) {
+        self.exact_arithmetic = b;
+    }
+
+    pub fn set_explain(&mut self, enable: bool) {
+        self.explanation = enable.then(Vec::new);
+        self.explain_query_ids.clear();
+        self.next_explain_query_id = 0;
+    }
+
+    pub fn take_explanation(&mut self) -> Vec<serde_json::Value> {
+        self.explanation.take().unwrap_or_default()
+    }
+
+    pub fn set_trace_filter(&mut self, paths: Option<BTreeSet<String>>) {
+        self.trace_filter = paths;
+    }
+
+    // Whether trace and explanation events are recorded at this point of evaluation.
+    fn is_trace_enabled(&self) -> bool {
+        self.trace_filter.is_none() || self.trace_filter_depth > 0
+    }
+
+    // Whether the rule is one of the rules (or within a package) listed in the trace filter.
+    fn is_trace_filtered_rule(&self, rule: &Ref<Rule>) -> bool {
+        match &self.trace_filter {
+            Some(paths) => paths
+                .iter()
+                .any(|p| matches!(self.rules.get(p), Some(rules) if rules.contains(rule))),
+            None => false,
+        }
+    }
+
+    // Convert a value to OPA's JSON representation of terms.
+    fn to_opa_term(v: &Value) -> serde_json::Value {
+        let (ty, value) = match v {
+            Value::Null => ("null", serde_json::Value::Null),
+            Value::Bool(b) => ("boolean", serde_json::Value::Bool(*b)),
+            Value::Number(_) => (
+                "number",
+                serde_json::to_value(v).unwrap_or(serde_json::Value::Null),
+            ),
+            Value::String(s) => ("string", serde_json::Value::from(s.as_ref())),
+            Value::Array(a) => ("array", a.iter().map(Self::to_opa_term).collect()),
+            Value::Set(s) => ("set", s.iter().map(Self::to_opa_term).collect()),
+            Value::Object(o) => (
+                "object",
+                o.iter()
+                    .map(|(k, v)| {
+                        serde_json::Value::Array(vec![Self::to_opa_term(k), Self::to_opa_term(v)])
+                    })
+                    .collect(),
+            ),
+            Value::Undefined => ("undefined", serde_json::Value::Null),
+        };
+        serde_json::json!({ "type": ty, "value": value })
+    }
+
+    // Record an explanation event.
+    // The node is described by its source text and location.
+    fn explain(&mut self, op: &str, ty: &str, span: &Span) {
+        if self.explanation.is_none() || !self.is_trace_enabled() {
+            return;
+        }
+
+        let n = self.explain_query_ids.len();
+        let query_id = self.explain_query_ids.last().copied().unwrap_or_default();
+        let parent_id = match n {
+            n if n > 1 => self.explain_query_ids[n - 2],
+            _ => 0,
+        };
+        let location = serde_json::json!({
+            "file": span.source.get_path(),
+            "row": span.source.reported_line(span.line),
+            "col": span.source.reported_col(span.col),
+        });
+        let locals: Vec<serde_json::Value> = match self.scopes.last() {
+            Some(scope) => scope
+                .iter()
+                .filter(|(_, v)| *v != &Value::Undefined)
+                .map(|(k, v)| {
+                    serde_json::json!({
+                        "key": { "type": "var", "value": k.text() },
+                        "value": Self::to_opa_term(v),
+                    })
+                })
+                .collect(),
+            _ => vec![],
+        };
+
+        if let Some(explanation) = &mut self.explanation {
+            explanation.push(serde_json::json!({
+                "op": op,
+                "query_id": query_id,
+                "parent_id": parent_id,
+                "type": ty,
+                "node": { "text": span.text(), "location": location.clone() },
+                "locals": locals,
+                "location": location,
+            }));
+        }
+    }
+
+    // Record that the default value of the rule at the given path was used.
+    fn explain_default(&mut self, span: &Span, path: &str) {
+        let filtered = matches!(&self.trace_filter, Some(paths)
+            if paths.iter().any(|p| path == p || path.starts_with(&format!("{p}."))));
+        if filtered {
+            self.trace_filter_depth += 1;
+        }
+        // The default rule is evaluated and exits with its value.
+        let n = self.explanation.as_ref().map_or(0, Vec::len);
+        self.explain("Eval", "rule", span);
+        self.explain("Exit", "rule", span);
+        if let Some(explanation) = &mut self.explanation {
+            for event in explanation.iter_mut().skip(n) {
+                event["path"] = serde_json::Value::from(path);
+            }
+        }
+        if filtered {
+            self.trace_filter_depth -= 1;
+        }
+    }
+
+    pub fn set_include_undefined_results(&mut self, b: bool) {
+        self.include_undefined_results = b;
+    }
+
+    pub fn set_string_collation(&mut self, collation: Collation) {
+        self.string_collation = collation;
+    }
+
+    pub fn set_allowed_builtins(&mut self, allowed_builtins: Option<BTreeSet<String>>) {
+        self.allowed_builtins = allowed_builtins;
+    }
+
+    pub fn set_max_results(&mut self, max_results: usize) {
+        self.max_results = max_results;
+    }
+
+    pub fn set_max_comprehension_size(&mut self, max_comprehension_size: usize) {
+        self.max_comprehension_size = max_comprehension_size;
+    }
+
+    pub fn set_max_eval_depth(&mut self, max_eval_depth: usize) {
+        self.max_eval_depth = max_eval_depth;
+    }
+
+    // Track nested evaluation of rules and expressions so that deeply nested policies
+    // fail with an error instead of overflowing the native stack.
+    fn eval_nested<T>(&mut self, span: &Span, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        if self.eval_depth >= self.max_eval_depth {
+            bail!(span.error_of_kind(
+                ErrorKind::RecursionLimit,
+                format!("maximum evaluation depth {} exceeded", self.max_eval_depth).as_str()
+            ));
+        }
+        self.eval_depth += 1;
+        let result = f(self);
+        self.eval_depth -= 1;
+        result
+    }
+
+    // Evaluation recurses via rules and functions. With the stacker feature, the native stack
+    // is extended on the heap there when it runs low, so that the depth limit rather than the
+    // size of the thread's stack bounds recursion.
+    #[cfg(feature = "stacker")]
+    fn with_stack<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT_SIZE, || f(self))
+    }
+
+    #[cfg(not(feature = "stacker"))]
+    fn with_stack<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        f(self)
+    }
+
+    #[cfg(feature = "arc")]
+    pub fn set_cancel(&mut self, cancel: Option<alloc::sync::Arc<core::sync::atomic::AtomicBool>>) {
+        self.cancel = cancel;
+    }
+
+    fn check_cancelled(&self, span: &Span) -> Result<()> {
+        #[cfg(feature = "arc")]
+        if let Some(cancel) = &self.cancel {
+            if cancel.load(core::sync::atomic::Ordering::Relaxed) {
+                bail!(span.error_of_kind(ErrorKind::Cancelled, "evaluation cancelled"));
+            }
+        }
+        #[cfg(not(feature = "arc"))]
+        let _ = span;
+        Ok(())
+    }
+
     pub fn set_input(&mut self, input: Value) {
         self.input = input;
+        self.sync_with_document_input();
+    }
+
+    pub fn update_input(&mut self, f: impl FnOnce(&mut Value) -> Result<()>) -> Result<()> {
+        // Drop the with-document's reference to the input so that it is not copied on write.
+        if let Value::Object(doc) = &mut self.with_document {
+            Rc::make_mut(doc).remove(&Value::from("input"));
+        }
+        let r = f(&mut self.input);
+        self.sync_with_document_input();
+        r
+    }
+
+    // With-modifiers are applied to the with-document and must see the current input.
+    fn sync_with_document_input(&mut self) {
+        if let Value::Object(doc) = &mut self.with_document {
+            Rc::make_mut(doc).insert(Value::from("input"), self.input.clone());
+        }
+    }
+
+    pub fn get_input(&self) -> &Value {
+        &self.input
     }
 
     pub fn init_with_document(&mut self) -> Result<()> {
@@ -256,6 +645,132 @@ impl Interpreter {
 
     pub fn clear_builtins_cache(&mut self) {
         self.builtins_cache.clear();
+        self.memoized_builtins.clear();
+    }
+
+    #[cfg(feature = "opa-runtime")]
+    pub fn set_runtime_env(&mut self, env: Option<Value>) {
+        self.runtime_env = env;
+        // opa.runtime is cached across evaluations.
+        self.builtins_cache
+            .retain(|(name, _), _| *name != "opa.runtime");
+    }
+
+    #[cfg(feature = "time")]
+    pub fn set_clock(&mut self, fixed_ns: Option<i64>) {
+        self.clock_ns = fixed_ns;
+        // time.now_ns is cached across evaluations.
+        self.builtins_cache
+            .retain(|(name, _), _| *name != "time.now_ns");
+    }
+
+    // Environment variables are only exposed if explicitly injected.
+    #[cfg(feature = "opa-runtime")]
+    fn add_runtime_env(&self, name: &str, mut v: Value) -> Result<Value> {
+        if let (true, Some(env)) = (name == "opa.runtime", &self.runtime_env) {
+            v.as_object_mut()?
+                .insert(Value::String("env".into()), env.clone());
+        }
+        Ok(v)
+    }
+
+    pub fn set_query_scope(&mut self, scope: Option<(Source, Vec<String>)>) {
+        self.query_scope = scope;
+    }
+
+    // Evaluate the partial rule at the given path and pass each of its members to the callback
+    // once, as they are produced. Object entries are passed as [key, value] pairs.
+    pub fn eval_rule_stream_in_path(
+        &mut self,
+        path: String,
+        out: &mut dyn FnMut(Value) -> Result<()>,
+    ) -> Result<()> {
+        self.rule_stream.0 = Some(RuleStream {
+            path: path.clone(),
+            // SAFETY: the guard removes the callback before this function returns.
+            out: unsafe { BorrowedFn::new(out) },
+            streamed: BTreeSet::new(),
+        });
+        let this = StreamGuard(self);
+        let value = this.0.eval_rule_in_path(path)?;
+
+        // Members contributed by rules that are not streamed, such as `p["k"] := v`.
+        match value {
+            Value::Set(set) => {
+                for member in set.iter() {
+                    this.0.stream_rule_member(member.clone(), member.clone())?;
+                }
+            }
+            Value::Object(obj) => {
+                for (key, value) in obj.iter() {
+                    let member = Value::from(vec![key.clone(), value.clone()]);
+                    this.0.stream_rule_member(key.clone(), member)?;
+                }
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    // Pass results of user queries to the callback instead of gathering them.
+    // Safety: the callback must be removed using clear_query_result_stream before `out` is
+    // released, including during unwinding.
+    #[cfg(feature = "std")]
+    pub unsafe fn set_query_result_stream<'a>(
+        &mut self,
+        out: &'a mut (dyn FnMut(QueryResult) -> Result<()> + 'a),
+    ) {
+        self.query_result_stream.0 = Some(QueryResultStream {
+            // SAFETY: upheld by the caller.
+            out: unsafe { BorrowedFn::new(out) },
+            count: 0,
+            order: None,
+        });
+    }
+
+    #[cfg(feature = "std")]
+    pub fn clear_query_result_stream(&mut self) {
+        self.query_result_stream = Stream::default();
+    }
+
+    // Pass a member to the rule stream unless a member with the same key has been passed.
+    // Conflicting values for a key are reported when the rule's values are merged.
+    fn stream_rule_member(&mut self, key: Value, member: Value) -> Result<()> {
+        let Some(stream) = &mut self.rule_stream.0 else {
+            bail!("internal error: no rule stream");
+        };
+        if stream.streamed.insert(key) {
+            stream.out.call(member)?;
+        }
+        Ok(())
+    }
+
+    pub fn set_memoize_builtins(&mut self, memoize: bool) {
+        self.memoize_builtins = memoize;
+        if !memoize {
+            self.memoized_builtins.clear();
+        }
+    }
+
+    pub fn get_memoized_builtin_hits(&self) -> u64 {
+        self.memoized_builtin_hits
+    }
+
+    #[cfg(feature = "regex")]
+    pub fn clear_regex_cache(&mut self) {
+        self.regex_cache.clear();
+    }
+
+    pub fn set_cache_input_independent_rules(&mut self, cache: bool) {
+        self.cache_input_independent_rules = cache;
+        if !cache {
+            self.clear_input_independent_rules_cache();
+        }
+    }
+
+    pub fn clear_input_independent_rules_cache(&mut self) {
+        self.input_independent_paths = None;
+        self.input_independent_values.clear();
     }
 
     pub fn clean_internal_evaluation_state(&mut self) {
@@ -266,6 +781,8 @@ impl Interpreter {
         self.scopes = vec![Scope::new()];
         self.contexts = vec![];
         self.rule_values.clear();
+        self.trace_filter_depth = 0;
+        self.with_depth = 0;
     }
 
     fn current_module(&self) -> Result<Ref<Module>> {
@@ -548,6 +1065,15 @@ impl Interpreter {
             return Ok(Value::Undefined);
         }
 
+        if let (Value::String(s1), Value::String(s2)) = (&lhs, &rhs) {
+            return Ok(builtins::comparison::compare_strings(
+                op,
+                s1,
+                s2,
+                self.string_collation,
+            ));
+        }
+
         builtins::comparison::compare(op, &lhs, &rhs)
     }
 
@@ -591,6 +1117,7 @@ impl Interpreter {
                 lhs_value,
                 rhs_value,
                 self.strict_builtin_errors,
+                self.exact_arithmetic,
             ),
         }
     }
@@ -1065,13 +1592,23 @@ impl Interpreter {
         Ok(count > 0)
     }
 
+    fn get_literal_span(literal: &Literal) -> &Span {
+        match literal {
+            Literal::SomeVars { span, .. }
+            | Literal::SomeIn { span, .. }
+            | Literal::Expr { span, .. }
+            | Literal::NotExpr { span, .. }
+            | Literal::Every { span, .. } => span,
+        }
+    }
+
     fn make_expression_result(span: &Span, v: &Value) -> Expression {
         Expression {
             value: v.clone(),
             text: span.text().to_string().into(),
             location: Location {
-                row: span.line,
-                col: span.col,
+                row: span.source.reported_line(span.line),
+                col: span.source.reported_col(span.col),
             },
         }
     }
@@ -1097,7 +1634,7 @@ impl Interpreter {
 
                 if let Some(ctx) = self.contexts.last_mut() {
                     if let Some(result) = &mut ctx.result {
-                        if value != Value::Undefined {
+                        if value != Value::Undefined || self.include_undefined_results {
                             result
                                 .expressions
                                 .push(Self::make_expression_result(span, &value))
@@ -1296,6 +1833,7 @@ impl Interpreter {
 
             self.data = self.with_document["data"].clone();
             self.input = self.with_document["input"].clone();
+            self.with_depth += 1;
             Ok((
                 Some((
                     with_document,
@@ -1324,17 +1862,23 @@ impl Interpreter {
                 self.with_functions,
                 self.rule_values,
             ) = s;
+            self.with_depth -= 1;
         }
         Ok(())
     }
 
     fn eval_stmt(&mut self, stmt: &LiteralStmt, stmts: &[&LiteralStmt]) -> Result<bool> {
+        self.check_cancelled(&stmt.span)?;
+        self.explain("Eval", "expr", &stmt.span);
         let (saved_state, skip_exec) = self.apply_with_modifiers(stmt)?;
         let r = if !skip_exec {
             self.eval_stmt_impl(stmt, stmts)
         } else {
             Ok(false)
         };
+        if matches!(r, Ok(false)) {
+            self.explain("Fail", "expr", &stmt.span);
+        }
 
         self.restore_state(saved_state)?;
 
@@ -1348,6 +1892,50 @@ impl Interpreter {
         }
     }
 
+    // Record the result of a failed iteration of a query snippet.
+    fn gather_undefined_result(&mut self) -> Result<()> {
+        let ctx = self.contexts.last_mut().expect("no current context");
+        if let Some(result) = &ctx.result {
+            if result.expressions.is_empty() {
+                return Ok(());
+            }
+            let mut result = result.clone();
+            if let Some(scope) = self.scopes.last() {
+                for (name, value) in scope.iter() {
+                    result
+                        .bindings
+                        .as_object_mut()?
+                        .insert(Value::String(name.to_string().into()), value.clone());
+                }
+            }
+            self.push_query_result(result)?;
+        }
+        Ok(())
+    }
+
+    // Record a result of the user query, or pass it to the query result stream if there is one.
+    fn push_query_result(&mut self, mut result: QueryResult) -> Result<()> {
+        let (max_results, include_undefined_results) =
+            (self.max_results, self.include_undefined_results);
+        let Some(stream) = &mut self.query_result_stream.0 else {
+            let ctx = self.contexts.last_mut().expect("no current context");
+            ctx.results.result.push(result);
+            if ctx.results.result.len() > self.max_results {
+                bail!("query produced more than {} results", self.max_results);
+            }
+            return Ok(());
+        };
+
+        stream.count += 1;
+        if stream.count > max_results {
+            bail!("query produced more than {max_results} results");
+        }
+        if let Some((query, ord)) = &stream.order {
+            Self::order_query_result(&mut result, query, ord, include_undefined_results);
+        }
+        stream.out.call(result)
+    }
+
     fn eval_stmts_in_loop(&mut self, stmts: &[&LiteralStmt], loops: &[LoopExpr]) -> Result<bool> {
         if loops.is_empty() {
             if !stmts.is_empty() {
@@ -1359,6 +1947,12 @@ impl Interpreter {
                         Ok(true)
                     }
                 } else {
+                    // Results of some-in statements are gathered by the statements that follow.
+                    if self.include_undefined_results
+                        && !matches!(&stmts[0].literal, Literal::SomeIn { .. })
+                    {
+                        self.gather_undefined_result()?;
+                    }
                     Ok(false)
                 }
             } else {
@@ -1594,8 +2188,10 @@ impl Interpreter {
                         }
                         BTreeMapEntry::Occupied(o) => {
                             if o.get() != &value && value != Value::Undefined {
-                                bail!(span
-                                    .error("complete rules should not produce multiple outputs"))
+                                bail!(span.error_of_kind(
+                                    ErrorKind::Conflict,
+                                    "complete rules should not produce multiple outputs"
+                                ))
                             }
                         }
                     }
@@ -1657,11 +2253,12 @@ impl Interpreter {
             let (key_expr, output_expr) = self.get_exprs_from_context()?;
 
             let ctx = self.get_current_context()?;
-            let (is_set, is_old_style_set, is_rule, constness_determined) = (
+            let (is_set, is_old_style_set, is_rule, constness_determined, streamed) = (
                 ctx.is_set,
                 ctx.is_old_style_set,
                 !ctx.is_compr,
                 ctx.output_constness_determined,
+                ctx.streamed,
             );
 
             if let Some(rule_ref) = ctx.rule_ref.clone() {
@@ -1714,12 +2311,26 @@ impl Interpreter {
                         .entry(Value::from_array(comps))
                         .or_insert(Value::new_set());
                     if output != Value::Undefined {
-                        set.as_set_mut()?.insert(output);
+                        if streamed {
+                            if set.as_set_mut()?.insert(output.clone()) {
+                                self.stream_rule_member(output.clone(), output)?;
+                            }
+                        } else {
+                            set.as_set_mut()?.insert(output);
+                        }
                         return Ok(true);
                     }
                     return Ok(false);
                 }
 
+                // Partial object entries are streamed as [key, value] pairs.
+                let member = match comps.last() {
+                    Some(key) if streamed => {
+                        Some((key.clone(), Value::from(vec![key.clone(), output.clone()])))
+                    }
+                    _ => None,
+                };
+
                 // Non-set rule.
                 match ctx
                     .rule_value
@@ -1728,10 +2339,16 @@ impl Interpreter {
                 {
                     BTreeMapEntry::Vacant(v) => {
                         v.insert(output);
+                        if let Some((key, member)) = member {
+                            self.stream_rule_member(key, member)?;
+                        }
+                    }
+                    BTreeMapEntry::Occupied(o) if o.get() != &output => {
+                        bail!(rule_ref.span().error_of_kind(
+                            ErrorKind::Conflict,
+                            "rules must not produce multiple outputs"
+                        ))
                     }
-                    BTreeMapEntry::Occupied(o) if o.get() != &output => bail!(rule_ref
-                        .span()
-                        .error("rules must not produce multiple outputs")),
                     _ => {
                         // Rule produced same value.
                     }
@@ -1751,7 +2368,8 @@ impl Interpreter {
                         match map.get(&key) {
                             Some(pv) if *pv != value => {
                                 let span = ke.span();
-                                return Err(span.source.error(
+                                return Err(span.source.error_of_kind(
+                                    ErrorKind::Conflict,
                                     span.line,
                                     span.col,
                                     format!(
@@ -1765,6 +2383,12 @@ impl Interpreter {
                             }
                             _ => map.insert(key, value),
                         };
+                        if ctx.is_compr && map.len() > self.max_comprehension_size {
+                            bail!(oe.span().error(&format!(
+                                "comprehension produced more than {} elements",
+                                self.max_comprehension_size
+                            )));
+                        }
                     } else {
                         match &ctx.value {
                             Value::Object(_) => (),
@@ -1776,14 +2400,22 @@ impl Interpreter {
                     let output = self.eval_expr(&oe)?;
                     let ctx = self.contexts.last_mut().unwrap();
                     if output != Value::Undefined {
-                        match &mut ctx.value {
+                        let len = match &mut ctx.value {
                             Value::Array(a) => {
                                 Rc::make_mut(a).push(output);
+                                a.len()
                             }
                             Value::Set(ref mut s) => {
                                 Rc::make_mut(s).insert(output);
+                                s.len()
                             }
                             a => bail!("internal error: invalid context value {a}"),
+                        };
+                        if ctx.is_compr && len > self.max_comprehension_size {
+                            bail!(oe.span().error(&format!(
+                                "comprehension produced more than {} elements",
+                                self.max_comprehension_size
+                            )));
                         }
                     } else if !ctx.is_compr {
                         match &ctx.value {
@@ -1816,7 +2448,7 @@ impl Interpreter {
                        .all(|v| v.value != Value::Undefined && v.value != Value::Bool(false))
                        && !result.expressions.is_empty()
                 {
-                    ctx.results.result.push(result);
+                    self.push_query_result(result)?;
                 }
             }
 
@@ -1936,8 +2568,9 @@ impl Interpreter {
                     .iter()
                     .all(|v| v.value != Value::Undefined && v.value != Value::Bool(false))
                     && !result.expressions.is_empty()
+                    || self.include_undefined_results && !result.expressions.is_empty()
                 {
-                    ctx.results.result.push(result);
+                    self.push_query_result(result)?;
                 }
             }
         }
@@ -1960,7 +2593,22 @@ impl Interpreter {
             query.stmts.iter().collect()
         };
 
+        if self.explanation.is_some() {
+            self.explain_query_ids.push(self.next_explain_query_id);
+            self.next_explain_query_id += 1;
+            self.explain("Enter", "body", &query.span);
+        }
+
         let r = self.eval_stmts(&ordered_stmts);
+
+        if self.explanation.is_some() {
+            match &r {
+                Ok(true) => self.explain("Exit", "body", &query.span),
+                _ => self.explain("Fail", "body", &query.span),
+            }
+            self.explain_query_ids.pop();
+        }
+
         self.scopes.pop();
         r
     }
@@ -2119,7 +2767,11 @@ impl Interpreter {
     fn lookup_function_by_name(&self, path: &str) -> Option<(&Vec<Ref<Rule>>, &Ref<Module>)> {
         let mut path = path.to_owned();
         if !path.starts_with("data.") {
-            path = self.current_module_path.clone() + "." + &path;
+            let local_path = self.current_module_path.clone() + "." + &path;
+            path = match self.resolve_imported_path(&path) {
+                Some(p) if !self.functions.contains_key(&local_path) => p,
+                _ => local_path,
+            };
         }
 
         match self.functions.get(&path) {
@@ -2128,6 +2780,26 @@ impl Interpreter {
         }
     }
 
+    // Resolve a path whose first component is an import of the current module.
+    // E.g. with `import data.lib`, `lib.f` resolves to `data.lib.f`.
+    fn resolve_imported_path(&self, path: &str) -> Option<String> {
+        let (head, rest) = match path.split_once('.') {
+            Some((head, rest)) => (head, Some(rest)),
+            None => (path, None),
+        };
+        let refr = self
+            .imports
+            .get(&(self.current_module_path.clone() + "." + head))?;
+        let import_path = get_path_string(refr, None).ok()?;
+        if !import_path.starts_with("data.") {
+            return None;
+        }
+        Some(match rest {
+            Some(rest) => import_path + "." + rest,
+            None => import_path,
+        })
+    }
+
     fn eval_builtin_call(
         &mut self,
         span: &Span,
@@ -2141,6 +2813,15 @@ impl Interpreter {
             return Ok(Value::Undefined);
         }
 
+        if let Some(logger) = &mut self.builtin_call_logger {
+            Rc::make_mut(logger)(name, &args[..]);
+        }
+
+        #[cfg(feature = "time")]
+        if let (true, Some(ns)) = (name == "time.now_ns", self.clock_ns) {
+            return Ok(Value::from(ns));
+        }
+
         let cache = builtins::must_cache(name);
         if let Some(name) = &cache {
             if let Some(v) = self.builtins_cache.get(&(name, args.clone())) {
@@ -2148,24 +2829,52 @@ impl Interpreter {
             }
         }
 
-        let v = match builtin.0(span, params, &args[..], self.strict_builtin_errors) {
+        let memoize = self.memoize_builtins && builtin.0.is_pure();
+        if memoize {
+            if let Some(v) = self
+                .memoized_builtins
+                .get(&(name.to_string(), args.clone()))
+            {
+                self.memoized_builtin_hits += 1;
+                return Ok(v.clone());
+            }
+        }
+
+        let strict = self.strict_builtin_errors;
+        let r = match builtin.0 {
+            Builtin::Pure(f) | Builtin::Impure(f) => f(span, params, &args[..], strict),
+            // Builtins that compile regexes reuse the regexes compiled by earlier calls.
+            #[cfg(feature = "regex")]
+            Builtin::Regex(f) => f(&mut self.regex_cache, span, params, &args[..], strict),
+        };
+
+        let v = match r {
             Ok(v) => v,
             // Ignore errors if we are not evaluating in strict mode.
             Err(_) if !self.strict_builtin_errors => return Ok(Value::Undefined),
-            Err(e) => Err(e)?,
+            Err(e) => Err(classify(ErrorKind::Builtin)(e))?,
         };
 
+        #[cfg(feature = "opa-runtime")]
+        let v = self.add_runtime_env(name, v)?;
+
         // Handle trace function.
         // TODO: with modifier.
         if name == "trace" {
+            let enabled = self.is_trace_enabled();
             if let (Some(traces), Value::String(msg)) = (&mut self.traces, &v) {
-                traces.push(msg.clone());
+                if enabled {
+                    traces.push(msg.clone());
+                }
                 return Ok(Value::Bool(true));
             }
         }
 
         if let Some(name) = cache {
             self.builtins_cache.insert((name, args), v.clone());
+        } else if memoize {
+            self.memoized_builtins
+                .insert((name.to_string(), args), v.clone());
         }
         Ok(v)
     }
@@ -2173,6 +2882,7 @@ impl Interpreter {
     #[allow(unused_variables)]
     fn lookup_builtin(&self, span: &Span, path: &str) -> Result<Option<&BuiltinFcn>> {
         if let Some(builtin) = builtins::BUILTINS.get(path) {
+            self.check_capabilities(span, path)?;
             return Ok(Some(builtin));
         }
 
@@ -2182,12 +2892,45 @@ impl Interpreter {
             if !allow {
                 bail!(span.error(format!("{path} is deprecated").as_str()))
             }
+            self.check_capabilities(span, path)?;
             return Ok(Some(builtin));
         }
 
         Ok(None)
     }
 
+    // Builtins that policies can call, taking capabilities into account.
+    pub fn builtins(
+        &self,
+        include_deprecated: bool,
+    ) -> impl Iterator<Item = (&'static str, &'static BuiltinFcn)> + '_ {
+        let builtins = builtins::BUILTINS.iter();
+        #[cfg(feature = "deprecated")]
+        let builtins = builtins.chain(
+            builtins::DEPRECATED
+                .iter()
+                .filter(move |_| include_deprecated && self.allow_deprecated),
+        );
+        #[cfg(not(feature = "deprecated"))]
+        let _ = include_deprecated;
+        builtins
+            .map(|(name, builtin)| (*name, builtin))
+            .filter(|(name, _)| match &self.allowed_builtins {
+                Some(allowed) => allowed.contains(*name),
+                None => true,
+            })
+    }
+
+    // Ensure that the builtin is part of the capabilities set via Engine::set_capabilities_from_json.
+    fn check_capabilities(&self, span: &Span, path: &str) -> Result<()> {
+        match &self.allowed_builtins {
+            Some(allowed) if !allowed.contains(path) => {
+                bail!(span.error(format!("{path} is not allowed by capabilities").as_str()))
+            }
+            _ => Ok(()),
+        }
+    }
+
     fn is_builtin(&self, span: &Span, path: &str) -> bool {
         path == "print" || matches!(self.lookup_builtin(span, path), Ok(Some(_)))
     }
@@ -2239,27 +2982,42 @@ impl Interpreter {
         // If not compiling for std target, return early if gathering is not
         // requested.
         #[cfg(not(feature = "std"))]
-        if !self.gather_prints {
+        if !self.gather_prints && !self.gather_print_values {
             return Ok(Value::Bool(true));
         }
 
         let mut msg = String::default();
+        let mut values = vec![];
         for (i, p) in params.iter().enumerate() {
             if i > 0 {
                 msg.push(' ');
             }
-            match self.eval_expr(p)? {
+            let value = self.eval_expr(p)?;
+            match &value {
                 Value::Undefined => msg.push_str("<undefined>"),
                 // Do not print quotes for string values.
                 Value::String(s) => msg.push_str(&format!("{s}")),
-                a => Self::to_printable(&a, &mut msg),
+                a => match &self.print_value_formatter {
+                    Some(formatter) => msg.push_str(&formatter(a)),
+                    None => Self::to_printable(a, &mut msg),
+                },
             }
+            if self.gather_print_values {
+                values.push(value);
+            }
+        }
+
+        if self.gather_print_values {
+            self.print_values.push(values);
         }
 
         if self.gather_prints {
             // Prefix location information.
-            self.prints
-                .push(format!("{}:{}: {msg}", span.source.file(), span.line));
+            self.prints.push(format!(
+                "{}:{}: {msg}",
+                span.source.file(),
+                span.source.reported_line(span.line)
+            ));
         }
 
         // Print to stderr only if not gathering.
@@ -2330,6 +3088,7 @@ impl Interpreter {
                     extension = Some(ext);
                     (&empty, None)
                 } else if fcn_path == "print" {
+                    self.check_capabilities(span, &fcn_path)?;
                     return self.eval_print(span, params, param_values);
                 }
                 // Look up builtin function.
@@ -2350,6 +3109,17 @@ impl Interpreter {
                 }
             }
         };
+        // Validate the number of arguments before invoking the extension.
+        if let Some((Some(nargs), _)) = &extension {
+            if param_values.len() != *nargs as usize {
+                let plural = if *nargs == 1 { "" } else { "s" };
+                bail!(span.error(&format!(
+                    "{fcn_path}: expected {nargs} argument{plural}, got {}",
+                    param_values.len()
+                )));
+            }
+        }
+
         if param_values.iter().any(|v| v == &Value::Undefined) {
             if let Some(with_functions) = with_functions_saved {
                 self.with_functions = with_functions;
@@ -2357,10 +3127,7 @@ impl Interpreter {
             return Ok(Value::Undefined);
         }
 
-        if let Some((nargs, ext)) = extension {
-            if param_values.len() != *nargs as usize {
-                bail!(span.error("incorrect number of parameters supplied to extension"));
-            }
+        if let Some((_, ext)) = extension {
             let r = Rc::make_mut(ext)(param_values);
             // Restore with_functions.
             if let Some(with_functions) = with_functions_saved {
@@ -2372,8 +3139,37 @@ impl Interpreter {
             }
         }
 
-        let fcns = fcns_rules.clone();
+        let default_fcn_path = get_path_string(fcn, Some(&self.current_module_path))?;
+        let fcns_rules = fcns_rules.clone();
+        let r = self.with_stack(|this| {
+            this.eval_user_function(
+                span,
+                &fcn_path,
+                &default_fcn_path,
+                fcns_rules,
+                fcn_module,
+                param_values,
+            )
+        });
+
+        if let Some(with_functions) = with_functions_saved {
+            self.with_functions = with_functions;
+        }
+        r
+    }
 
+    // Evaluate the definitions of a user defined function with given argument values.
+    // If no definition produces a value, the default function (looked up using `fcn_path`
+    // or `default_fcn_path`) is evaluated.
+    fn eval_user_function(
+        &mut self,
+        span: &Span,
+        fcn_path: &str,
+        default_fcn_path: &str,
+        fcns: Vec<Ref<Rule>>,
+        fcn_module: Option<Ref<Module>>,
+        param_values: Vec<Value>,
+    ) -> Result<Value> {
         let mut results: Vec<Value> = Vec::new();
         let mut errors: Vec<anyhow::Error> = Vec::new();
 
@@ -2387,13 +3183,13 @@ impl Interpreter {
                 _ => bail!("internal error not a function"),
             };
 
-            if args.len() != params.len() {
+            if args.len() != param_values.len() {
                 return Err(span.source.error(
                     span.line,
                     span.col,
                     format!(
                         "mismatch in number of arguments. supplied {}, expected {}",
-                        params.len(),
+                        param_values.len(),
                         args.len()
                     )
                     .as_str(),
@@ -2448,7 +3244,8 @@ impl Interpreter {
             let result = match &value {
                 Value::Set(s) if s.len() == 1 => s.iter().next().unwrap().clone(),
                 Value::Set(s) if !s.is_empty() => {
-                    return Err(span.source.error(
+                    return Err(span.source.error_of_kind(
+                        ErrorKind::Conflict,
                         span.line,
                         span.col,
                         format!("function produced multiple outputs {value:?}").as_str(),
@@ -2475,7 +3272,7 @@ impl Interpreter {
         }
 
         if self.strict_builtin_errors && !errors.is_empty() {
-            return Err(anyhow!(errors[0].to_string()));
+            return Err(errors.swap_remove(0));
         }
 
         if results.is_empty() {
@@ -2485,19 +3282,23 @@ impl Interpreter {
             if errors.is_empty() {
                 // Check if any default rules can be evaluated.
                 // TODO: with mod
-                let rules = match self.default_rules.get(&fcn_path).cloned() {
-                    Some(rules) => Some(rules),
-                    None => {
-                        let fcn_path = get_path_string(fcn, Some(&self.current_module_path))?;
-                        self.default_rules.get(&fcn_path).cloned()
-                    }
+                let rules = match self.default_rules.get(fcn_path).cloned() {
+                    Some(rules) => Some((fcn_path, rules)),
+                    None => self
+                        .default_rules
+                        .get(default_fcn_path)
+                        .cloned()
+                        .map(|rules| (default_fcn_path, rules)),
                 };
 
-                if let Some(rules) = rules {
+                if let Some((path, rules)) = rules {
                     for (rule, _) in rules.iter() {
                         if let Rule::Default { value, .. } = rule.as_ref() {
                             match self.eval_expr(value) {
-                                Ok(v) => results.push(v),
+                                Ok(v) => {
+                                    self.explain_default(rule.span(), path);
+                                    results.push(v)
+                                }
                                 Err(e) => errors.push(e),
                             }
                         }
@@ -2507,21 +3308,18 @@ impl Interpreter {
             self.scopes = scopes;
         }
 
-        if let Some(with_functions) = with_functions_saved {
-            self.with_functions = with_functions;
-        }
-
         if results.is_empty() {
             if errors.is_empty() {
                 return Ok(Value::Undefined);
             } else {
-                return Err(anyhow!(errors[0].to_string()));
+                return Err(errors.swap_remove(0));
             }
         }
 
         // all defined values should be the equal to the same value that should be returned
         if !results.windows(2).all(|w| w[0] == w[1]) {
-            return Err(span.source.error(
+            return Err(span.source.error_of_kind(
+                ErrorKind::Conflict,
                 span.line,
                 span.col,
                 "functions must not produce multiple outputs for same inputs",
@@ -2623,6 +3421,10 @@ impl Interpreter {
     }
 
     fn ensure_rule_evaluated(&mut self, path: String) -> Result<()> {
+        if self.restore_input_independent_value(&path)? {
+            return Ok(());
+        }
+
         let mut matched = false;
         if let Some(rules) = self.rules.get(&path) {
             matched = true;
@@ -2650,10 +3452,93 @@ impl Interpreter {
         if matched {
             let comps: Vec<&str> = path.split('.').collect();
             self.mark_processed(&comps[1..])?;
+            self.cache_input_independent_value(&path)?;
+        }
+        Ok(())
+    }
+
+    // Rules contributing to the value at given path.
+    fn get_contributing_rules(&self, path: &str) -> Vec<Ref<Rule>> {
+        let mut rules = self.rules.get(path).cloned().unwrap_or_default();
+        let under = path.to_string() + ".";
+        for (p, defaults) in &self.default_rules {
+            if p == path || p.starts_with(&under) {
+                rules.extend(defaults.iter().map(|(r, _)| r.clone()));
+            }
+        }
+        rules
+    }
+
+    // Input independent rules are cached only outside of with modifiers since they can
+    // replace data and functions.
+    fn can_use_input_independent_values(&self) -> bool {
+        self.cache_input_independent_rules && self.with_depth == 0
+    }
+
+    fn cache_input_independent_value(&mut self, path: &str) -> Result<()> {
+        if !self.can_use_input_independent_values()
+            || self.input_independent_values.contains_key(path)
+        {
+            return Ok(());
+        }
+
+        if self.input_independent_paths.is_none() {
+            self.input_independent_paths = Some(self.find_input_independent_paths()?);
+        }
+        match &self.input_independent_paths {
+            Some(paths) if paths.contains(path) => (),
+            _ => return Ok(()),
+        }
+
+        // Default rules nested within the path may not have been evaluated yet.
+        if !self
+            .get_contributing_rules(path)
+            .iter()
+            .all(|r| self.processed.contains(r))
+        {
+            return Ok(());
         }
+
+        let comps: Vec<&str> = path.split('.').collect();
+        let value = Self::get_value_chained(self.data.clone(), &comps[1..]);
+        let prefix: Vec<Value> = comps[1..]
+            .iter()
+            .map(|c| Value::String((*c).into()))
+            .collect();
+        let rule_values = self
+            .rule_values
+            .iter()
+            .filter(|(k, _)| k.starts_with(&prefix))
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        self.input_independent_values
+            .insert(path.to_string(), (value, rule_values));
         Ok(())
     }
 
+    fn restore_input_independent_value(&mut self, path: &str) -> Result<bool> {
+        if !self.can_use_input_independent_values() {
+            return Ok(false);
+        }
+        let Some((value, rule_values)) = self.input_independent_values.get(path).cloned() else {
+            return Ok(false);
+        };
+        let comps: Vec<&str> = path.split('.').collect();
+        if self.is_processed(&comps[1..])? {
+            return Ok(false);
+        }
+
+        if value != Value::Undefined {
+            *Self::make_or_get_value_mut(&mut self.data, &comps[1..])? = value;
+        }
+        self.rule_values.extend(rule_values);
+        for r in self.get_contributing_rules(path) {
+            self.processed.insert(r);
+        }
+        self.mark_processed(&comps[1..])?;
+        Ok(true)
+    }
+
     fn is_processed(&self, path: &[&str]) -> Result<bool> {
         let mut obj = &self.processed_paths;
         for p in path {
@@ -2681,6 +3566,41 @@ impl Interpreter {
         Ok(())
     }
 
+    // Look up references to `data` made by a query that is restricted to a package.
+    // Returns None if the reference is not subject to the restriction.
+    fn lookup_scoped_data(
+        &mut self,
+        span: &Span,
+        fields: &[&str],
+        no_error: bool,
+    ) -> Result<Option<Value>> {
+        let scope = match &self.query_scope {
+            Some((source, scope)) if *source == span.source => scope.clone(),
+            _ => return Ok(None),
+        };
+        let n = fields.len().min(scope.len());
+        if fields[..n].iter().zip(&scope[..n]).any(|(f, s)| f != s) {
+            // Other packages are not visible.
+            return Ok(Some(Value::Undefined));
+        }
+        if fields.len() >= scope.len() {
+            return Ok(None);
+        }
+
+        // Only the package is visible within its parents.
+        let path: Vec<&str> = scope.iter().map(|s| s.as_str()).collect();
+        let mut value = self.lookup_var(span, &path, no_error)?;
+        if value == Value::Undefined {
+            value = Value::new_object();
+        }
+        for f in path[fields.len()..].iter().rev() {
+            let mut obj = Value::new_object();
+            obj.as_object_mut()?.insert(Value::from(*f), value);
+            value = obj;
+        }
+        Ok(Some(value))
+    }
+
     fn lookup_var(&mut self, span: &Span, fields: &[&str], no_error: bool) -> Result<Value> {
         let name = span.source_str();
 
@@ -2699,11 +3619,14 @@ impl Interpreter {
             if no_error {
                 return Ok(Value::Undefined);
             }
-            return Err(span.error("undefined var"));
+            return Err(span.error_of_kind(ErrorKind::Compile, "undefined var"));
         }
 
         // Ensure that rules are evaluated
         if name.text() == "data" {
+            if let Some(value) = self.lookup_scoped_data(span, fields, no_error)? {
+                return Ok(value);
+            }
             if self.is_processed(fields)? {
                 return Ok(Self::get_value_chained(self.data.clone(), fields));
             }
@@ -2749,7 +3672,7 @@ impl Interpreter {
                 && !self.default_rules.contains_key(&rule_path)
                 && !self.imports.contains_key(&rule_path)
             {
-                bail!(span.error("var is unsafe"));
+                bail!(span.error_of_kind(ErrorKind::Compile, "var is unsafe"));
             }
 
             // Find the rule to which the var being looked up corresponds to. This is the prefix for
@@ -2787,6 +3710,10 @@ impl Interpreter {
     }
 
     fn eval_expr(&mut self, expr: &ExprRef) -> Result<Value> {
+        self.eval_nested(expr.span(), |this| this.eval_expr_impl(expr))
+    }
+
+    fn eval_expr_impl(&mut self, expr: &ExprRef) -> Result<Value> {
         #[cfg(feature = "coverage")]
         if self.enable_coverage {
             let span = expr.span();
@@ -2867,6 +3794,7 @@ impl Interpreter {
                         Value::from(0),
                         self.eval_expr(uexpr)?,
                         self.strict_builtin_errors,
+                        self.exact_arithmetic,
                     )
                 }
                 _ => bail!(expr
@@ -2991,7 +3919,8 @@ impl Interpreter {
                 Value::Array(a) if a.len() == 1 => a[0].clone(),
                 Value::Array(a) if a.is_empty() => Value::Bool(true),
                 Value::Array(_) => {
-                    return Err(span.source.error(
+                    return Err(span.source.error_of_kind(
+                        ErrorKind::Conflict,
                         span.line,
                         span.col,
                         "complete rules should not produce multiple outputs",
@@ -3043,9 +3972,12 @@ impl Interpreter {
     }
 
     pub fn merge_rule_value(span: &Span, value: &mut Value, new: Value) -> Result<()> {
-        match value.merge(new) {
+        match value.merge_rule_output(new) {
             Ok(()) => Ok(()),
-            Err(_) => Err(span.error("rules should not produce multiple outputs.")),
+            Err(_) => Err(span.error_of_kind(
+                ErrorKind::Conflict,
+                "rules should not produce multiple outputs.",
+            )),
         }
     }
 
@@ -3210,17 +4142,22 @@ impl Interpreter {
 
                 let vref = Self::make_or_get_value_mut(&mut self.data, &paths)?;
 
-                if let Value::Object(btree) = &vref {
-                    if !btree.contains_key(&index) {
-                        Self::merge_rule_value(span, vref, object)?;
-                    }
-                } else if let Value::Undefined = vref {
+                let used = match &vref {
+                    Value::Object(btree) => !btree.contains_key(&index),
+                    Value::Undefined => true,
+                    _ => false,
+                };
+                if used {
                     Self::merge_rule_value(span, vref, object)?;
+                    let path = format!("{}[{index}]", self.get_rule_path(rule)?);
+                    self.explain_default(span, &path);
                 }
             } else {
                 let vref = Self::make_or_get_value_mut(&mut self.data, &paths)?;
                 if let Value::Undefined = &vref {
                     Self::merge_rule_value(span, vref, value)?;
+                    let path = self.get_rule_path(rule)?;
+                    self.explain_default(span, &path);
                 }
             };
 
@@ -3276,10 +4213,12 @@ impl Interpreter {
         }
 
         if let Some((_, r)) = conflict {
-            bail!(refr.span().error(&format!(
-                "rule conflicts with the following rule:\n{}",
-                r.span().message("", "defined here")
-            )));
+            bail!(refr.span().error_with_note(
+                ErrorKind::Conflict,
+                "rule conflicts with the following rule:",
+                r.span(),
+                "defined here"
+            ));
         }
         self.rule_values
             .insert(path.to_vec(), (value.clone(), refr.clone()));
@@ -3287,7 +4226,9 @@ impl Interpreter {
         Ok(())
     }
 
-    fn eval_rule_impl(&mut self, module: &Ref<Module>, rule: &Ref<Rule>) -> Result<()> {
+    // Evaluate the rule and return whether it produced a value.
+    fn eval_rule_impl(&mut self, module: &Ref<Module>, rule: &Ref<Rule>) -> Result<bool> {
+        let mut produced = false;
         match rule.as_ref() {
             Rule::Spec {
                 span,
@@ -3296,14 +4237,20 @@ impl Interpreter {
             } => {
                 match rule_head {
                     RuleHead::Compr { refr, .. } | RuleHead::Set { refr, .. } => {
-                        let (ctx, _) = self.make_rule_context(rule_head)?;
+                        let (mut ctx, _) = self.make_rule_context(rule_head)?;
+                        // Rules evaluated within with modifiers do not contribute to the result.
+                        if let Some(stream) = &self.rule_stream.0 {
+                            ctx.streamed = self.with_depth == 0
+                                && self.get_partial_rule_path(rule)?.as_ref() == Some(&stream.path);
+                        }
                         let is_set = ctx.is_set;
                         let is_object = ctx.key_expr.is_some() && !is_set;
 
                         let value = self.eval_rule_bodies(ctx, span, rule_body)?;
                         let package_components = self.eval_rule_ref(&module.package.refr)?;
 
-                        if value != Value::Undefined {
+                        produced = value != Value::Undefined;
+                        if produced {
                             for (path, value) in value.as_object()? {
                                 let mut full_path = package_components.clone();
                                 full_path.append(&mut path.as_array()?.clone());
@@ -3361,6 +4308,7 @@ impl Interpreter {
                             };
 
                             let value = self.eval_rule_bodies(ctx, span, rule_body)?;
+                            produced = value != Value::Undefined;
                             self.update_data(refr.span(), refr, &path[..], value)?;
                         }
                     }
@@ -3368,7 +4316,7 @@ impl Interpreter {
             }
             _ => bail!("internal error: unexpected"),
         }
-        Ok(())
+        Ok(produced)
     }
 
     pub fn eval_rule(&mut self, module: &Ref<Module>, rule: &Ref<Rule>) -> Result<()> {
@@ -3382,6 +4330,7 @@ impl Interpreter {
             return Ok(());
         }
 
+        self.check_cancelled(rule.span())?;
         self.active_rules.push(rule.clone());
         if self.active_rules.iter().filter(|&r| r == rule).count() == 2 {
             let mut msg = String::default();
@@ -3397,7 +4346,8 @@ impl Interpreter {
             self.active_rules.pop();
             let refr = Self::get_rule_refr(rule);
             let span = refr.span();
-            return Err(span.source.error(
+            return Err(span.source.error_of_kind(
+                ErrorKind::Recursion,
                 span.line,
                 span.col,
                 format!("recursion detected when evaluating rule:{msg}").as_str(),
@@ -3407,18 +4357,83 @@ impl Interpreter {
         // Back up local variables of current function and empty
         // the local variables of callee function.
         let scopes = core::mem::take(&mut self.scopes);
+        let traced = self.is_trace_filtered_rule(rule);
+        if traced {
+            self.trace_filter_depth += 1;
+        }
+        // Functions with arguments are evaluated when they are called.
+        let explained = !matches!(rule.as_ref(), Rule::Spec { head: RuleHead::Func { args, .. }, .. } if !args.is_empty());
+        if explained {
+            self.explain("Enter", "rule", rule.span());
+        }
         let prev_module = self.set_current_module(Some(module.clone()))?;
 
-        let res = self.eval_rule_impl(module, rule);
+        let res = self.eval_nested(rule.span(), |this| {
+            this.with_stack(|this| this.eval_rule_impl(module, rule))
+        });
+        if explained {
+            match res {
+                Ok(true) => self.explain("Exit", "rule", rule.span()),
+                _ => self.explain("Fail", "rule", rule.span()),
+            }
+        }
+        if traced {
+            self.trace_filter_depth -= 1;
+        }
 
         self.set_current_module(prev_module)?;
         self.scopes = scopes;
         match self.active_rules.pop() {
-            Some(ref r) if r == rule => res,
+            Some(ref r) if r == rule => res.map(|_| ()),
             _ => bail!("internal error: current rule not active"),
         }
     }
 
+    // Order the expressions in a result of a user query as they appear in the query.
+    fn order_query_result(
+        result: &mut QueryResult,
+        query: &Ref<Query>,
+        ord: &[u16],
+        include_undefined_results: bool,
+    ) {
+        let e = Expression {
+            value: Value::Undefined,
+            text: "".into(),
+            location: Location { row: 0, col: 0 },
+        };
+        let num_expressions = result.expressions.len();
+        if include_undefined_results && num_expressions < ord.len() {
+            // Evaluation stopped at a false or undefined expression.
+            // Report the remaining expressions as undefined.
+            let mut ordered_expressions = vec![e; ord.len()];
+            for (expr_idx, orig_idx) in ord.iter().enumerate() {
+                let orig_idx = *orig_idx as usize;
+                ordered_expressions[orig_idx] = match result.expressions.get(expr_idx) {
+                    Some(value) => value.clone(),
+                    _ => Self::make_expression_result(
+                        Self::get_literal_span(&query.stmts[orig_idx].literal),
+                        &Value::Undefined,
+                    ),
+                };
+            }
+            result.expressions = ordered_expressions;
+            return;
+        }
+
+        let mut ordered_expressions = vec![e; num_expressions];
+        for (expr_idx, value) in result.expressions.iter().enumerate() {
+            let orig_idx = ord[expr_idx] as usize;
+            ordered_expressions[orig_idx] = value.clone();
+        }
+        if include_undefined_results
+            || !ordered_expressions
+                .iter()
+                .any(|v| v.value == Value::Undefined)
+        {
+            result.expressions = ordered_expressions;
+        }
+    }
+
     pub fn eval_user_query(
         &mut self,
         module: &Ref<Module>,
@@ -3438,6 +4453,13 @@ impl Interpreter {
             }
         }
 
+        if let Some(stream) = &mut self.query_result_stream.0 {
+            stream.order = match (schedule.order.get(query), &self.schedule) {
+                (Some(ord), Some(_)) => Some((query.clone(), ord.clone())),
+                _ => None,
+            };
+        }
+
         // Push new context.
         self.contexts.push(Context {
             value: Value::new_set(),
@@ -3456,31 +4478,16 @@ impl Interpreter {
             _ => bail!("internal error: no context"),
         };
 
+        // Order expressions as they appear in the query.
+        if let (Some(ord), Some(_)) = (schedule.order.get(query), &self.schedule) {
+            for result in results.result.iter_mut() {
+                Self::order_query_result(result, query, ord, self.include_undefined_results);
+            }
+        }
+
         // Restore schedules.
         if let Some(self_schedule) = &mut self.schedule {
-            for (k, ord) in schedule.order.iter() {
-                if k == query {
-                    for idx in 0..results.result.len() {
-                        let e = Expression {
-                            value: Value::Undefined,
-                            text: "".into(),
-                            location: Location { row: 0, col: 0 },
-                        };
-                        let mut ordered_expressions =
-                            vec![e; results.result[idx].expressions.len()];
-                        for (expr_idx, value) in results.result[idx].expressions.iter().enumerate()
-                        {
-                            let orig_idx = ord[expr_idx] as usize;
-                            ordered_expressions[orig_idx] = value.clone();
-                        }
-                        if !ordered_expressions
-                            .iter()
-                            .any(|v| v.value == Value::Undefined)
-                        {
-                            results.result[idx].expressions = ordered_expressions;
-                        }
-                    }
-                }
+            for k in schedule.order.keys() {
                 self_schedule.order.remove(k);
             }
         }
@@ -3488,7 +4495,8 @@ impl Interpreter {
         self.set_current_module(prev_module)?;
 
         if let Some(r) = results.result.last() {
-            if matches!(&r.bindings, Value::Object(obj) if obj.is_empty())
+            if !self.include_undefined_results
+                && matches!(&r.bindings, Value::Object(obj) if obj.is_empty())
                 && (r.expressions.len() > 1
                     && r.expressions.iter().any(|e| e.value == Value::Bool(false)))
             {
@@ -3634,6 +4642,7 @@ impl Interpreter {
     }
 
     pub fn process_imports(&mut self) -> Result<()> {
+        self.imports.clear();
         for module in &self.modules {
             let module_path = get_path_string(&module.package.refr, Some("data"))?;
             for import in &module.imports {
@@ -3674,6 +4683,10 @@ impl Interpreter {
     }
 
     pub fn gather_rules(&mut self) -> Result<()> {
+        // Rules are gathered afresh since modules may have been added or removed.
+        self.rules.clear();
+        self.default_rules.clear();
+        self.rule_paths.clear();
         for module in self.modules.clone() {
             let prev_module = self.set_current_module(Some(module.clone()))?;
             for rule in &module.policy {
@@ -3719,10 +4732,535 @@ impl Interpreter {
         Ok(())
     }
 
+    // Ensure that every function called in the modules resolves to a user defined function,
+    // an extension or a builtin so that typos are reported before evaluation.
+    pub fn check_function_calls(&mut self) -> Result<()> {
+        for module in self.modules.clone() {
+            let prev_module = self.set_current_module(Some(module.clone()))?;
+            for rule in &module.policy {
+                self.check_calls_in_rule(rule)?;
+            }
+            self.set_current_module(prev_module)?;
+        }
+        Ok(())
+    }
+
+    fn check_calls_in_rule(&self, rule: &Ref<Rule>) -> Result<()> {
+        visit_calls_in_rule(rule, &mut |span, fcn| self.check_function_call(span, fcn))
+    }
+
+    // Determine the rule paths whose values are the same for every input.
+    // A rule is input independent if it does not refer to input, does not call extensions or
+    // builtins whose results vary across calls, and all rules and functions it refers to are
+    // input independent.
+    fn find_input_independent_paths(&mut self) -> Result<BTreeSet<String>> {
+        let mut dependencies = BTreeMap::new();
+        let mut dependent = BTreeSet::new();
+        for module in self.modules.clone() {
+            let prev_module = self.set_current_module(Some(module.clone()))?;
+            for rule in &module.policy {
+                let (deps, independent) = self.gather_rule_dependencies(rule)?;
+                if !independent {
+                    dependent.insert(rule.clone());
+                }
+                dependencies.insert(rule.clone(), deps);
+            }
+            self.set_current_module(prev_module)?;
+        }
+
+        // Propagate input dependence until no more rules are affected.
+        loop {
+            let count = dependent.len();
+            for (rule, deps) in &dependencies {
+                if deps.iter().any(|d| dependent.contains(d)) {
+                    dependent.insert(rule.clone());
+                }
+            }
+            if dependent.len() == count {
+                break;
+            }
+        }
+
+        let mut paths = BTreeSet::new();
+        for path in self.rules.keys().chain(self.default_rules.keys()) {
+            if self
+                .get_contributing_rules(path)
+                .iter()
+                .all(|r| !dependent.contains(r))
+            {
+                paths.insert(path.clone());
+            }
+        }
+        Ok(paths)
+    }
+
+    // Path of the given rule in the current module.
+    fn get_rule_path(&self, rule: &Ref<Rule>) -> Result<String> {
+        let refr = Self::get_rule_refr(rule);
+        let refr = match refr.as_ref() {
+            Expr::RefBrack { refr, index, .. } if !matches!(index.as_ref(), Expr::String(_)) => {
+                refr
+            }
+            Expr::RefBrack { refr, .. } if matches!(rule.as_ref(), Rule::Default { .. }) => refr,
+            _ => refr,
+        };
+        let comps = Parser::get_path_ref_components(refr)?;
+        let comps: Vec<&str> = comps.iter().map(|s| s.text()).collect();
+        Ok(self.current_module_path.clone() + "." + &comps.join("."))
+    }
+
+    // Raise an error if any rule depends on itself, directly or via other rules.
+    pub fn check_rule_recursion(&mut self) -> Result<()> {
+        let mut rules = vec![];
+        let mut graph = BTreeMap::new();
+        for module in self.modules.clone() {
+            let prev_module = self.set_current_module(Some(module.clone()))?;
+            for rule in &module.policy {
+                let path = self.get_rule_path(rule)?;
+                let (deps, _) = self.gather_rule_dependencies(rule)?;
+                rules.push(rule.clone());
+                graph.insert(rule.clone(), (path, deps));
+            }
+            self.set_current_module(prev_module)?;
+        }
+
+        let mut visited = BTreeSet::new();
+        for rule in &rules {
+            let mut stack = vec![];
+            if let Some(cycle) = Self::find_cycle(rule, &graph, &mut visited, &mut stack) {
+                let paths: Vec<&str> = cycle
+                    .iter()
+                    .chain(cycle.first())
+                    .map(|r| graph[r].0.as_str())
+                    .collect();
+                let span = Self::get_rule_refr(&cycle[0]).span();
+                bail!(span.error_of_kind(
+                    ErrorKind::Recursion,
+                    &format!(
+                        "recursion detected in rule dependencies: {}",
+                        paths.join(" -> ")
+                    )
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // Depth first search for a cycle through the given rule. The cycle is returned as the
+    // rules along it, starting with the first rule of the cycle that was visited.
+    fn find_cycle(
+        rule: &Ref<Rule>,
+        graph: &BTreeMap<Ref<Rule>, (String, BTreeSet<Ref<Rule>>)>,
+        visited: &mut BTreeSet<Ref<Rule>>,
+        stack: &mut Vec<Ref<Rule>>,
+    ) -> Option<Vec<Ref<Rule>>> {
+        if let Some(pos) = stack.iter().position(|r| r == rule) {
+            return Some(stack[pos..].to_vec());
+        }
+        if !visited.insert(rule.clone()) {
+            return None;
+        }
+        stack.push(rule.clone());
+        if let Some((_, deps)) = graph.get(rule) {
+            for dep in deps {
+                if let Some(cycle) = Self::find_cycle(dep, graph, visited, stack) {
+                    return Some(cycle);
+                }
+            }
+        }
+        stack.pop();
+        None
+    }
+
+    // Map from each rule path to its package and the rule paths it depends on.
+    pub fn get_rule_dependency_graph(
+        &mut self,
+    ) -> Result<BTreeMap<String, (String, BTreeSet<String>)>> {
+        Ok(self
+            .get_rule_dependency_kinds()?
+            .into_iter()
+            .map(|(path, (package, deps))| {
+                (path, (package, deps.into_iter().map(|(d, _)| d).collect()))
+            })
+            .collect())
+    }
+
+    // Map from each rule path to its package and the rule paths it depends on, along with
+    // how it depends on them.
+    pub fn get_rule_dependency_kinds(&mut self) -> Result<RuleDependencies> {
+        let mut rule_paths = BTreeMap::new();
+        let mut rule_deps = vec![];
+        for module in self.modules.clone() {
+            let prev_module = self.set_current_module(Some(module.clone()))?;
+            for rule in &module.policy {
+                let path = self.get_rule_path(rule)?;
+                let (deps, _) = self.gather_rule_dependency_kinds(rule)?;
+                rule_paths.insert(rule.clone(), path.clone());
+                rule_deps.push((path, self.current_module_path.clone(), deps));
+            }
+            self.set_current_module(prev_module)?;
+        }
+
+        let mut graph = RuleDependencies::new();
+        for (path, package, deps) in rule_deps {
+            let deps: BTreeSet<(String, DependencyKind)> = deps
+                .iter()
+                .filter_map(|(d, kind)| rule_paths.get(d).map(|d| (d.clone(), *kind)))
+                .filter(|(d, _)| *d != path)
+                .collect();
+            graph
+                .entry(path)
+                .or_insert_with(|| (package, BTreeSet::new()))
+                .1
+                .extend(deps);
+        }
+        Ok(graph)
+    }
+
+    // Returns the rules that given rule refers to and whether the rule is independent of input
+    // when those rules are.
+    fn gather_rule_dependencies(&self, rule: &Ref<Rule>) -> Result<(BTreeSet<Ref<Rule>>, bool)> {
+        let (deps, independent) = self.gather_rule_dependency_kinds(rule)?;
+        Ok((deps.into_iter().map(|(r, _)| r).collect(), independent))
+    }
+
+    // Same as gather_rule_dependencies, but also returns how the rule depends on each rule.
+    fn gather_rule_dependency_kinds(
+        &self,
+        rule: &Ref<Rule>,
+    ) -> Result<(RuleDependencyKinds, bool)> {
+        let mut deps = BTreeSet::new();
+        let mut independent = true;
+        // Refs into data whose dependencies have already been gathered.
+        let mut data_refs = BTreeSet::new();
+        // Local variables shadow rules of the same name.
+        let locals = Self::gather_rule_locals(rule)?;
+        // The rule's own head does not make it depend on anything.
+        Self::add_ref_chain(Self::get_rule_refr(rule), &mut data_refs);
+        // Targets and values of with modifiers.
+        let mut with_exprs = BTreeSet::new();
+        visit_with_mods_in_rule(rule, &mut |wm| {
+            with_exprs.insert(wm.refr.as_ref() as *const Expr);
+            with_exprs.insert(wm.r#as.as_ref() as *const Expr);
+            Ok(())
+        })?;
+        visit_exprs_in_rule(rule, &mut |e| {
+            if data_refs.contains(&(e.as_ref() as *const Expr)) {
+                return Ok(());
+            }
+            let mut expr_deps = BTreeSet::new();
+            match e.as_ref() {
+                Expr::Call { fcn, .. } => match get_path_string(fcn, None) {
+                    Ok(path) => match self.lookup_function_by_name(&path) {
+                        Some((fcns, _)) => {
+                            expr_deps.extend(fcns.iter().cloned());
+                            // The function name itself is not a reference into data.
+                            Self::add_ref_chain(fcn, &mut data_refs);
+                        }
+                        None => independent &= self.is_deterministic_builtin(&path),
+                    },
+                    _ => independent = false,
+                },
+                Expr::Var(v) if locals.contains(v.0.text()) => (),
+                Expr::Var(v) if v.0.text() == "input" => independent = false,
+                Expr::Var(v) if v.0.text() == "data" => {
+                    self.add_data_dependencies("data", &[], &mut expr_deps)
+                }
+                Expr::Var(v) => {
+                    let path = self.current_module_path.clone() + "." + v.0.text();
+                    self.add_data_dependencies(&path, &[], &mut expr_deps);
+                    if let Some(import) = self.imports.get(&path) {
+                        match self.get_data_ref_path(import, &mut BTreeSet::new()) {
+                            Some((path, pattern)) => {
+                                self.add_data_dependencies(&path, &pattern, &mut expr_deps)
+                            }
+                            None => independent = false,
+                        }
+                    }
+                }
+                Expr::RefDot { .. } | Expr::RefBrack { .. }
+                    if locals.contains(get_root_var(e)?.text()) => {}
+                Expr::RefDot { .. } | Expr::RefBrack { .. } => {
+                    if let Some((path, pattern)) = self.get_data_ref_path(e, &mut data_refs) {
+                        self.add_data_dependencies(&path, &pattern, &mut expr_deps);
+                    }
+                }
+                _ => (),
+            }
+            let kind = match e.as_ref() {
+                Expr::Call { .. } => DependencyKind::Call,
+                _ if with_exprs.contains(&(e.as_ref() as *const Expr)) => DependencyKind::With,
+                _ => DependencyKind::DataRef,
+            };
+            deps.extend(expr_deps.into_iter().map(|r| (r, kind)));
+            Ok(())
+        })?;
+        Ok((deps, independent))
+    }
+
+    // Mark the given reference and the references it is based on as already processed.
+    fn add_ref_chain(mut refr: &ExprRef, data_refs: &mut BTreeSet<*const Expr>) {
+        loop {
+            data_refs.insert(refr.as_ref() as *const Expr);
+            match refr.as_ref() {
+                Expr::RefDot { refr: r, .. } | Expr::RefBrack { refr: r, .. } => refr = r,
+                _ => break,
+            }
+        }
+    }
+
+    // Names of function parameters and of variables introduced via some, every or :=
+    // anywhere within the rule.
+    fn gather_rule_locals(rule: &Ref<Rule>) -> Result<BTreeSet<String>> {
+        let mut locals = BTreeSet::new();
+        if let Rule::Spec {
+            head: RuleHead::Func { args, .. },
+            ..
+        } = rule.as_ref()
+        {
+            for arg in args {
+                Self::gather_vars_in_expr(arg, &mut locals)?;
+            }
+        }
+        if let Rule::Spec { bodies, .. } = rule.as_ref() {
+            for body in bodies {
+                Self::gather_query_locals(&body.query, &mut locals)?;
+            }
+        }
+        visit_exprs_in_rule(rule, &mut |e| match e.as_ref() {
+            Expr::AssignExpr {
+                op: AssignOp::ColEq,
+                lhs,
+                ..
+            } => Self::gather_vars_in_expr(lhs, &mut locals),
+            Expr::ArrayCompr { query, .. }
+            | Expr::SetCompr { query, .. }
+            | Expr::ObjectCompr { query, .. } => Self::gather_query_locals(query, &mut locals),
+            _ => Ok(()),
+        })?;
+        Ok(locals)
+    }
+
+    fn gather_query_locals(query: &Ref<Query>, locals: &mut BTreeSet<String>) -> Result<()> {
+        for stmt in &query.stmts {
+            match &stmt.literal {
+                Literal::SomeVars { vars, .. } => {
+                    locals.extend(vars.iter().map(|v| v.text().to_string()))
+                }
+                Literal::SomeIn { key, value, .. } => {
+                    if let Some(key) = key {
+                        Self::gather_vars_in_expr(key, locals)?;
+                    }
+                    Self::gather_vars_in_expr(value, locals)?;
+                }
+                Literal::Every {
+                    key, value, query, ..
+                } => {
+                    locals.extend(key.iter().map(|k| k.text().to_string()));
+                    locals.insert(value.text().to_string());
+                    Self::gather_query_locals(query, locals)?;
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    fn gather_vars_in_expr(expr: &ExprRef, vars: &mut BTreeSet<String>) -> Result<()> {
+        traverse(expr, &mut |e| {
+            if let Expr::Var(v) = e.as_ref() {
+                vars.insert(v.0.text().to_string());
+            }
+            Ok(true)
+        })
+    }
+
+    // Returns the static prefix of a ref into data along with the components that follow it,
+    // e.g. data.a.b and [None, Some("c")] for data.a.b[x].c. Dynamic components are None.
+    // Refs that start with a rule or an import of the current module are resolved to the
+    // corresponding data path.
+    fn get_data_ref_path(
+        &self,
+        expr: &ExprRef,
+        visited: &mut BTreeSet<*const Expr>,
+    ) -> Option<(String, Vec<Option<String>>)> {
+        let mut nodes = vec![];
+        let mut comps = vec![];
+        let mut pattern = vec![];
+        let mut expr = expr;
+        loop {
+            nodes.push(expr.as_ref() as *const Expr);
+            match expr.as_ref() {
+                Expr::RefDot { refr, field, .. } => {
+                    comps.push(field.0.text());
+                    expr = refr;
+                }
+                Expr::RefBrack { refr, index, .. } => {
+                    match index.as_ref() {
+                        Expr::String(s) | Expr::Number(s) => comps.push(s.0.text()),
+                        _ => {
+                            pattern.extend(comps.drain(..).map(|c| Some(c.to_string())));
+                            pattern.push(None);
+                        }
+                    }
+                    expr = refr;
+                }
+                Expr::Var(v) if v.0.text() == "data" => {
+                    comps.push("data");
+                    break;
+                }
+                Expr::Var(v) if v.0.text() != "input" => {
+                    let path = self.current_module_path.clone() + "." + v.0.text();
+                    let root = match self.imports.get(&path) {
+                        Some(import) => self.get_data_ref_path(import, &mut BTreeSet::new())?.0,
+                        None => path,
+                    };
+                    visited.extend(nodes);
+                    comps.reverse();
+                    pattern.reverse();
+                    let path = comps.into_iter().fold(root, |p, c| p + "." + c);
+                    return Some((path, pattern));
+                }
+                _ => return None,
+            }
+        }
+        visited.extend(nodes);
+        comps.reverse();
+        pattern.reverse();
+        Some((comps.join("."), pattern))
+    }
+
+    // Rules (including default rules) whose path is exactly the given path.
+    fn get_rules_at_path(&self, path: &str) -> Vec<Ref<Rule>> {
+        let Some(rules) = self.rules.get(path) else {
+            return self.get_default_rules_at_path(path);
+        };
+        // Rules are also recorded at each prefix of their paths.
+        let under = path.to_string() + ".";
+        let deeper: BTreeSet<&Ref<Rule>> = self
+            .rules
+            .iter()
+            .filter(|(p, _)| p.starts_with(&under))
+            .flat_map(|(_, rules)| rules)
+            .collect();
+        let mut at_path: Vec<Ref<Rule>> = rules
+            .iter()
+            .filter(|r| !deeper.contains(r))
+            .cloned()
+            .collect();
+        at_path.extend(self.get_default_rules_at_path(path));
+        at_path
+    }
+
+    fn get_default_rules_at_path(&self, path: &str) -> Vec<Ref<Rule>> {
+        self.default_rules
+            .get(path)
+            .map(|rules| rules.iter().map(|(r, _)| r.clone()).collect())
+            .unwrap_or_default()
+    }
+
+    // Add the rules that may contribute to the value at given data path followed by the
+    // given components.
+    fn add_data_dependencies(
+        &self,
+        path: &str,
+        pattern: &[Option<String>],
+        deps: &mut BTreeSet<Ref<Rule>>,
+    ) {
+        let under = path.to_string() + ".";
+        if pattern.is_empty() {
+            deps.extend(self.get_contributing_rules(path));
+            for (p, rules) in &self.rules {
+                if p.starts_with(&under) {
+                    deps.extend(rules.iter().cloned());
+                }
+            }
+        } else {
+            // Match the path relative to the given path against the components.
+            let matches = |rel: &str| {
+                let comps: Vec<&str> = rel.split('.').collect();
+                let matched = comps
+                    .iter()
+                    .zip(pattern)
+                    .all(|(c, pc)| pc.is_none() || pc.as_deref() == Some(c));
+                matched.then_some(comps.len())
+            };
+            for (p, rules) in &self.rules {
+                match p.strip_prefix(&under).and_then(matches) {
+                    // Rules at or below the referenced path.
+                    Some(len) if len >= pattern.len() => deps.extend(rules.iter().cloned()),
+                    // Rules that produce objects containing the referenced value.
+                    Some(_) => deps.extend(self.get_rules_at_path(p)),
+                    None => (),
+                }
+            }
+            for (p, defaults) in &self.default_rules {
+                if p.strip_prefix(&under).and_then(matches).is_some() {
+                    deps.extend(defaults.iter().map(|(r, _)| r.clone()));
+                }
+            }
+        }
+        // Rules at a prefix of the path produce objects that may contain the value.
+        let mut prefix = path;
+        while let Some((p, _)) = prefix.rsplit_once('.') {
+            deps.extend(self.get_rules_at_path(p));
+            prefix = p;
+        }
+        if let Some((fcns, _, _)) = self.functions.get(path) {
+            deps.extend(fcns.iter().cloned());
+        }
+    }
+
+    fn is_deterministic_builtin(&self, path: &str) -> bool {
+        if self.extensions.contains_key(path) || path == "print" {
+            return false;
+        }
+        #[cfg(feature = "deprecated")]
+        if let Some(builtin) = builtins::DEPRECATED.get(path) {
+            return builtin.0.is_pure();
+        }
+        builtins::BUILTINS
+            .get(path)
+            .is_some_and(|builtin| builtin.0.is_pure())
+    }
+
+    pub fn is_builtin_or_extension(&self, path: &str) -> bool {
+        #[cfg(feature = "deprecated")]
+        if builtins::DEPRECATED.contains_key(path) {
+            return true;
+        }
+        path == "print"
+            || builtins::BUILTINS.contains_key(path)
+            || self.extensions.contains_key(path)
+    }
+
+    // Mirrors the resolution order used by eval_call_impl.
+    fn check_function_call(&self, span: &Span, fcn: &ExprRef) -> Result<()> {
+        let fcn_path = match get_path_string(fcn, None) {
+            Ok(p) => p,
+            _ => bail!(span.error("invalid function expression")),
+        };
+
+        if self.lookup_function_by_name(&fcn_path).is_some()
+            || self.default_rules.contains_key(&fcn_path)
+            || self
+                .default_rules
+                .contains_key(&get_path_string(fcn, Some(&self.current_module_path))?)
+            || self.extensions.contains_key(&fcn_path)
+            || (fcn_path == "print" && self.check_capabilities(span, &fcn_path).map(|_| true)?)
+            // Reports deprecated builtins and builtins outside the capabilities that are not allowed.
+            || self.lookup_builtin(span, &fcn_path)?.is_some()
+        {
+            return Ok(());
+        }
+
+        bail!(span.error(format!("could not find function {fcn_path}").as_str()))
+    }
+
     pub fn add_extension(
         &mut self,
         path: String,
-        nargs: u8,
+        nargs: Option<u8>,
         extension: Box<dyn Extension>,
     ) -> Result<()> {
         if let MapEntry::Vacant(v) = self.extensions.entry(path) {
@@ -3733,6 +5271,50 @@ impl Interpreter {
         }
     }
 
+    pub fn dump_state(&self, s: &mut String) {
+        s.push_str(&format!("schedule cached: {}\n", self.schedule.is_some()));
+        s.push_str(&format!(
+            "strict builtin errors: {}\n",
+            self.strict_builtin_errors
+        ));
+        s.push_str(&format!("exact arithmetic: {}\n", self.exact_arithmetic));
+        #[cfg(feature = "coverage")]
+        s.push_str(&format!("coverage enabled: {}\n", self.enable_coverage));
+        s.push_str(&format!("gather prints: {}\n", self.gather_prints));
+        s.push_str(&format!("tracing: {}\n", self.traces.is_some()));
+
+        let data_keys: Vec<String> = match self.init_data.as_object() {
+            Ok(fields) => fields.keys().map(|k| k.to_string()).collect(),
+            _ => vec![],
+        };
+        s.push_str(&format!("data keys: [{}]\n", data_keys.join(", ")));
+
+        let extensions: BTreeSet<&String> = self.extensions.keys().collect();
+        s.push_str(&format!(
+            "extensions: [{}]\n",
+            extensions
+                .iter()
+                .map(|e| e.as_str())
+                .collect::<Vec<&str>>()
+                .join(", ")
+        ));
+
+        s.push_str(&format!("functions: {}\n", self.functions.len()));
+        s.push_str(&format!("rule paths: {}\n", self.rule_paths.len()));
+        s.push_str(&format!("builtins cache: {}\n", self.builtins_cache.len()));
+        s.push_str(&format!("rule values cache: {}\n", self.rule_values.len()));
+        s.push_str(&format!("processed rules: {}\n", self.processed.len()));
+        s.push_str(&format!("pending prints: {}\n", self.prints.len()));
+    }
+
+    pub fn set_builtin_call_logger(&mut self, logger: Option<Box<dyn BuiltinCallLogger>>) {
+        self.builtin_call_logger = logger.map(Rc::new);
+    }
+
+    pub fn set_print_value_formatter(&mut self, formatter: Option<Box<dyn PrintValueFormatter>>) {
+        self.print_value_formatter = formatter.map(Rc::new);
+    }
+
     #[cfg(feature = "coverage")]
     fn gather_coverage_in_query(
         &self,
@@ -3777,11 +5359,15 @@ impl Interpreter {
                     false
                 }
                 _ => {
-                    let line = e.span().line as usize;
+                    let span = e.span();
+                    let line = span.line as usize;
+                    // Coverage is tracked using lines within the source, but reported
+                    // relative to the enclosing file.
+                    let reported_line = span.source.reported_line(span.line);
                     if line >= covered.len() || !covered[line] {
-                        file.not_covered.insert(line as u32);
+                        file.not_covered.insert(reported_line);
                     } else if line < covered.len() && covered[line] {
-                        file.covered.insert(line as u32);
+                        file.covered.insert(reported_line);
                     }
                     true
                 }
@@ -3805,6 +5391,7 @@ impl Interpreter {
             let mut file = crate::coverage::File {
                 path: span.source.file().clone(),
                 code: span.source.contents().clone(),
+                line_offset: span.source.reported_line(0),
                 covered: BTreeSet::new(),
                 not_covered: BTreeSet::new(),
             };
@@ -3854,18 +5441,144 @@ impl Interpreter {
         self.coverage = Map::new();
     }
 
+    // Sources are matched by path and contents so that coverage can also be merged from
+    // interpreters that loaded the same policies independently.
+    #[cfg(feature = "coverage")]
+    pub fn merge_coverage(&mut self, other: &Interpreter) {
+        for (source, lines) in &other.coverage {
+            let Some(target) = self
+                .modules
+                .iter()
+                .map(|m| &m.package.refr.span().source)
+                .find(|s| {
+                    *s == source || (s.file() == source.file() && s.contents() == source.contents())
+                })
+                .cloned()
+            else {
+                continue;
+            };
+
+            let c = self.coverage.entry(target).or_default();
+            if c.len() < lines.len() {
+                c.resize(lines.len(), false);
+            }
+            for (line, covered) in lines.iter().enumerate() {
+                c[line] |= *covered;
+            }
+        }
+    }
+
     pub fn set_gather_prints(&mut self, b: bool) {
         if b != self.gather_prints {
             // Clear existing prints.
             core::mem::take(&mut self.prints);
+            self.print_groups.clear();
         }
         self.gather_prints = b;
     }
 
     pub fn take_prints(&mut self) -> Result<Vec<String>> {
+        self.print_groups.clear();
         Ok(core::mem::take(&mut self.prints))
     }
 
+    pub fn set_gather_print_values(&mut self, b: bool) {
+        if b != self.gather_print_values {
+            self.print_values.clear();
+        }
+        self.gather_print_values = b;
+    }
+
+    pub fn take_print_values(&mut self) -> Vec<Vec<Value>> {
+        core::mem::take(&mut self.print_values)
+    }
+
+    // Mark the start of prints produced for a new input.
+    pub fn start_print_group(&mut self) {
+        if self.gather_prints {
+            self.print_groups.push(self.prints.len());
+        }
+    }
+
+    pub fn take_prints_grouped(&mut self) -> Result<Vec<Vec<String>>> {
+        let groups = core::mem::take(&mut self.print_groups);
+        let mut prints = self.take_prints()?;
+
+        let mut grouped = vec![];
+        // Split from the back so that each group can be drained off the end.
+        for start in groups.into_iter().rev() {
+            grouped.push(prints.split_off(start));
+        }
+        // Prints made before any input was set.
+        if !prints.is_empty() {
+            grouped.push(prints);
+        }
+        grouped.reverse();
+        Ok(grouped)
+    }
+
+    pub fn get_rule_paths(&self) -> Vec<String> {
+        let paths: BTreeSet<&String> = self
+            .rule_paths
+            .iter()
+            .filter(|p| !self.functions.contains_key(*p))
+            .collect();
+        paths.into_iter().cloned().collect()
+    }
+
+    pub fn is_rule_path(&self, path: &str) -> bool {
+        self.rule_paths.contains(path) && !self.functions.contains_key(path)
+    }
+
+    // Path of the partial set (`p contains x`) or partial object (`p[k] := v`) defined by the rule.
+    fn get_partial_rule_path(&self, rule: &Ref<Rule>) -> Result<Option<String>> {
+        let Rule::Spec { head, .. } = rule.as_ref() else {
+            return Ok(None);
+        };
+        let refr = match head {
+            RuleHead::Set { refr, .. } => match refr.as_ref() {
+                Expr::RefBrack { refr, index, .. }
+                    if !matches!(index.as_ref(), Expr::String(_)) =>
+                {
+                    refr
+                }
+                _ => refr,
+            },
+            RuleHead::Compr { refr, .. } => match refr.as_ref() {
+                Expr::RefBrack { refr, index, .. }
+                    if !matches!(index.as_ref(), Expr::String(_)) =>
+                {
+                    refr
+                }
+                _ => return Ok(None),
+            },
+            RuleHead::Func { .. } => return Ok(None),
+        };
+        // Rules with variables in their refs, such as `p[x][y] := 1`, do not define a partial
+        // set or object at a fixed path.
+        let Ok(path) = Self::get_path_string(refr, None) else {
+            return Ok(None);
+        };
+        let module = self.get_rule_module(rule)?;
+        Ok(Some(
+            Self::get_path_string(&module.package.refr, Some("data"))? + "." + &path,
+        ))
+    }
+
+    // Whether the path is defined by partial set rules (`p contains x`) or
+    // partial object rules (`p[k] := v`).
+    pub fn is_partial_rule_path(&self, path: &str) -> Result<bool> {
+        let Some(rules) = self.rules.get(path) else {
+            return Ok(false);
+        };
+        for rule in rules {
+            if self.get_partial_rule_path(rule)?.as_deref() == Some(path) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
     pub fn eval_rule_in_path(&mut self, path: String) -> Result<Value> {
         if !self.rule_paths.contains(&path) {
             bail!("not a valid rule path");
@@ -3875,4 +5588,24 @@ impl Interpreter {
 
         Ok(Self::get_value_chained(self.data.clone(), &parts[1..]))
     }
+
+    pub fn eval_function_in_path(&mut self, path: String, args: Vec<Value>) -> Result<Value> {
+        let (fcns, nargs, module) = match self.functions.get(&path) {
+            Some((fcns, nargs, module)) => (fcns.clone(), *nargs as usize, module.clone()),
+            _ => bail!("not a valid function path"),
+        };
+        if args.len() != nargs {
+            bail!(
+                "mismatch in number of arguments. supplied {}, expected {nargs}",
+                args.len()
+            );
+        }
+        let Some(fcn) = fcns.first() else {
+            bail!("internal error: function {path} has no definitions");
+        };
+
+        // Errors that are not specific to a definition are reported at the first definition.
+        let span = Self::get_rule_refr(fcn).span().clone();
+        self.eval_user_function(&span, &path, &path, fcns, Some(module), args)
+    }
 }
@@ -1,6 +1,7 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
+use crate::error::{ErrorKind, ErrorLocation, RegorusError};
 use crate::*;
 use core::cmp;
 use core::fmt::{self, Debug, Formatter};
@@ -9,7 +10,7 @@ use core::str::CharIndices;
 
 use crate::Value;
 
-use anyhow::{anyhow, bail, Result};
+use anyhow::{bail, Result};
 
 #[derive(Clone)]
 #[cfg_attr(feature = "ast", derive(serde::Serialize))]
@@ -18,6 +19,10 @@ struct SourceInternal {
     pub contents: String,
     #[cfg_attr(feature = "ast", serde(skip_serializing))]
     pub lines: Vec<(u32, u32)>,
+    #[cfg_attr(feature = "ast", serde(skip_serializing))]
+    pub line_offset: u32,
+    #[cfg_attr(feature = "ast", serde(skip_serializing))]
+    pub col_offset: u32,
 }
 
 /// A policy file.
@@ -132,6 +137,19 @@ impl cmp::Ord for SourceStr {
 
 impl Source {
     pub fn from_contents(file: String, contents: String) -> Result<Source> {
+        Self::from_contents_with_offset(file, contents, 0, 0)
+    }
+
+    /// Create a source whose lines and columns are reported relative to an enclosing file.
+    ///
+    /// `line_offset` and `col_offset` are the number of lines and columns that precede
+    /// the contents in the enclosing file.
+    pub fn from_contents_with_offset(
+        file: String,
+        contents: String,
+        line_offset: u32,
+        col_offset: u32,
+    ) -> Result<Source> {
         let max_size = u32::MAX as usize - 2; // Account for rows, cols possibly starting at 1, EOF etc.
         if contents.len() > max_size {
             bail!("{file} exceeds maximum allowed policy file size {max_size}");
@@ -166,6 +184,8 @@ impl Source {
                 file,
                 contents,
                 lines,
+                line_offset,
+                col_offset,
             }),
         })
     }
@@ -196,12 +216,26 @@ impl Source {
         }
     }
 
+    /// Line number of the given line as reported in diagnostics.
+    pub fn reported_line(&self, line: u32) -> u32 {
+        line + self.src.line_offset
+    }
+
+    /// Column number of the given column as reported in diagnostics.
+    pub fn reported_col(&self, col: u32) -> u32 {
+        col + self.src.col_offset
+    }
+
     pub fn message(&self, line: u32, col: u32, kind: &str, msg: &str) -> String {
         if line as usize > self.src.lines.len() {
-            return format!("{}: invalid line {} specified", self.src.file, line);
+            return format!(
+                "{}: invalid line {} specified",
+                self.src.file,
+                self.reported_line(line)
+            );
         }
 
-        let line_str = format!("{line}");
+        let line_str = format!("{}", self.reported_line(line));
         let line_num_width = line_str.len() + 1;
         let col_spaces = col as usize - 1;
 
@@ -211,10 +245,10 @@ impl Source {
 		{:<line_num_width$}| {:<col_spaces$}^\n\
 		{}: {}",
             self.src.file,
-            line,
-            col,
+            line_str,
+            self.reported_col(col),
             "",
-            line,
+            line_str,
             self.line(line - 1),
             "",
             "",
@@ -224,7 +258,60 @@ impl Source {
     }
 
     pub fn error(&self, line: u32, col: u32, msg: &str) -> anyhow::Error {
-        anyhow!(self.message(line, col, "error", msg))
+        self.error_of_kind(ErrorKind::Eval, line, col, msg)
+    }
+
+    pub(crate) fn error_of_kind(
+        &self,
+        kind: ErrorKind,
+        line: u32,
+        col: u32,
+        msg: &str,
+    ) -> anyhow::Error {
+        let message = self.message(line, col, "error", msg);
+        self.error_with_reason(kind, line, col, message, msg.to_string())
+    }
+
+    // Error that also points at a related location, e.g. a conflicting rule.
+    pub(crate) fn error_with_note(
+        &self,
+        kind: ErrorKind,
+        line: u32,
+        col: u32,
+        msg: &str,
+        note: &Span,
+        note_msg: &str,
+    ) -> anyhow::Error {
+        let message = self.message(line, col, "error", msg) + &note.message("note", note_msg);
+        let reason = match note.location() {
+            Some(location) => format!("{msg} {location}: {note_msg}"),
+            None => format!("{msg} {note_msg}"),
+        };
+        self.error_with_reason(kind, line, col, message, reason)
+    }
+
+    fn error_with_reason(
+        &self,
+        kind: ErrorKind,
+        line: u32,
+        col: u32,
+        message: String,
+        reason: String,
+    ) -> anyhow::Error {
+        anyhow::Error::new(RegorusError::new(
+            kind,
+            message,
+            reason,
+            self.location(line, col),
+        ))
+    }
+
+    fn location(&self, line: u32, col: u32) -> Option<ErrorLocation> {
+        (line as usize <= self.src.lines.len()).then(|| ErrorLocation {
+            file: self.src.file.clone(),
+            line: self.reported_line(line),
+            col: self.reported_col(col),
+        })
     }
 }
 
@@ -255,6 +342,25 @@ impl Span {
     pub fn error(&self, msg: &str) -> anyhow::Error {
         self.source.error(self.line, self.col, msg)
     }
+
+    pub(crate) fn error_of_kind(&self, kind: ErrorKind, msg: &str) -> anyhow::Error {
+        self.source.error_of_kind(kind, self.line, self.col, msg)
+    }
+
+    pub(crate) fn error_with_note(
+        &self,
+        kind: ErrorKind,
+        msg: &str,
+        note: &Span,
+        note_msg: &str,
+    ) -> anyhow::Error {
+        self.source
+            .error_with_note(kind, self.line, self.col, msg, note, note_msg)
+    }
+
+    pub(crate) fn location(&self) -> Option<ErrorLocation> {
+        self.source.location(self.line, self.col)
+    }
 }
 
 impl Debug for Span {
@@ -19,20 +19,27 @@ use serde::Serialize;
 #[cfg(any(feature = "std", test))]
 extern crate std;
 
+#[cfg(feature = "arrow")]
+mod arrow;
 mod ast;
+mod ast_cache;
 mod builtins;
 mod engine;
+mod error;
 mod interpreter;
 mod lexer;
+mod lru;
 mod number;
 mod parser;
+mod partial;
 mod scheduler;
 mod utils;
 mod value;
 
-pub use engine::Engine;
+pub use engine::{compare_policies, Engine};
+pub use error::{ErrorLocation, RegorusError};
 pub use lexer::Source;
-pub use value::Value;
+pub use value::{MergeStrategy, StructuralType, Value};
 
 #[cfg(feature = "arc")]
 use alloc::sync::Arc as Rc;
@@ -333,6 +340,265 @@ pub struct QueryResults {
     pub result: Vec<QueryResult>,
 }
 
+/// Information about a policy module.
+///
+/// See [`Engine::add_policy_with_info`].
+#[derive(Debug, Clone, Default, Serialize, Eq, PartialEq)]
+pub struct ModuleInfo {
+    /// The package declared in the module, e.g. `data.test`.
+    pub package: String,
+
+    /// The paths imported by the module, e.g. `data.lib` or `rego.v1`, in declaration order.
+    pub imports: Vec<String>,
+
+    /// The sorted paths of the rules and functions defined in the module.
+    pub rule_paths: Vec<String>,
+}
+
+/// Scheduling information of a statement in a query.
+///
+/// See [`Engine::get_query_schedule`].
+#[derive(Debug, Clone, Default, Serialize, Eq, PartialEq)]
+pub struct StatementSchedule {
+    /// The text of the statement.
+    pub text: String,
+
+    /// The position of the statement in the evaluation order, starting from 0.
+    pub order: usize,
+
+    /// The variables defined by the statement.
+    pub defines: Vec<String>,
+
+    /// The variables that the statement depends on.
+    pub uses: Vec<String>,
+}
+
+/// Scheduling information of a query.
+///
+/// See [`Engine::get_query_schedule`].
+#[derive(Debug, Clone, Default, Serialize, Eq, PartialEq)]
+pub struct ScheduleInfo {
+    /// The statements of the query, in the order in which they are written.
+    pub statements: Vec<StatementSchedule>,
+}
+
+/// How a rule depends on another rule.
+///
+/// See [`DependencyEdge`].
+#[derive(Debug, Clone, Copy, Serialize, Eq, PartialEq, Ord, PartialOrd)]
+pub enum DependencyKind {
+    /// The rule calls a function.
+    Call,
+
+    /// The rule refers to a value in `data` that the other rule contributes to.
+    DataRef,
+
+    /// The rule replaces or refers to the other rule via a `with` modifier.
+    With,
+}
+
+/// A dependency of one rule on another.
+///
+/// See [`DependencyGraph`].
+#[derive(Debug, Clone, Serialize, Eq, PartialEq)]
+pub struct DependencyEdge {
+    /// The path of the dependent rule, e.g. `data.test.allow`.
+    pub source: String,
+
+    /// The path of the rule or function depended on.
+    pub target: String,
+
+    /// How `source` depends on `target`.
+    pub kind: DependencyKind,
+}
+
+/// Static dependencies between the rules of the loaded policies.
+///
+/// See [`Engine::rule_dependency_graph`].
+#[derive(Debug, Clone, Default, Serialize, Eq, PartialEq)]
+pub struct DependencyGraph {
+    /// The paths of the rules and functions, ordered such that each rule comes after the
+    /// rules it depends on.
+    pub rules: Vec<String>,
+
+    /// The dependencies, sorted by source, target and kind.
+    pub edges: Vec<DependencyEdge>,
+}
+
+/// Performance metrics of a decision.
+///
+/// See [`Engine::decide`]. Timers are only available with the `std` feature.
+#[derive(Debug, Clone, Default, Serialize, Eq, PartialEq)]
+pub struct Metrics {
+    /// Time taken to evaluate the decision, in nanoseconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timer_rego_query_eval_ns: Option<u64>,
+}
+
+/// A decision log record, following the shape of OPA's decision logs.
+///
+/// See [`Engine::decide`].
+#[derive(Debug, Clone, Serialize, Eq, PartialEq)]
+pub struct DecisionLog {
+    /// The path of the rule that was evaluated, e.g. `data.example.allow`.
+    pub path: String,
+
+    /// The input the decision was made for.
+    #[serde(skip_serializing_if = "Value::is_undefined")]
+    pub input: Value,
+
+    /// The value of the rule. [`Value::Undefined`] if the rule is undefined.
+    #[serde(skip_serializing_if = "Value::is_undefined")]
+    pub result: Value,
+
+    /// Time at which the decision was made, in nanoseconds since the Unix epoch.
+    /// Only available with the `std` feature.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp_ns: Option<i64>,
+
+    /// Performance metrics of the decision.
+    pub metrics: Metrics,
+}
+
+/// An allow/deny decision together with the reasons for denial.
+///
+/// See [`Engine::evaluate_decision`].
+#[derive(Debug, Clone, Serialize, Eq, PartialEq)]
+pub struct Decision {
+    /// Whether the allow rule evaluated to `true`.
+    pub allowed: bool,
+
+    /// The values produced by the deny rule when not allowed; empty when allowed.
+    pub reasons: Vec<Value>,
+}
+
+/// A difference between the decisions of two policy versions for one input.
+///
+/// See [`compare_policies`].
+#[derive(Debug, Clone, Serialize, Eq, PartialEq)]
+pub struct Divergence {
+    /// Index of the input in the array of inputs.
+    pub input_index: usize,
+
+    /// Value produced by the old policy.
+    pub old: Value,
+
+    /// Value produced by the new policy.
+    pub new: Value,
+}
+
+/// An immutable data document that can be shared by many engines.
+///
+/// Cloning a snapshot does not copy the data. Engines that use the snapshot share the data
+/// until one of them modifies it, at which point only the modified parts are copied.
+/// With the `arc` feature, snapshots can be sent to other threads.
+///
+/// See [`Engine::freeze_data`] and [`Engine::use_data_snapshot`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DataSnapshot {
+    data: Value,
+}
+
+impl DataSnapshot {
+    /// The data document of the snapshot.
+    pub fn data(&self) -> &Value {
+        &self.data
+    }
+}
+
+/// Signature of a builtin function.
+///
+/// See [`Engine::builtin_signature`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct BuiltinSignature {
+    /// Number of arguments.
+    pub arity: u8,
+
+    /// Names of the arguments.
+    ///
+    /// Builtins are registered without parameter names; positional names `arg1`, `arg2`, ...
+    /// are used.
+    pub arg_names: Vec<String>,
+
+    /// Whether the builtin is available only when regorus is built with the `std` feature.
+    pub requires_std: bool,
+}
+
+/// Result of partially evaluating a query.
+///
+/// See [`Engine::partial_eval`].
+#[derive(Debug, Clone)]
+pub struct PartialResult {
+    /// Residual queries. The original query is satisfied by an input if any of the residual
+    /// queries is. There are no residual queries if the query cannot be satisfied.
+    pub queries: Vec<ast::Ref<ast::Query>>,
+
+    /// The residual queries as a Rego module that defines rule `query` once per residual query.
+    pub rego: String,
+}
+
+/// Outcome of evaluating a rule.
+///
+/// See [`Engine::eval_rule_explained`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum RuleOutcome {
+    /// The rule produced a value.
+    Defined(Value),
+
+    /// There is no rule defined at the path.
+    UndefinedNoMatch,
+
+    /// The rule is defined, but none of its definitions (including any default) produced a value.
+    UndefinedAllBodiesFalse,
+
+    /// Evaluation of the rule raised an error.
+    Error(String),
+}
+
+/// Collation used for comparing strings.
+///
+/// See [`Engine::set_string_collation`].
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum Collation {
+    /// Compare strings by their bytes. This is the OPA behavior.
+    #[default]
+    Exact,
+
+    /// Compare strings by their bytes, ignoring the case of ASCII letters.
+    CaseInsensitiveAscii,
+}
+
+/// How numbers are rendered when serializing to JSON.
+///
+/// See [`Engine::set_number_output`].
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum NumberOutput {
+    /// Whole numbers are rendered as integers and others as floats, e.g. `5` and `2.5`.
+    /// Whole numbers too large for 64 bits are rendered as floats, e.g. `1e+20`.
+    #[default]
+    Natural,
+
+    /// All numbers are rendered as floats, e.g. `5.0` and `2.5`.
+    AlwaysFloat,
+
+    /// All whole numbers are rendered as integers, including those too large for 64 bits,
+    /// e.g. `100000000000000000000` instead of `1e+20`.
+    IntegerWhenWhole,
+}
+
+/// How errors produced by the engine are rendered.
+///
+/// See [`Engine::set_error_format`].
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum ErrorFormat {
+    /// Multi-line messages that show the offending source line with a caret.
+    #[default]
+    Annotated,
+
+    /// Single-line messages of the form `file:line:col: message`.
+    Terse,
+}
+
 /// A user defined builtin function implementation.
 ///
 /// It is not necessary to implement this trait directly.
@@ -370,6 +636,82 @@ impl fmt::Debug for dyn Extension {
     }
 }
 
+/// A callback that is invoked with the name and arguments of each builtin call.
+///
+/// It is not necessary to implement this trait directly.
+/// See [`Engine::set_builtin_call_logger`].
+pub trait BuiltinCallLogger: FnMut(&str, &[Value]) + Send + Sync {
+    /// Fn, FnMut etc are not sized and cannot be cloned in their boxed form.
+    /// clone_box exists to overcome that.
+    fn clone_box<'a>(&self) -> Box<dyn 'a + BuiltinCallLogger>
+    where
+        Self: 'a;
+}
+
+/// Automatically make matching closures a valid [`BuiltinCallLogger`].
+impl<F> BuiltinCallLogger for F
+where
+    F: FnMut(&str, &[Value]) + Clone + Send + Sync,
+{
+    fn clone_box<'a>(&self) -> Box<dyn 'a + BuiltinCallLogger>
+    where
+        Self: 'a,
+    {
+        Box::new(self.clone())
+    }
+}
+
+/// Implement clone for a boxed logger using [`BuiltinCallLogger::clone_box`].
+impl<'a> Clone for Box<dyn 'a + BuiltinCallLogger> {
+    fn clone(&self) -> Self {
+        (**self).clone_box()
+    }
+}
+
+impl fmt::Debug for dyn BuiltinCallLogger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> core::result::Result<(), fmt::Error> {
+        f.write_fmt(format_args!("<builtin call logger>"))
+    }
+}
+
+/// A callback that renders the values passed to `print`.
+///
+/// It is not necessary to implement this trait directly.
+/// See [`Engine::set_print_value_formatter`].
+pub trait PrintValueFormatter: Fn(&Value) -> String + Send + Sync {
+    /// Fn, FnMut etc are not sized and cannot be cloned in their boxed form.
+    /// clone_box exists to overcome that.
+    fn clone_box<'a>(&self) -> Box<dyn 'a + PrintValueFormatter>
+    where
+        Self: 'a;
+}
+
+/// Automatically make matching closures a valid [`PrintValueFormatter`].
+impl<F> PrintValueFormatter for F
+where
+    F: Fn(&Value) -> String + Clone + Send + Sync,
+{
+    fn clone_box<'a>(&self) -> Box<dyn 'a + PrintValueFormatter>
+    where
+        Self: 'a,
+    {
+        Box::new(self.clone())
+    }
+}
+
+/// Implement clone for a boxed formatter using [`PrintValueFormatter::clone_box`].
+impl<'a> Clone for Box<dyn 'a + PrintValueFormatter> {
+    fn clone(&self) -> Self {
+        (**self).clone_box()
+    }
+}
+
+impl fmt::Debug for dyn PrintValueFormatter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> core::result::Result<(), fmt::Error> {
+        f.write_fmt(format_args!("<print value formatter>"))
+    }
+}
+
 #[cfg(feature = "coverage")]
 #[cfg_attr(docsrs, doc(cfg(feature = "coverage")))]
 pub mod coverage {
@@ -384,6 +726,12 @@ pub mod coverage {
         /// The rego policy.
         pub code: String,
 
+        /// Number of lines that precede the policy in its enclosing file.
+        /// See [`Engine::add_policy_with_offset`].
+        /// Line numbers in `covered` and `not_covered` include this offset.
+        #[serde(default)]
+        pub line_offset: u32,
+
         /// Lines that were evaluated.
         pub covered: alloc::collections::BTreeSet<u32>,
 
@@ -391,6 +739,17 @@ pub mod coverage {
         pub not_covered: alloc::collections::BTreeSet<u32>,
     }
 
+    impl File {
+        // Hit count of each line that coverage is tracked for.
+        fn line_hits(&self) -> alloc::collections::BTreeMap<u32, u32> {
+            self.covered
+                .iter()
+                .map(|l| (*l, 1))
+                .chain(self.not_covered.iter().map(|l| (*l, 0)))
+                .collect()
+        }
+    }
+
     #[derive(Default, serde::Serialize, serde::Deserialize)]
     /// Policy coverage report.
     pub struct Report {
@@ -399,6 +758,31 @@ pub mod coverage {
     }
 
     impl Report {
+        /// Merge another report into this report.
+        ///
+        /// Files are matched by path. Covered lines are unioned and lines covered in either
+        /// report are removed from the lines that are not covered.
+        /// Files present only in `other` are added.
+        pub fn merge(&mut self, other: &Report) {
+            for file in other.files.iter() {
+                match self.files.iter_mut().find(|f| f.path == file.path) {
+                    Some(f) => {
+                        f.covered.extend(file.covered.iter().cloned());
+                        f.not_covered.extend(file.not_covered.iter().cloned());
+                        let covered = &f.covered;
+                        f.not_covered.retain(|l| !covered.contains(l));
+                    }
+                    None => self.files.push(File {
+                        path: file.path.clone(),
+                        code: file.code.clone(),
+                        line_offset: file.line_offset,
+                        covered: file.covered.clone(),
+                        not_covered: file.not_covered.clone(),
+                    }),
+                }
+            }
+        }
+
         /// Produce an ANSI color encoded version of the report.
         ///
         /// Covered lines are green.
@@ -417,7 +801,7 @@ pub mod coverage {
 
                 s.push_str(&format!("{}:\n", file.path));
                 for (line, code) in file.code.split('\n').enumerate() {
-                    let line = line as u32 + 1;
+                    let line = line as u32 + 1 + file.line_offset;
                     if file.not_covered.contains(&line) {
                         s.push_str(&format!("\x1b[31m {line:4}  {code}\x1b[0m\n"));
                     } else if file.covered.contains(&line) {
@@ -431,6 +815,93 @@ pub mod coverage {
             s.push('\n');
             Ok(s)
         }
+
+        /// Produce the report in LCOV format.
+        ///
+        /// Each file is emitted as an `SF:` record with a `DA:line,hits` entry per line.
+        /// Covered lines have a hit count of 1 and lines that are not covered have a hit count of 0.
+        pub fn to_lcov(&self) -> String {
+            let mut s = String::default();
+            for file in self.files.iter() {
+                s.push_str(&format!("SF:{}\n", file.path));
+                let lines = file.line_hits();
+                for (line, hits) in &lines {
+                    s.push_str(&format!("DA:{line},{hits}\n"));
+                }
+                s.push_str(&format!("LH:{}\n", file.covered.len()));
+                s.push_str(&format!("LF:{}\n", lines.len()));
+                s.push_str("end_of_record\n");
+            }
+            s
+        }
+
+        /// Produce the report in Cobertura XML format.
+        ///
+        /// Each file is emitted as a class within a single package.
+        /// Line rates are computed per file and for the report as a whole.
+        pub fn to_cobertura_xml(&self) -> String {
+            let rate = |covered: usize, valid: usize| match valid {
+                0 => 1.0,
+                _ => covered as f64 / valid as f64,
+            };
+            let covered: usize = self.files.iter().map(|f| f.covered.len()).sum();
+            let valid: usize = self
+                .files
+                .iter()
+                .map(|f| f.covered.len() + f.not_covered.len())
+                .sum();
+            let line_rate = rate(covered, valid);
+
+            let mut s = String::default();
+            s.push_str("<?xml version=\"1.0\" ?>\n");
+            s.push_str(
+                "<!DOCTYPE coverage SYSTEM \"http://cobertura.sourceforge.net/xml/coverage-04.dtd\">\n",
+            );
+            s.push_str(&format!(
+                "<coverage line-rate=\"{line_rate:.4}\" branch-rate=\"0\" lines-covered=\"{covered}\" \
+                 lines-valid=\"{valid}\" branches-covered=\"0\" branches-valid=\"0\" complexity=\"0\" \
+                 version=\"{}\" timestamp=\"0\">\n",
+                env!("CARGO_PKG_VERSION")
+            ));
+            s.push_str("  <packages>\n");
+            s.push_str(&format!(
+                "    <package name=\"policies\" line-rate=\"{line_rate:.4}\" branch-rate=\"0\" complexity=\"0\">\n"
+            ));
+            s.push_str("      <classes>\n");
+            for file in self.files.iter() {
+                let path = xml_escape(&file.path);
+                let file_rate = rate(
+                    file.covered.len(),
+                    file.covered.len() + file.not_covered.len(),
+                );
+                s.push_str(&format!(
+                    "        <class name=\"{path}\" filename=\"{path}\" line-rate=\"{file_rate:.4}\" branch-rate=\"0\" complexity=\"0\">\n"
+                ));
+                s.push_str("          <methods/>\n");
+                s.push_str("          <lines>\n");
+                let lines = file.line_hits();
+                for (line, hits) in &lines {
+                    s.push_str(&format!(
+                        "            <line number=\"{line}\" hits=\"{hits}\"/>\n"
+                    ));
+                }
+                s.push_str("          </lines>\n");
+                s.push_str("        </class>\n");
+            }
+            s.push_str("      </classes>\n");
+            s.push_str("    </package>\n");
+            s.push_str("  </packages>\n");
+            s.push_str("</coverage>\n");
+            s
+        }
+    }
+
+    fn xml_escape(s: &str) -> String {
+        s.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+            .replace('\'', "&apos;")
     }
 }
 
@@ -0,0 +1,69 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use alloc::collections::BTreeMap;
+use core::borrow::Borrow;
+
+/// A map holding a bounded number of entries.
+///
+/// When full, inserting an entry evicts the least recently used one.
+/// Lookups and insertions take logarithmic time.
+#[derive(Debug, Clone)]
+pub(crate) struct LruCache<K, V> {
+    capacity: usize,
+    // Entries along with the tick at which they were last used.
+    entries: BTreeMap<K, (V, u64)>,
+    // Keys ordered by the tick at which they were last used.
+    recency: BTreeMap<u64, K>,
+    tick: u64,
+}
+
+impl<K: Ord + Clone, V> LruCache<K, V> {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: BTreeMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+
+    pub(crate) fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let (value, tick) = self.entries.get_mut(key)?;
+        if let Some(key) = self.recency.remove(tick) {
+            self.tick += 1;
+            *tick = self.tick;
+            self.recency.insert(self.tick, key);
+        }
+        Some(value)
+    }
+
+    pub(crate) fn insert(&mut self, key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.tick += 1;
+        if let Some((_, tick)) = self.entries.insert(key.clone(), (value, self.tick)) {
+            self.recency.remove(&tick);
+        }
+        self.recency.insert(self.tick, key);
+
+        while self.entries.len() > self.capacity {
+            match self.recency.pop_first() {
+                Some((_, key)) => {
+                    self.entries.remove(&key);
+                }
+                None => break,
+            }
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+    }
+}
@@ -14,12 +14,19 @@ use crate::*;
 
 pub type BigInt = i128;
 
+// A fraction (numerator, denominator).
+type Ratio = (BigInt, BigInt);
+
 type BigFloat = scientific::Scientific;
 const PRECISION: scientific::Precision = scientific::Precision::Digits(100);
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct BigDecimal {
     d: BigFloat,
+    // Exact value as a reduced fraction when `d` is a truncated approximation of it.
+    ratio: Option<Ratio>,
+    // Whether `d` is an approximation whose exact value is not known.
+    inexact: bool,
 }
 
 impl AsRef<BigFloat> for BigDecimal {
@@ -36,15 +43,17 @@ impl AsMut<BigFloat> for BigDecimal {
 
 impl From<BigFloat> for BigDecimal {
     fn from(value: BigFloat) -> Self {
-        BigDecimal { d: value }
+        BigDecimal {
+            d: value,
+            ratio: None,
+            inexact: false,
+        }
     }
 }
 
 impl From<i128> for BigDecimal {
     fn from(value: i128) -> Self {
-        BigDecimal {
-            d: Into::<BigFloat>::into(value),
-        }
+        Into::<BigFloat>::into(value).into()
     }
 }
 
@@ -52,6 +61,25 @@ impl BigDecimal {
     fn is_integer(&self) -> bool {
         self.d.decimals() <= 0
     }
+
+    fn is_exact(&self) -> bool {
+        self.ratio.is_none() && !self.inexact
+    }
+
+    fn approximate(d: BigFloat) -> Self {
+        BigDecimal {
+            d,
+            ratio: None,
+            inexact: true,
+        }
+    }
+}
+
+fn gcd(mut a: u128, mut b: u128) -> u128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
 }
 
 #[derive(Clone)]
@@ -209,7 +237,11 @@ impl FromStr for Number {
         if let Ok(v) = BigFloat::from_str(s) {
             return Ok(v.into());
         }
-        Ok(f64::from_str(s).map_err(|_| ParseNumberError)?.into())
+        match f64::from_str(s) {
+            // Non-finite floats cannot be represented and would be parsed again by From<f64>.
+            Ok(f) if f.is_finite() => Ok(f.into()),
+            _ => Err(ParseNumberError),
+        }
     }
 }
 
@@ -244,8 +276,16 @@ impl Number {
     }
 
     pub fn add(&self, rhs: &Self) -> Result<Number> {
+        if let Some(n) = Self::rational_op(self, rhs, |(a, b), (c, d)| {
+            Some((
+                a.checked_mul(d)?.checked_add(c.checked_mul(b)?)?,
+                b.checked_mul(d)?,
+            ))
+        }) {
+            return Ok(n);
+        }
         match (self, rhs) {
-            (Big(a), Big(b)) => Ok(Big(BigDecimal::from(&a.d + &b.d).into())),
+            (Big(a), Big(b)) => Ok(Self::decimal(&a.d + &b.d, a, b)),
         }
     }
 
@@ -255,8 +295,16 @@ impl Number {
     }
 
     pub fn sub(&self, rhs: &Self) -> Result<Number> {
+        if let Some(n) = Self::rational_op(self, rhs, |(a, b), (c, d)| {
+            Some((
+                a.checked_mul(d)?.checked_sub(c.checked_mul(b)?)?,
+                b.checked_mul(d)?,
+            ))
+        }) {
+            return Ok(n);
+        }
         match (self, rhs) {
-            (Big(a), Big(b)) => Ok(Big(BigDecimal::from(&a.d - &b.d).into())),
+            (Big(a), Big(b)) => Ok(Self::decimal(&a.d - &b.d, a, b)),
         }
     }
 
@@ -266,19 +314,130 @@ impl Number {
     }
 
     pub fn mul(&self, rhs: &Self) -> Result<Number> {
+        if let Some(n) = Self::rational_op(self, rhs, |(a, b), (c, d)| {
+            Some((a.checked_mul(c)?, b.checked_mul(d)?))
+        }) {
+            return Ok(n);
+        }
         match (self, rhs) {
-            (Big(a), Big(b)) => Ok(Big(BigDecimal::from(&a.d * &b.d).into())),
+            (Big(a), Big(b)) => Ok(Self::decimal(&a.d * &b.d, a, b)),
         }
     }
 
     pub fn divide(self, rhs: &Self) -> Result<Number> {
-        match (self, rhs) {
+        if let Some(n) = Self::rational_op(&self, rhs, Self::rational_div) {
+            return Ok(n);
+        }
+        match (&self, rhs) {
             (Big(a), Big(b)) => {
                 let c =
                     a.d.div_truncate(&b.d, PRECISION)
                         .map_err(|e| anyhow!("{e}"))?;
-                Ok(Big(BigDecimal::from(c).into()))
+                // The quotient was truncated if it does not multiply back to the dividend.
+                if &c * &b.d != a.d {
+                    return Ok(Big(BigDecimal::approximate(c).into()));
+                }
+                Ok(Self::decimal(c, a, b))
+            }
+        }
+    }
+
+    /// Divide, tracking the exact quotient as a fraction if it has no finite decimal form.
+    ///
+    /// Subsequent arithmetic on the quotient is carried out on the fraction, so that
+    /// `1 / 3 * 3` is exactly `1`. Falls back to [`Number::divide`] if the operands or
+    /// the quotient cannot be represented as a fraction of `i128`s.
+    pub fn divide_exact(self, rhs: &Self) -> Result<Number> {
+        if let (Some(a), Some(b)) = (self.to_rational(), rhs.to_rational()) {
+            if let Some(n) = Self::rational_div(a, b).and_then(Self::from_rational) {
+                return Ok(n);
+            }
+        }
+        self.divide(rhs)
+    }
+
+    /// Get the exact value of the number as a reduced fraction `(numerator, denominator)`.
+    ///
+    /// The denominator is always positive. Returns `None` if the exact value is not known,
+    /// e.g. for the result of a truncated division, or if it does not fit in `i128`s.
+    pub fn to_rational(&self) -> Option<(i128, i128)> {
+        match self {
+            Big(b) if b.inexact => None,
+            Big(b) => match b.ratio {
+                Some(r) => Some(r),
+                None => {
+                    let scale = u32::try_from(b.d.decimals().max(0)).ok()?;
+                    let den = BigInt::checked_pow(10, scale)?;
+                    let num = BigInt::try_from(&(&b.d * &BigFloat::from(den))).ok()?;
+                    Self::reduce(num, den)
+                }
+            },
+        }
+    }
+
+    /// Whether the decimal value of the number is exact rather than an approximation.
+    pub fn is_exact(&self) -> bool {
+        match self {
+            Big(b) => b.is_exact(),
+        }
+    }
+
+    fn reduce(num: BigInt, den: BigInt) -> Option<Ratio> {
+        if den == 0 {
+            return None;
+        }
+        let (num, den) = if den < 0 {
+            (num.checked_neg()?, den.checked_neg()?)
+        } else {
+            (num, den)
+        };
+        // The gcd divides den and therefore fits.
+        let g = gcd(num.unsigned_abs(), den.unsigned_abs()) as BigInt;
+        Some((num / g, den / g))
+    }
+
+    fn from_rational((num, den): Ratio) -> Option<Number> {
+        let (num, den) = Self::reduce(num, den)?;
+        let (n, d) = (BigFloat::from(num), BigFloat::from(den));
+        let c = n.div_truncate(&d, PRECISION).ok()?;
+        if &c * &d == n {
+            return Some(c.into());
+        }
+        Some(Big(BigDecimal {
+            d: c,
+            ratio: Some((num, den)),
+            inexact: false,
+        }
+        .into()))
+    }
+
+    fn rational_div((a, b): Ratio, (c, d): Ratio) -> Option<Ratio> {
+        if c == 0 {
+            return None;
+        }
+        Some((a.checked_mul(d)?, b.checked_mul(c)?))
+    }
+
+    // Operate on fractions if either operand is tracked as one.
+    fn rational_op(
+        a: &Number,
+        b: &Number,
+        op: fn(Ratio, Ratio) -> Option<Ratio>,
+    ) -> Option<Number> {
+        match (a, b) {
+            (Big(x), Big(y)) if x.ratio.is_some() || y.ratio.is_some() => {
+                op(a.to_rational()?, b.to_rational()?).and_then(Self::from_rational)
             }
+            _ => None,
+        }
+    }
+
+    // Decimal operations on exact operands are exact.
+    fn decimal(c: BigFloat, a: &BigDecimal, b: &BigDecimal) -> Number {
+        if a.is_exact() && b.is_exact() {
+            c.into()
+        } else {
+            Big(BigDecimal::approximate(c).into())
         }
     }
 
@@ -303,7 +462,7 @@ impl Number {
         }
     }
 
-    fn ensure_integers(a: &Number, b: &Number) -> Option<(BigInt, BigInt)> {
+    fn ensure_integers(a: &Number, b: &Number) -> Option<Ratio> {
         match (a, b) {
             (Big(a), Big(b)) if a.is_integer() && b.is_integer() => {
                 match (BigInt::try_from(&a.d), BigInt::try_from(&b.d)) {
@@ -435,6 +594,11 @@ impl Number {
     }
 
     pub fn format_decimal(&self) -> String {
+        // Fractions tracked by exact arithmetic are rendered as floats.
+        let Big(b) = self;
+        if b.ratio.is_some() {
+            return f64::from(&b.d).to_string();
+        }
         if let Some(u) = self.as_u64() {
             u.to_string()
         } else if let Some(i) = self.as_i64() {
@@ -504,4 +668,30 @@ mod test {
         let n = Number::from(123456f64);
         assert_eq!(format!("{}", n.format_decimal()), "123456");
     }
+
+    #[test]
+    fn rational() -> Result<()> {
+        assert_eq!(Number::from(-1.25f64).to_rational(), Some((-5, 4)));
+        assert_eq!(Number::from(6u64).to_rational(), Some((6, 1)));
+
+        let third = Number::from(1u64).divide(&Number::from(3u64))?;
+        assert!(!third.is_exact());
+        assert_eq!(third.to_rational(), None);
+        let share = Number::from(100u64).divide(&Number::from(3u64))?;
+        assert_eq!(share.format_decimal(), format!("33.{}", "3".repeat(98)));
+
+        let third = Number::from(1u64).divide_exact(&Number::from(3u64))?;
+        assert!(!third.is_exact());
+        assert_eq!(third.to_rational(), Some((1, 3)));
+        let share = Number::from(100u64).divide_exact(&Number::from(3u64))?;
+        assert_eq!(share.format_decimal(), "33.333333333333336");
+        let one = third.mul(&Number::from(3u64))?;
+        assert!(one.is_exact());
+        assert_eq!(one, Number::from(1u64));
+
+        let sum = Number::from(0.1f64).add(&Number::from(0.2f64))?;
+        assert_eq!(sum, Number::from(0.3f64));
+        assert_eq!(sum.to_rational(), Some((3, 10)));
+        Ok(())
+    }
 }
@@ -2,6 +2,7 @@
 // Licensed under the MIT License.
 
 use crate::ast::*;
+use crate::error::{classify, ErrorKind};
 use crate::lexer::*;
 use crate::number::*;
 use crate::value::*;
@@ -21,6 +22,7 @@ pub struct Parser<'source> {
     end: u32,
     future_keywords: BTreeMap<String, Span>,
     rego_v1: bool,
+    max_literal_elements: usize,
 }
 
 const FUTURE_KEYWORDS: [&str; 4] = ["contains", "every", "if", "in"];
@@ -28,7 +30,7 @@ const FUTURE_KEYWORDS: [&str; 4] = ["contains", "every", "if", "in"];
 impl<'source> Parser<'source> {
     pub fn new(source: &'source Source) -> Result<Self> {
         let mut lexer = Lexer::new(source);
-        let tok = lexer.next_token()?;
+        let tok = lexer.next_token().map_err(classify(ErrorKind::Parse))?;
         Ok(Self {
             source: source.clone(),
             lexer,
@@ -37,9 +39,27 @@ impl<'source> Parser<'source> {
             end: 0,
             future_keywords: BTreeMap::new(),
             rego_v1: false,
+            max_literal_elements: usize::MAX,
         })
     }
 
+    pub fn set_max_literal_elements(&mut self, max_literal_elements: usize) {
+        self.max_literal_elements = max_literal_elements;
+    }
+
+    fn check_literal_elements(&self, span: &Span, kind: &str, len: usize) -> Result<()> {
+        if len > self.max_literal_elements {
+            bail!(span.error(
+                format!(
+                    "{kind} literal exceeds maximum of {} elements",
+                    self.max_literal_elements
+                )
+                .as_str()
+            ));
+        }
+        Ok(())
+    }
+
     pub fn enable_rego_v1(&mut self) -> Result<()> {
         self.turn_on_rego_v1(self.tok.1.clone())
     }
@@ -99,14 +119,11 @@ impl<'source> Parser<'source> {
 
     pub fn set_future_keyword(&mut self, kw: &str, span: &Span) -> Result<()> {
         match &self.future_keywords.get(kw) {
-            Some(s) if self.rego_v1 => Err(self.source.error(
-                span.line,
-                span.col,
-                format!(
-                    "this import shadows previous import of `{kw}` defined at:{}",
-                    s.message("", "this import is shadowed.")
-                )
-                .as_str(),
+            Some(s) if self.rego_v1 => Err(span.error_with_note(
+                ErrorKind::Eval,
+                format!("this import shadows previous import of `{kw}` defined at:").as_str(),
+                s,
+                "this import is shadowed.",
             )),
             _ => {
                 self.future_keywords.insert(kw.to_string(), span.clone());
@@ -360,13 +377,17 @@ impl<'source> Parser<'source> {
                 // Parse as array.
                 let mut items = vec![];
                 if self.token_text() != "]" {
+                    self.check_literal_elements(&span, "array", 1)?;
                     items.push(Ref::new(self.parse_in_expr()?));
                     while self.token_text() == "," {
                         self.next_token()?;
                         match self.token_text() {
                             "]" => break,
                             "" if self.tok.0 == TokenKind::Eof => break,
-                            _ => items.push(Ref::new(self.parse_in_expr()?)),
+                            _ => {
+                                self.check_literal_elements(&span, "array", items.len() + 1)?;
+                                items.push(Ref::new(self.parse_in_expr()?))
+                            }
                         }
                     }
                 }
@@ -380,6 +401,7 @@ impl<'source> Parser<'source> {
 
     fn parse_compr_set_or_object(&mut self) -> Result<Expr> {
         let mut span = self.tok.1.clone();
+        let open_span = span.clone();
         self.expect("{", "while parsing set, object or comprehension")?;
 
         let pos = self.end;
@@ -416,13 +438,17 @@ impl<'source> Parser<'source> {
 
         if self.token_text() != ":" {
             // Parse as set.
+            self.check_literal_elements(&open_span, "set", 1)?;
             let mut items = vec![Ref::new(first)];
             while self.token_text() == "," {
                 self.next_token()?;
                 match self.token_text() {
                     "}" => break,
                     "" if self.tok.0 == TokenKind::Eof => break,
-                    _ => items.push(Ref::new(self.parse_in_expr()?)),
+                    _ => {
+                        self.check_literal_elements(&open_span, "set", items.len() + 1)?;
+                        items.push(Ref::new(self.parse_in_expr()?))
+                    }
                 }
             }
             self.expect("}", "while parsing set")?;
@@ -453,6 +479,7 @@ impl<'source> Parser<'source> {
         }
 
         // Parse object
+        self.check_literal_elements(&open_span, "object", 1)?;
         let mut items = vec![];
 
         let value = self.parse_in_expr()?;
@@ -465,7 +492,10 @@ impl<'source> Parser<'source> {
             let key = match self.token_text() {
                 "}" => break,
                 "" if self.tok.0 == TokenKind::Eof => break,
-                _ => self.parse_in_expr()?,
+                _ => {
+                    self.check_literal_elements(&open_span, "object", items.len() + 1)?;
+                    self.parse_in_expr()?
+                }
             };
 
             let mut item_span = self.tok.1.clone();
@@ -1755,6 +1785,10 @@ impl<'source> Parser<'source> {
     }
 
     pub fn parse(&mut self) -> Result<Module> {
+        self.parse_module().map_err(classify(ErrorKind::Parse))
+    }
+
+    fn parse_module(&mut self) -> Result<Module> {
         let package = self.parse_package()?;
         let imports = self.parse_imports()?;
 
@@ -1773,9 +1807,12 @@ impl<'source> Parser<'source> {
 
     pub fn parse_user_query(&mut self) -> Result<Ref<Query>> {
         let span = self.tok.1.clone();
-        let query = Ref::new(self.parse_query(span, "")?);
+        let query = Ref::new(
+            self.parse_query(span, "")
+                .map_err(classify(ErrorKind::Parse))?,
+        );
         if self.tok.0 != TokenKind::Eof {
-            bail!(self.tok.1.error("expecting EOF"));
+            bail!(self.tok.1.error_of_kind(ErrorKind::Parse, "expecting EOF"));
         }
         Ok(query)
     }
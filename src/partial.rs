@@ -0,0 +1,952 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+// Partial evaluation of queries.
+//
+// Parts of the input designated as unknown are treated symbolically. Expressions that depend
+// on unknowns are retained in residual queries with all known values substituted, while
+// everything else is evaluated by the engine. Rules that depend on unknowns are inlined when
+// used as conditions; each of their bodies produces a separate residual query.
+
+use crate::ast::*;
+use crate::engine::Engine;
+use crate::lexer::*;
+use crate::parser::Parser;
+use crate::utils::{get_path_string, visit_exprs_in_rule};
+use crate::value::Value;
+use crate::*;
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use anyhow::{bail, Result};
+
+// Name of the binding used to retrieve values evaluated by the engine.
+const VALUE_BINDING: &str = "__partial_value";
+
+#[derive(Clone)]
+enum Term {
+    Known(Value),
+    // Rego text that computes the value. Atomic texts can be used as operands without
+    // parentheses.
+    Symbolic { text: String, atomic: bool },
+}
+
+type Env = BTreeMap<String, Term>;
+
+// A residual conjunction along with the variables bound while producing it.
+type Branch = (Env, Vec<String>);
+
+// Rules and functions by path, along with the module that defines them.
+type RuleTable = BTreeMap<String, Vec<(Ref<Module>, Ref<Rule>)>>;
+
+pub struct PartialEvaluator<'a> {
+    engine: &'a mut Engine,
+    unknowns: Vec<String>,
+    rules: RuleTable,
+    // Whether the value at a data path depends on unknowns.
+    depends: BTreeMap<String, bool>,
+    // Rules currently being inlined.
+    inlining: BTreeSet<String>,
+}
+
+impl<'a> PartialEvaluator<'a> {
+    pub fn new(
+        engine: &'a mut Engine,
+        modules: Vec<Ref<Module>>,
+        unknowns: Vec<String>,
+    ) -> Result<Self> {
+        for u in &unknowns {
+            if u != "input" && !u.starts_with("input.") {
+                bail!("unknown `{u}` must be `input` or a path within `input`");
+            }
+        }
+
+        let mut rules = RuleTable::new();
+        for module in modules {
+            let package = get_path_string(&module.package.refr, Some("data"))?;
+            for rule in &module.policy {
+                let refr = match rule.as_ref() {
+                    Rule::Spec { head, .. } => match head {
+                        RuleHead::Compr { refr, .. }
+                        | RuleHead::Set { refr, .. }
+                        | RuleHead::Func { refr, .. } => refr,
+                    },
+                    Rule::Default { refr, .. } => refr,
+                };
+                // Rules with dynamic keys are recorded at the static prefix of their paths.
+                let refr = match refr.as_ref() {
+                    Expr::RefBrack { refr, index, .. }
+                        if !matches!(index.as_ref(), Expr::String(_)) =>
+                    {
+                        refr
+                    }
+                    _ => refr,
+                };
+                let comps = Parser::get_path_ref_components(refr)?;
+                let comps: Vec<&str> = comps.iter().map(|s| s.text()).collect();
+                rules
+                    .entry(format!("{package}.{}", comps.join(".")))
+                    .or_default()
+                    .push((module.clone(), rule.clone()));
+            }
+        }
+
+        Ok(Self {
+            engine,
+            unknowns,
+            rules,
+            depends: BTreeMap::new(),
+            inlining: BTreeSet::new(),
+        })
+    }
+
+    // Returns the residual conjunctions under which the query is satisfied.
+    pub fn eval(&mut self, query: &Ref<Query>) -> Result<Vec<Vec<String>>> {
+        let mut queries: Vec<Vec<String>> = vec![];
+        for (_, conj) in self.eval_query(query, None, Env::new())? {
+            if !queries.contains(&conj) {
+                queries.push(conj);
+            }
+        }
+        Ok(queries)
+    }
+
+    fn eval_query(
+        &mut self,
+        query: &Ref<Query>,
+        module: Option<&Ref<Module>>,
+        env: Env,
+    ) -> Result<Vec<Branch>> {
+        let mut branches = vec![(env, vec![])];
+        for stmt in &query.stmts {
+            let mut next = vec![];
+            for (env, conj) in branches {
+                for (env, residual) in self.eval_stmt(stmt, module, env)? {
+                    let mut conj = conj.clone();
+                    conj.extend(residual);
+                    next.push((env, conj));
+                }
+            }
+            branches = next;
+        }
+        Ok(branches)
+    }
+
+    fn eval_stmt(
+        &mut self,
+        stmt: &LiteralStmt,
+        module: Option<&Ref<Module>>,
+        mut env: Env,
+    ) -> Result<Vec<Branch>> {
+        if !stmt.with_mods.is_empty() {
+            bail!(stmt
+                .span
+                .error("partial evaluation does not support `with`"));
+        }
+        match &stmt.literal {
+            Literal::Expr { expr, .. } => {
+                if let Expr::AssignExpr { op, lhs, rhs, .. } = expr.as_ref() {
+                    if let Expr::Var(v) = lhs.as_ref() {
+                        let name = v.0.text();
+                        if *op == AssignOp::ColEq || !self.is_bound(name, module, &env) {
+                            return match self.eval_term(rhs, module, &env)? {
+                                Term::Known(Value::Undefined) => Ok(vec![]),
+                                t => {
+                                    env.insert(name.to_string(), t);
+                                    Ok(vec![(env, vec![])])
+                                }
+                            };
+                        }
+                    } else if *op == AssignOp::ColEq {
+                        bail!(lhs
+                            .span()
+                            .error("partial evaluation does not support destructuring"));
+                    }
+                }
+
+                if let Some(path) = self.rule_condition_path(expr, module, &env)? {
+                    return Ok(self
+                        .inline_rules(&path, expr.span())?
+                        .into_iter()
+                        .map(|conj| (env.clone(), conj))
+                        .collect());
+                }
+
+                Ok(match self.eval_term(expr, module, &env)? {
+                    Term::Known(Value::Bool(false) | Value::Undefined) => vec![],
+                    Term::Known(_) => vec![(env, vec![])],
+                    Term::Symbolic { text, .. } => vec![(env, vec![text])],
+                })
+            }
+            Literal::NotExpr { span, expr } => {
+                if self.rule_condition_path(expr, module, &env)?.is_some() {
+                    bail!(span.error(
+                        "partial evaluation does not support negating rules that depend on unknowns"
+                    ));
+                }
+                Ok(match self.eval_term(expr, module, &env)? {
+                    Term::Known(Value::Bool(false) | Value::Undefined) => vec![(env, vec![])],
+                    Term::Known(_) => vec![],
+                    t => {
+                        let text = format!("not {}", Self::operand(&t)?);
+                        vec![(env, vec![text])]
+                    }
+                })
+            }
+            Literal::SomeVars { span, .. } | Literal::SomeIn { span, .. } => {
+                bail!(span.error("partial evaluation does not support `some`"))
+            }
+            Literal::Every { span, .. } => {
+                bail!(span.error("partial evaluation does not support `every`"))
+            }
+        }
+    }
+
+    // Returns the residual conjunctions under which the rules at the path produce a value
+    // other than false.
+    fn inline_rules(&mut self, path: &str, span: &Span) -> Result<Vec<Vec<String>>> {
+        if !self.inlining.insert(path.to_string()) {
+            bail!(span.error(&format!("recursion detected while inlining {path}")));
+        }
+        let mut disjuncts = vec![];
+        for (module, rule) in self.rules.get(path).cloned().unwrap_or_default() {
+            match rule.as_ref() {
+                Rule::Default { value, .. } if matches!(value.as_ref(), Expr::False(_)) => (),
+                Rule::Spec {
+                    head: RuleHead::Compr { assign, .. },
+                    bodies,
+                    ..
+                } => {
+                    for (idx, body) in bodies.iter().enumerate() {
+                        if idx > 0 && body.span.text().starts_with("else") {
+                            bail!(body
+                                .span
+                                .error("partial evaluation does not support `else`"));
+                        }
+                        let value = body.assign.as_ref().or(assign.as_ref());
+                        for (env, mut conj) in
+                            self.eval_query(&body.query, Some(&module), Env::new())?
+                        {
+                            let Some(value) = value else {
+                                disjuncts.push(conj);
+                                continue;
+                            };
+                            match self.eval_term(&value.value, Some(&module), &env)? {
+                                Term::Known(Value::Bool(false) | Value::Undefined) => continue,
+                                Term::Known(_) => (),
+                                t => conj.push(Self::operand(&t)?),
+                            }
+                            disjuncts.push(conj);
+                        }
+                    }
+                }
+                _ => bail!(rule.span().error(
+                    "partial evaluation only supports inlining complete rules whose default value is false"
+                )),
+            }
+        }
+        self.inlining.remove(path);
+        Ok(disjuncts)
+    }
+
+    // The path of the complete rules referred to by the expression if their value depends on
+    // unknowns.
+    fn rule_condition_path(
+        &mut self,
+        expr: &ExprRef,
+        module: Option<&Ref<Module>>,
+        env: &Env,
+    ) -> Result<Option<String>> {
+        if !matches!(
+            expr.as_ref(),
+            Expr::Var(_) | Expr::RefDot { .. } | Expr::RefBrack { .. }
+        ) {
+            return Ok(None);
+        }
+        let (root, comps) = Self::split_ref(expr);
+        let Some(mut path) = self.data_root(root, module, env) else {
+            return Ok(None);
+        };
+        for comp in comps {
+            match comp {
+                Comp::Field(f) => path = path + "." + f,
+                Comp::Index(e) => match e.as_ref() {
+                    Expr::String(s) => path = path + "." + s.0.text(),
+                    _ => return Ok(None),
+                },
+            }
+        }
+        if self.rules.contains_key(&path) && self.depends_on_unknowns(&path)? {
+            return Ok(Some(path));
+        }
+        Ok(None)
+    }
+
+    fn is_bound(&self, name: &str, module: Option<&Ref<Module>>, env: &Env) -> bool {
+        env.contains_key(name)
+            || name == "input"
+            || name == "data"
+            || self.data_root(name, module, env).is_some()
+    }
+
+    // Resolves a variable that refers to data, i.e. `data`, a rule of the current package
+    // or an import of data.
+    fn data_root(&self, name: &str, module: Option<&Ref<Module>>, env: &Env) -> Option<String> {
+        if env.contains_key(name) {
+            return None;
+        }
+        if name == "data" {
+            return Some(name.to_string());
+        }
+        let module = module?;
+        if let Some(path) = Self::import_path(name, module) {
+            return path.starts_with("data.").then_some(path);
+        }
+        let package = get_path_string(&module.package.refr, Some("data")).ok()?;
+        let path = format!("{package}.{name}");
+        let under = format!("{path}.");
+        self.rules
+            .keys()
+            .any(|p| *p == path || p.starts_with(&under))
+            .then_some(path)
+    }
+
+    fn import_path(name: &str, module: &Ref<Module>) -> Option<String> {
+        module.imports.iter().find_map(|import| {
+            let alias = match &import.r#as {
+                Some(alias) => alias.text(),
+                None => match import.refr.as_ref() {
+                    Expr::RefDot { field, .. } => field.0.text(),
+                    Expr::RefBrack { index, .. } => match index.as_ref() {
+                        Expr::String(s) => s.0.text(),
+                        _ => return None,
+                    },
+                    _ => return None,
+                },
+            };
+            if alias != name {
+                return None;
+            }
+            get_path_string(&import.refr, None).ok()
+        })
+    }
+
+    fn split_ref(mut expr: &ExprRef) -> (&str, Vec<Comp<'_>>) {
+        let mut comps = vec![];
+        let root = loop {
+            match expr.as_ref() {
+                Expr::RefDot { refr, field, .. } => {
+                    comps.push(Comp::Field(field.0.text()));
+                    expr = refr;
+                }
+                Expr::RefBrack { refr, index, .. } => {
+                    comps.push(Comp::Index(index));
+                    expr = refr;
+                }
+                Expr::Var(v) => break v.0.text(),
+                _ => break "",
+            }
+        };
+        comps.reverse();
+        (root, comps)
+    }
+
+    fn overlaps_unknowns(&self, path: &str) -> bool {
+        self.unknowns.iter().any(|u| {
+            let (short, long) = if u.len() <= path.len() {
+                (u.as_str(), path)
+            } else {
+                (path, u.as_str())
+            };
+            long == short || long.starts_with(&format!("{short}."))
+        })
+    }
+
+    // Whether the value at the data path depends on unknowns.
+    fn depends_on_unknowns(&mut self, path: &str) -> Result<bool> {
+        if let Some(depends) = self.depends.get(path) {
+            return Ok(*depends);
+        }
+        // Assume independence while the path is being analyzed to handle cycles.
+        self.depends.insert(path.to_string(), false);
+
+        let under = format!("{path}.");
+        let rules: Vec<_> = self
+            .rules
+            .iter()
+            .filter(|(p, _)| {
+                *p == path || p.starts_with(&under) || path.starts_with(&format!("{p}."))
+            })
+            .flat_map(|(_, rules)| rules.iter().cloned())
+            .collect();
+
+        let mut depends = false;
+        for (module, rule) in rules {
+            let mut paths = vec![];
+            let mut visited = BTreeSet::new();
+            visit_exprs_in_rule(&rule, &mut |e| {
+                if visited.contains(&(e.as_ref() as *const Expr)) {
+                    return Ok(());
+                }
+                match e.as_ref() {
+                    Expr::Call { fcn, .. } => {
+                        if let Ok(name) = get_path_string(fcn, None) {
+                            paths.push(self.function_path(&name, Some(&module)));
+                        }
+                    }
+                    Expr::Var(_) | Expr::RefDot { .. } | Expr::RefBrack { .. } => {
+                        paths.push(self.static_prefix(e, &module, &mut visited));
+                    }
+                    _ => (),
+                }
+                Ok(())
+            })?;
+            for p in paths.into_iter().flatten() {
+                if p.starts_with("input") && self.overlaps_unknowns(&p)
+                    || p.starts_with("data") && self.depends_on_unknowns(&p)?
+                {
+                    depends = true;
+                    break;
+                }
+            }
+            if depends {
+                break;
+            }
+        }
+        self.depends.insert(path.to_string(), depends);
+        Ok(depends)
+    }
+
+    // Static prefix of a ref into input or data, e.g. `input.a` for `input.a[x].b`.
+    fn static_prefix(
+        &self,
+        mut expr: &ExprRef,
+        module: &Ref<Module>,
+        visited: &mut BTreeSet<*const Expr>,
+    ) -> Option<String> {
+        let mut comps = vec![];
+        loop {
+            visited.insert(expr.as_ref() as *const Expr);
+            match expr.as_ref() {
+                Expr::RefDot { refr, field, .. } => {
+                    comps.push(field.0.text());
+                    expr = refr;
+                }
+                Expr::RefBrack { refr, index, .. } => {
+                    match index.as_ref() {
+                        Expr::String(s) => comps.push(s.0.text()),
+                        _ => comps.clear(),
+                    }
+                    expr = refr;
+                }
+                Expr::Var(v) => {
+                    let name = v.0.text();
+                    let root = match name {
+                        "input" => name.to_string(),
+                        _ => match Self::import_path(name, module) {
+                            Some(path) => path,
+                            None => self.data_root(name, Some(module), &Env::new())?,
+                        },
+                    };
+                    comps.reverse();
+                    return Some(comps.into_iter().fold(root, |p, c| p + "." + c));
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    fn function_path(&self, name: &str, module: Option<&Ref<Module>>) -> Option<String> {
+        let path = match (name.starts_with("data."), module) {
+            (true, _) => name.to_string(),
+            (false, Some(module)) => {
+                let package = get_path_string(&module.package.refr, Some("data")).ok()?;
+                format!("{package}.{name}")
+            }
+            (false, None) => return None,
+        };
+        self.rules.contains_key(&path).then_some(path)
+    }
+
+    fn eval_term(
+        &mut self,
+        expr: &ExprRef,
+        module: Option<&Ref<Module>>,
+        env: &Env,
+    ) -> Result<Term> {
+        use Expr::*;
+        Ok(match expr.as_ref() {
+            String((_, v)) | RawString((_, v)) | Number((_, v)) => Term::Known(v.clone()),
+            True(_) => Term::Known(Value::Bool(true)),
+            False(_) => Term::Known(Value::Bool(false)),
+            Null(_) => Term::Known(Value::Null),
+            Var(_) | RefDot { .. } | RefBrack { .. } => self.eval_ref(expr, module, env)?,
+            Array { items, .. } => {
+                let items = self.eval_terms(items, module, env)?;
+                match Self::known_values(&items) {
+                    Some(values) => Term::Known(Value::from(values)),
+                    None => Self::composite("[", &items, "]")?,
+                }
+            }
+            Set { items, .. } => {
+                let items = self.eval_terms(items, module, env)?;
+                match Self::known_values(&items) {
+                    Some(values) => {
+                        Term::Known(Value::from(values.into_iter().collect::<BTreeSet<_>>()))
+                    }
+                    None => Self::composite("{", &items, "}")?,
+                }
+            }
+            Object { fields, .. } => {
+                let mut terms = vec![];
+                for (_, key, value) in fields {
+                    terms.push(self.eval_term(key, module, env)?);
+                    terms.push(self.eval_term(value, module, env)?);
+                }
+                match Self::known_values(&terms) {
+                    Some(values) => {
+                        let mut obj = BTreeMap::new();
+                        for kv in values.chunks(2) {
+                            obj.insert(kv[0].clone(), kv[1].clone());
+                        }
+                        Term::Known(Value::from(obj))
+                    }
+                    None if terms.iter().any(Self::is_undefined) => Term::Known(Value::Undefined),
+                    None => {
+                        let mut fields = vec![];
+                        for kv in terms.chunks(2) {
+                            fields.push(format!(
+                                "{}: {}",
+                                Self::operand(&kv[0])?,
+                                Self::operand(&kv[1])?
+                            ));
+                        }
+                        Term::Symbolic {
+                            text: format!("{{{}}}", fields.join(", ")),
+                            atomic: true,
+                        }
+                    }
+                }
+            }
+            Call { span, fcn, params } => {
+                let name = get_path_string(fcn, None)?;
+                let function = self.function_path(&name, module);
+                if let Some(path) = &function {
+                    if self.depends_on_unknowns(path)? {
+                        bail!(span.error(&format!(
+                            "partial evaluation does not support calling function {path} that depends on unknowns"
+                        )));
+                    }
+                }
+                let args = self.eval_terms(params, module, env)?;
+                if args.iter().any(Self::is_undefined) {
+                    return Ok(Term::Known(Value::Undefined));
+                }
+                let known = Self::known_values(&args).is_some();
+                if !known && function.is_some() {
+                    bail!(span.error(&format!(
+                        "partial evaluation does not support calling function {name} with unknown arguments"
+                    )));
+                }
+                let args: Vec<_> = args.iter().map(Self::operand).collect::<Result<_>>()?;
+                let text = format!("{}({})", function.unwrap_or(name), args.join(", "));
+                match known {
+                    true => Term::Known(self.eval_value(&text)?),
+                    false => Term::Symbolic { text, atomic: true },
+                }
+            }
+            UnaryExpr { expr, .. } => {
+                let t = self.eval_term(expr, module, env)?;
+                self.eval_operation(&[t], |ops| format!("-{}", ops[0]))?
+            }
+            BinExpr { op, lhs, rhs, .. } => {
+                let op = match op {
+                    BinOp::Intersection => "&",
+                    BinOp::Union => "|",
+                };
+                self.eval_infix(op, lhs, rhs, module, env)?
+            }
+            BoolExpr { op, lhs, rhs, .. } => {
+                let op = match op {
+                    BoolOp::Lt => "<",
+                    BoolOp::Le => "<=",
+                    BoolOp::Eq => "==",
+                    BoolOp::Ge => ">=",
+                    BoolOp::Gt => ">",
+                    BoolOp::Ne => "!=",
+                };
+                self.eval_infix(op, lhs, rhs, module, env)?
+            }
+            ArithExpr { op, lhs, rhs, .. } => {
+                let op = match op {
+                    ArithOp::Add => "+",
+                    ArithOp::Sub => "-",
+                    ArithOp::Mul => "*",
+                    ArithOp::Div => "/",
+                    ArithOp::Mod => "%",
+                };
+                self.eval_infix(op, lhs, rhs, module, env)?
+            }
+            // Unification of bound terms is a comparison.
+            AssignExpr {
+                op: AssignOp::Eq,
+                lhs,
+                rhs,
+                ..
+            } => self.eval_infix("==", lhs, rhs, module, env)?,
+            Membership {
+                key,
+                value,
+                collection,
+                ..
+            } => {
+                let mut terms = vec![];
+                if let Some(key) = key {
+                    terms.push(self.eval_term(key, module, env)?);
+                }
+                terms.push(self.eval_term(value, module, env)?);
+                terms.push(self.eval_term(collection, module, env)?);
+                match Self::known_values(&terms) {
+                    Some(values) => Term::Known(Value::Bool(Self::contains(&values))),
+                    None if terms.iter().any(Self::is_undefined) => Term::Known(Value::Undefined),
+                    None => {
+                        let ops: Vec<_> = terms.iter().map(Self::operand).collect::<Result<_>>()?;
+                        let text = match ops.as_slice() {
+                            [k, v, c] => format!("{k}, {v} in {c}"),
+                            [v, c] => format!("{v} in {c}"),
+                            _ => unreachable!(),
+                        };
+                        Term::Symbolic {
+                            text,
+                            atomic: false,
+                        }
+                    }
+                }
+            }
+            _ => bail!(expr
+                .span()
+                .error("partial evaluation does not support this expression")),
+        })
+    }
+
+    fn eval_terms(
+        &mut self,
+        exprs: &[ExprRef],
+        module: Option<&Ref<Module>>,
+        env: &Env,
+    ) -> Result<Vec<Term>> {
+        exprs
+            .iter()
+            .map(|e| self.eval_term(e, module, env))
+            .collect()
+    }
+
+    fn eval_infix(
+        &mut self,
+        op: &str,
+        lhs: &ExprRef,
+        rhs: &ExprRef,
+        module: Option<&Ref<Module>>,
+        env: &Env,
+    ) -> Result<Term> {
+        let terms = [
+            self.eval_term(lhs, module, env)?,
+            self.eval_term(rhs, module, env)?,
+        ];
+        self.eval_operation(&terms, |ops| format!("{} {op} {}", ops[0], ops[1]))
+    }
+
+    // Evaluates an operation on the given operands if they are all known.
+    fn eval_operation(
+        &mut self,
+        terms: &[Term],
+        render: impl Fn(&[String]) -> String,
+    ) -> Result<Term> {
+        if terms.iter().any(Self::is_undefined) {
+            return Ok(Term::Known(Value::Undefined));
+        }
+        let ops: Vec<_> = terms.iter().map(Self::operand).collect::<Result<_>>()?;
+        let text = render(&ops);
+        match Self::known_values(terms) {
+            Some(_) => Ok(Term::Known(self.eval_value(&text)?)),
+            None => Ok(Term::Symbolic {
+                text,
+                atomic: false,
+            }),
+        }
+    }
+
+    fn eval_ref(
+        &mut self,
+        expr: &ExprRef,
+        module: Option<&Ref<Module>>,
+        env: &Env,
+    ) -> Result<Term> {
+        let (root, comps) = Self::split_ref(expr);
+        let mut terms = vec![];
+        for comp in &comps {
+            terms.push(match comp {
+                Comp::Field(f) => Term::Known(Value::from(*f)),
+                Comp::Index(e) => self.eval_term(e, module, env)?,
+            });
+        }
+        if terms.iter().any(Self::is_undefined) {
+            return Ok(Term::Known(Value::Undefined));
+        }
+
+        // Number of leading components whose values are known.
+        let known = terms
+            .iter()
+            .take_while(|t| matches!(t, Term::Known(_)))
+            .count();
+
+        let base = if let Some(t) = env.get(root) {
+            t.clone()
+        } else if root == "input" {
+            let path = Self::path(root, &terms[..known])?;
+            if self.overlaps_unknowns(&path) {
+                return Ok(Term::Symbolic {
+                    text: Self::render_ref(root, &terms)?,
+                    atomic: true,
+                });
+            }
+            return self.apply_comps(&Self::render_ref(root, &terms[..known])?, &terms[known..]);
+        } else if let Some(root) = self.resolve_root(root, module, env) {
+            let path = Self::path(&root, &terms[..known])?;
+            if root.starts_with("input") {
+                if self.overlaps_unknowns(&path) {
+                    return Ok(Term::Symbolic {
+                        text: Self::render_ref(&root, &terms)?,
+                        atomic: true,
+                    });
+                }
+            } else if self.depends_on_unknowns(&path)? {
+                bail!(expr.span().error(&format!(
+                    "partial evaluation does not support using {path} as a value since it depends on unknowns"
+                )));
+            }
+            return self.apply_comps(&Self::render_ref(&root, &terms[..known])?, &terms[known..]);
+        } else {
+            bail!(expr.span().error(&format!(
+                "partial evaluation does not support unbound variable `{root}`"
+            )));
+        };
+
+        match base {
+            Term::Known(Value::Undefined) => Ok(base),
+            Term::Known(v) if known == terms.len() => {
+                let text = Self::render_ref(&Self::literal(&v)?, &terms)?;
+                Ok(Term::Known(self.eval_value(&text)?))
+            }
+            base => Ok(Term::Symbolic {
+                text: Self::render_ref(&Self::operand(&base)?, &terms)?,
+                atomic: true,
+            }),
+        }
+    }
+
+    fn resolve_root(&self, name: &str, module: Option<&Ref<Module>>, env: &Env) -> Option<String> {
+        if let Some(path) = module.and_then(|m| Self::import_path(name, m)) {
+            return Some(path);
+        }
+        self.data_root(name, module, env)
+    }
+
+    // Evaluates the known prefix of a ref and applies the remaining symbolic components.
+    fn apply_comps(&mut self, prefix: &str, terms: &[Term]) -> Result<Term> {
+        let base = self.eval_value(prefix)?;
+        match (base, terms.is_empty()) {
+            (Value::Undefined, _) => Ok(Term::Known(Value::Undefined)),
+            (v, true) => Ok(Term::Known(v)),
+            (v, false) => Ok(Term::Symbolic {
+                text: Self::render_ref(&Self::literal(&v)?, terms)?,
+                atomic: true,
+            }),
+        }
+    }
+
+    fn path(root: &str, terms: &[Term]) -> Result<String> {
+        let mut path = root.to_string();
+        for t in terms {
+            path.push('.');
+            match t {
+                Term::Known(Value::String(s)) => path.push_str(s),
+                t => path.push_str(&Self::operand(t)?),
+            }
+        }
+        Ok(path)
+    }
+
+    fn eval_value(&mut self, text: &str) -> Result<Value> {
+        let results = self
+            .engine
+            .eval_query(format!("{VALUE_BINDING} := {text}"), false)?;
+        Ok(match results.result.first() {
+            Some(r) => r.bindings[VALUE_BINDING].clone(),
+            None => Value::Undefined,
+        })
+    }
+
+    fn known_values(terms: &[Term]) -> Option<Vec<Value>> {
+        terms
+            .iter()
+            .map(|t| match t {
+                Term::Known(v) if *v != Value::Undefined => Some(v.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn is_undefined(t: &Term) -> bool {
+        matches!(t, Term::Known(Value::Undefined))
+    }
+
+    fn contains(values: &[Value]) -> bool {
+        match values {
+            [key, value, collection] => match collection {
+                Value::Array(a) => key
+                    .as_number()
+                    .ok()
+                    .and_then(|n| n.as_u64())
+                    .and_then(|i| a.get(i as usize))
+                    .is_some_and(|v| v == value),
+                Value::Object(o) => o.get(key) == Some(value),
+                _ => false,
+            },
+            [value, collection] => match collection {
+                Value::Array(a) => a.contains(value),
+                Value::Set(s) => s.contains(value),
+                Value::Object(o) => o.values().any(|v| v == value),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    fn composite(open: &str, items: &[Term], close: &str) -> Result<Term> {
+        if items.iter().any(Self::is_undefined) {
+            return Ok(Term::Known(Value::Undefined));
+        }
+        let items: Vec<_> = items.iter().map(Self::operand).collect::<Result<_>>()?;
+        Ok(Term::Symbolic {
+            text: format!("{open}{}{close}", items.join(", ")),
+            atomic: true,
+        })
+    }
+
+    // Rego text for a term that can be used as an operand.
+    fn operand(t: &Term) -> Result<String> {
+        match t {
+            Term::Known(v) => Self::literal(v),
+            Term::Symbolic { text, atomic: true } => Ok(text.clone()),
+            Term::Symbolic { text, .. } => Ok(format!("({text})")),
+        }
+    }
+
+    fn render_ref(base: &str, terms: &[Term]) -> Result<String> {
+        let mut text = base.to_string();
+        for t in terms {
+            match t {
+                Term::Known(Value::String(s)) if Self::is_identifier(s) => {
+                    text.push('.');
+                    text.push_str(s);
+                }
+                t => {
+                    text.push('[');
+                    text.push_str(&Self::operand(t)?);
+                    text.push(']');
+                }
+            }
+        }
+        Ok(text)
+    }
+
+    fn is_identifier(s: &str) -> bool {
+        let mut chars = s.chars();
+        chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+            && !matches!(
+                s,
+                "as" | "contains"
+                    | "data"
+                    | "default"
+                    | "else"
+                    | "every"
+                    | "false"
+                    | "if"
+                    | "import"
+                    | "in"
+                    | "input"
+                    | "not"
+                    | "null"
+                    | "package"
+                    | "some"
+                    | "true"
+                    | "with"
+            )
+    }
+
+    // Rego literal for a value.
+    fn literal(v: &Value) -> Result<String> {
+        Ok(match v {
+            Value::Null | Value::Bool(_) | Value::String(_) => serde_json::to_string(v)?,
+            Value::Number(n) => n.format_decimal(),
+            Value::Array(a) => {
+                let items: Vec<_> = a.iter().map(Self::literal).collect::<Result<_>>()?;
+                format!("[{}]", items.join(", "))
+            }
+            Value::Set(s) if s.is_empty() => "set()".to_string(),
+            Value::Set(s) => {
+                let items: Vec<_> = s.iter().map(Self::literal).collect::<Result<_>>()?;
+                format!("{{{}}}", items.join(", "))
+            }
+            Value::Object(o) => {
+                let mut fields = vec![];
+                for (k, v) in o.iter() {
+                    fields.push(format!("{}: {}", Self::literal(k)?, Self::literal(v)?));
+                }
+                format!("{{{}}}", fields.join(", "))
+            }
+            Value::Undefined => bail!("internal error: undefined value in residual query"),
+        })
+    }
+}
+
+enum Comp<'a> {
+    Field(&'a str),
+    Index(&'a ExprRef),
+}
+
+// Renders residual conjunctions as a module with one `query` rule per conjunction, and parses
+// each conjunction into a query.
+pub fn make_result(queries: Vec<Vec<String>>) -> Result<PartialResult> {
+    let mut rego = "package partial\n\nimport rego.v1\n".to_string();
+    let mut asts = vec![];
+    for conj in &queries {
+        if conj.is_empty() {
+            rego.push_str("\nquery := true\n");
+        } else {
+            rego.push_str("\nquery if {\n");
+            for expr in conj {
+                rego.push('\t');
+                rego.push_str(expr);
+                rego.push('\n');
+            }
+            rego.push_str("}\n");
+        }
+
+        let text = match conj.is_empty() {
+            true => "true".to_string(),
+            false => conj.join("; "),
+        };
+        let source = Source::from_contents("<residual>".to_string(), text)?;
+        let mut parser = Parser::new(&source)?;
+        parser.enable_rego_v1()?;
+        asts.push(parser.parse_user_query()?);
+    }
+    Ok(PartialResult {
+        queries: asts,
+        rego,
+    })
+}
@@ -3,6 +3,7 @@
 
 use crate::ast::Expr::{Set, *};
 use crate::ast::*;
+use crate::error::ErrorKind;
 use crate::lexer::*;
 use crate::utils::*;
 use crate::*;
@@ -387,6 +388,7 @@ pub struct Analyzer {
     scope_table: BTreeMap<Ref<Query>, Scope>,
     scopes: Vec<Scope>,
     order: BTreeMap<Ref<Query>, Vec<u16>>,
+    stmt_infos: BTreeMap<Ref<Query>, Vec<StmtInfo<SourceStr>>>,
     functions: FunctionTable,
     current_module_path: String,
 }
@@ -412,6 +414,7 @@ impl Analyzer {
             scope_table: BTreeMap::new(),
             scopes: vec![],
             order: BTreeMap::new(),
+            stmt_infos: BTreeMap::new(),
             functions: FunctionTable::new(),
             current_module_path: String::default(),
         }
@@ -445,6 +448,47 @@ impl Analyzer {
         })
     }
 
+    // Analyze a query snippet in which unqualified references may refer to
+    // the rules of the given package, e.g. `data.test`.
+    pub fn analyze_query_snippet_in_package(
+        mut self,
+        modules: &[Ref<Module>],
+        package: &str,
+        query: &Ref<Query>,
+    ) -> Result<Schedule> {
+        self.add_rules_and_aliases(modules)?;
+        if let Some(scope) = self.packages.get(package) {
+            self.scopes.push(scope.clone());
+        }
+        self.current_module_path = package.to_string();
+        self.analyze_query(None, None, query, Scope::default())?;
+
+        Ok(Schedule {
+            scopes: self.scope_table,
+            order: self.order,
+        })
+    }
+
+    // Analyze a query snippet, also returning the variable definitions of each statement
+    // of the query, in the order in which the statements are written.
+    pub fn analyze_query_snippet_with_infos(
+        mut self,
+        modules: &[Ref<Module>],
+        query: &Ref<Query>,
+    ) -> Result<(Schedule, Vec<StmtInfo<SourceStr>>)> {
+        self.add_rules_and_aliases(modules)?;
+        self.analyze_query(None, None, query, Scope::default())?;
+
+        let infos = self.stmt_infos.remove(query).unwrap_or_default();
+        Ok((
+            Schedule {
+                scopes: self.scope_table,
+                order: self.order,
+            },
+            infos,
+        ))
+    }
+
     fn add_rules_and_aliases(&mut self, modules: &[Ref<Module>]) -> Result<()> {
         for m in modules {
             let path = get_path_string(&m.package.refr, Some("data"))?;
@@ -464,9 +508,16 @@ impl Analyzer {
             }
 
             for import in &m.imports {
-                if let Some(var) = &import.r#as {
-                    scope.unscoped.insert(var.source_str());
-                }
+                let root = get_root_var(&import.refr)?;
+                let var = match (&import.r#as, import.refr.as_ref()) {
+                    (Some(var), _) => var.source_str(),
+                    // Imports without an alias are referred to by the last field.
+                    (_, Expr::RefDot { field, .. }) if matches!(root.text(), "data" | "input") => {
+                        field.0.source_str()
+                    }
+                    _ => continue,
+                };
+                scope.unscoped.insert(var);
             }
         }
 
@@ -948,12 +999,11 @@ impl Analyzer {
         let name = var.source_str();
         if let Some(r#use) = first_use.get(&name) {
             if r#use.line < var.line || (r#use.line == var.line && r#use.col < var.col) {
-                bail!(r#use.error(
-                    format!(
-                        "var `{name}` used before definition below.{}",
-                        var.message("definition", "")
-                    )
-                    .as_str()
+                bail!(r#use.error_with_note(
+                    ErrorKind::Eval,
+                    format!("var `{name}` used before definition below.").as_str(),
+                    var,
+                    "definition"
                 ));
             }
         }
@@ -1229,6 +1279,7 @@ impl Analyzer {
             _ => (),
         }
         self.scope_table.insert(query.clone(), scope);
+        self.stmt_infos.insert(query.clone(), infos);
 
         Ok(())
     }
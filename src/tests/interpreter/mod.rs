@@ -21,12 +21,7 @@ pub fn process_value(v: &Value) -> Result<Value> {
         //   - item2
         //   ...
         Value::Object(ref fields) if fields.len() == 1 && matches!(&v["set!"], Value::Array(_)) => {
-            let mut set_value = Value::new_set();
-            let set = set_value.as_set_mut()?;
-            for item in v["set!"].as_array()? {
-                set.insert(process_value(item)?);
-            }
-            Ok(set_value)
+            process_value(&v["set!"])?.into_set()
         }
 
         // Handle complex object specified explicitly:
@@ -139,9 +134,11 @@ pub fn eval_file(
     query: &str,
     enable_tracing: bool,
     strict: bool,
+    collation: Collation,
 ) -> Result<(Vec<Value>, Vec<String>)> {
     let mut engine: Engine = Engine::new();
     engine.set_strict_builtin_errors(strict);
+    engine.set_string_collation(collation);
     engine.set_gather_prints(true);
 
     #[cfg(feature = "coverage")]
@@ -264,6 +261,7 @@ struct TestCase {
     want_error_code: Option<String>,
     #[serde(default = "default_strict")]
     strict: bool,
+    collation: Option<String>,
 }
 
 fn default_strict() -> bool {
@@ -326,6 +324,11 @@ fn yaml_test_impl(file: &str) -> Result<()> {
         }
 
         let enable_tracing = case.traces.is_some() && case.traces.unwrap();
+        let collation = match case.collation.as_deref() {
+            None | Some("exact") => Collation::Exact,
+            Some("case-insensitive-ascii") => Collation::CaseInsensitiveAscii,
+            Some(c) => bail!("unknown collation {c}"),
+        };
 
         match eval_file(
             &case.modules,
@@ -334,6 +337,7 @@ fn yaml_test_impl(file: &str) -> Result<()> {
             case.query.as_str(),
             enable_tracing,
             case.strict,
+            collation,
         ) {
             Ok((results, prints)) => match case.want_result {
                 Some(want_result) => {
@@ -4,6 +4,7 @@
 use crate::ast::*;
 use crate::builtins::*;
 use crate::lexer::*;
+use crate::scheduler::traverse;
 use crate::*;
 
 use alloc::collections::BTreeMap;
@@ -115,6 +116,148 @@ pub fn gather_functions(modules: &[Ref<Module>]) -> Result<FunctionTable> {
     Ok(table)
 }
 
+// Call the given closure with the span and function expression of each call within the rule.
+// Calls are not allowed in default values and are reported when the rule is evaluated,
+// hence default values are not visited.
+pub fn visit_calls_in_rule(
+    rule: &Ref<Rule>,
+    f: &mut dyn FnMut(&Span, &ExprRef) -> Result<()>,
+) -> Result<()> {
+    visit_exprs_in_rule(rule, &mut |e| match e.as_ref() {
+        Expr::Call { span, fcn, .. } => f(span, fcn),
+        _ => Ok(()),
+    })
+}
+
+// Visit every expression in the head and bodies of a rule, including the targets of
+// with modifiers and the expressions within comprehensions. Default values are not visited.
+pub fn visit_exprs_in_rule(
+    rule: &Ref<Rule>,
+    f: &mut dyn FnMut(&ExprRef) -> Result<()>,
+) -> Result<()> {
+    match rule.as_ref() {
+        Rule::Spec { head, bodies, .. } => {
+            match head {
+                RuleHead::Compr { refr, assign, .. } => {
+                    visit_exprs_in_expr(refr, f)?;
+                    if let Some(assign) = assign {
+                        visit_exprs_in_expr(&assign.value, f)?;
+                    }
+                }
+                RuleHead::Set { refr, key, .. } => {
+                    visit_exprs_in_expr(refr, f)?;
+                    if let Some(key) = key {
+                        visit_exprs_in_expr(key, f)?;
+                    }
+                }
+                RuleHead::Func { assign, .. } => {
+                    if let Some(assign) = assign {
+                        visit_exprs_in_expr(&assign.value, f)?;
+                    }
+                }
+            }
+            for body in bodies {
+                if let Some(assign) = &body.assign {
+                    visit_exprs_in_expr(&assign.value, f)?;
+                }
+                visit_exprs_in_query(&body.query, f)?;
+            }
+            Ok(())
+        }
+        Rule::Default { .. } => Ok(()),
+    }
+}
+
+// Call the given closure with each with modifier within the rule, including those of
+// statements within comprehensions and every expressions.
+pub fn visit_with_mods_in_rule(
+    rule: &Ref<Rule>,
+    f: &mut dyn FnMut(&WithModifier) -> Result<()>,
+) -> Result<()> {
+    fn visit_query(
+        query: &Ref<Query>,
+        f: &mut dyn FnMut(&WithModifier) -> Result<()>,
+    ) -> Result<()> {
+        for stmt in &query.stmts {
+            for wm in &stmt.with_mods {
+                f(wm)?;
+            }
+            if let Literal::Every { query, .. } = &stmt.literal {
+                visit_query(query, f)?;
+            }
+        }
+        Ok(())
+    }
+
+    if let Rule::Spec { bodies, .. } = rule.as_ref() {
+        for body in bodies {
+            visit_query(&body.query, f)?;
+        }
+    }
+    visit_exprs_in_rule(rule, &mut |e| match e.as_ref() {
+        Expr::ArrayCompr { query, .. }
+        | Expr::SetCompr { query, .. }
+        | Expr::ObjectCompr { query, .. } => visit_query(query, f),
+        _ => Ok(()),
+    })
+}
+
+fn visit_exprs_in_query(
+    query: &Ref<Query>,
+    f: &mut dyn FnMut(&ExprRef) -> Result<()>,
+) -> Result<()> {
+    for stmt in &query.stmts {
+        for wm in &stmt.with_mods {
+            visit_exprs_in_expr(&wm.refr, f)?;
+            visit_exprs_in_expr(&wm.r#as, f)?;
+        }
+        match &stmt.literal {
+            Literal::SomeVars { .. } => (),
+            Literal::SomeIn {
+                key,
+                value,
+                collection,
+                ..
+            } => {
+                if let Some(key) = key {
+                    visit_exprs_in_expr(key, f)?;
+                }
+                visit_exprs_in_expr(value, f)?;
+                visit_exprs_in_expr(collection, f)?;
+            }
+            Literal::Expr { expr, .. } | Literal::NotExpr { expr, .. } => {
+                visit_exprs_in_expr(expr, f)?;
+            }
+            Literal::Every { domain, query, .. } => {
+                visit_exprs_in_expr(domain, f)?;
+                visit_exprs_in_query(query, f)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn visit_exprs_in_expr(expr: &ExprRef, f: &mut dyn FnMut(&ExprRef) -> Result<()>) -> Result<()> {
+    traverse(expr, &mut |e| {
+        f(e)?;
+        match e.as_ref() {
+            Expr::ArrayCompr { term, query, .. } | Expr::SetCompr { term, query, .. } => {
+                visit_exprs_in_expr(term, f)?;
+                visit_exprs_in_query(query, f)?;
+            }
+            Expr::ObjectCompr {
+                key, value, query, ..
+            } => {
+                visit_exprs_in_expr(key, f)?;
+                visit_exprs_in_expr(value, f)?;
+                visit_exprs_in_query(query, f)?;
+            }
+            _ => (),
+        }
+        Ok(true)
+    })
+}
+
 pub fn get_root_var(mut expr: &Expr) -> Result<SourceStr> {
     let empty = expr.span().source_str().clone_empty();
     loop {
@@ -1,6 +1,7 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
+use crate::builtins::types::get_type;
 use crate::number::Number;
 
 use alloc::collections::{BTreeMap, BTreeSet};
@@ -48,10 +49,12 @@ pub enum Value {
     /// A set of values.
     /// No JSON equivalent.
     /// Sets are serialized as arrays in JSON.
+    /// Elements are always iterated in sorted order, with or without the `std` feature.
     Set(Rc<BTreeSet<Value>>),
 
     /// An object.
     /// Unlike JSON, keys can be any value, not just string.
+    /// Keys are always iterated in sorted order, with or without the `std` feature.
     Object(Rc<BTreeMap<Value, Value>>),
 
     /// Undefined value.
@@ -213,6 +216,512 @@ impl<'de> Deserialize<'de> for Value {
     }
 }
 
+// Deserializes native types directly from a value without going through JSON.
+struct ValueDeserializer<'de>(&'de Value);
+
+impl<'de> Deserializer<'de> for ValueDeserializer<'de> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0 {
+            Value::Null => visitor.visit_unit(),
+            Value::Bool(b) => visitor.visit_bool(*b),
+            Value::Number(n) => {
+                if let Some(v) = n.as_u64() {
+                    visitor.visit_u64(v)
+                } else if let Some(v) = n.as_i64() {
+                    visitor.visit_i64(v)
+                } else if let Some(v) = n.as_u128() {
+                    visitor.visit_u128(v)
+                } else if let Some(v) = n.as_i128() {
+                    visitor.visit_i128(v)
+                } else {
+                    match f64::from_str(&n.format_decimal()) {
+                        Ok(v) => visitor.visit_f64(v),
+                        _ => Err(de::Error::custom("could not deserialize number")),
+                    }
+                }
+            }
+            Value::String(s) => visitor.visit_borrowed_str(s.as_ref()),
+            // Sets are deserialized as sequences.
+            Value::Array(a) => visitor.visit_seq(de::value::SeqDeserializer::new(
+                a.iter().map(ValueDeserializer),
+            )),
+            Value::Set(s) => visitor.visit_seq(de::value::SeqDeserializer::new(
+                s.iter().map(ValueDeserializer),
+            )),
+            Value::Object(fields) => visitor.visit_map(de::value::MapDeserializer::new(
+                fields
+                    .iter()
+                    .map(|(k, v)| (ValueDeserializer(k), ValueDeserializer(v))),
+            )),
+            Value::Undefined => Err(de::Error::custom("cannot deserialize undefined")),
+        }
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0 {
+            Value::Null | Value::Undefined => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0 {
+            // Unit variant.
+            Value::String(_) => visitor.visit_enum(EnumDeserializer(self.0, None)),
+            // Variant with contents represented as a single entry object.
+            Value::Object(fields) if fields.len() == 1 => match fields.iter().next() {
+                Some((k, v)) => visitor.visit_enum(EnumDeserializer(k, Some(v))),
+                _ => Err(de::Error::custom("expected enum")),
+            },
+            _ => Err(de::Error::custom("expected enum")),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+impl<'de> de::IntoDeserializer<'de, de::value::Error> for ValueDeserializer<'de> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+struct EnumDeserializer<'de>(&'de Value, Option<&'de Value>);
+
+impl<'de> de::EnumAccess<'de> for EnumDeserializer<'de> {
+    type Error = de::value::Error;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self), Self::Error>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        Ok((seed.deserialize(ValueDeserializer(self.0))?, self))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for EnumDeserializer<'de> {
+    type Error = de::value::Error;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        match self.1 {
+            None => Ok(()),
+            Some(v) => de::Deserialize::deserialize(ValueDeserializer(v)),
+        }
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Self::Error>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        match self.1 {
+            Some(v) => seed.deserialize(ValueDeserializer(v)),
+            None => Err(de::Error::custom("expected newtype variant")),
+        }
+    }
+
+    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.1 {
+            Some(v) => ValueDeserializer(v).deserialize_any(visitor),
+            None => Err(de::Error::custom("expected tuple variant")),
+        }
+    }
+
+    fn struct_variant<V>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.1 {
+            Some(v) => ValueDeserializer(v).deserialize_any(visitor),
+            None => Err(de::Error::custom("expected struct variant")),
+        }
+    }
+}
+
+// Serializes native types directly into a value without going through JSON.
+struct ValueSerializer;
+
+// Accumulates the elements of sequences, tuples and variants thereof.
+struct SerializeSeq {
+    variant: Option<&'static str>,
+    items: Vec<Value>,
+}
+
+// Accumulates the fields of maps, structs and struct variants.
+struct SerializeObject {
+    variant: Option<&'static str>,
+    key: Option<Value>,
+    fields: BTreeMap<Value, Value>,
+}
+
+// Variants with contents are represented as a single entry object.
+fn wrap_variant(variant: Option<&'static str>, value: Value) -> Value {
+    match variant {
+        Some(variant) => Value::from(BTreeMap::from([(Value::from(variant), value)])),
+        None => value,
+    }
+}
+
+impl Serializer for ValueSerializer {
+    type Ok = Value;
+    type Error = de::value::Error;
+    type SerializeSeq = SerializeSeq;
+    type SerializeTuple = SerializeSeq;
+    type SerializeTupleStruct = SerializeSeq;
+    type SerializeTupleVariant = SerializeSeq;
+    type SerializeMap = SerializeObject;
+    type SerializeStruct = SerializeObject;
+    type SerializeStructVariant = SerializeObject;
+
+    fn serialize_bool(self, v: bool) -> Result<Value, Self::Error> {
+        Ok(Value::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value, Self::Error> {
+        Ok(Value::from(v as i64))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value, Self::Error> {
+        Ok(Value::from(v as i64))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value, Self::Error> {
+        Ok(Value::from(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value, Self::Error> {
+        Ok(Value::from(v))
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Value, Self::Error> {
+        Ok(Value::from(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value, Self::Error> {
+        Ok(Value::from(v as u64))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value, Self::Error> {
+        Ok(Value::from(v as u64))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value, Self::Error> {
+        Ok(Value::from(v))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value, Self::Error> {
+        Ok(Value::from(v))
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Value, Self::Error> {
+        Ok(Value::from(v))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value, Self::Error> {
+        Ok(Value::from(v as f64))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value, Self::Error> {
+        Ok(Value::from(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value, Self::Error> {
+        Ok(Value::from(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value, Self::Error> {
+        Ok(Value::from(v))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value, Self::Error> {
+        Ok(Value::from_bytes(v))
+    }
+
+    fn serialize_none(self) -> Result<Value, Self::Error> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Value, Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value, Self::Error> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, Self::Error> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Value, Self::Error> {
+        Ok(Value::from(variant))
+    }
+
+    fn serialize_newtype_struct<T>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Value, Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Value, Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        Ok(wrap_variant(Some(variant), value.serialize(self)?))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeSeq, Self::Error> {
+        Ok(SerializeSeq {
+            variant: None,
+            items: Vec::with_capacity(len.unwrap_or_default()),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SerializeSeq, Self::Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SerializeSeq, Self::Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SerializeSeq, Self::Error> {
+        Ok(SerializeSeq {
+            variant: Some(variant),
+            items: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<SerializeObject, Self::Error> {
+        Ok(SerializeObject {
+            variant: None,
+            key: None,
+            fields: BTreeMap::new(),
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SerializeObject, Self::Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<SerializeObject, Self::Error> {
+        Ok(SerializeObject {
+            variant: Some(variant),
+            key: None,
+            fields: BTreeMap::new(),
+        })
+    }
+}
+
+impl serde::ser::SerializeSeq for SerializeSeq {
+    type Ok = Value;
+    type Error = de::value::Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.items.push(value.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, Self::Error> {
+        Ok(wrap_variant(self.variant, Value::from(self.items)))
+    }
+}
+
+impl serde::ser::SerializeTuple for SerializeSeq {
+    type Ok = Value;
+    type Error = de::value::Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        serde::ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value, Self::Error> {
+        serde::ser::SerializeSeq::end(self)
+    }
+}
+
+impl serde::ser::SerializeTupleStruct for SerializeSeq {
+    type Ok = Value;
+    type Error = de::value::Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        serde::ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value, Self::Error> {
+        serde::ser::SerializeSeq::end(self)
+    }
+}
+
+impl serde::ser::SerializeTupleVariant for SerializeSeq {
+    type Ok = Value;
+    type Error = de::value::Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        serde::ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value, Self::Error> {
+        serde::ser::SerializeSeq::end(self)
+    }
+}
+
+impl SerializeMap for SerializeObject {
+    type Ok = Value;
+    type Error = de::value::Error;
+
+    // Keys need not be strings.
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.key = Some(key.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        match self.key.take() {
+            Some(key) => {
+                self.fields.insert(key, value.serialize(ValueSerializer)?);
+                Ok(())
+            }
+            None => Err(serde::ser::Error::custom("map value serialized before key")),
+        }
+    }
+
+    fn end(self) -> Result<Value, Self::Error> {
+        Ok(wrap_variant(self.variant, Value::from(self.fields)))
+    }
+}
+
+impl serde::ser::SerializeStruct for SerializeObject {
+    type Ok = Value;
+    type Error = de::value::Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.fields
+            .insert(Value::from(key), value.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, Self::Error> {
+        SerializeMap::end(self)
+    }
+}
+
+impl serde::ser::SerializeStructVariant for SerializeObject {
+    type Ok = Value;
+    type Error = de::value::Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        serde::ser::SerializeStruct::serialize_field(self, key, value)
+    }
+
+    fn end(self) -> Result<Value, Self::Error> {
+        SerializeMap::end(self)
+    }
+}
+
 impl fmt::Display for Value {
     /// Display a value.
     ///
@@ -249,6 +758,25 @@ impl Value {
         Value::from(vec![])
     }
 
+    /// Create a [`Value::Array`] of numbers from bytes.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let v = Value::from_bytes(b"Hi");
+    /// assert_eq!(v, Value::from_json_str("[72, 105]")?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_bytes(bytes: &[u8]) -> Value {
+        Value::from(
+            bytes
+                .iter()
+                .map(|b| Value::from(*b as u64))
+                .collect::<Vec<Value>>(),
+        )
+    }
+
     /// Create an empty [`Value::Object`]
     ///
     /// ```
@@ -276,6 +804,62 @@ impl Value {
     pub fn new_set() -> Value {
         Value::from(BTreeSet::new())
     }
+
+    /// Convert an array into a set.
+    ///
+    /// Duplicate elements are removed and the remaining elements are sorted.
+    /// A set is returned as is.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let set = Value::from_json_str("[3, 1, 2, 1]")?.into_set()?;
+    /// assert_eq!(set.as_set()?.len(), 3);
+    /// assert_eq!(set.into_array()?, Value::from_json_str("[1, 2, 3]")?);
+    ///
+    /// // Only arrays and sets can be converted to sets.
+    /// assert!(Value::from("hello").into_set().is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn into_set(self) -> Result<Value> {
+        match self {
+            Value::Set(_) => Ok(self),
+            Value::Array(a) => Ok(Value::from(
+                Rc::unwrap_or_clone(a)
+                    .into_iter()
+                    .collect::<BTreeSet<Value>>(),
+            )),
+            _ => bail!("cannot convert {} to set", get_type(&self)),
+        }
+    }
+
+    /// Convert a set into an array.
+    ///
+    /// The elements of the array are in sorted order.
+    /// An array is returned as is.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let array = Value::from_json_str("[2, 1]")?.into_set()?.into_array()?;
+    /// assert_eq!(array[0], Value::from(1));
+    /// assert_eq!(array[1], Value::from(2));
+    ///
+    /// // Only arrays and sets can be converted to arrays.
+    /// assert!(Value::new_object().into_array().is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn into_array(self) -> Result<Value> {
+        match self {
+            Value::Array(_) => Ok(self),
+            Value::Set(s) => Ok(Value::from(
+                Rc::unwrap_or_clone(s).into_iter().collect::<Vec<Value>>(),
+            )),
+            _ => bail!("cannot convert {} to array", get_type(&self)),
+        }
+    }
 }
 
 impl Value {
@@ -312,6 +896,58 @@ impl Value {
         serde_json::from_str(json).map_err(anyhow::Error::msg)
     }
 
+    /// Deserialize a native type from the value.
+    ///
+    /// Unlike serializing the value to JSON and deserializing the JSON, no intermediate
+    /// string is produced. Sets are deserialized as sequences and `null` or undefined values
+    /// as `None` for optional fields.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// #[derive(serde::Deserialize)]
+    /// struct User {
+    ///   name: String,
+    ///   roles: Vec<String>,
+    /// }
+    ///
+    /// let value = Value::from_json_str(r#"{"name": "alice", "roles": ["admin"]}"#)?;
+    /// let user: User = value.deserialize_into()?;
+    /// assert_eq!(user.name, "alice");
+    /// assert_eq!(user.roles, vec!["admin"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn deserialize_into<T: serde::de::DeserializeOwned>(&self) -> Result<T> {
+        T::deserialize(ValueDeserializer(self)).map_err(anyhow::Error::msg)
+    }
+
+    /// Create a value from any serializable type.
+    ///
+    /// Unlike serializing to JSON and deserializing the JSON, no intermediate string is
+    /// produced and map keys need not be strings. Sequences and tuples become arrays,
+    /// structs and maps become objects, unit variants become strings and other enum variants
+    /// become single entry objects keyed by the variant name.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # use std::collections::BTreeMap;
+    /// # fn main() -> anyhow::Result<()> {
+    /// #[derive(serde::Serialize)]
+    /// struct Limits {
+    ///   ports: BTreeMap<u16, String>,
+    /// }
+    ///
+    /// let limits = Limits { ports: BTreeMap::from([(80, "http".to_string())]) };
+    /// let value = Value::from_serde(&limits)?;
+    /// assert_eq!(value["ports"][&Value::from(80)], Value::from("http"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_serde<T: Serialize + ?Sized>(value: &T) -> Result<Value> {
+        value.serialize(ValueSerializer).map_err(anyhow::Error::msg)
+    }
+
     /// Deserialize a [`Value`] from a file containing JSON.
     ///
     /// ```
@@ -336,18 +972,41 @@ impl Value {
         }
     }
 
-    /// Serialize a value to JSON.
+    /// Deserialize a value from a reader producing JSON.
+    ///
+    /// The JSON is parsed as it is read, without first reading it into a string. The reader is
+    /// buffered internally.
     ///
     /// ```
     /// # use regorus::*;
     /// # fn main() -> anyhow::Result<()> {
-    /// let value = Value::from_json_file("tests/aci/input.json")?;
+    /// let file = std::fs::File::open("tests/aci/input.json")?;
+    /// let value = Value::from_json_reader(file)?;
+    ///
+    /// assert_eq!(value, Value::from_json_file("tests/aci/input.json")?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "std")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+    pub fn from_json_reader<R: std::io::Read>(reader: R) -> Result<Value> {
+        serde_json::from_reader(std::io::BufReader::new(reader)).map_err(anyhow::Error::msg)
+    }
+
+    /// Serialize a value to JSON.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// # #[cfg(feature = "std")] {
+    /// let value = Value::from_json_file("tests/aci/input.json")?;
     ///
     /// // Convert the value back to json.
     /// let json_str = value.to_json_str()?;
     ///
     /// assert_eq!(json_str.trim(),
     ///            std::fs::read_to_string("tests/aci/input.json")?.trim().replace("\r\n", "\n"));
+    /// # }
     /// # Ok(())
     /// # }
     /// ```
@@ -401,11 +1060,67 @@ impl Value {
     }
 
     /// Deserialize a value from YAML.
+    ///
+    /// Duplicate mapping keys, tags other than the standard ones (e.g. `!!str`), `%TAG`
+    /// directives and non-finite numbers such as `.inf` are rejected since they have no JSON
+    /// equivalent.
     /// Note: Deserialization from YAML does not support arbitrary precision numbers.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let v = Value::from_yaml_str("name: app\nports: [80, 443]")?;
+    /// assert_eq!(v, Value::from_json_str(r#"{"name": "app", "ports": [80, 443]}"#)?);
+    ///
+    /// assert!(Value::from_yaml_str("a: 1\na: 2").is_err());
+    /// let err = Value::from_yaml_str("a:\n  b: !secret xyz").unwrap_err();
+    /// assert_eq!(err.to_string(), "unsupported YAML tag `!secret` at `a.b`");
+    /// # Ok(())
+    /// # }
+    /// ```
     #[cfg(feature = "yaml")]
-    #[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+    #[cfg_attr(docsrs, doc(cfg(feature = "yaml")))]
     pub fn from_yaml_str(yaml: &str) -> Result<Value> {
-        Ok(serde_yaml::from_str(yaml)?)
+        // Tags resolved via %TAG directives are not retained by serde_yaml.
+        if yaml.lines().any(|l| l.starts_with("%TAG")) {
+            bail!("YAML %TAG directives are not supported");
+        }
+        // serde_yaml::Value rejects duplicate keys and retains tags.
+        let value: serde_yaml::Value = serde_yaml::from_str(yaml)?;
+        Self::check_yaml_value(&value, "")?;
+        Ok(serde_yaml::from_value(value)?)
+    }
+
+    #[cfg(feature = "yaml")]
+    fn check_yaml_value(value: &serde_yaml::Value, path: &str) -> Result<()> {
+        let at = || match path {
+            "" => "document root".to_string(),
+            _ => format!("`{path}`"),
+        };
+        match value {
+            serde_yaml::Value::Tagged(t) => bail!("unsupported YAML tag `{}` at {}", t.tag, at()),
+            serde_yaml::Value::Number(n) if n.as_f64().is_some_and(|f| !f.is_finite()) => {
+                bail!("unsupported YAML number `{n}` at {}", at())
+            }
+            serde_yaml::Value::Sequence(items) => {
+                for (idx, item) in items.iter().enumerate() {
+                    Self::check_yaml_value(item, &format!("{path}[{idx}]"))?;
+                }
+            }
+            serde_yaml::Value::Mapping(fields) => {
+                for (key, field) in fields {
+                    let key_path = match key {
+                        serde_yaml::Value::String(k) if path.is_empty() => k.clone(),
+                        serde_yaml::Value::String(k) => format!("{path}.{k}"),
+                        _ => format!("{path}[{}]", serde_yaml::to_string(key)?.trim()),
+                    };
+                    Self::check_yaml_value(key, &key_path)?;
+                    Self::check_yaml_value(field, &key_path)?;
+                }
+            }
+            _ => (),
+        }
+        Ok(())
     }
 
     /// Deserialize a value from a file containing YAML.
@@ -763,6 +1478,10 @@ impl Value {
     pub(crate) fn is_empty_object(&self) -> bool {
         self == &Value::new_object()
     }
+
+    pub(crate) fn is_undefined(&self) -> bool {
+        self == &Value::Undefined
+    }
 }
 
 impl Value {
@@ -1180,6 +1899,35 @@ impl Value {
         }
     }
 
+    /// Convert a [`Value::Array`] of numbers to bytes.
+    ///
+    /// Error is raised if the value is not an array or if any element is not an integer in 0..=255.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let v = Value::from_json_str("[72, 105]")?;
+    /// assert_eq!(v.as_bytes()?, b"Hi");
+    ///
+    /// let v = Value::from_json_str("[72, 256]")?;
+    /// assert!(v.as_bytes().is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn as_bytes(&self) -> Result<Vec<u8>> {
+        self.as_array()?
+            .iter()
+            .enumerate()
+            .map(|(idx, v)| match v {
+                Value::Number(n) => n
+                    .as_u64()
+                    .and_then(|n| u8::try_from(n).ok())
+                    .ok_or_else(|| anyhow!("element {idx} is not a byte: {v}")),
+                _ => Err(anyhow!("element {idx} is not a number")),
+            })
+            .collect()
+    }
+
     /// Cast value to [`& BTreeSet<Value>`] if [`Value::Set`].
     /// ```
     /// # use regorus::*;
@@ -1266,6 +2014,519 @@ impl Value {
             _ => Err(anyhow!("not an object")),
         }
     }
+
+    /// Iterate over the key-value pairs of a [`Value::Object`] in key order.
+    ///
+    /// Yields nothing for values that are not objects.
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// let results = engine.eval_query(r#"{"b": 2, "a": 1}"#.to_string(), false)?;
+    /// let obj = &results.result[0].expressions[0].value;
+    ///
+    /// let mut total = 0;
+    /// for (key, value) in obj.entries() {
+    ///     println!("{key} = {value}");
+    ///     total += value.as_i64()?;
+    /// }
+    /// assert_eq!(total, 3);
+    /// assert_eq!(obj.entries().next(), Some((&Value::from("a"), &Value::from(1))));
+    ///
+    /// assert_eq!(Value::from(1).entries().count(), 0);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn entries(&self) -> impl Iterator<Item = (&Value, &Value)> {
+        let map = match self {
+            Value::Object(m) => Some(m.iter()),
+            _ => None,
+        };
+        map.into_iter().flatten()
+    }
+
+    /// Iterate over the elements of a [`Value::Array`] or [`Value::Set`].
+    ///
+    /// Arrays are visited in index order and sets in sorted order.
+    /// Yields nothing for other values.
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// let results = engine.eval_query(r#"{"x": [3, 1], "y": {"b", "a"}}"#.to_string(), false)?;
+    /// let obj = &results.result[0].expressions[0].value;
+    ///
+    /// let x: Vec<&Value> = obj["x"].elements().collect();
+    /// assert_eq!(x, [&Value::from(3), &Value::from(1)]);
+    ///
+    /// let y: Vec<&Value> = obj["y"].elements().collect();
+    /// assert_eq!(y, [&Value::from("a"), &Value::from("b")]);
+    ///
+    /// assert_eq!(obj.elements().count(), 0);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn elements(&self) -> impl Iterator<Item = &Value> {
+        let (array, set) = match self {
+            Value::Array(a) => (Some(a.iter()), None),
+            Value::Set(s) => (None, Some(s.iter())),
+            _ => (None, None),
+        };
+        array.into_iter().flatten().chain(set.into_iter().flatten())
+    }
+
+    /// Index a [`Value`] using a [`Value`], distinguishing missing keys from invalid indexing.
+    ///
+    /// Unlike the `Index` operator which returns [`Value::Undefined`] in either case,
+    /// - `Ok(None)` is returned if the key does not exist in the collection.
+    /// - An error is returned if the value is not an array, set or object, or if an array is
+    ///   indexed by a non-number.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let v = Value::from_json_str(r#"{ "a": [10, 20] }"#)?;
+    ///
+    /// let a = v.try_get(&Value::from("a"))?.expect("missing key");
+    /// assert_eq!(a.try_get(&Value::from(1))?, Some(&Value::from(20)));
+    ///
+    /// // Missing key and out of bounds index.
+    /// assert_eq!(v.try_get(&Value::from("b"))?, None);
+    /// assert_eq!(a.try_get(&Value::from(5))?, None);
+    ///
+    /// // Type mismatches are errors.
+    /// assert!(a.try_get(&Value::from("x")).is_err());
+    /// assert!(Value::from(1).try_get(&Value::from(0)).is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn try_get(&self, key: &Value) -> Result<Option<&Value>> {
+        match (self, key) {
+            (Value::Object(o), _) => Ok(o.get(key)),
+            (Value::Set(s), _) => Ok(s.get(key)),
+            (Value::Array(a), Value::Number(n)) => Ok(match n.as_u64() {
+                Some(index) if n.is_integer() => a.get(index as usize),
+                _ => None,
+            }),
+            (Value::Array(_), _) => bail!("array cannot be indexed by {}", get_type(key)),
+            _ => bail!("{} cannot be indexed", get_type(self)),
+        }
+    }
+
+    /// Compare two values as they would compare after conversion to JSON.
+    ///
+    /// A set is considered equal to an array of the same elements in sorted order,
+    /// and numbers are compared by their mathematical value. Collections are compared recursively.
+    ///
+    /// This is a convenience for tests and interop with JSON based tools, where sets are
+    /// represented as arrays. It is not Rego's `==`, which distinguishes sets from arrays.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::new();
+    /// let results = engine.eval_query("x := {3, 1, 2}; y := 4 / 2".to_string(), false)?;
+    /// let bindings = &results.result[0].bindings;
+    ///
+    /// let expected = Value::from_json_str(r#"{ "x": [1, 2, 3], "y": 2.0 }"#)?;
+    /// assert_ne!(bindings, &expected);
+    /// assert!(bindings.eq_json_semantics(&expected));
+    ///
+    /// // Arrays must be sorted to match sets.
+    /// assert!(!bindings.eq_json_semantics(&Value::from_json_str(r#"{ "x": [3, 1, 2], "y": 2 }"#)?));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn eq_json_semantics(&self, other: &Value) -> bool {
+        fn eq_elements<'a>(
+            a: impl ExactSizeIterator<Item = &'a Value>,
+            b: impl ExactSizeIterator<Item = &'a Value>,
+        ) -> bool {
+            a.len() == b.len() && a.zip(b).all(|(a, b)| a.eq_json_semantics(b))
+        }
+
+        match (self, other) {
+            (Value::Array(a), Value::Array(b)) => eq_elements(a.iter(), b.iter()),
+            (Value::Set(a), Value::Set(b)) => eq_elements(a.iter(), b.iter()),
+            (Value::Set(s), Value::Array(a)) | (Value::Array(a), Value::Set(s)) => {
+                eq_elements(s.iter(), a.iter())
+            }
+            (Value::Object(a), Value::Object(b)) => {
+                a.len() == b.len()
+                    && a.iter().zip(b.iter()).all(|((ka, va), (kb, vb))| {
+                        ka.eq_json_semantics(kb) && va.eq_json_semantics(vb)
+                    })
+            }
+            (a, b) => a == b,
+        }
+    }
+
+    /// Index a [`Value`] using a sequence of keys.
+    ///
+    /// Each key is applied as per the `Index` operator and [`Value::Undefined`] is
+    /// returned if any key is missing.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let v = Value::from_json_str(r#"{ "a": { "b": [10, 20] } }"#)?;
+    ///
+    /// let path = [Value::from("a"), Value::from("b"), Value::from(1)];
+    /// assert_eq!(v.get_path(&path), &Value::from(20));
+    /// assert_eq!(v.get_path(&[Value::from("a"), Value::from("c")]), &Value::Undefined);
+    /// assert_eq!(v.get_path(&[]), &v);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn get_path(&self, path: &[Value]) -> &Value {
+        path.iter().fold(self, |v, key| &v[key])
+    }
+
+    /// Set the value at a sequence of keys, creating intermediate objects as needed.
+    ///
+    /// Undefined values along the path are replaced by objects.
+    /// An error is raised if the path goes through a value that is not an object.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut v = Value::new_object();
+    ///
+    /// v.set_path(&[Value::from("a"), Value::from("b")], Value::from(1))?;
+    /// v.set_path(&[Value::from("a"), Value::from("c")], Value::from(2))?;
+    /// assert_eq!(v, Value::from_json_str(r#"{ "a": { "b": 1, "c": 2 } }"#)?);
+    ///
+    /// // a.b is not an object.
+    /// assert!(v.set_path(&[Value::from("a"), Value::from("b"), Value::from("d")], Value::Null).is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_path(&mut self, path: &[Value], value: Value) -> Result<()> {
+        let mut target = self;
+        for key in path {
+            if target == &Value::Undefined {
+                *target = Value::new_object();
+            }
+            target = match target {
+                Value::Object(map) => Rc::make_mut(map)
+                    .entry(key.clone())
+                    .or_insert(Value::Undefined),
+                _ => bail!("cannot set key {key} in {}", get_type(target)),
+            };
+        }
+        *target = value;
+        Ok(())
+    }
+
+    /// Remove the elements of arrays, sets and objects for which the predicate returns false.
+    ///
+    /// The predicate is called with the path to each element and the element itself.
+    /// Path components are object keys, array indices and set members.
+    /// Nested collections are pruned before the predicate is called on them, so that the
+    /// predicate can decide whether to keep collections that have become empty.
+    /// Collections that are left empty are otherwise retained.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut v = Value::from_json_str(r#"{
+    ///   "users": { "alice": { "admin": true }, "bob": { "admin": false } },
+    ///   "logs": [1, 2, 3]
+    /// }"#)?;
+    ///
+    /// v.retain(&mut |path, _| path[0] != Value::from("logs") && path.get(2) != Some(&Value::from("admin")));
+    /// assert_eq!(v, Value::from_json_str(r#"{ "users": { "alice": {}, "bob": {} } }"#)?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn retain(&mut self, f: &mut dyn FnMut(&[Value], &Value) -> bool) {
+        self.retain_impl(&mut vec![], f);
+    }
+
+    fn retain_impl(&mut self, path: &mut Vec<Value>, f: &mut dyn FnMut(&[Value], &Value) -> bool) {
+        match self {
+            Value::Array(a) => {
+                let mut idx = 0;
+                Rc::make_mut(a).retain_mut(|v| {
+                    path.push(Value::from(idx));
+                    idx += 1;
+                    v.retain_impl(path, f);
+                    let keep = f(path, v);
+                    path.pop();
+                    keep
+                });
+            }
+            Value::Set(s) => {
+                let set = Rc::make_mut(s);
+                for mut v in core::mem::take(set) {
+                    path.push(v.clone());
+                    v.retain_impl(path, f);
+                    if f(path, &v) {
+                        set.insert(v);
+                    }
+                    path.pop();
+                }
+            }
+            Value::Object(o) => {
+                Rc::make_mut(o).retain(|k, v| {
+                    path.push(k.clone());
+                    v.retain_impl(path, f);
+                    let keep = f(path, v);
+                    path.pop();
+                    keep
+                });
+            }
+            _ => (),
+        }
+    }
+
+    /// Compute a content hash that is stable across platforms, runs and versions.
+    ///
+    /// Unlike [`core::hash::Hash`], the result depends only on the contents of the value.
+    /// Sets and objects are hashed in their canonical (sorted) order, and numbers are hashed
+    /// using their normalized representation so that equal values always have equal hashes.
+    /// The hash is computed using 64-bit FNV-1a and is suitable for cache keys and deduplication,
+    /// but not for cryptographic purposes.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let a = Value::from_json_str(r#"{ "x": [1, 2], "y": "z" }"#)?;
+    ///
+    /// let mut b = Value::new_object();
+    /// b.as_object_mut()?.insert(Value::from("y"), Value::from("z"));
+    /// b.as_object_mut()?.insert(Value::from("x"), Value::from(vec![Value::from(1.0), Value::from(2u64)]));
+    ///
+    /// assert_eq!(a.stable_hash(), b.stable_hash());
+    /// assert_ne!(a.stable_hash(), Value::new_object().stable_hash());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn stable_hash(&self) -> u64 {
+        let mut hasher = StableHasher::new();
+        self.stable_hash_impl(&mut hasher);
+        hasher.finish()
+    }
+
+    fn stable_hash_impl(&self, hasher: &mut StableHasher) {
+        // Each value is prefixed with a tag so that, for example, an array and a set
+        // with the same elements hash differently.
+        match self {
+            Value::Null => hasher.write(&[0]),
+            Value::Bool(b) => hasher.write(&[1, *b as u8]),
+            Value::Number(n) => {
+                hasher.write(&[2]);
+                hasher.write_str(&n.format_scientific());
+            }
+            Value::String(s) => {
+                hasher.write(&[3]);
+                hasher.write_str(s);
+            }
+            Value::Array(a) => {
+                hasher.write(&[4]);
+                hasher.write_len(a.len());
+                a.iter().for_each(|v| v.stable_hash_impl(hasher));
+            }
+            Value::Set(s) => {
+                hasher.write(&[5]);
+                hasher.write_len(s.len());
+                s.iter().for_each(|v| v.stable_hash_impl(hasher));
+            }
+            Value::Object(o) => {
+                hasher.write(&[6]);
+                hasher.write_len(o.len());
+                for (k, v) in o.iter() {
+                    k.stable_hash_impl(hasher);
+                    v.stable_hash_impl(hasher);
+                }
+            }
+            Value::Undefined => hasher.write(&[7]),
+        }
+    }
+}
+
+impl Value {
+    /// Describe the shape of the value.
+    ///
+    /// Produces the narrowest [`StructuralType`] of the value: objects record the type of
+    /// each field, and arrays and sets record the union of the types of their elements.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let v = Value::from_json_str(r#"{"name": "x", "ports": [80, 443], "tags": ["a", 1.5]}"#)?;
+    /// assert_eq!(
+    ///    v.describe().to_string(),
+    ///    r#"{"name": string, "ports": array[integer], "tags": array[string | number]}"#
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn describe(&self) -> StructuralType {
+        match self {
+            Value::Null => StructuralType::Null,
+            Value::Bool(_) => StructuralType::Boolean,
+            Value::Number(n) if n.is_integer() => StructuralType::Integer,
+            Value::Number(_) => StructuralType::Number,
+            Value::String(_) => StructuralType::String,
+            Value::Array(a) => StructuralType::Array(Box::new(StructuralType::union_of(a.iter()))),
+            Value::Set(s) => StructuralType::Set(Box::new(StructuralType::union_of(s.iter()))),
+            Value::Object(o) => {
+                StructuralType::Object(o.iter().map(|(k, v)| (k.clone(), v.describe())).collect())
+            }
+            Value::Undefined => StructuralType::Undefined,
+        }
+    }
+}
+
+/// How [`Value::merge`] resolves conflicting values.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum MergeStrategy {
+    /// Conflicting values are errors.
+    #[default]
+    Error,
+
+    /// The value being merged in replaces the existing value.
+    OverwriteWithRhs,
+
+    /// The existing value is retained.
+    KeepLhs,
+
+    /// Arrays are concatenated. Other conflicting values are errors.
+    DeepArrayConcat,
+}
+
+/// The structural type of a [`Value`].
+///
+/// See [`Value::describe`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum StructuralType {
+    /// Any value. Used as the element type of empty arrays and sets.
+    Any,
+
+    /// `null`.
+    Null,
+
+    /// A boolean.
+    Boolean,
+
+    /// A whole number.
+    Integer,
+
+    /// A number that may have a fractional part.
+    Number,
+
+    /// A string.
+    String,
+
+    /// An array with elements of the given type.
+    Array(Box<StructuralType>),
+
+    /// A set with elements of the given type.
+    Set(Box<StructuralType>),
+
+    /// An object with the given fields.
+    Object(BTreeMap<Value, StructuralType>),
+
+    /// One of the given types, in order of first occurrence.
+    Union(Vec<StructuralType>),
+
+    /// An undefined value.
+    Undefined,
+}
+
+impl StructuralType {
+    fn union_of<'a>(values: impl Iterator<Item = &'a Value>) -> StructuralType {
+        let mut types: Vec<StructuralType> = vec![];
+        for v in values {
+            let t = v.describe();
+            if !types.contains(&t) {
+                types.push(t);
+            }
+        }
+
+        // Whole numbers are also numbers.
+        if types.contains(&StructuralType::Number) {
+            types.retain(|t| t != &StructuralType::Integer);
+        }
+
+        match types.len() {
+            0 => StructuralType::Any,
+            1 => types.remove(0),
+            _ => StructuralType::Union(types),
+        }
+    }
+}
+
+impl fmt::Display for StructuralType {
+    /// Display a structural type using a compact notation, e.g. `{"a": array[string | null]}`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StructuralType::Any => write!(f, "any"),
+            StructuralType::Null => write!(f, "null"),
+            StructuralType::Boolean => write!(f, "boolean"),
+            StructuralType::Integer => write!(f, "integer"),
+            StructuralType::Number => write!(f, "number"),
+            StructuralType::String => write!(f, "string"),
+            StructuralType::Array(t) => write!(f, "array[{t}]"),
+            StructuralType::Set(t) => write!(f, "set[{t}]"),
+            StructuralType::Object(fields) => {
+                write!(f, "{{")?;
+                for (idx, (k, t)) in fields.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{k}: {t}")?;
+                }
+                write!(f, "}}")
+            }
+            StructuralType::Union(types) => {
+                for (idx, t) in types.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, " | ")?;
+                    }
+                    write!(f, "{t}")?;
+                }
+                Ok(())
+            }
+            StructuralType::Undefined => write!(f, "undefined"),
+        }
+    }
+}
+
+// 64-bit FNV-1a.
+struct StableHasher(u64);
+
+impl StableHasher {
+    fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    fn write_len(&mut self, len: usize) {
+        self.write(&(len as u64).to_le_bytes());
+    }
+
+    fn write_str(&mut self, s: &str) {
+        self.write_len(s.len());
+        self.write(s.as_bytes());
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
 }
 
 impl Value {
@@ -1299,7 +2560,270 @@ impl Value {
         }
     }
 
-    pub(crate) fn merge(&mut self, mut new: Value) -> Result<()> {
+    /// Look up a value by a JSON pointer (RFC 6901), e.g. `/a/b/0`.
+    ///
+    /// The empty pointer refers to the value itself. Each token refers to an object field with
+    /// the given string key or to an array element with the given index. Sets are not keyed
+    /// and hence their elements cannot be referred to. Returns `None` if there is no value at
+    /// the pointer or if the pointer is malformed.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let v = Value::from_json_str(r#"{ "a": { "b": [1, 2], "c/d": 3 } }"#)?;
+    ///
+    /// assert_eq!(v.pointer("/a/b/1"), Some(&Value::from(2)));
+    /// assert_eq!(v.pointer("/a/c~1d"), Some(&Value::from(3)));
+    /// assert_eq!(v.pointer(""), Some(&v));
+    /// assert_eq!(v.pointer("/a/b/2"), None);
+    /// assert_eq!(v.pointer("a"), None);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn pointer(&self, pointer: &str) -> Option<&Value> {
+        if pointer.is_empty() {
+            return Some(self);
+        }
+        pointer
+            .strip_prefix('/')?
+            .split('/')
+            .try_fold(self, |value, token| {
+                let token = Self::json_pointer_token(token);
+                match value {
+                    Value::Object(fields) => fields.get(&Value::from(token)),
+                    Value::Array(items) => {
+                        items.get(Self::json_pointer_index(&token, items.len())?)
+                    }
+                    _ => None,
+                }
+            })
+    }
+
+    /// Look up a value by a JSON pointer (RFC 6901) for modification.
+    ///
+    /// See [`Value::pointer`].
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut v = Value::from_json_str(r#"{ "a": { "b": [1, 2] } }"#)?;
+    ///
+    /// if let Some(b) = v.pointer_mut("/a/b/0") {
+    ///     *b = Value::from("one");
+    /// }
+    /// assert_eq!(v, Value::from_json_str(r#"{ "a": { "b": ["one", 2] } }"#)?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn pointer_mut(&mut self, pointer: &str) -> Option<&mut Value> {
+        if pointer.is_empty() {
+            return Some(self);
+        }
+        pointer
+            .strip_prefix('/')?
+            .split('/')
+            .try_fold(self, |value, token| {
+                value.get_json_pointer_child_mut(&Self::json_pointer_token(token))
+            })
+    }
+
+    // Split a JSON pointer (RFC 6901) into its parent tokens and last token.
+    fn split_json_pointer(pointer: &str) -> Result<(Vec<String>, String)> {
+        let Some(pointer) = pointer.strip_prefix('/') else {
+            bail!("invalid JSON pointer `{pointer}`: must be empty or start with `/`");
+        };
+        let mut tokens: Vec<String> = pointer.split('/').map(Self::json_pointer_token).collect();
+        let last = tokens.pop().unwrap_or_default();
+        Ok((tokens, last))
+    }
+
+    fn json_pointer_token(token: &str) -> String {
+        token.replace("~1", "/").replace("~0", "~")
+    }
+
+    fn json_pointer_index(token: &str, len: usize) -> Option<usize> {
+        match token {
+            "-" => Some(len),
+            _ if token.len() > 1 && token.starts_with('0') => None,
+            _ if !token.bytes().all(|b| b.is_ascii_digit()) => None,
+            _ => token.parse().ok(),
+        }
+    }
+
+    fn get_json_pointer_child_mut(&mut self, token: &str) -> Option<&mut Value> {
+        match self {
+            Value::Object(fields) => Rc::make_mut(fields).get_mut(&Value::from(token)),
+            Value::Array(items) => {
+                let items = Rc::make_mut(items);
+                let idx = Self::json_pointer_index(token, items.len())?;
+                items.get_mut(idx)
+            }
+            _ => None,
+        }
+    }
+
+    fn get_json_pointer_parent_mut<'a>(
+        &'a mut self,
+        pointer: &str,
+        tokens: &[String],
+    ) -> Result<&'a mut Value> {
+        let mut value = self;
+        for token in tokens {
+            value = value
+                .get_json_pointer_child_mut(token)
+                .ok_or_else(|| anyhow!("JSON pointer `{pointer}` does not exist"))?;
+        }
+        Ok(value)
+    }
+
+    // Set the value referred to by a JSON pointer. Object fields are added or replaced.
+    // Array elements are replaced, or appended if the index is the array length or `-`.
+    pub(crate) fn set_at_json_pointer(&mut self, pointer: &str, value: Value) -> Result<()> {
+        if pointer.is_empty() {
+            *self = value;
+            return Ok(());
+        }
+        let (tokens, last) = Self::split_json_pointer(pointer)?;
+        match self.get_json_pointer_parent_mut(pointer, &tokens)? {
+            Value::Object(fields) => {
+                Rc::make_mut(fields).insert(Value::from(last), value);
+            }
+            Value::Array(items) => match Self::json_pointer_index(&last, items.len()) {
+                Some(idx) if idx == items.len() => Rc::make_mut(items).push(value),
+                Some(idx) if idx < items.len() => Rc::make_mut(items)[idx] = value,
+                _ => bail!("JSON pointer `{pointer}` does not exist"),
+            },
+            _ => {
+                bail!("JSON pointer `{pointer}` does not refer to an object field or array element")
+            }
+        }
+        Ok(())
+    }
+
+    // Remove the value referred to by a JSON pointer.
+    pub(crate) fn remove_at_json_pointer(&mut self, pointer: &str) -> Result<Value> {
+        if pointer.is_empty() {
+            return Ok(core::mem::replace(self, Value::Undefined));
+        }
+        let (tokens, last) = Self::split_json_pointer(pointer)?;
+        let removed = match self.get_json_pointer_parent_mut(pointer, &tokens)? {
+            Value::Object(fields) => Rc::make_mut(fields).remove(&Value::from(last)),
+            Value::Array(items) => match Self::json_pointer_index(&last, items.len()) {
+                Some(idx) if idx < items.len() => Some(Rc::make_mut(items).remove(idx)),
+                _ => None,
+            },
+            _ => None,
+        };
+        removed.ok_or_else(|| anyhow!("JSON pointer `{pointer}` does not exist"))
+    }
+
+    /// Recursively merge another value into this value.
+    ///
+    /// Objects are merged key by key, recursively. Other values at the same path conflict
+    /// unless they are equal, and conflicts are resolved as per `strategy`. An undefined value
+    /// is replaced by the other value. In case of error, `self` is left unmodified.
+    ///
+    /// ```
+    /// # use regorus::*;
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut v = Value::from_json_str(r#"{ "a": { "x": 1, "list": [1] }, "b": 2 }"#)?;
+    /// let other = Value::from_json_str(r#"{ "a": { "x": 10, "list": [2], "y": 3 } }"#)?;
+    ///
+    /// // Conflicting values are errors by default.
+    /// let err = v.clone().merge(other.clone(), MergeStrategy::Error).unwrap_err();
+    /// assert_eq!(err.to_string(), "conflicting values for `a.list`: array and array");
+    ///
+    /// let mut rhs = v.clone();
+    /// rhs.merge(other.clone(), MergeStrategy::OverwriteWithRhs)?;
+    /// assert_eq!(rhs, Value::from_json_str(r#"{ "a": { "x": 10, "list": [2], "y": 3 }, "b": 2 }"#)?);
+    ///
+    /// let mut lhs = v.clone();
+    /// lhs.merge(other.clone(), MergeStrategy::KeepLhs)?;
+    /// assert_eq!(lhs, Value::from_json_str(r#"{ "a": { "x": 1, "list": [1], "y": 3 }, "b": 2 }"#)?);
+    ///
+    /// // Arrays are concatenated, but other conflicts are still errors.
+    /// let other = Value::from_json_str(r#"{ "a": { "list": [2], "y": 3 } }"#)?;
+    /// v.merge(other, MergeStrategy::DeepArrayConcat)?;
+    /// assert_eq!(v, Value::from_json_str(r#"{ "a": { "x": 1, "list": [1, 2], "y": 3 }, "b": 2 }"#)?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn merge(&mut self, other: Value, strategy: MergeStrategy) -> Result<()> {
+        // Collections are reference counted; hence cloning is cheap and only the parts of the
+        // value that are modified are copied.
+        let mut merged = self.clone();
+        merged.merge_with(other, strategy, "")?;
+        *self = merged;
+        Ok(())
+    }
+
+    /// Recursively merge `new` into a data document.
+    ///
+    /// `path` is the path of `self` within the document and is used in error messages.
+    /// Conflicting leaf values result in an error unless `overwrite` is true.
+    pub(crate) fn merge_data(&mut self, new: Value, overwrite: bool, path: &str) -> Result<()> {
+        let strategy = match overwrite {
+            true => MergeStrategy::OverwriteWithRhs,
+            false => MergeStrategy::Error,
+        };
+        self.merge_with(new, strategy, path)
+    }
+
+    fn merge_with(&mut self, new: Value, strategy: MergeStrategy, path: &str) -> Result<()> {
+        match (self, new) {
+            (Value::Object(map), Value::Object(new)) => {
+                let map = Rc::make_mut(map);
+                for (k, v) in Rc::unwrap_or_clone(new) {
+                    let path = match &k {
+                        Value::String(s) if is_identifier(s) && path.is_empty() => s.to_string(),
+                        Value::String(s) if is_identifier(s) => format!("{path}.{s}"),
+                        _ => format!("{path}[{k}]"),
+                    };
+                    match map.get_mut(&k) {
+                        Some(pv) => pv.merge_with(v, strategy, &path)?,
+                        None => {
+                            map.insert(k, v);
+                        }
+                    }
+                }
+            }
+            (Value::Array(a), Value::Array(new)) if strategy == MergeStrategy::DeepArrayConcat => {
+                Rc::make_mut(a).extend(Rc::unwrap_or_clone(new))
+            }
+            (v, new) if *v == new || *v == Value::Undefined => *v = new,
+            (v, new) if strategy == MergeStrategy::OverwriteWithRhs => *v = new,
+            (_, _) if strategy == MergeStrategy::KeepLhs => (),
+            (v, new) if path.is_empty() => bail!(
+                "conflicting values: {} and {}",
+                Self::describe_conflict(v),
+                Self::describe_conflict(&new)
+            ),
+            (v, new) => bail!(
+                "conflicting values for `{path}`: {} and {}",
+                Self::describe_conflict(v),
+                Self::describe_conflict(&new)
+            ),
+        }
+        Ok(())
+    }
+
+    // Collections are described by their type and long scalars are truncated
+    // so that conflicts between large documents produce short error messages.
+    fn describe_conflict(v: &Value) -> String {
+        const MAX_LEN: usize = 32;
+        match v {
+            Value::Array(_) | Value::Set(_) | Value::Object(_) => get_type(v).to_string(),
+            _ => {
+                let s = v.to_string();
+                match s.char_indices().nth(MAX_LEN) {
+                    Some((idx, _)) => format!("`{}...`", &s[..idx]),
+                    None => format!("`{s}`"),
+                }
+            }
+        }
+    }
+
+    pub(crate) fn merge_rule_output(&mut self, mut new: Value) -> Result<()> {
         if self == &new {
             return Ok(());
         }
@@ -58,9 +58,7 @@ fn eval_test_case(dir: &Path, case: &TestCase) -> Result<Value> {
             Value::Undefined
         });
     }
-    let result = Value::from(values);
-    // Make result json compatible. (E.g: avoid sets).
-    Value::from_json_str(&result.to_string())
+    Ok(Value::from(values))
 }
 
 fn run_aci_tests(dir: &Path) -> Result<()> {
@@ -86,7 +84,7 @@ fn run_aci_tests(dir: &Path) -> Result<()> {
             let duration = start.elapsed();
 
             match results {
-                Ok(actual) if actual == case.want_result => {
+                Ok(actual) if actual.eq_json_semantics(&case.want_result) => {
                     println!("passed    {:?}", duration);
                 }
                 Ok(actual) => {
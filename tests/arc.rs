@@ -3,6 +3,7 @@
 
 #![allow(unused)]
 use lazy_static::lazy_static;
+use std::collections::BTreeMap;
 use std::sync::Mutex;
 
 use regorus::*;
@@ -34,3 +35,37 @@ allow = true
     assert_eq!(results.result[0].expressions[0].value, Value::from(true));
     Ok(())
 }
+
+#[test]
+#[cfg(feature = "arc")]
+fn shared_data_snapshot() -> anyhow::Result<()> {
+    let mut engine = Engine::new();
+    engine.add_data(Value::from_json_str(r#"{ "limit": 5 }"#)?)?;
+    let snapshot = engine.freeze_data();
+
+    let handles: Vec<_> = (0..4)
+        .map(|x| {
+            let snapshot = snapshot.clone();
+            std::thread::spawn(move || -> anyhow::Result<Value> {
+                let mut engine = Engine::new();
+                engine.add_policy(
+                    "policy.rego".to_string(),
+                    "package test\nallow := input.x <= data.limit".to_string(),
+                )?;
+                engine.use_data_snapshot(snapshot);
+                engine.set_input(Value::from(BTreeMap::from([(
+                    Value::from("x"),
+                    Value::from(x * 2),
+                )])));
+                Ok(engine.eval_rule("data.test.allow".to_string())?)
+            })
+        })
+        .collect();
+
+    let results = handles
+        .into_iter()
+        .map(|h| h.join().expect("thread panicked"))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    assert_eq!(results, [true, true, true, false].map(Value::from).to_vec());
+    Ok(())
+}
@@ -28,13 +28,13 @@ fn extension() -> Result<()> {
     assert!(engine.eval_query("data.test.x".to_string(), false).is_err());
 
     // Register extension.
-    engine.add_extension("repeat".to_string(), 1, Box::new(repeat))?;
+    engine.add_extension("repeat".to_string(), Some(1), Box::new(repeat))?;
 
     // Adding extension twice is error.
     assert!(engine
         .add_extension(
             "repeat".to_string(),
-            1,
+            Some(1),
             Box::new(|_| { Ok(Value::Undefined) })
         )
         .is_err());
@@ -67,7 +67,7 @@ fn extension_with_state() -> Result<()> {
     let mut g = Box::new(Gen { n: 5 });
     engine.add_extension(
         "gen".to_string(),
-        0,
+        Some(0),
         Box::new(move |_: Vec<Value>| {
             let v = Value::from(g.n);
             g.n += 1;
@@ -101,3 +101,4322 @@ fn extension_with_state() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn extension_arity() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "test.rego".to_string(),
+        r#"package test
+               x = pair(1, 2, 3)
+               y = join_all("a", "b", "c")
+               z = join_all()
+        "#
+        .to_string(),
+    )?;
+
+    engine.add_extension(
+        "pair".to_string(),
+        Some(2),
+        Box::new(|_: Vec<Value>| bail!("pair must not be invoked")),
+    )?;
+    engine.add_extension(
+        "join_all".to_string(),
+        None,
+        Box::new(|params: Vec<Value>| {
+            let mut s = String::new();
+            for p in &params {
+                s.push_str(p.as_string()?.as_ref());
+            }
+            Ok(Value::from(s))
+        }),
+    )?;
+
+    let err = engine
+        .eval_query("data.test.x".to_string(), false)
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("pair: expected 2 arguments, got 3"), "{err}");
+    assert!(err.contains("test.rego:2:"), "{err}");
+
+    let r = engine.eval_query("data.test.y".to_string(), false)?;
+    assert_eq!(
+        r.result[0].expressions[0].value.as_string()?.as_ref(),
+        "abc"
+    );
+
+    let r = engine.eval_query("data.test.z".to_string(), false)?;
+    assert_eq!(r.result[0].expressions[0].value.as_string()?.as_ref(), "");
+
+    Ok(())
+}
+
+#[test]
+fn merge_from() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "a.rego".to_string(),
+        r#"package a
+               x = data.b.y + data.n
+        "#
+        .to_string(),
+    )?;
+    engine.add_data(Value::from_json_str(r#"{ "n": 1 }"#)?)?;
+
+    let mut plugin = Engine::new();
+    plugin.add_policy("b.rego".to_string(), "package b\n y = 2".to_string())?;
+    plugin.add_data(Value::from_json_str(r#"{ "m": 3 }"#)?)?;
+
+    engine.merge_from(&plugin)?;
+    assert_eq!(engine.get_packages()?, vec!["data.a", "data.b"]);
+    assert_eq!(engine.eval_rule("data.a.x".to_string())?, Value::from(3));
+    assert_eq!(engine.get_data()["m"], Value::from(3));
+
+    // Merging a clone is a no-op since the modules and data are shared.
+    let clone = engine.clone();
+    engine.merge_from(&clone)?;
+    assert_eq!(engine.get_packages()?.len(), 2);
+
+    // A package may span both engines.
+    let mut same_package = Engine::new();
+    same_package.add_policy("b2.rego".to_string(), "package b\n w = 4".to_string())?;
+    engine.merge_from(&same_package)?;
+    assert_eq!(engine.eval_rule("data.b.w".to_string())?, Value::from(4));
+
+    // Data that overlaps a rule.
+    let mut conflict = Engine::new();
+    conflict.add_data(Value::from_json_str(r#"{ "b": { "y": 5 } }"#)?)?;
+    let err = engine.merge_from(&conflict).unwrap_err().to_string();
+    assert!(err.contains("data overlaps rule `data.b.y`"));
+
+    // Rule that overlaps data.
+    let mut conflict = Engine::new();
+    conflict.add_policy("d.rego".to_string(), "package n\n v = 3".to_string())?;
+    let err = engine.merge_from(&conflict).unwrap_err().to_string();
+    assert!(err.contains("rule `data.n.v` overlaps existing data"));
+
+    // Conflicting data.
+    let mut conflict = Engine::new();
+    conflict.add_data(Value::from_json_str(r#"{ "n": 2 }"#)?)?;
+    assert!(engine.merge_from(&conflict).is_err());
+
+    // Engine is unchanged after failed merges.
+    assert_eq!(engine.get_packages()?, vec!["data.a", "data.b", "data.b"]);
+    assert_eq!(engine.eval_rule("data.b.y".to_string())?, Value::from(2));
+    assert_eq!(engine.get_data()["n"], Value::from(1));
+
+    Ok(())
+}
+
+#[test]
+fn merge_from_split_rules() -> Result<()> {
+    let first = r#"package test
+        import rego.v1
+
+        s contains 1
+        o["a"] := 1
+        f(x) := 1 if x == "a"
+        default d := "default"
+        c := 1
+    "#;
+    let second = r#"package test
+        import rego.v1
+
+        s contains 2
+        s contains 1
+        o["b"] := 2
+        f(x) := 2 if x == "b"
+        d := "rule" if input.x
+        c := 2 if input.conflict
+    "#;
+    let input = Value::from_json_str(r#"{ "x": true }"#)?;
+
+    let mut single = Engine::new();
+    single.add_policy("first.rego".to_string(), first.to_string())?;
+    single.add_policy("second.rego".to_string(), second.to_string())?;
+    single.set_input(input.clone());
+
+    let mut merged = Engine::new();
+    merged.add_policy("first.rego".to_string(), first.to_string())?;
+    let mut plugin = Engine::new();
+    plugin.add_policy("second.rego".to_string(), second.to_string())?;
+    merged.merge_from(&plugin)?;
+    merged.set_input(input);
+
+    for query in [
+        "data.test.s",
+        "data.test.o",
+        "data.test.d",
+        r#"[data.test.f("a"), data.test.f("b")]"#,
+    ] {
+        assert_eq!(
+            merged.eval_query(query.to_string(), false)?,
+            single.eval_query(query.to_string(), false)?
+        );
+    }
+    let s = merged.eval_rule("data.test.s".to_string())?;
+    assert_eq!(s.as_set()?.len(), 2);
+    assert_eq!(
+        merged.eval_rule("data.test.d".to_string())?,
+        Value::from("rule")
+    );
+
+    // Conflicting definitions are reported during evaluation, as in a single engine.
+    let conflict = Value::from_json_str(r#"{ "conflict": true }"#)?;
+    single.set_input(conflict.clone());
+    merged.set_input(conflict);
+    let single_err = single.eval_rule("data.test.c".to_string()).unwrap_err();
+    let merged_err = merged.eval_rule("data.test.c".to_string()).unwrap_err();
+    assert_eq!(merged_err.to_string(), single_err.to_string());
+
+    Ok(())
+}
+
+#[test]
+fn builtin_call_logger() -> Result<()> {
+    use std::sync::{Arc, Mutex};
+
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "test.rego".to_string(),
+        r#"package test
+               x = sprintf("%s-%d", ["a", 1])
+        "#
+        .to_string(),
+    )?;
+
+    let calls = Arc::new(Mutex::new(vec![]));
+    let log = calls.clone();
+    engine.set_builtin_call_logger(Box::new(move |name: &str, args: &[Value]| {
+        log.lock().unwrap().push((name.to_string(), args.to_vec()));
+    }));
+
+    let r = engine.eval_rule("data.test.x".to_string())?;
+    assert_eq!(r, Value::from("a-1"));
+
+    assert_eq!(
+        *calls.lock().unwrap(),
+        vec![(
+            "sprintf".to_string(),
+            vec![Value::from("%s-%d"), Value::from_json_str(r#"["a", 1]"#)?]
+        )]
+    );
+
+    // No calls are logged once the logger is removed.
+    engine.clear_builtin_call_logger();
+    engine.eval_rule("data.test.x".to_string())?;
+    assert_eq!(calls.lock().unwrap().len(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn eval_function() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "lib.rego".to_string(),
+        r#"package lib
+               import rego.v1
+
+               limit := data.limit
+
+               f(x, y) := x + y
+
+               within(x) if x < limit
+
+               keys(obj) := {k | some k, _ in obj}
+
+               name(x) := "small" if x < 10
+               name(x) := "large" if x >= 10
+        "#
+        .to_string(),
+    )?;
+    engine.add_data(Value::from_json_str(r#"{ "limit": 5 }"#)?)?;
+
+    assert_eq!(
+        engine.eval_function("data.lib.f", vec![Value::from(1), Value::from(2)])?,
+        Value::from(3)
+    );
+    assert_eq!(
+        engine.eval_function("data.lib.within", vec![Value::from(3)])?,
+        Value::from(true)
+    );
+    assert_eq!(
+        engine.eval_function("data.lib.within", vec![Value::from(7)])?,
+        Value::Undefined
+    );
+    assert_eq!(
+        engine.eval_function(
+            "data.lib.keys",
+            vec![Value::from_json_str(r#"{"a": 1, "b": 2}"#)?]
+        )?,
+        Value::from_json_str(r#"["a", "b"]"#)?.into_set()?
+    );
+    assert_eq!(
+        engine.eval_function("data.lib.name", vec![Value::from(12)])?,
+        Value::from("large")
+    );
+
+    // Arity mismatch.
+    assert!(engine
+        .eval_function("data.lib.f", vec![Value::from(1)])
+        .is_err());
+
+    // Not functions.
+    assert!(engine.eval_function("data.lib.limit", vec![]).is_err());
+    assert!(engine.eval_function("data.lib.g", vec![]).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn dump_state() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_policy("a.rego".to_string(), "package a\n x = 1".to_string())?;
+    engine.add_data(Value::from_json_str(r#"{ "users": [], "roles": {} }"#)?)?;
+
+    let state = engine.dump_state();
+    assert!(state.contains("a.rego: data.a"));
+    assert!(state.contains("prepared: false"));
+    assert!(state.contains(r#"data keys: ["roles", "users"]"#));
+
+    engine.eval_rule("data.a.x".to_string())?;
+    let state = engine.dump_state();
+    assert!(state.contains("prepared: true"));
+    assert!(state.contains("schedule cached: true"));
+
+    Ok(())
+}
+
+#[test]
+fn sort_results() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.set_input(Value::from_json_str(
+        r#"{ "items": [{"n": "b", "v": 2}, {"n": "a", "v": 3}, {"n": "b", "v": 1}] }"#,
+    )?);
+
+    let query = "item = input.items[_]; n = item.n; v = item.v".to_string();
+    let unsorted = engine.eval_query(query.clone(), false)?;
+    let values: Vec<Value> = unsorted
+        .result
+        .iter()
+        .map(|r| r.bindings["v"].clone())
+        .collect();
+    assert_eq!(values, vec![Value::from(2), Value::from(3), Value::from(1)]);
+
+    engine.set_sort_results(true);
+    let sorted = engine.eval_query(query, false)?;
+    let values: Vec<(Value, Value)> = sorted
+        .result
+        .iter()
+        .map(|r| (r.bindings["n"].clone(), r.bindings["v"].clone()))
+        .collect();
+
+    // Bindings are compared by variable name first: item, then n, then v.
+    assert_eq!(
+        values,
+        vec![
+            (Value::from("a"), Value::from(3)),
+            (Value::from("b"), Value::from(1)),
+            (Value::from("b"), Value::from(2)),
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn add_data_conflicts() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_data(Value::from_json_str(
+        r#"{ "config": { "limits": { "cpu": 2 }, "name": "a" } }"#,
+    )?)?;
+
+    // Nested objects are merged.
+    engine.add_data(Value::from_json_str(
+        r#"{ "config": { "limits": { "memory": 4 } } }"#,
+    )?)?;
+    assert_eq!(
+        engine.get_data()["config"]["limits"],
+        Value::from_json_str(r#"{ "cpu": 2, "memory": 4 }"#)?
+    );
+
+    // Setting the same value again is not a conflict.
+    engine.add_data(Value::from_json_str(r#"{ "config": { "name": "a" } }"#)?)?;
+
+    // Conflicting leaf values.
+    let err = engine
+        .add_data(Value::from_json_str(
+            r#"{ "config": { "limits": { "cpu": 8 } } }"#,
+        )?)
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "conflicting values for `data.config.limits.cpu`: `2` and `8`"
+    );
+
+    // Keys that are not identifiers are quoted.
+    engine.add_data(Value::from_json_str(r#"{ "a-b": [1] }"#)?)?;
+    let err = engine
+        .add_data(Value::from_json_str(r#"{ "a-b": [2] }"#)?)
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"conflicting values for `data["a-b"]`: array and array"#
+    );
+
+    // Long values are truncated.
+    engine.add_data(Value::from_json_str(
+        r#"{ "s": "0123456789012345678901234567890123456789" }"#,
+    )?)?;
+    let err = engine
+        .add_data(Value::from_json_str(r#"{ "s": { "t": 1 } }"#)?)
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"conflicting values for `data.s`: `"0123456789012345678901234567890...` and object"#
+    );
+
+    // Failed merges leave the data unchanged.
+    assert_eq!(engine.get_data()["config"]["limits"]["cpu"], Value::from(2));
+
+    // Overwrite mode.
+    engine.set_data_merge_overwrite(true);
+    engine.add_data(Value::from_json_str(
+        r#"{ "config": { "limits": { "cpu": 8 } } }"#,
+    )?)?;
+    assert_eq!(
+        engine.get_data()["config"],
+        Value::from_json_str(r#"{ "limits": { "cpu": 8, "memory": 4 }, "name": "a" }"#)?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn get_rule_paths() -> Result<()> {
+    let mut engine = Engine::new();
+    assert!(engine.get_rule_paths()?.is_empty());
+
+    engine.add_policy(
+        "a.rego".to_string(),
+        r#"package a.b
+               import rego.v1
+
+               x := 1
+               default y := 2
+               z[k] := 3 if some k in ["p", "q"]
+               f(v) := v
+        "#
+        .to_string(),
+    )?;
+    engine.add_policy("c.rego".to_string(), "package c\n w = 1".to_string())?;
+
+    let paths = engine.get_rule_paths()?;
+    assert_eq!(
+        paths,
+        vec!["data.a.b.x", "data.a.b.y", "data.a.b.z", "data.c.w"]
+    );
+
+    // All rule paths can be evaluated.
+    for path in paths {
+        assert_ne!(engine.eval_rule(path)?, Value::Undefined);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn add_policy_with_offset() -> Result<()> {
+    let mut engine = Engine::new();
+
+    // Parse errors.
+    let err = engine
+        .add_policy_with_offset(
+            "policy.md".to_string(),
+            "package test\n\nx := {".to_string(),
+            20,
+            2,
+        )
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("--> policy.md:23:"), "{err}");
+
+    engine.add_policy_with_offset(
+        "policy.md".to_string(),
+        r#"package test
+x := 1 + "a"
+y {
+  print("hello")
+}"#
+        .to_string(),
+        20,
+        2,
+    )?;
+
+    // Evaluation errors.
+    let err = engine
+        .eval_rule("data.test.x".to_string())
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("--> policy.md:22:13"), "{err}");
+
+    // Prints.
+    engine.set_gather_prints(true);
+    engine.eval_rule("data.test.y".to_string())?;
+    assert_eq!(engine.take_prints()?, vec!["policy.md:24: hello"]);
+
+    // Coverage.
+    #[cfg(feature = "coverage")]
+    {
+        engine.set_enable_coverage(true);
+        engine.eval_rule("data.test.y".to_string())?;
+        let report = engine.get_coverage_report()?;
+        assert_eq!(report.files[0].line_offset, 20);
+        assert!(report.files[0].covered.contains(&24));
+        assert!(report.files[0].not_covered.contains(&22));
+        assert!(report.to_string_pretty()?.contains("  24    print"));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn eval_query_with_overrides() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_data(Value::from_json_str(
+        r#"{ "config": { "limits": { "max": 5, "min": 1 }, "mode": "strict" }, "n": 1 }"#,
+    )?)?;
+    engine.add_policy(
+        "policy.rego".to_string(),
+        r#"package test
+import rego.v1
+
+max := data.config.limits.max
+mode := m if {
+  m := data.config.mode
+  data.n == 1
+}
+"#
+        .to_string(),
+    )?;
+
+    let value = |r: QueryResults| r.result[0].expressions[0].value.clone();
+
+    // Subtree is replaced and siblings are untouched.
+    let r = engine.eval_query_with_overrides(
+        "[data.test.max, data.config.limits, data.test.mode]".to_string(),
+        vec![(
+            "data.config.limits".to_string(),
+            Value::from_json_str(r#"{ "max": 7 }"#)?,
+        )],
+        false,
+    )?;
+    assert_eq!(
+        value(r),
+        Value::from_json_str(r#"[7, { "max": 7 }, "strict"]"#)?
+    );
+
+    // Multiple overrides, including new paths.
+    let r = engine.eval_query_with_overrides(
+        "[data.test.mode, data.extra.x]".to_string(),
+        vec![
+            ("data.config.mode".to_string(), Value::from("lenient")),
+            ("data.extra.x".to_string(), Value::from(true)),
+        ],
+        false,
+    )?;
+    assert_eq!(value(r), Value::from_json_str(r#"["lenient", true]"#)?);
+
+    // Overrides compose with `with` modifiers in the query.
+    let r = engine.eval_query_with_overrides(
+        "x := data.test.max with data.n as 2".to_string(),
+        vec![("data.config.limits.max".to_string(), Value::from(9))],
+        false,
+    )?;
+    assert_eq!(r.result[0].bindings["x"], Value::from(9));
+
+    // Original data is restored.
+    let r = engine.eval_query("data.test.max".to_string(), false)?;
+    assert_eq!(value(r), Value::from(5));
+    assert_eq!(
+        engine
+            .eval_query("data.extra".to_string(), false)?
+            .result
+            .len(),
+        0
+    );
+
+    // Invalid overrides.
+    for (path, msg) in [
+        ("input.x", "invalid override path"),
+        ("data..x", "invalid override path"),
+        ("data.test", "overlaps rule `data.test.max`"),
+        ("data.test.max", "overlaps rule `data.test.max`"),
+        ("data.test.max.y", "overlaps rule `data.test.max`"),
+        ("data.n.x", "parent is not an object"),
+    ] {
+        let err = engine
+            .eval_query_with_overrides(
+                "data.n".to_string(),
+                vec![(path.to_string(), Value::from(1))],
+                false,
+            )
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains(msg), "{path}: {err}");
+    }
+
+    // Errors raised while evaluating with the overrides.
+    assert!(engine
+        .eval_query_with_overrides(
+            "x := data.config.limits.max +".to_string(),
+            vec![("data.config.limits.max".to_string(), Value::from(9))],
+            false,
+        )
+        .is_err());
+
+    // Data is restored after errors.
+    let r = engine.eval_query("x := data.config.limits.max".to_string(), false)?;
+    assert_eq!(r.result[0].bindings["x"], Value::from(5));
+
+    Ok(())
+}
+
+#[test]
+fn undefined_function_calls() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "lib.rego".to_string(),
+        r#"package lib
+double(x) := x * 2
+"#
+        .to_string(),
+    )?;
+    engine.add_policy(
+        "test.rego".to_string(),
+        r#"package test
+import rego.v1
+
+ok := data.lib.double(count([1]))
+bad if {
+  some x in [1]
+  s := [y | y := startswithh("abc", "a")]
+}
+"#
+        .to_string(),
+    )?;
+
+    // The typo is reported before evaluation even if the rule is never evaluated.
+    let err = engine
+        .eval_rule("data.test.ok".to_string())
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("--> test.rego:7:29"), "{err}");
+    assert!(err.contains("could not find function startswithh"), "{err}");
+
+    // Extensions are valid functions.
+    engine.add_extension(
+        "startswithh".to_string(),
+        Some(2),
+        Box::new(|_: Vec<Value>| Ok(Value::from(true))),
+    )?;
+    assert_eq!(
+        engine.eval_rule("data.test.ok".to_string())?,
+        Value::from(2)
+    );
+
+    // Deprecated builtins are reported distinctly.
+    #[cfg(feature = "deprecated")]
+    {
+        engine.add_policy(
+            "deprecated.rego".to_string(),
+            r#"package deprecated
+import rego.v1
+
+x := any([true])
+"#
+            .to_string(),
+        )?;
+        let err = engine
+            .eval_rule("data.test.ok".to_string())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("--> deprecated.rego:4:9"), "{err}");
+        assert!(err.contains("any is deprecated"), "{err}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn imported_function_calls() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "lib.rego".to_string(),
+        "package lib\ndouble(x) := x * 2".to_string(),
+    )?;
+    engine.add_policy(
+        "test.rego".to_string(),
+        r#"package t
+import data.lib
+import data.lib.double as dbl
+
+a := lib.double(1)
+b := 1
+c := dbl(3)
+"#
+        .to_string(),
+    )?;
+
+    assert_eq!(engine.eval_rule("data.t.b".to_string())?, Value::from(1));
+    assert_eq!(engine.eval_rule("data.t.a".to_string())?, Value::from(2));
+    assert_eq!(engine.eval_rule("data.t.c".to_string())?, Value::from(6));
+
+    // Undefined functions in imported packages are reported.
+    engine.add_policy(
+        "bad.rego".to_string(),
+        "package bad\nimport data.lib\nx := lib.triple(1)".to_string(),
+    )?;
+    let err = engine
+        .eval_rule("data.t.b".to_string())
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("could not find function lib.triple"), "{err}");
+
+    Ok(())
+}
+
+#[test]
+fn include_undefined_results() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.set_input(Value::from_json_str(r#"{ "x": [1, 2, 3] }"#)?);
+
+    // Query, results when excluded, results when included.
+    // Each result is the bindings followed by the value of each expression.
+    let cases = [
+        ("data.missing", "[]", r#"[[{}, "<undefined>"]]"#),
+        (
+            "x := 1; y := data.missing",
+            "[]",
+            r#"[[{"x": 1}, true, "<undefined>"]]"#,
+        ),
+        (
+            "input.x[i] > 1",
+            r#"[[{"i": 1}, true], [{"i": 2}, true]]"#,
+            r#"[[{"i": 0}, false], [{"i": 1}, true], [{"i": 2}, true]]"#,
+        ),
+        (
+            "v := input.x[_]; v > 2; input.y",
+            "[]",
+            r#"[
+              [{"v": 1}, true, false, "<undefined>"],
+              [{"v": 2}, true, false, "<undefined>"],
+              [{"v": 3}, true, true, "<undefined>"]
+            ]"#,
+        ),
+        (
+            "v := input.x[_]; v > 2",
+            r#"[[{"v": 3}, true, true]]"#,
+            r#"[[{"v": 1}, true, false], [{"v": 2}, true, false], [{"v": 3}, true, true]]"#,
+        ),
+    ];
+
+    let to_rows = |results: QueryResults| -> Value {
+        Value::from(
+            results
+                .result
+                .iter()
+                .map(|r| {
+                    let mut row = vec![r.bindings.clone()];
+                    row.extend(r.expressions.iter().map(|e| match &e.value {
+                        Value::Undefined => Value::from("<undefined>"),
+                        v => v.clone(),
+                    }));
+                    Value::from(row)
+                })
+                .collect::<Vec<Value>>(),
+        )
+    };
+
+    for (query, excluded, included) in cases {
+        engine.set_include_undefined_results(false);
+        let results = engine.eval_query(query.to_string(), false)?;
+        assert_eq!(
+            to_rows(results),
+            Value::from_json_str(excluded)?,
+            "excluded: {query}"
+        );
+
+        engine.set_include_undefined_results(true);
+        let results = engine.eval_query(query.to_string(), false)?;
+        assert_eq!(
+            to_rows(results),
+            Value::from_json_str(included)?,
+            "included: {query}"
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn data_snapshots() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_data(Value::from_json_str(
+        r#"{ "roles": { "alice": "admin" } }"#,
+    )?)?;
+    engine.add_policy(
+        "policy.rego".to_string(),
+        r#"package test
+import rego.v1
+
+role := data.roles[input.user]
+allow if role == "admin"
+"#
+        .to_string(),
+    )?;
+    engine.set_input(Value::from_json_str(r#"{ "user": "bob" }"#)?);
+
+    engine.add_data_snapshot(
+        "2023".to_string(),
+        Value::from_json_str(r#"{ "roles": { "bob": "admin" } }"#)?,
+    )?;
+    engine.add_data_snapshot(
+        "2024".to_string(),
+        Value::from_json_str(r#"{ "roles": { "bob": "viewer" } }"#)?,
+    )?;
+
+    let query = "x := data.test.role; allow := data.test.allow".to_string();
+    let r = engine.eval_query_against(query.clone(), "2023", false)?;
+    assert_eq!(r.result[0].bindings["allow"], Value::from(true));
+
+    // Snapshots do not merge with the engine's data or with each other.
+    let r = engine.eval_query_against("data.roles".to_string(), "2024", false)?;
+    assert_eq!(
+        r.result[0].expressions[0].value,
+        Value::from_json_str(r#"{ "bob": "viewer" }"#)?
+    );
+    let r = engine.eval_query_against(query.clone(), "2024", false)?;
+    assert_eq!(r.result.len(), 0);
+
+    // Replacing a snapshot.
+    engine.add_data_snapshot(
+        "2024".to_string(),
+        Value::from_json_str(r#"{ "roles": { "bob": "admin" } }"#)?,
+    )?;
+    let r = engine.eval_query_against(query.clone(), "2024", false)?;
+    assert_eq!(r.result[0].bindings["x"], Value::from("admin"));
+
+    // The engine's data is unaffected.
+    assert_eq!(
+        engine.eval_rule("data.test.role".to_string())?,
+        Value::Undefined
+    );
+    engine.set_input(Value::from_json_str(r#"{ "user": "alice" }"#)?);
+    assert_eq!(
+        engine.eval_rule("data.test.allow".to_string())?,
+        Value::from(true)
+    );
+
+    // Unknown snapshot.
+    let err = engine
+        .eval_query_against(query, "2025", false)
+        .unwrap_err()
+        .to_string();
+    assert_eq!(err, "unknown data snapshot `2025`");
+
+    Ok(())
+}
+
+#[test]
+fn eval_query_explain() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "policy.rego".to_string(),
+        r#"package test
+import rego.v1
+
+allow if {
+  some x in input.xs
+  x > 1
+}
+"#
+        .to_string(),
+    )?;
+    engine.set_input(Value::from_json_str(r#"{ "xs": [1, 2] }"#)?);
+
+    let (results, explanation) = engine.eval_query_explain("data.test.allow".to_string())?;
+    assert_eq!(results.result[0].expressions[0].value, Value::from(true));
+
+    let body = "{\n  some x in input.xs\n  x > 1\n}";
+    let rule = "allow if {\n  some x in input.xs\n  x > 1\n}";
+    // Events in Regorus' explanation format.
+    // op, type, query_id, parent_id, file, row, col, text, locals
+    let expected = serde_json::json!([
+        ["Enter", "body", 0, 0, "<query.rego>", 1, 1, "data.test.allow", {}],
+        ["Eval", "expr", 0, 0, "<query.rego>", 1, 1, "data.test.allow", {}],
+        ["Enter", "rule", 0, 0, "policy.rego", 4, 1, rule, {}],
+        ["Enter", "body", 1, 0, "policy.rego", 4, 10, body, {}],
+        ["Eval", "expr", 1, 0, "policy.rego", 5, 3, "some x in input.xs", {}],
+        ["Eval", "expr", 1, 0, "policy.rego", 6, 3, "x > 1", {"x": 1}],
+        ["Fail", "expr", 1, 0, "policy.rego", 6, 3, "x > 1", {"x": 1}],
+        ["Eval", "expr", 1, 0, "policy.rego", 6, 3, "x > 1", {"x": 2}],
+        ["Exit", "body", 1, 0, "policy.rego", 4, 10, body, {}],
+        ["Exit", "rule", 0, 0, "policy.rego", 4, 1, rule, {}],
+        ["Exit", "body", 0, 0, "<query.rego>", 1, 1, "data.test.allow", {}],
+    ]);
+
+    let actual: Vec<serde_json::Value> = explanation
+        .as_array()
+        .expect("explanation must be an array")
+        .iter()
+        .map(|e| {
+            assert_eq!(e["node"]["location"], e["location"]);
+            let mut locals = serde_json::Map::new();
+            for l in e["locals"].as_array().expect("locals must be an array") {
+                assert_eq!(l["key"]["type"], "var");
+                assert_eq!(l["value"]["type"], "number");
+                locals.insert(
+                    l["key"]["value"].as_str().unwrap_or_default().to_string(),
+                    l["value"]["value"].clone(),
+                );
+            }
+            serde_json::json!([
+                e["op"],
+                e["type"],
+                e["query_id"],
+                e["parent_id"],
+                e["location"]["file"],
+                e["location"]["row"],
+                e["location"]["col"],
+                e["node"]["text"],
+                locals,
+            ])
+        })
+        .collect();
+    assert_eq!(serde_json::Value::from(actual), expected);
+
+    // Explanations are not produced by subsequent evaluations.
+    let (_, explanation) = engine.eval_query_explain("1 > 2".to_string())?;
+    assert_eq!(explanation.as_array().map(|a| a.len()), Some(4));
+
+    // A rule that does not produce a value fails.
+    engine.set_input(Value::from_json_str(r#"{ "xs": [0, 1] }"#)?);
+    let (results, explanation) = engine.eval_query_explain("data.test.allow".to_string())?;
+    assert!(results.result.is_empty());
+    let rule_events: Vec<_> = explanation
+        .as_array()
+        .expect("explanation must be an array")
+        .iter()
+        .filter(|e| e["type"] == "rule")
+        .map(|e| e["op"].clone())
+        .collect();
+    assert_eq!(rule_events, ["Enter", "Fail"]);
+
+    Ok(())
+}
+
+#[test]
+fn eval_query_explain_default() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "policy.rego".to_string(),
+        r#"package test
+import rego.v1
+
+default allow := false
+allow if input.role == "admin"
+
+default limits["cpu"] := 1
+limits[k] := v if some k, v in input.limits
+
+default size(_) := 0
+size(x) := count(x) if is_string(x)
+n := size(5)
+"#
+        .to_string(),
+    )?;
+    engine.set_input(Value::from_json_str(
+        r#"{ "role": "guest", "limits": { "mem": 2 } }"#,
+    )?);
+
+    // Each use of a default value is recorded as an Eval and an Exit of the default rule.
+    let defaults = |explanation: &serde_json::Value| -> Vec<serde_json::Value> {
+        let events: Vec<&serde_json::Value> = explanation
+            .as_array()
+            .expect("explanation must be an array")
+            .iter()
+            .filter(|e| !e["path"].is_null())
+            .collect();
+        events
+            .chunks(2)
+            .map(|pair| {
+                let [eval, exit] = pair else {
+                    panic!("unpaired default events: {pair:?}");
+                };
+                assert_eq!([&eval["op"], &exit["op"]], ["Eval", "Exit"]);
+                assert_eq!([&eval["type"], &exit["type"]], ["rule", "rule"]);
+                assert_eq!(eval["location"], exit["location"]);
+                assert_eq!(eval["path"], exit["path"]);
+                serde_json::json!([exit["path"], exit["location"]["row"], exit["node"]["text"]])
+            })
+            .collect()
+    };
+
+    // The bodies fail and the defaults are used.
+    let (results, explanation) = engine.eval_query_explain("data.test".to_string())?;
+    assert_eq!(
+        results.result[0].expressions[0].value,
+        Value::from_json_str(r#"{ "allow": false, "limits": { "cpu": 1, "mem": 2 }, "n": 0 }"#)?
+    );
+    assert_eq!(
+        defaults(&explanation),
+        [
+            serde_json::json!(["data.test.size", 10, "default size(_) := 0"]),
+            serde_json::json!(["data.test.allow", 4, "default allow := false"]),
+            serde_json::json!([
+                "data.test.limits[\"cpu\"]",
+                7,
+                "default limits[\"cpu\"] := 1"
+            ]),
+        ]
+    );
+
+    // No event when a body succeeds.
+    engine.set_input(Value::from_json_str(
+        r#"{ "role": "admin", "limits": { "cpu": 4 } }"#,
+    )?);
+    let (_, explanation) = engine.eval_query_explain("data.test.allow".to_string())?;
+    assert!(defaults(&explanation).is_empty());
+    let (_, explanation) = engine.eval_query_explain("data.test.limits".to_string())?;
+    assert!(defaults(&explanation).is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn capabilities() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.set_capabilities_from_json(
+        r#"{
+  "builtins": [
+    { "name": "count", "decl": { "type": "function" } },
+    { "name": "print" }
+  ],
+  "future_keywords": ["in"]
+}"#,
+    )?;
+    engine.add_policy(
+        "test.rego".to_string(),
+        r#"package test
+double(x) := x * 2
+
+a := count([1, 2])
+b := double(a)
+"#
+        .to_string(),
+    )?;
+
+    assert_eq!(engine.eval_rule("data.test.b".to_string())?, Value::from(4));
+    assert!(engine
+        .eval_query("upper(\"a\")".to_string(), false)
+        .unwrap_err()
+        .to_string()
+        .contains("upper is not allowed by capabilities"));
+
+    // Policies using disallowed builtins are rejected even if the rule is not evaluated.
+    engine.add_policy(
+        "bad.rego".to_string(),
+        "package bad\nx := lower(\"A\")".to_string(),
+    )?;
+    let err = engine
+        .eval_rule("data.test.a".to_string())
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("--> bad.rego:2:11"), "{err}");
+    assert!(
+        err.contains("lower is not allowed by capabilities"),
+        "{err}"
+    );
+
+    assert!(engine
+        .set_capabilities_from_json(r#"{ "builtins": [ { "decl": {} } ] }"#)
+        .is_err());
+    assert!(engine.set_capabilities_from_json("{}").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn eval_rule_over_array() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "test.rego".to_string(),
+        r#"package test
+import rego.v1
+
+default allow := false
+allow if input.role == "admin"
+
+ratio := input.a / input.b
+"#
+        .to_string(),
+    )?;
+    engine.set_input(Value::from_json_str(r#"{"role": "admin"}"#)?);
+
+    let inputs =
+        Value::from_json_str(r#"[{"role": "guest"}, {"role": "admin"}, {}, {"role": "admin"}]"#)?;
+    assert_eq!(
+        engine.eval_rule_over_array("data.test.allow".to_string(), inputs)?,
+        vec![
+            Value::from(false),
+            Value::from(true),
+            Value::from(false),
+            Value::from(true)
+        ]
+    );
+
+    // The previous input is restored.
+    assert_eq!(
+        engine.eval_rule("data.test.allow".to_string())?,
+        Value::from(true)
+    );
+
+    // Errors are reported per element.
+    let inputs = Value::from_json_str(r#"[{"a": 4, "b": 2}, {"a": 1, "b": 0}, {"a": 9, "b": 3}]"#)?;
+    let results =
+        engine.eval_rule_over_array_results("data.test.ratio".to_string(), inputs.clone())?;
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].as_ref().unwrap(), &Value::from(2));
+    assert!(results[1].is_err());
+    assert_eq!(results[2].as_ref().unwrap(), &Value::from(3));
+
+    let err = engine
+        .eval_rule_over_array("data.test.ratio".to_string(), inputs)
+        .unwrap_err()
+        .to_string();
+    assert!(err.starts_with("input 1:"), "{err}");
+
+    assert!(engine
+        .eval_rule_over_array("data.test.allow".to_string(), Value::new_object())
+        .is_err());
+
+    Ok(())
+}
+
+#[test]
+fn take_prints_grouped() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.set_gather_prints(true);
+    engine.add_policy(
+        "test.rego".to_string(),
+        r#"package test
+import rego.v1
+
+allow if {
+  input.role
+  print("checking", input.role)
+  input.role == "admin"
+}
+"#
+        .to_string(),
+    )?;
+
+    engine.eval_query("print(\"before\")".to_string(), false)?;
+    engine.set_input(Value::from_json_str(r#"{"role": "admin"}"#)?);
+    engine.eval_rule("data.test.allow".to_string())?;
+    engine.set_input_json("{}")?;
+    engine.eval_rule("data.test.allow".to_string())?;
+
+    let prints = engine.take_prints_grouped()?;
+    assert_eq!(prints.len(), 3);
+    assert!(prints[0][0].contains("before"));
+    assert!(prints[1][0].contains("checking admin"));
+    assert!(prints[2].is_empty());
+    assert!(engine.take_prints_grouped()?.is_empty());
+
+    // Each element of a batch is a group.
+    let inputs = Value::from_json_str(r#"[{"role": "a"}, {}, {"role": "b"}]"#)?;
+    engine.eval_rule_over_array("data.test.allow".to_string(), inputs)?;
+    let prints = engine.take_prints_grouped()?;
+    assert_eq!(prints.len(), 3);
+    assert_eq!(prints[0].len(), 1);
+    assert!(prints[0][0].contains("checking a"));
+    assert!(prints[1].is_empty());
+    assert!(prints[2][0].contains("checking b"));
+
+    Ok(())
+}
+
+#[test]
+fn number_output() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.set_input(Value::from_json_str(r#"{"a": 2.0, "b": 1e3}"#)?);
+    let results = engine.eval_query(
+        "x := [10 / 2, 10 / 4, -3, input.a, input.b, 1e20, -1e20, {\"c\": [1e20]}]".to_string(),
+        false,
+    )?;
+    let x = &results.result[0].bindings["x"];
+
+    // Strip whitespace from the pretty printed output.
+    let render = |engine: &Engine| -> Result<String> {
+        Ok(engine.to_json_str(x)?.split_whitespace().collect())
+    };
+
+    let natural = engine.to_json_str(x)?;
+    assert_eq!(
+        render(&engine)?,
+        r#"[5,2.5,-3,2,1000,1e+20,-1e+20,{"c":[1e+20]}]"#
+    );
+
+    engine.set_number_output(NumberOutput::AlwaysFloat);
+    assert_eq!(
+        render(&engine)?,
+        r#"[5.0,2.5,-3.0,2.0,1000.0,1e+20,-1e+20,{"c":[1e+20]}]"#
+    );
+
+    engine.set_number_output(NumberOutput::IntegerWhenWhole);
+    assert_eq!(
+        render(&engine)?,
+        r#"[5,2.5,-3,2,1000,100000000000000000000,-100000000000000000000,{"c":[100000000000000000000]}]"#
+    );
+    // Layout is the same as in the other modes.
+    assert_eq!(
+        engine.to_json_str(x)?,
+        natural.replace("1e+20", "100000000000000000000")
+    );
+
+    // Query results are serialized using the mode too.
+    engine.set_number_output(NumberOutput::AlwaysFloat);
+    let json = engine.to_json_str(&results)?;
+    assert!(json.contains("2.0,"), "{json}");
+
+    Ok(())
+}
+
+#[test]
+fn eval_bindings() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.set_input(Value::from_json_str(r#"{"user": "alice"}"#)?);
+
+    let bindings = engine
+        .eval_bindings("x := input.user; y := count(x)".to_string())?
+        .unwrap();
+    assert_eq!(bindings["x"], Value::from("alice"));
+    assert_eq!(bindings["y"], Value::from(5));
+
+    // Queries without bindings produce an empty object.
+    assert_eq!(
+        engine.eval_bindings("1 < 2".to_string())?,
+        Some(Value::new_object())
+    );
+
+    assert_eq!(engine.eval_bindings("input.missing".to_string())?, None);
+
+    let err = engine
+        .eval_bindings("x := [1, 2][_]".to_string())
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("more than one result"), "{err}");
+
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "arc")]
+fn eval_query_cancellable() -> Result<()> {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "test.rego".to_string(),
+        r#"package test
+import rego.v1
+
+items := [x | some x in numbers.range(1, 100); tick(x)]
+"#
+        .to_string(),
+    )?;
+
+    // The extension sets the flag midway through the loop.
+    let cancel = Arc::new(AtomicBool::new(false));
+    let calls = Arc::new(AtomicUsize::new(0));
+    let (flag, count) = (cancel.clone(), calls.clone());
+    engine.add_extension(
+        "tick".to_string(),
+        Some(1),
+        Box::new(move |_: Vec<Value>| {
+            if count.fetch_add(1, Ordering::Relaxed) == 9 {
+                flag.store(true, Ordering::Relaxed);
+            }
+            Ok(Value::from(true))
+        }),
+    )?;
+
+    let err = engine
+        .eval_query_cancellable("data.test.items".to_string(), cancel.clone())
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("evaluation cancelled"), "{err}");
+    assert_eq!(calls.load(Ordering::Relaxed), 10);
+
+    // The flag is not consulted by other evaluations.
+    let results = engine.eval_query("count(data.test.items)".to_string(), false)?;
+    assert_eq!(results.result[0].expressions[0].value, Value::from(100));
+
+    cancel.store(false, Ordering::Relaxed);
+    let results = engine.eval_query_cancellable("count(data.test.items)".to_string(), cancel)?;
+    assert_eq!(results.result[0].expressions[0].value, Value::from(100));
+
+    Ok(())
+}
+
+#[test]
+fn add_policy_with_info() -> Result<()> {
+    let mut engine = Engine::new();
+    let info = engine.add_policy_with_info(
+        "lib.rego".to_string(),
+        r#"package lib.users
+import future.keywords.if
+import input.user as u
+
+all := ["alice", "bob"]
+is_admin(x) if x == "alice"
+is_admin(x) if x == u
+x.y.z := 1
+"#
+        .to_string(),
+    )?;
+    assert_eq!(
+        info,
+        ModuleInfo {
+            package: "data.lib.users".to_string(),
+            imports: vec!["future.keywords.if".to_string(), "input.user".to_string()],
+            rule_paths: vec![
+                "data.lib.users.all".to_string(),
+                "data.lib.users.is_admin".to_string(),
+                "data.lib.users.x.y.z".to_string(),
+            ],
+        }
+    );
+
+    let info =
+        engine.add_policy_with_info("empty.rego".to_string(), "package empty".to_string())?;
+    assert_eq!(info.package, "data.empty");
+    assert!(info.imports.is_empty());
+    assert!(info.rule_paths.is_empty());
+
+    // The policies are added as with add_policy.
+    assert_eq!(engine.get_packages()?, vec!["data.lib.users", "data.empty"]);
+    assert_eq!(
+        engine.eval_rule("data.lib.users.x.y.z".to_string())?,
+        Value::from(1)
+    );
+
+    assert!(engine
+        .add_policy_with_info("bad.rego".to_string(), "package".to_string())
+        .is_err());
+
+    Ok(())
+}
+
+#[test]
+fn get_virtual_data() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_data(Value::from_json_str(
+        r#"{ "namespaces": { "prod": { "replicas": 3 }, "dev": { "replicas": 1 } } }"#,
+    )?)?;
+    engine.add_policy(
+        "test.rego".to_string(),
+        r#"package test
+import rego.v1
+
+names := object.keys(data.namespaces)
+large contains name if {
+  some name, ns in data.namespaces
+  ns.replicas > 2
+}
+f(x) := x
+"#
+        .to_string(),
+    )?;
+    engine.add_policy("empty.rego".to_string(), "package a.b".to_string())?;
+
+    let data = engine.get_virtual_data()?;
+    assert_eq!(data["namespaces"], engine.get_data()["namespaces"]);
+    assert_eq!(
+        data["test"]["names"],
+        Value::from_json_str(r#"["dev", "prod"]"#)?.into_set()?
+    );
+    assert_eq!(
+        data["test"]["large"],
+        Value::from_json_str(r#"["prod"]"#)?.into_set()?
+    );
+    assert_eq!(data["a"]["b"], Value::new_object());
+
+    // The same document is produced by evaluating `data`.
+    let results = engine.eval_query("data".to_string(), false)?;
+    assert_eq!(results.result[0].expressions[0].value, data);
+
+    // Base data does not include rules or packages.
+    assert_eq!(engine.get_data()["test"], Value::Undefined);
+    assert_eq!(engine.get_data()["a"], Value::Undefined);
+
+    Ok(())
+}
+
+#[test]
+fn max_results() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "test.rego".to_string(),
+        r#"package test
+items := numbers.range(1, 20)
+"#
+        .to_string(),
+    )?;
+    engine.set_max_results(50);
+
+    // Cartesian product of 20 x 20 results.
+    let query = "x := data.test.items[_]; y := data.test.items[_]".to_string();
+    let err = engine
+        .eval_query(query.clone(), false)
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("query produced more than 50 results"), "{err}");
+
+    // Results within the limit.
+    let results = engine.eval_query("x := data.test.items[_]".to_string(), false)?;
+    assert_eq!(results.result.len(), 20);
+
+    // Rules and comprehensions are not limited.
+    let results = engine.eval_query(
+        "x := count([[a, b] | a := data.test.items[_]; b := data.test.items[_]])".to_string(),
+        false,
+    )?;
+    assert_eq!(results.result[0].bindings["x"], Value::from(400));
+
+    // Undefined results count towards the limit.
+    engine.set_include_undefined_results(true);
+    let err = engine
+        .eval_query(
+            "x := data.test.items[_]; y := data.test.items[_]; false".to_string(),
+            false,
+        )
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("query produced more than 50 results"), "{err}");
+    engine.set_include_undefined_results(false);
+
+    engine.set_max_results(usize::MAX);
+    assert_eq!(engine.eval_query(query, false)?.result.len(), 400);
+
+    Ok(())
+}
+
+#[test]
+fn eval_rule_explained() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "test.rego".to_string(),
+        r#"package test
+import rego.v1
+
+default level := "low"
+level := "high" if input.score > 10
+
+allow if input.admin
+allow if input.owner
+
+x := 10 / input.n
+f(a) := a
+
+s contains "a" if input.admin
+"#
+        .to_string(),
+    )?;
+    engine.set_input(Value::from_json_str(
+        r#"{"admin": false, "owner": false, "score": 1, "n": 0}"#,
+    )?);
+
+    assert_eq!(
+        engine.eval_rule_explained("data.test.allow".to_string())?,
+        RuleOutcome::UndefinedAllBodiesFalse
+    );
+
+    // Defaults are used when all bodies are false.
+    assert_eq!(
+        engine.eval_rule_explained("data.test.level".to_string())?,
+        RuleOutcome::Defined(Value::from("low"))
+    );
+
+    // Partial sets are empty rather than undefined.
+    assert_eq!(
+        engine.eval_rule_explained("data.test.s".to_string())?,
+        RuleOutcome::Defined(Value::new_set())
+    );
+
+    // No rules at these paths.
+    for path in [
+        "data.test.deny",
+        "data.other.allow",
+        "data.test.f",
+        "data",
+        "input",
+    ] {
+        assert_eq!(
+            engine.eval_rule_explained(path.to_string())?,
+            RuleOutcome::UndefinedNoMatch,
+            "{path}"
+        );
+    }
+
+    match engine.eval_rule_explained("data.test.x".to_string())? {
+        RuleOutcome::Error(e) => assert!(e.contains("divide by zero"), "{e}"),
+        r => panic!("unexpected outcome {r:?}"),
+    }
+
+    engine.set_input(Value::from_json_str(
+        r#"{"owner": true, "score": 20, "n": 5}"#,
+    )?);
+    assert_eq!(
+        engine.eval_rule_explained("data.test.allow".to_string())?,
+        RuleOutcome::Defined(Value::from(true))
+    );
+    assert_eq!(
+        engine.eval_rule_explained("data.test.level".to_string())?,
+        RuleOutcome::Defined(Value::from("high"))
+    );
+    assert_eq!(
+        engine.eval_rule_explained("data.test.x".to_string())?,
+        RuleOutcome::Defined(Value::from(2))
+    );
+
+    Ok(())
+}
+
+#[test]
+fn eval_rule_evaluates_reachable_rules_only() -> Result<()> {
+    use std::sync::{Arc, Mutex};
+
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "lib.rego".to_string(),
+        r#"package lib
+import rego.v1
+
+admins := {name | some name in input.users; trace_rule("lib.admins", name)}
+unused := trace_rule("lib.unused", 1)
+"#
+        .to_string(),
+    )?;
+    engine.add_policy(
+        "test.rego".to_string(),
+        r#"package test
+import rego.v1
+
+allow if is_admin
+is_admin if input.user in data.lib.admins
+
+unreachable := 1 / 0
+other := trace_rule("test.other", 1)
+"#
+        .to_string(),
+    )?;
+
+    let evaluated = Arc::new(Mutex::new(std::collections::BTreeSet::new()));
+    let log = evaluated.clone();
+    engine.add_extension(
+        "trace_rule".to_string(),
+        Some(2),
+        Box::new(move |args: Vec<Value>| {
+            log.lock().unwrap().insert(args[0].as_string()?.to_string());
+            Ok(Value::from(true))
+        }),
+    )?;
+    engine.set_input(Value::from_json_str(
+        r#"{"user": "alice", "users": ["alice", "bob"]}"#,
+    )?);
+
+    assert_eq!(
+        engine.eval_rule("data.test.allow".to_string())?,
+        Value::from(true)
+    );
+    assert_eq!(
+        *evaluated.lock().unwrap(),
+        ["lib.admins".to_string()].into_iter().collect()
+    );
+
+    // Evaluating all rules reports the error in the unreachable rule.
+    assert!(engine
+        .eval_query("data.test".to_string(), false)
+        .unwrap_err()
+        .to_string()
+        .contains("divide by zero"));
+
+    Ok(())
+}
+
+#[test]
+fn eval_query_opa_format() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.set_input(Value::from_json_str(r#"{"roles": ["admin", "dev"]}"#)?);
+
+    // Recorded using `opa eval --format json`.
+    let query = r#"x := 1 + 2; y := {"a": x, "b": {r | r := input.roles[_]}}"#;
+    let opa = r#"{
+  "result": [
+    {
+      "expressions": [
+        {
+          "value": true,
+          "text": "x := 1 + 2",
+          "location": {
+            "row": 1,
+            "col": 1
+          }
+        },
+        {
+          "value": true,
+          "text": "y := {\"a\": x, \"b\": {r | r := input.roles[_]}}",
+          "location": {
+            "row": 1,
+            "col": 13
+          }
+        }
+      ],
+      "bindings": {
+        "x": 3,
+        "y": {
+          "a": 3,
+          "b": [
+            "admin",
+            "dev"
+          ]
+        }
+      }
+    }
+  ]
+}"#;
+    assert_eq!(
+        engine.eval_query_opa_format(query.to_string())?,
+        serde_json::from_str::<serde_json::Value>(opa)?
+    );
+    let results = engine.eval_query(query.to_string(), false)?;
+    assert_eq!(serde_json::to_string_pretty(&results)?, opa);
+
+    let query = "input.roles[_]";
+    let opa = r#"{
+  "result": [
+    {
+      "expressions": [
+        {
+          "value": "admin",
+          "text": "input.roles[_]",
+          "location": {
+            "row": 1,
+            "col": 1
+          }
+        }
+      ]
+    },
+    {
+      "expressions": [
+        {
+          "value": "dev",
+          "text": "input.roles[_]",
+          "location": {
+            "row": 1,
+            "col": 1
+          }
+        }
+      ]
+    }
+  ]
+}"#;
+    let results = engine.eval_query(query.to_string(), false)?;
+    assert_eq!(serde_json::to_string_pretty(&results)?, opa);
+
+    // No results.
+    let results = engine.eval_query("input.missing".to_string(), false)?;
+    assert_eq!(serde_json::to_string_pretty(&results)?, "{}");
+
+    Ok(())
+}
+
+#[test]
+fn apply_input_defaults() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.set_input_schema(Value::from_json_str(
+        r#"{
+      "type": "object",
+      "properties": {
+        "role": { "type": "string", "default": "guest" },
+        "mfa": { "type": "boolean", "default": false },
+        "limits": {
+          "type": "object",
+          "properties": { "max": { "type": "integer", "default": 10 } }
+        },
+        "resources": {
+          "type": "array",
+          "items": {
+            "type": "object",
+            "properties": { "owner": { "type": "string", "default": "root" } }
+          }
+        }
+      }
+    }"#,
+    )?);
+
+    // Defaults are not applied unless enabled.
+    engine.set_input(Value::from_json_str(
+        r#"{ "role": "admin", "limits": {}, "resources": [{ "name": "a" }] }"#,
+    )?);
+    let results = engine.eval_query("input.mfa".to_string(), false)?;
+    assert!(results.result.is_empty());
+
+    // Enabling applies defaults to the current input.
+    engine.set_apply_input_defaults(true);
+    let results = engine.eval_query("input".to_string(), false)?;
+    assert_eq!(
+        results.result[0].expressions[0].value,
+        Value::from_json_str(
+            r#"{
+          "role": "admin",
+          "mfa": false,
+          "limits": { "max": 10 },
+          "resources": [{ "name": "a", "owner": "root" }]
+        }"#
+        )?
+    );
+
+    // Absent objects are not created.
+    engine.set_input(Value::from_json_str(r#"{ "mfa": true }"#)?);
+    let results = engine.eval_query("input".to_string(), false)?;
+    assert_eq!(
+        results.result[0].expressions[0].value,
+        Value::from_json_str(r#"{ "role": "guest", "mfa": true }"#)?
+    );
+
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn decide() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "policy.rego".to_string(),
+        r#"
+        package example
+        import rego.v1
+
+        default allow := false
+        allow if input.role == "admin"
+
+        reason := "admin" if allow
+        "#
+        .to_string(),
+    )?;
+    let prev_input = Value::from_json_str(r#"{"role": "guest"}"#)?;
+    engine.set_input(prev_input.clone());
+
+    let input = Value::from_json_str(r#"{"role": "admin"}"#)?;
+    let log = engine.decide("data.example.allow".to_string(), input.clone())?;
+    assert_eq!(log.path, "data.example.allow");
+    assert_eq!(log.input, input);
+    assert_eq!(log.result, Value::from(true));
+    assert!(log.timestamp_ns.is_some_and(|t| t > 0));
+    assert!(log.metrics.timer_rego_query_eval_ns.is_some());
+
+    // The previous input is restored.
+    assert_eq!(
+        engine.eval_rule("data.example.allow".to_string())?,
+        Value::from(false)
+    );
+
+    // Undefined results are omitted from the serialized record.
+    let log = engine.decide("data.example.reason".to_string(), prev_input)?;
+    assert_eq!(log.result, Value::Undefined);
+    let json: serde_json::Value = serde_json::from_str(&serde_json::to_string(&log)?)?;
+    assert_eq!(json["path"], "data.example.reason");
+    assert_eq!(json["input"]["role"], "guest");
+    assert!(json.get("result").is_none());
+    assert!(json["metrics"]["timer_rego_query_eval_ns"].is_u64());
+
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "regex")]
+fn memoize_builtins() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "policy.rego".to_string(),
+        r#"
+        package test
+        import rego.v1
+
+        valid_names contains name if {
+          some name in input.names
+          regex.match("^[a-z][a-z0-9-]*$", name)
+        }
+
+        ids := [rand.intn("x", 1000000) | some _ in input.names]
+        "#
+        .to_string(),
+    )?;
+    engine.set_input(Value::from_json_str(r#"{"names": ["a", "b", "a", "B"]}"#)?);
+
+    // Memoization is off by default.
+    engine.eval_rule("data.test.valid_names".to_string())?;
+    assert_eq!(engine.get_memoized_builtin_hits(), 0);
+
+    engine.set_memoize_builtins(true);
+    let expected = Value::from(std::collections::BTreeSet::from([
+        Value::from("a"),
+        Value::from("b"),
+    ]));
+    assert_eq!(
+        engine.eval_rule("data.test.valid_names".to_string())?,
+        expected
+    );
+    // The second "a" hits the cache.
+    assert_eq!(engine.get_memoized_builtin_hits(), 1);
+
+    // The cache is retained across evaluations.
+    assert_eq!(
+        engine.eval_rule("data.test.valid_names".to_string())?,
+        expected
+    );
+    assert_eq!(engine.get_memoized_builtin_hits(), 5);
+
+    // Non-deterministic builtins are not memoized.
+    engine.eval_rule("data.test.ids".to_string())?;
+    assert_eq!(engine.get_memoized_builtin_hits(), 5);
+
+    // The cache is bounded; the least recently used results are evicted.
+    let mut engine = Engine::new();
+    engine.set_memoize_builtins(true);
+    engine.eval_query(
+        "x := [abs(i) | i := numbers.range(0, 10000)[_]]".to_string(),
+        false,
+    )?;
+    assert_eq!(engine.get_memoized_builtin_hits(), 0);
+    engine.eval_query("x := abs(10000)".to_string(), false)?;
+    assert_eq!(engine.get_memoized_builtin_hits(), 1);
+    engine.eval_query("x := abs(0)".to_string(), false)?;
+    assert_eq!(engine.get_memoized_builtin_hits(), 1);
+
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "regex")]
+fn regex_cache_many_inputs() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "policy.rego".to_string(),
+        r#"
+        package test
+
+        valid := regex.match("^user-[0-9]{1,4}@(example|test)\\.com$", input.email)
+        "#
+        .to_string(),
+    )?;
+
+    let count = 2000;
+    let inputs = Value::from(
+        (0..count)
+            .map(|i| {
+                let domain = if i % 3 == 0 {
+                    "example.org"
+                } else {
+                    "test.com"
+                };
+                Value::from_json_str(&format!(r#"{{"email": "user-{i}@{domain}"}}"#))
+            })
+            .collect::<Result<Vec<Value>>>()?,
+    );
+
+    let results = engine.eval_rule_over_array("data.test.valid".to_string(), inputs.clone())?;
+    for (i, r) in results.iter().enumerate() {
+        assert_eq!(r, &Value::from(i % 3 != 0), "input {i}");
+    }
+
+    // Results are unaffected by clearing the cache of this or another engine.
+    let mut other = engine.clone();
+    other.clear_regex_cache();
+    assert_eq!(
+        engine.eval_rule_over_array("data.test.valid".to_string(), inputs.clone())?,
+        results
+    );
+    assert_eq!(
+        other.eval_rule_over_array("data.test.valid".to_string(), inputs)?,
+        results
+    );
+
+    Ok(())
+}
+
+#[test]
+fn get_query_schedule() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "policy.rego".to_string(),
+        r#"
+        package test
+
+        limit := 10
+        "#
+        .to_string(),
+    )?;
+
+    let query = "z = y * 2; z < data.test.limit; y = x + 1; x = input.values[_]";
+    let info = engine.get_query_schedule(query.to_string())?;
+    let summary: Vec<_> = info
+        .statements
+        .iter()
+        .map(|s| (s.text.as_str(), s.order, s.defines.clone(), s.uses.clone()))
+        .collect();
+    let strs = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        vec![
+            ("z = y * 2", 2, strs(&["z"]), strs(&["y"])),
+            ("z < data.test.limit", 3, strs(&[]), strs(&["z"])),
+            ("y = x + 1", 1, strs(&["y"]), strs(&["x"])),
+            ("x = input.values[_]", 0, strs(&["x"]), strs(&[])),
+        ]
+    );
+
+    // The schedule matches evaluation.
+    engine.set_input(Value::from_json_str(r#"{"values": [1, 3, 5]}"#)?);
+    let results = engine.eval_query(query.to_string(), false)?;
+    assert_eq!(results.result.len(), 2);
+
+    // Unsafe variables are reported.
+    let err = engine
+        .get_query_schedule("x := y + 1".to_string())
+        .unwrap_err()
+        .to_string();
+    assert!(
+        err.contains("use of undefined variable `y` is unsafe"),
+        "{err}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn add_policies_from_combined() -> Result<()> {
+    let mut engine = Engine::new();
+    let bundle = r#"# Shared configuration.
+package lib
+limit := 10
+
+package test
+import rego.v1
+
+allow if input.x < data.lib.limit
+"#;
+    let packages =
+        engine.add_policies_from_combined("bundle.rego".to_string(), bundle.to_string())?;
+    assert_eq!(packages, vec!["data.lib", "data.test"]);
+    assert_eq!(engine.get_packages()?, vec!["data.lib", "data.test"]);
+
+    engine.set_input(Value::from_json_str(r#"{"x": 5}"#)?);
+    assert_eq!(
+        engine.eval_rule("data.test.allow".to_string())?,
+        Value::from(true)
+    );
+
+    // Errors are reported relative to the combined source, and no modules are added.
+    let mut engine = Engine::new();
+    let bundle = "package a\nx := 1\n\npackage b\ny := [1,\n";
+    let err = engine
+        .add_policies_from_combined("bundle.rego".to_string(), bundle.to_string())
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("bundle.rego:6:"), "{err}");
+    assert!(engine.get_packages()?.is_empty());
+
+    // Content before the first package declaration.
+    let err = engine
+        .add_policies_from_combined(
+            "bundle.rego".to_string(),
+            "# ok\nx := 1\npackage a".to_string(),
+        )
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("bundle.rego:2:1"), "{err}");
+    assert!(err.contains("expecting package declaration"), "{err}");
+
+    // Malformed package declaration at a boundary.
+    let err = engine
+        .add_policies_from_combined(
+            "bundle.rego".to_string(),
+            "package a\nx := 1\npackage 5\n".to_string(),
+        )
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("bundle.rego:3:"), "{err}");
+
+    Ok(())
+}
+
+#[test]
+fn take_print_values() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "policy.rego".to_string(),
+        r#"
+        package test
+        import rego.v1
+
+        latencies := [10, 25, 5]
+
+        slow contains i if {
+          some i, l in latencies
+          print("latency", i, l, input.missing)
+          l > 8
+        }
+        "#
+        .to_string(),
+    )?;
+
+    // Values are not gathered by default.
+    engine.set_gather_prints(true);
+    engine.eval_rule("data.test.slow".to_string())?;
+    assert!(engine.take_print_values().is_empty());
+    assert_eq!(engine.take_prints()?.len(), 3);
+
+    engine.set_gather_print_values(true);
+    engine.eval_rule("data.test.slow".to_string())?;
+    let values = engine.take_print_values();
+    let total: u64 = values.iter().map(|v| v[2].as_u64()).sum::<Result<u64>>()?;
+    assert_eq!(total, 40);
+    assert_eq!(
+        values[1],
+        vec![
+            Value::from("latency"),
+            Value::from(1),
+            Value::from(25),
+            Value::Undefined
+        ]
+    );
+
+    // Formatted prints are unaffected and both are cleared when taken.
+    assert_eq!(engine.take_prints()?.len(), 3);
+    assert!(engine.take_print_values().is_empty());
+
+    // Values are gathered even when formatted prints are not.
+    engine.set_gather_prints(false);
+    engine.eval_query("print(1 + 1)".to_string(), false)?;
+    assert_eq!(engine.take_print_values(), vec![vec![Value::from(2)]]);
+
+    Ok(())
+}
+
+#[test]
+fn eval_rule_stream() -> Result<()> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "policy.rego".to_string(),
+        r#"
+        package test
+        import rego.v1
+
+        ids contains sprintf("id-%d", [i]) if some i in numbers.range(1, 20000)
+
+        owners[name] := count(name) if some name in ["alice", "bob"]
+        owners["carol"] := 5
+
+        config := {"a", "b"}
+
+        missing contains x if some x in input.missing
+
+        items contains produce(i) if some i in numbers.range(1, 1000)
+
+        repeated contains x if some x in [1, 2, 1]
+        repeated contains x if some x in [2, 3]
+
+        sizes[x] := 1 if some x in ["a", "b", "a"]
+        sizes[x] := 1 if some x in ["b", "c"]
+
+        conflict[x] := 1 if some x in ["a", "b"]
+        conflict[x] := 2 if some x in ["b"]
+        "#
+        .to_string(),
+    )?;
+
+    let produced = Arc::new(AtomicUsize::new(0));
+    let counter = produced.clone();
+    engine.add_extension(
+        "produce".to_string(),
+        Some(1),
+        Box::new(move |mut params: Vec<Value>| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Ok(params.remove(0))
+        }),
+    )?;
+
+    let collect = |engine: &mut Engine, path: &str| -> Result<Vec<Value>> {
+        let mut values = vec![];
+        engine.eval_rule_stream(path.to_string(), &mut |v| {
+            values.push(v);
+            Ok(())
+        })?;
+        Ok(values)
+    };
+
+    // Large partial set, passed in evaluation order.
+    let ids = collect(&mut engine, "data.test.ids")?;
+    assert_eq!(ids.len(), 20000);
+    assert_eq!(ids[9999], Value::from("id-10000"));
+    assert_eq!(ids[19999], Value::from("id-20000"));
+
+    // Partial object rules yield entries, including those of rules with constant keys.
+    let entries = collect(&mut engine, "data.test.owners")?;
+    assert_eq!(
+        Value::from(entries),
+        Value::from_json_str(r#"[["alice", 5], ["bob", 3], ["carol", 5]]"#)?
+    );
+
+    // Complete rules call back once, even if the value is a set.
+    let values = collect(&mut engine, "data.test.config")?;
+    assert_eq!(values.len(), 1);
+    assert_eq!(values[0].as_set()?.len(), 2);
+
+    // Empty partial sets do not call back.
+    engine.eval_rule_stream("data.test.missing".to_string(), &mut |_| {
+        bail!("unexpected value")
+    })?;
+
+    // Members produced more than once, within a rule or by several rules, are passed once.
+    let members = collect(&mut engine, "data.test.repeated")?;
+    assert_eq!(Value::from(members), Value::from_json_str("[1, 2, 3]")?);
+    let entries = collect(&mut engine, "data.test.sizes")?;
+    assert_eq!(
+        Value::from(entries),
+        Value::from_json_str(r#"[["a", 1], ["b", 1], ["c", 1]]"#)?
+    );
+
+    // Conflicting values for a key are reported as by eval_rule.
+    let err = engine
+        .eval_rule("data.test.conflict".to_string())
+        .unwrap_err();
+    let stream_err = collect(&mut engine, "data.test.conflict").unwrap_err();
+    assert_eq!(stream_err.to_string(), err.to_string());
+
+    // Errors from the callback stop evaluation of the rule before further members are produced.
+    let mut received = 0;
+    let err = engine
+        .eval_rule_stream("data.test.items".to_string(), &mut |_| {
+            received += 1;
+            if received == 10 {
+                bail!("enough");
+            }
+            Ok(())
+        })
+        .unwrap_err();
+    assert!(err.to_string().contains("enough"));
+    assert_eq!(received, 10);
+    assert_eq!(produced.load(Ordering::SeqCst), 10);
+
+    // The engine is usable after a stream is stopped, and the rule's value is unaffected.
+    assert_eq!(
+        engine
+            .eval_rule("data.test.items".to_string())?
+            .as_set()?
+            .len(),
+        1000
+    );
+
+    Ok(())
+}
+
+#[test]
+fn eval_query_in_package() -> Result<()> {
+    let mut engine = Engine::new();
+    for (tenant, max) in [("tenant_a", 10), ("tenant_b", 20)] {
+        engine.add_policy(
+            format!("{tenant}.rego"),
+            format!(
+                r#"
+                package {tenant}
+                import rego.v1
+
+                max := {max}
+                allow if input.x <= max
+                "#
+            ),
+        )?;
+    }
+    engine.add_policy(
+        "tenant_b_sub.rego".to_string(),
+        "package tenant_b.sub\nnote := \"b\"".to_string(),
+    )?;
+    engine.set_input(Value::from_json_str(r#"{"x": 15}"#)?);
+
+    let value = |r: QueryResults| r.result[0].expressions[0].value.clone();
+
+    // Unqualified references resolve to the package's rules.
+    assert!(engine
+        .eval_query_in_package("tenant_a", "allow".to_string())?
+        .result
+        .is_empty());
+    assert_eq!(
+        value(engine.eval_query_in_package("data.tenant_b", "allow".to_string())?),
+        Value::from(true)
+    );
+    assert_eq!(
+        value(engine.eval_query_in_package("tenant_b", "[max, data.tenant_b.max]".to_string())?),
+        Value::from_json_str("[20, 20]")?
+    );
+
+    // Other packages are excluded from data.
+    assert_eq!(
+        value(engine.eval_query_in_package("tenant_b", "data".to_string())?),
+        Value::from_json_str(r#"{"tenant_b": {"allow": true, "max": 20, "sub": {"note": "b"}}}"#)?
+    );
+    assert!(engine
+        .eval_query_in_package("tenant_b", "data.tenant_a.max".to_string())?
+        .result
+        .is_empty());
+
+    // The engine is unaffected.
+    assert_eq!(
+        value(engine.eval_query("[data.tenant_a.max, data.tenant_b.max]".to_string(), false)?),
+        Value::from_json_str("[10, 20]")?
+    );
+
+    let err = engine
+        .eval_query_in_package("tenant_c", "allow".to_string())
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "no policies found in package data.tenant_c"
+    );
+
+    // Names imported by the package's policies are visible.
+    engine.add_policy(
+        "lib.rego".to_string(),
+        "package lib\nlimits := {\"max\": 7}".to_string(),
+    )?;
+    engine.add_policy(
+        "tenant_c.rego".to_string(),
+        r#"
+        package tenant_c
+        import rego.v1
+        import data.lib.limits
+        import input.x as requested
+
+        deny if requested > limits.max
+        "#
+        .to_string(),
+    )?;
+    assert_eq!(
+        value(
+            engine
+                .eval_query_in_package("tenant_c", "[deny, limits.max, requested]".to_string())?
+        ),
+        Value::from_json_str("[true, 7, 15]")?
+    );
+    assert!(engine
+        .eval_query_in_package("tenant_c", "data.lib.limits".to_string())?
+        .result
+        .is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn iteration_order_is_sorted() -> Result<()> {
+    // Objects and sets are backed by ordered collections in every build
+    // configuration, so comprehensions yield the same order with and without
+    // the `std` feature.
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "test.rego".to_string(),
+        r#"
+        package test
+
+        obj := {"zeta": 1, "alpha": 2, "mid": 3, "beta": 4}
+        s := {"pear", "apple", 10, "fig", 2, false}
+
+        obj_keys := [k | obj[k]]
+        obj_values := [v | v := obj[_]]
+        set_elems := [x | x := s[_]]
+        nested := [[k, x] | obj[k]; x := s[_]; is_string(x)]
+        "#
+        .to_string(),
+    )?;
+
+    for _ in 0..3 {
+        let results = engine.eval_query("data.test".to_string(), false)?;
+        let v = &results.result[0].expressions[0].value;
+        assert_eq!(
+            v["obj_keys"],
+            Value::from_json_str(r#"["alpha", "beta", "mid", "zeta"]"#)?
+        );
+        assert_eq!(v["obj_values"], Value::from_json_str("[2, 4, 3, 1]")?);
+        assert_eq!(
+            v["set_elems"],
+            Value::from_json_str(r#"[false, 2, 10, "apple", "fig", "pear"]"#)?
+        );
+        assert_eq!(v["nested"].as_array()?.len(), 12);
+        assert_eq!(
+            v["nested"][0],
+            Value::from_json_str(r#"["alpha", "apple"]"#)?
+        );
+        assert_eq!(
+            v["nested"][11],
+            Value::from_json_str(r#"["zeta", "pear"]"#)?
+        );
+    }
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "coverage")]
+fn coverage_lcov() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "policy.rego".to_string(),
+        r#"package test
+import rego.v1
+
+allow if {
+  input.n > 2
+  input.n < 10
+}
+"#
+        .to_string(),
+    )?;
+    engine.set_enable_coverage(true);
+    engine.set_input(Value::from_json_str(r#"{"n": 1}"#)?);
+    engine.eval_rule("data.test.allow".to_string())?;
+
+    let lcov = engine.get_coverage_report()?.to_lcov();
+    assert_eq!(
+        lcov,
+        "SF:policy.rego\n\
+         DA:5,1\n\
+         DA:6,0\n\
+         LH:1\n\
+         LF:2\n\
+         end_of_record\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "coverage")]
+fn coverage_cobertura_xml() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "policy.rego".to_string(),
+        r#"package test
+import rego.v1
+
+allow if {
+  input.n > 2
+  input.n < 10
+}
+"#
+        .to_string(),
+    )?;
+    engine.add_policy(
+        "a&b.rego".to_string(),
+        "package other\nx := 1\n".to_string(),
+    )?;
+    engine.set_enable_coverage(true);
+    engine.set_input(Value::from_json_str(r#"{"n": 1}"#)?);
+    engine.eval_rule("data.test.allow".to_string())?;
+    engine.eval_rule("data.other.x".to_string())?;
+
+    let xml = engine.get_coverage_report()?.to_cobertura_xml();
+
+    // Elements are properly nested.
+    let mut stack = vec![];
+    for tag in xml.split('<').skip(1) {
+        let tag = &tag[..tag.find('>').unwrap()];
+        if tag.starts_with('?') || tag.starts_with('!') || tag.ends_with('/') {
+            continue;
+        }
+        match tag.strip_prefix('/') {
+            Some(name) => assert_eq!(stack.pop(), Some(name)),
+            None => stack.push(tag.split(' ').next().unwrap()),
+        }
+    }
+    assert!(stack.is_empty());
+
+    assert!(xml.contains(
+        r#"<coverage line-rate="0.6667" branch-rate="0" lines-covered="2" lines-valid="3""#
+    ));
+    assert!(xml.contains(r#"<class name="policy.rego" filename="policy.rego" line-rate="0.5000""#));
+    assert!(
+        xml.contains(r#"<class name="a&amp;b.rego" filename="a&amp;b.rego" line-rate="1.0000""#)
+    );
+    assert_eq!(xml.matches(r#"hits="1""#).count(), 2);
+    assert_eq!(xml.matches(r#"hits="0""#).count(), 1);
+    assert!(xml.contains(r#"<line number="6" hits="0"/>"#));
+
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "coverage")]
+fn merge_coverage_from() -> Result<()> {
+    let policy = r#"package test
+import rego.v1
+
+kind := "neg" if input.n < 0
+
+kind := "small" if {
+  input.n >= 0
+  input.n < 10
+}
+
+kind := "large" if {
+  input.n >= 10
+}
+"#;
+    let mut engine = Engine::new();
+    engine.add_policy("policy.rego".to_string(), policy.to_string())?;
+    engine.set_enable_coverage(true);
+
+    let eval = |engine: &mut Engine, n: i64| -> Result<coverage::Report> {
+        engine.set_input(Value::from_json_str(&format!(r#"{{"n": {n}}}"#))?);
+        engine.eval_rule("data.test.kind".to_string())?;
+        Ok(engine.get_coverage_report()?)
+    };
+
+    // Two shards with partial coverage.
+    let mut shard1 = engine.clone();
+    let mut shard2 = engine.clone();
+    let report1 = eval(&mut shard1, 5)?;
+    let report2 = eval(&mut shard2, 50)?;
+    assert_eq!(report1.files[0].covered, [4, 6, 7, 8, 12].into());
+    assert_eq!(report2.files[0].covered, [4, 7, 8, 11, 12].into());
+
+    engine.merge_coverage_from(&shard1)?;
+    engine.merge_coverage_from(&shard2)?;
+    let merged = engine.get_coverage_report()?;
+    assert_eq!(merged.files[0].covered, [4, 6, 7, 8, 11, 12].into());
+    assert!(merged.files[0].not_covered.is_empty());
+
+    // Merging reports gives the same result.
+    let mut report = report1;
+    report.merge(&report2);
+    assert_eq!(report.files[0].covered, merged.files[0].covered);
+    assert_eq!(report.files[0].not_covered, merged.files[0].not_covered);
+
+    // Coverage can also be merged from engines that loaded the same policy independently.
+    let mut other = Engine::new();
+    other.add_policy("policy.rego".to_string(), policy.to_string())?;
+    other.set_enable_coverage(true);
+    eval(&mut other, -1)?;
+
+    let mut engine = Engine::new();
+    engine.add_policy("policy.rego".to_string(), policy.to_string())?;
+    engine.set_enable_coverage(true);
+    assert!(engine.get_coverage_report()?.files.is_empty());
+    engine.merge_coverage_from(&other)?;
+    assert_eq!(
+        engine.get_coverage_report()?.files[0].covered,
+        [4, 7, 12].into()
+    );
+
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "coverage")]
+fn coverage_delta_since_last() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "policy.rego".to_string(),
+        r#"package test
+import rego.v1
+
+kind := "neg" if input.n < 0
+
+kind := "small" if {
+  input.n >= 0
+  input.n < 10
+}
+
+kind := "large" if {
+  input.n >= 10
+}
+"#
+        .to_string(),
+    )?;
+    engine.set_enable_coverage(true);
+
+    let mut deltas = vec![];
+    for n in [5, 50, 7, -1, 3] {
+        engine.set_input(Value::from_json_str(&format!(r#"{{"n": {n}}}"#))?);
+        engine.eval_rule("data.test.kind".to_string())?;
+        let delta = engine.coverage_delta_since_last()?;
+        deltas.push(delta.files[0].covered.iter().cloned().collect::<Vec<u32>>());
+    }
+    assert_eq!(deltas[0], vec![4, 6, 7, 8, 12]);
+    assert_eq!(deltas[1], vec![11]);
+    assert!(deltas[2..].iter().all(|d| d.is_empty()), "{deltas:?}");
+
+    // The full report is unaffected.
+    let report = engine.get_coverage_report()?;
+    assert_eq!(
+        report.files[0]
+            .covered
+            .iter()
+            .cloned()
+            .collect::<Vec<u32>>(),
+        vec![4, 6, 7, 8, 11, 12]
+    );
+
+    // Clearing coverage data restarts the deltas.
+    engine.clear_coverage_data();
+    engine.eval_rule("data.test.kind".to_string())?;
+    assert_eq!(
+        engine.coverage_delta_since_last()?.files[0]
+            .covered
+            .iter()
+            .cloned()
+            .collect::<Vec<u32>>(),
+        vec![4, 6, 7, 8, 12]
+    );
+
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "ast")]
+fn parse_query_ast() -> Result<()> {
+    let engine = Engine::new();
+    let ast = engine.parse_query_ast("x = [1,2][_]".to_string())?;
+    assert_eq!(ast["stmts"].as_array()?.len(), 1);
+
+    let expr = &ast["stmts"][0]["literal"]["Expr"]["expr"]["AssignExpr"];
+    assert_eq!(expr["op"], Value::from("Eq"));
+    assert_eq!(expr["lhs"]["Var"][1], Value::from("x"));
+
+    let refr = &expr["rhs"]["RefBrack"];
+    assert_eq!(refr["index"]["Var"][1], Value::from("_"));
+    let items = &refr["refr"]["Array"]["items"];
+    assert_eq!(items[0]["Number"][1], Value::from(1));
+    assert_eq!(items[1]["Number"][1], Value::from(2));
+    assert_eq!(refr["span"]["start"], Value::from(4));
+    assert_eq!(refr["span"]["end"], Value::from(12));
+
+    // Syntax errors are reported without evaluating anything.
+    let err = engine
+        .parse_query_ast("x = [1,2".to_string())
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("<query.rego>:1:"), "{err}");
+    Ok(())
+}
+
+#[test]
+fn error_format() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "test.rego".to_string(),
+        "package test\nx := 1\nx := 2\n".to_string(),
+    )?;
+
+    // Annotated is the default.
+    let err = engine
+        .eval_rule("data.test.x".to_string())
+        .unwrap_err()
+        .to_string();
+    assert!(
+        err.starts_with("\n--> test.rego:3:1\n  |\n3 | x := 2\n  | ^\n"),
+        "{err}"
+    );
+    assert!(err.contains("error: rule conflicts with the following rule:"));
+    assert!(err.contains("--> test.rego:2:1\n"));
+
+    let err = engine
+        .add_policy("bad.rego".to_string(), "package bad\ny := [1,".to_string())
+        .unwrap_err()
+        .to_string();
+    assert_eq!(
+        err,
+        "\n--> bad.rego:2:9\n  |\n2 | y := [1,\n  |         ^\nerror: expecting `]` while parsing array"
+    );
+
+    engine.set_error_format(ErrorFormat::Terse);
+    let conflict =
+        "test.rego:3:1: rule conflicts with the following rule: test.rego:2:1: defined here";
+    let err = engine.eval_rule("data.test.x".to_string()).unwrap_err();
+    assert_eq!(err.to_string(), conflict);
+    assert_eq!(
+        err.location().map(|l| l.to_string()),
+        Some("test.rego:3:1".into())
+    );
+    assert_eq!(
+        err.reason(),
+        "rule conflicts with the following rule: test.rego:2:1: defined here"
+    );
+    let err = engine
+        .eval_query("data.test.x".to_string(), false)
+        .unwrap_err();
+    assert_eq!(err.to_string(), conflict);
+    let err = engine
+        .decide("data.test.x".to_string(), Value::new_object())
+        .unwrap_err();
+    assert_eq!(err.to_string(), conflict);
+
+    let err = engine
+        .add_policy("bad.rego".to_string(), "package bad\ny := [1,".to_string())
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "bad.rego:2:9: expecting `]` while parsing array"
+    );
+
+    let err = engine
+        .add_policy("bad.rego".to_string(), "package bad\nx := ".to_string())
+        .unwrap_err();
+    assert_eq!(err.to_string(), "bad.rego:2:6: expecting expression");
+
+    // Related locations are rendered the same way.
+    let err = engine
+        .add_policy(
+            "order.rego".to_string(),
+            "package order
+x if { y == 1; y := 2 }"
+                .to_string(),
+        )
+        .and_then(|_| engine.eval_query("data.order.x".to_string(), false))
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "order.rego:2:8: var `y` used before definition below. order.rego:2:16: definition"
+    );
+
+    // Messages without source locations are unchanged.
+    let err = engine
+        .eval_query_in_package("missing", "x".to_string())
+        .unwrap_err();
+    assert_eq!(err.to_string(), "no policies found in package data.missing");
+
+    Ok(())
+}
+
+#[test]
+fn evaluate_decision() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "policy.rego".to_string(),
+        r#"
+        package example
+        import rego.v1
+
+        allow if {
+          input.method == "GET"
+          count(deny) == 0
+        }
+
+        deny contains "user is not authenticated" if not input.user
+        deny contains sprintf("method %s is not permitted", [input.method]) if input.method != "GET"
+        deny contains msg if {
+          some h in input.headers
+          startswith(h, "x-internal")
+          msg := sprintf("header %s is not allowed", [h])
+        }
+        "#
+        .to_string(),
+    )?;
+
+    engine.set_input(Value::from_json_str(
+        r#"{"method": "POST", "headers": ["x-internal-id", "accept", "x-internal-trace"]}"#,
+    )?);
+    // allow has no default and is undefined, which is a denial.
+    assert_eq!(
+        engine.eval_rule("data.example.allow".to_string())?,
+        Value::Undefined
+    );
+    let decision = engine.evaluate_decision("data.example.allow", "data.example.deny")?;
+    assert!(!decision.allowed);
+    assert_eq!(
+        decision.reasons,
+        vec![
+            Value::from("header x-internal-id is not allowed"),
+            Value::from("header x-internal-trace is not allowed"),
+            Value::from("method POST is not permitted"),
+            Value::from("user is not authenticated"),
+        ]
+    );
+
+    // Reasons are not gathered when allowed.
+    engine.set_input(Value::from_json_str(
+        r#"{"method": "GET", "user": "alice", "headers": []}"#,
+    )?);
+    let decision = engine.evaluate_decision("data.example.allow", "data.example.deny")?;
+    assert!(decision.allowed);
+    assert!(decision.reasons.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn compare_policies() -> Result<()> {
+    let policy = |limit: u32| {
+        format!(
+            r#"
+            package example
+            import rego.v1
+
+            default allow := false
+            allow if input.amount <= {limit}
+            "#
+        )
+    };
+    let mut old = Engine::new();
+    old.add_policy("policy.rego".to_string(), policy(100))?;
+    let mut new = Engine::new();
+    new.add_policy("policy.rego".to_string(), policy(50))?;
+    new.set_input(Value::from_json_str(r#"{"amount": 1}"#)?);
+
+    let inputs = Value::from_json_str(r#"[{"amount": 10}, {"amount": 75}, {"amount": 500}]"#)?;
+    let divergences =
+        regorus::compare_policies(&mut old, &mut new, "data.example.allow", inputs.clone())?;
+    assert_eq!(
+        divergences,
+        vec![Divergence {
+            input_index: 1,
+            old: Value::from(true),
+            new: Value::from(false),
+        }]
+    );
+
+    // Identical policies never diverge and inputs are restored.
+    assert!(
+        regorus::compare_policies(&mut new.clone(), &mut new, "data.example.allow", inputs)?
+            .is_empty()
+    );
+    assert_eq!(
+        new.eval_rule("data.example.allow".to_string())?,
+        Value::from(true)
+    );
+
+    let err = regorus::compare_policies(&mut old, &mut new, "data.example.allow", Value::Null);
+    assert_eq!(err.unwrap_err().to_string(), "inputs must be an array");
+
+    Ok(())
+}
+
+#[test]
+fn max_comprehension_size() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "test.rego".to_string(),
+        r#"
+        package test
+        import rego.v1
+
+        items := numbers.range(1, 20)
+
+        squares := {x: y | x := items[_]; y := x * x}
+        pairs := [[a, b] | a := items[_]; b := items[_]]
+        low contains a if {
+          some a in items
+          a <= 10
+        }
+        small := [[a, b] | a := low[_]; b := low[_]; a < b]
+        "#
+        .to_string(),
+    )?;
+    engine.set_max_comprehension_size(20);
+
+    // Object comprehension at the limit.
+    assert_eq!(
+        engine
+            .eval_rule("data.test.squares".to_string())?
+            .as_object()?
+            .len(),
+        20
+    );
+
+    // Array comprehension over the limit.
+    let err = engine
+        .eval_rule("data.test.pairs".to_string())
+        .unwrap_err()
+        .to_string();
+    assert!(
+        err.contains("comprehension produced more than 20 elements"),
+        "{err}"
+    );
+    assert!(err.contains("--> test.rego:8:19"), "{err}");
+
+    // Only produced elements count; iterations filtered out by the body do not.
+    let err = engine
+        .eval_rule("data.test.small".to_string())
+        .unwrap_err()
+        .to_string();
+    assert!(
+        err.contains("comprehension produced more than 20 elements"),
+        "{err}"
+    );
+    engine.set_max_comprehension_size(45);
+    assert_eq!(
+        engine
+            .eval_rule("data.test.small".to_string())?
+            .as_array()?
+            .len(),
+        45
+    );
+
+    // Set comprehension in a query.
+    let err = engine
+        .eval_query(
+            "x := {[a, b] | a := data.test.items[_]; b := data.test.items[_]}".to_string(),
+            false,
+        )
+        .unwrap_err()
+        .to_string();
+    assert!(
+        err.contains("comprehension produced more than 45 elements"),
+        "{err}"
+    );
+
+    engine.set_max_comprehension_size(usize::MAX);
+    assert_eq!(
+        engine
+            .eval_rule("data.test.pairs".to_string())?
+            .as_array()?
+            .len(),
+        400
+    );
+
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "time")]
+fn set_clock() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "test.rego".to_string(),
+        r#"package test
+           now := time.now_ns()
+           clock := time.clock(now)
+           date := time.date(now)
+           weekday := time.weekday(now)
+        "#
+        .to_string(),
+    )?;
+
+    // 2024-02-29T13:45:30Z
+    engine.set_clock(Some(1_709_214_330_000_000_000));
+    let results = engine.eval_query("data.test".to_string(), false)?;
+    assert_eq!(
+        results.result[0].expressions[0].value,
+        Value::from_json_str(
+            r#"{
+              "now": 1709214330000000000,
+              "clock": [13, 45, 30],
+              "date": [2024, 2, 29],
+              "weekday": "Thursday"
+            }"#
+        )?
+    );
+
+    // Changing the clock is reflected in subsequent evaluations.
+    engine.set_clock(Some(0));
+    assert_eq!(
+        engine.eval_rule("data.test.date".to_string())?,
+        Value::from_json_str("[1970, 1, 1]")?
+    );
+
+    engine.set_clock(None);
+    let now = engine.eval_rule("data.test.now".to_string())?.as_i64()?;
+    assert!(now > 1_709_214_330_000_000_000);
+
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "opa-runtime")]
+fn set_env() -> Result<()> {
+    use std::collections::BTreeMap;
+
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "test.rego".to_string(),
+        r#"
+        package test
+        import rego.v1
+
+        region := opa.runtime().env.REGION
+        debug := opa.runtime().env.DEBUG == "1"
+        default has_env := false
+        has_env if opa.runtime().env
+        "#
+        .to_string(),
+    )?;
+
+    // The process environment is never exposed.
+    assert_eq!(
+        engine.eval_rule("data.test.has_env".to_string())?,
+        Value::from(false)
+    );
+
+    engine.set_env(BTreeMap::from([(
+        "REGION".to_string(),
+        "westus".to_string(),
+    )]));
+    assert_eq!(
+        engine.eval_rule("data.test.has_env".to_string())?,
+        Value::from(true)
+    );
+    assert_eq!(
+        engine.eval_rule("data.test.region".to_string())?,
+        Value::from("westus")
+    );
+    // Unset variables are undefined.
+    assert_eq!(
+        engine.eval_rule("data.test.debug".to_string())?,
+        Value::Undefined
+    );
+
+    // Replacing the environment is reflected in subsequent evaluations.
+    engine.set_env(BTreeMap::from([("DEBUG".to_string(), "1".to_string())]));
+    assert_eq!(
+        engine.eval_rule("data.test.debug".to_string())?,
+        Value::from(true)
+    );
+    assert_eq!(
+        engine.eval_rule("data.test.region".to_string())?,
+        Value::Undefined
+    );
+
+    Ok(())
+}
+
+#[test]
+fn set_trace_filter() -> Result<()> {
+    use std::collections::BTreeSet;
+
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "policy.rego".to_string(),
+        r#"package test
+import rego.v1
+
+allow if {
+  is_admin
+  not blocked
+}
+
+is_admin if has_role("admin")
+
+blocked if input.user in data.blocked
+
+has_role(r) if input.role == r
+
+audit contains input.user if input.log
+"#
+        .to_string(),
+    )?;
+    engine.add_data(Value::from_json_str(r#"{"blocked": ["mallory"]}"#)?)?;
+    engine.set_input(Value::from_json_str(
+        r#"{"user": "alice", "role": "admin", "log": true}"#,
+    )?);
+
+    let rows = |explanation: &serde_json::Value| -> BTreeSet<u64> {
+        explanation
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["location"]["row"].as_u64().unwrap())
+            .collect()
+    };
+
+    let (_, all) = engine.eval_query_explain("data.test".to_string())?;
+    assert!(rows(&all).is_superset(&BTreeSet::from([1, 4, 5, 6, 9, 11, 13, 15])));
+
+    // Only is_admin and its callees are recorded.
+    engine.set_trace_filter(vec!["data.test.is_admin".to_string()]);
+    let (results, filtered) = engine.eval_query_explain("data.test".to_string())?;
+    assert_eq!(
+        results.result[0].expressions[0].value["allow"],
+        Value::from(true)
+    );
+    assert_eq!(rows(&filtered), BTreeSet::from([9, 13]));
+    assert!(filtered.as_array().unwrap().len() < all.as_array().unwrap().len());
+
+    // Multiple rules.
+    engine.set_trace_filter(vec![
+        "data.test.blocked".to_string(),
+        "data.test.audit".to_string(),
+    ]);
+    let (_, filtered) = engine.eval_query_explain("data.test".to_string())?;
+    assert_eq!(rows(&filtered), BTreeSet::from([11, 15]));
+
+    // An empty filter records everything again.
+    engine.set_trace_filter(vec![]);
+    let (_, unfiltered) = engine.eval_query_explain("data.test".to_string())?;
+    assert_eq!(rows(&unfiltered), rows(&all));
+
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "regex")]
+fn used_builtins() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "policy.rego".to_string(),
+        r#"
+        package test
+        import rego.v1
+
+        deny contains msg if {
+          some user in input.users
+          not valid(user)
+          msg := sprintf("invalid user %v", [user])
+        }
+
+        valid(u) if regex.match("^[a-z]+$", u)
+
+        summary := {"count": count(input.users), "names": {lower(n) | some n in input.users}}
+
+        every_short if {
+          every u in input.users {
+            strings.count(u, "-") < 2
+          }
+        }
+
+        default fallback := 0
+        "#
+        .to_string(),
+    )?;
+    engine.add_policy(
+        "lib.rego".to_string(),
+        "package lib\nsize(x) := count(x) + to_number(\"1\")".to_string(),
+    )?;
+
+    let used = engine.used_builtins()?;
+    assert!(used.contains("sprintf"));
+    assert!(used.contains("count"));
+    assert_eq!(
+        used.into_iter().collect::<Vec<_>>(),
+        [
+            "count",
+            "lower",
+            "regex.match",
+            "sprintf",
+            "strings.count",
+            "to_number"
+        ]
+    );
+
+    // Extensions are included once added.
+    engine.add_policy(
+        "ext.rego".to_string(),
+        "package ext\nx := hash(input)".to_string(),
+    )?;
+    assert!(!engine.used_builtins()?.contains("hash"));
+    engine.add_extension(
+        "hash".to_string(),
+        Some(1),
+        Box::new(|_| Ok(Value::from(0))),
+    )?;
+    assert!(engine.used_builtins()?.contains("hash"));
+
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "yaml")]
+fn add_data_from_dir() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_data(Value::from_json_str(r#"{ "users": { "count": 1 } }"#)?)?;
+    engine.add_data_from_dir("tests/engine/data_dir")?;
+
+    assert_eq!(
+        engine.get_data(),
+        Value::from_json_str(
+            r#"{
+              "env": "prod",
+              "users": { "count": 1, "admins": ["alice"], "roles": { "editor": ["bob"] } },
+              "apps": { "web": { "config": { "port": 80 } } }
+            }"#
+        )?
+    );
+
+    // Conflicting files leave data unmodified.
+    let mut engine = Engine::new();
+    engine.add_data(Value::from_json_str(
+        r#"{ "apps": { "web": { "config": 1 } } }"#,
+    )?)?;
+    assert!(engine.add_data_from_dir("tests/engine/data_dir").is_err());
+    assert_eq!(engine.get_data()["env"], Value::Undefined);
+
+    assert!(engine.add_data_from_dir("tests/engine/missing").is_err());
+    Ok(())
+}
+
+#[test]
+fn data_snapshot_is_shared() -> Result<()> {
+    use std::collections::BTreeMap;
+
+    let mut engine = Engine::new();
+    engine.add_data(Value::from_json_str(
+        r#"{ "users": { "alice": { "role": "admin" } }, "limits": [1, 2, 3] }"#,
+    )?)?;
+    let snapshot = engine.freeze_data();
+
+    let users_ptr = |data: &Value| -> Result<*const BTreeMap<Value, Value>> {
+        Ok(data["users"].as_object()? as *const _)
+    };
+
+    // Snapshots and the engines using them share the data.
+    let mut workers = vec![engine.clone(), Engine::new(), Engine::new()];
+    for w in workers.iter_mut().skip(1) {
+        w.use_data_snapshot(snapshot.clone());
+    }
+    for w in &workers {
+        assert_eq!(users_ptr(&w.get_data())?, users_ptr(snapshot.data())?);
+        assert_eq!(
+            w.get_data()["limits"].as_array()?.as_ptr(),
+            snapshot.data()["limits"].as_array()?.as_ptr()
+        );
+    }
+
+    // Adding data copies only the modified parts.
+    workers[1].add_data(Value::from_json_str(
+        r#"{ "users": { "bob": { "role": "viewer" } } }"#,
+    )?)?;
+    let data = workers[1].get_data();
+    assert_ne!(users_ptr(&data)?, users_ptr(snapshot.data())?);
+    assert_eq!(
+        data["limits"].as_array()?.as_ptr(),
+        snapshot.data()["limits"].as_array()?.as_ptr()
+    );
+    assert_eq!(snapshot.data()["users"]["bob"], Value::Undefined);
+    assert_eq!(workers[2].get_data()["users"]["bob"], Value::Undefined);
+
+    workers[2].add_policy(
+        "policy.rego".to_string(),
+        "package test\nrole := data.users.alice.role".to_string(),
+    )?;
+    assert_eq!(
+        workers[2].eval_rule("data.test.role".to_string())?,
+        Value::from("admin")
+    );
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn eval_query_to_writer() -> Result<()> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "policy.rego".to_string(),
+        r#"
+        package test
+        import rego.v1
+
+        items contains {"id": i, "name": sprintf("item-%d", [i]), "tags": {"a", "b"}} if {
+          some i in numbers.range(1, 1000)
+        }
+        "#
+        .to_string(),
+    )?;
+
+    for query in [
+        "data.test.items",
+        "x := data.test.items[_]; x.id < 50",
+        "x := 1; y := \"a\\\"b\\u00e9\"",
+        "x := data.test.items[_]; x.id > 5000",
+        "y = x + 1; x = 1",
+    ] {
+        let mut json = String::new();
+        engine.eval_query_to_writer(query.to_string(), false, &mut json)?;
+        let expected = serde_json::to_string(&engine.eval_query(query.to_string(), false)?)?;
+        assert_eq!(json, expected, "{query}");
+    }
+
+    let mut json = String::new();
+    engine.eval_query_to_writer(
+        "x := data.test.items[_]; x.id > 5000".to_string(),
+        false,
+        &mut json,
+    )?;
+    assert_eq!(json, "{}");
+
+    // Numbers are rendered as per the number output setting.
+    engine.set_number_output(NumberOutput::AlwaysFloat);
+    let mut json = String::new();
+    engine.eval_query_to_writer("x := 2; y := 2.5".to_string(), false, &mut json)?;
+    assert!(json.contains(r#""bindings":{"x":2.0,"y":2.5}"#), "{json}");
+    engine.set_number_output(NumberOutput::IntegerWhenWhole);
+    let mut json = String::new();
+    engine.eval_query_to_writer("x := 1e20; y := 2.5".to_string(), false, &mut json)?;
+    assert!(
+        json.contains(r#""bindings":{"x":100000000000000000000,"y":2.5}"#),
+        "{json}"
+    );
+    engine.set_number_output(NumberOutput::Natural);
+    let mut json = String::new();
+    engine.eval_query_to_writer("x := 1e20; y := 2.5".to_string(), false, &mut json)?;
+    assert!(json.contains(r#""bindings":{"x":1e+20,"y":2.5}"#), "{json}");
+
+    // Errors are reported.
+    assert!(engine
+        .eval_query_to_writer("1 / 0".to_string(), false, &mut String::new())
+        .is_err());
+
+    // Results are written as they are produced: a failing writer stops evaluation.
+    // Writers may borrow local state.
+    struct LimitedWriter<'a>(&'a mut usize);
+    impl core::fmt::Write for LimitedWriter<'_> {
+        fn write_str(&mut self, s: &str) -> core::fmt::Result {
+            *self.0 = self.0.checked_sub(s.len()).ok_or(core::fmt::Error)?;
+            Ok(())
+        }
+    }
+    let produced = Arc::new(AtomicUsize::new(0));
+    let counter = produced.clone();
+    engine.add_extension(
+        "produce".to_string(),
+        Some(1),
+        Box::new(move |mut params: Vec<Value>| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Ok(params.remove(0))
+        }),
+    )?;
+    let mut remaining = 1000;
+    assert!(engine
+        .eval_query_to_writer(
+            "x := produce(numbers.range(1, 1000)[_])".to_string(),
+            false,
+            &mut LimitedWriter(&mut remaining),
+        )
+        .is_err());
+    assert!(remaining < 1000);
+    assert!(produced.load(Ordering::SeqCst) < 100);
+
+    // The engine is usable after a failed write.
+    let mut json = String::new();
+    engine.eval_query_to_writer("x := 1".to_string(), false, &mut json)?;
+    assert_eq!(
+        json,
+        serde_json::to_string(&engine.eval_query("x := 1".to_string(), false)?)?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn eval_queries() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "policy.rego".to_string(),
+        r#"
+        package test
+        import rego.v1
+
+        user := input.users[_] if print("computing user")
+        is_admin if user == "alice"
+        count_users := count(input.users)
+        "#
+        .to_string(),
+    )?;
+    engine.set_input(Value::from_json_str(r#"{ "users": ["alice"] }"#)?);
+    engine.set_gather_prints(true);
+
+    let results = engine.eval_queries(
+        vec![
+            "data.test.is_admin".to_string(),
+            "x := data.test.count_users; print(x)".to_string(),
+            "data.test.user".to_string(),
+            "data.test.missing_fcn(1)".to_string(),
+            "1 / 0".to_string(),
+            "data".to_string(),
+        ],
+        true,
+    )?;
+    assert_eq!(results.len(), 6);
+
+    let value = |r: &Result<QueryResults, RegorusError>| -> Value {
+        r.as_ref().unwrap().result[0].expressions[0].value.clone()
+    };
+    assert_eq!(value(&results[0]), Value::from(true));
+    assert_eq!(
+        results[1].as_ref().unwrap().result[0].bindings["x"],
+        Value::from(1)
+    );
+    assert_eq!(value(&results[2]), Value::from("alice"));
+    assert!(results[3].is_err());
+    assert!(results[4].is_err());
+    assert_eq!(value(&results[5])["test"]["count_users"], Value::from(1));
+
+    // Prints are grouped per query and rules are evaluated once.
+    let prints = engine.take_prints_grouped()?;
+    assert_eq!(prints.len(), 6);
+    assert_eq!(prints[0].len(), 1);
+    assert!(prints[0][0].contains("computing user"));
+    assert!(prints[1][0].contains("1"));
+    assert!(prints[2].is_empty());
+
+    // Errors are formatted as for eval_query.
+    assert_eq!(
+        results[3].as_ref().unwrap_err().to_string(),
+        engine
+            .eval_query("data.test.missing_fcn(1)".to_string(), false)
+            .unwrap_err()
+            .to_string()
+    );
+    Ok(())
+}
+
+#[test]
+fn unused_rules() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "policy.rego".to_string(),
+        r#"
+        package test
+        import rego.v1
+
+        deny contains msg if {
+          not valid_name(input.name)
+          msg := "invalid name"
+        }
+
+        valid_name(n) if count(n) > 0
+
+        # Referenced only by another unused rule.
+        max_len := 10
+        too_long if count(input.name) > max_len
+
+        # Referenced by nothing.
+        legacy_check if input.legacy == true
+        "#
+        .to_string(),
+    )?;
+    engine.add_policy(
+        "app.rego".to_string(),
+        "package app\nmain := data.lib.greeting".to_string(),
+    )?;
+    engine.add_policy(
+        "lib.rego".to_string(),
+        "package lib\ngreeting := \"hello\"\nunused_helper(x) := x".to_string(),
+    )?;
+
+    assert_eq!(
+        engine.unused_rules()?,
+        [
+            "data.lib.unused_helper",
+            "data.test.legacy_check",
+            "data.test.too_long"
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn partial_eval() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "test.rego".to_string(),
+        r#"
+        package test
+        import rego.v1
+
+        default allow := false
+        allow if is_admin
+        allow if {
+          input.user.name == input.resource.owner
+          input.action in data.readonly_actions
+        }
+        allow if {
+          input.action == "delete"
+          is_admin
+        }
+
+        is_admin if input.user.role == "admin"
+        is_admin if "admins" in input.user.groups
+
+        max_size := 10 * 2
+        small if input.user.quota < max_size - count(input.resource.tags)
+        "#
+        .to_string(),
+    )?;
+    engine.add_data(Value::from_json_str(
+        r#"{"readonly_actions": ["read", "list"]}"#,
+    )?)?;
+    engine.set_input(Value::from_json_str(
+        r#"{"action": "read", "resource": {"owner": "alice", "tags": ["a", "b"]}}"#,
+    )?);
+    let unknowns = vec!["input.user".to_string()];
+
+    let result = engine.partial_eval("data.test.allow".to_string(), unknowns.clone())?;
+    assert_eq!(result.queries.len(), 3);
+    assert_eq!(
+        result.rego,
+        r#"package partial
+
+import rego.v1
+
+query if {
+	input.user.role == "admin"
+}
+
+query if {
+	"admins" in input.user.groups
+}
+
+query if {
+	input.user.name == "alice"
+}
+"#
+    );
+
+    // Known values are substituted into the residual queries.
+    let result = engine.partial_eval("data.test.small".to_string(), unknowns.clone())?;
+    assert_eq!(result.rego.lines().nth(5), Some("\tinput.user.quota < 18"));
+
+    // The residual policy yields the same decisions as the original one.
+    let mut residual = Engine::new();
+    residual.add_policy("residual.rego".to_string(), result.rego)?;
+    residual.set_input(Value::from_json_str(r#"{"user": {"quota": 5}}"#)?);
+    assert_eq!(
+        residual.eval_rule("data.partial.query".to_string())?,
+        Value::from(true)
+    );
+
+    // Queries that do not depend on unknowns are fully evaluated.
+    let result = engine.partial_eval("input.action == \"write\"".to_string(), unknowns.clone())?;
+    assert!(result.queries.is_empty());
+
+    // Rules that depend on unknowns cannot be used as values.
+    let err = engine
+        .partial_eval("x := data.test.allow".to_string(), unknowns.clone())
+        .expect_err("value use of rules depending on unknowns is unsupported");
+    assert!(err.to_string().contains("depends on unknowns"), "{err}");
+
+    assert!(engine
+        .partial_eval("data.test.allow".to_string(), vec!["data.x".to_string()])
+        .is_err());
+    Ok(())
+}
+
+#[test]
+fn remove_policy() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_data(Value::from_json_str(r#"{"limit": 5}"#)?)?;
+    engine.add_policy(
+        "allow.rego".to_string(),
+        "package test\nallow := data.limit > 3".to_string(),
+    )?;
+    engine.add_policy(
+        "deny.rego".to_string(),
+        "package test\ndeny := data.limit > 10".to_string(),
+    )?;
+    engine.add_policy(
+        "other.rego".to_string(),
+        "package other\nx := 1".to_string(),
+    )?;
+
+    let results = engine.eval_query("data.test".to_string(), false)?;
+    assert_eq!(
+        results.result[0].expressions[0].value,
+        Value::from_json_str(r#"{"allow": true, "deny": false}"#)?
+    );
+
+    // Removal is reflected by subsequent evaluations.
+    assert!(engine.remove_policy("deny.rego")?);
+    assert!(!engine.remove_policy("missing.rego")?);
+    let results = engine.eval_query("data.test".to_string(), false)?;
+    assert_eq!(
+        results.result[0].expressions[0].value,
+        Value::from_json_str(r#"{"allow": true}"#)?
+    );
+
+    // Policies can be reloaded and data is retained.
+    engine.add_policy(
+        "deny.rego".to_string(),
+        "package test\ndeny := data.limit > 4".to_string(),
+    )?;
+    assert_eq!(
+        engine.eval_rule("data.test.deny".to_string())?,
+        Value::from(true)
+    );
+
+    assert_eq!(engine.remove_policies_in_package("data.test")?, 2);
+    assert_eq!(engine.remove_policies_in_package("data.test")?, 0);
+    assert_eq!(engine.get_packages()?, ["data.other"]);
+    assert!(engine.eval_rule("data.test.allow".to_string()).is_err());
+    assert_eq!(
+        engine.eval_rule("data.other.x".to_string())?,
+        Value::from(1)
+    );
+    Ok(())
+}
+
+#[test]
+fn export_import_parsed() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "lib.rego".to_string(),
+        r#"
+        package lib
+        import rego.v1
+
+        limits := {"cpu": 4, "big": 123456789012345678901234567890, "ratio": 0.25}
+        double(x) := x * 2
+        names contains n if some n in ["a\tb", `raw\n`]
+        "#
+        .to_string(),
+    )?;
+    engine.add_policy(
+        "test.rego".to_string(),
+        r#"
+        package test
+        import rego.v1
+
+        default allow := false
+        allow if {
+          every n in data.lib.names { count(n) > 0 }
+          input.cpu <= data.lib.limits.cpu
+          not deny
+        }
+        deny if input.user == null
+        squares := {x: y | some x in [1, 2, 3]; y := data.lib.double(x) * x}
+        overridden := v if { v := input.cpu with input.cpu as 8 }
+        union := {1, 2} | {2, 3}
+        "#
+        .to_string(),
+    )?;
+    let input = Value::from_json_str(r#"{"cpu": 2, "user": "u"}"#)?;
+    engine.set_input(input.clone());
+    let expected = engine.eval_query("data".to_string(), false)?;
+
+    let bytes = engine.export_parsed()?;
+    let mut restored = Engine::new();
+    restored.import_parsed(&bytes)?;
+    restored.set_input(input);
+    assert_eq!(restored.get_packages()?, ["data.lib", "data.test"]);
+    assert_eq!(
+        serde_json::to_string(&restored.eval_query("data".to_string(), false)?)?,
+        serde_json::to_string(&expected)?
+    );
+
+    // Diagnostics still point into the original sources.
+    restored.add_policy(
+        "bad.rego".to_string(),
+        "package bad\nx := data.lib.double(1, 2)".to_string(),
+    )?;
+    let err = restored
+        .eval_rule("data.bad.x".to_string())
+        .expect_err("call with wrong arity must fail");
+    assert!(err.to_string().contains("bad.rego:2"), "{err}");
+
+    // Caches from a different format version are rejected.
+    let mut stale = bytes.clone();
+    stale[5] += 1;
+    let err = Engine::new()
+        .import_parsed(&stale)
+        .expect_err("stale cache must be rejected");
+    assert!(err.to_string().contains("version mismatch"), "{err}");
+    assert!(Engine::new()
+        .import_parsed(&bytes[..bytes.len() - 1])
+        .is_err());
+    Ok(())
+}
+
+#[test]
+fn rule_recursion() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "policy.rego".to_string(),
+        r#"
+        package test
+        import rego.v1
+
+        a if b
+        b if a
+
+        c := 1
+        f(c) := c + 1
+        d := f(c)
+        "#
+        .to_string(),
+    )?;
+
+    // Recursion is reported before any rule is evaluated, naming the whole cycle.
+    let err = engine
+        .eval_rule("data.test.d".to_string())
+        .expect_err("recursive rules must be rejected");
+    assert!(
+        err.to_string().contains(
+            "recursion detected in rule dependencies: data.test.a -> data.test.b -> data.test.a"
+        ),
+        "{err}"
+    );
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn json_readers() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_data_from_json_reader(std::fs::File::open("tests/aci/data.json")?)?;
+    engine.add_data_from_json_reader(r#"{ "extra": [1, 2] }"#.as_bytes())?;
+    engine.set_input_from_json_reader(std::fs::File::open("tests/aci/input.json")?)?;
+
+    let mut expected = Engine::new();
+    expected.add_data(Value::from_json_file("tests/aci/data.json")?)?;
+    expected.add_data_json(r#"{ "extra": [1, 2] }"#)?;
+    assert_eq!(engine.get_data(), expected.get_data());
+
+    let results = engine.eval_query("[input.containerID, data.extra]".to_string(), false)?;
+    assert_eq!(
+        results.result[0].expressions[0].value,
+        Value::from_json_str(r#"["container0", [1, 2]]"#)?
+    );
+
+    // Invalid JSON leaves the input unchanged.
+    assert!(engine
+        .set_input_from_json_reader(r#"{ "a": "#.as_bytes())
+        .is_err());
+    assert!(engine
+        .add_data_from_json_reader(r#"[1, 2]"#.as_bytes())
+        .is_err());
+    let results = engine.eval_query("input.containerID".to_string(), false)?;
+    assert_eq!(
+        results.result[0].expressions[0].value,
+        Value::from("container0")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn patch_input() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "test.rego".to_string(),
+        r#"package test
+           import rego.v1
+
+           allow if input.user.role == "admin"
+           names := [n | some u in input.users; n := u.name]
+           # Evaluated with a modified data document to check that the current input is used.
+           role := r if { r := input.user.role with data.x as 1 }
+        "#
+        .to_string(),
+    )?;
+    engine.set_input(Value::from_json_str(
+        r#"{ "user": { "role": "dev" }, "users": [ { "name": "a" }, { "name": "b" } ], "a/~b": 1 }"#,
+    )?);
+    assert_eq!(
+        engine.eval_rule("data.test.role".to_string())?,
+        "dev".into()
+    );
+
+    engine.patch_input("/user/role", Value::from("admin"))?;
+    assert_eq!(
+        engine.eval_rule("data.test.allow".to_string())?,
+        true.into()
+    );
+    assert_eq!(
+        engine.eval_rule("data.test.role".to_string())?,
+        "admin".into()
+    );
+
+    engine.patch_input("/users/1/name", Value::from("c"))?;
+    engine.patch_input("/users/2", Value::from_json_str(r#"{ "name": "d" }"#)?)?;
+    engine.patch_input("/users/-", Value::from_json_str(r#"{ "name": "e" }"#)?)?;
+    assert_eq!(
+        engine.eval_rule("data.test.names".to_string())?,
+        Value::from_json_str(r#"["a", "c", "d", "e"]"#)?
+    );
+
+    engine.remove_input_at("/users/0")?;
+    engine.remove_input_at("/a~1~0b")?;
+    engine.remove_input_at("/user/role")?;
+    assert_eq!(
+        engine.eval_rule("data.test.names".to_string())?,
+        Value::from_json_str(r#"["c", "d", "e"]"#)?
+    );
+    assert_eq!(
+        engine.eval_rule("data.test.allow".to_string())?,
+        Value::Undefined
+    );
+
+    // Invalid pointers leave the input unmodified.
+    let input = engine.eval_query("input".to_string(), false)?.result[0].expressions[0]
+        .value
+        .clone();
+    assert!(engine.patch_input("users/0", Value::Null).is_err());
+    assert!(engine.patch_input("/users/5", Value::Null).is_err());
+    assert!(engine.patch_input("/users/01", Value::Null).is_err());
+    assert!(engine.patch_input("/group/name", Value::Null).is_err());
+    assert!(engine.remove_input_at("/users/-").is_err());
+    assert!(engine.remove_input_at("/user/role").is_err());
+    assert_eq!(
+        engine.eval_query("input".to_string(), false)?.result[0].expressions[0].value,
+        input
+    );
+
+    // The empty pointer refers to the whole input.
+    engine.patch_input(
+        "",
+        Value::from_json_str(r#"{ "user": { "role": "admin" } }"#)?,
+    )?;
+    assert_eq!(
+        engine.eval_rule("data.test.allow".to_string())?,
+        true.into()
+    );
+    engine.remove_input_at("")?;
+    assert_eq!(
+        engine.eval_rule("data.test.allow".to_string())?,
+        Value::Undefined
+    );
+
+    Ok(())
+}
+
+#[test]
+fn exact_arithmetic() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "test.rego".to_string(),
+        r#"package test
+           import rego.v1
+
+           share := 100 / 3
+           total := (share * 3) + (0.1 + 0.2)
+           allow if total == 100.3
+           share_str := sprintf("%v", [share])
+        "#
+        .to_string(),
+    )?;
+
+    assert_eq!(
+        engine.eval_rule("data.test.allow".to_string())?,
+        Value::Undefined
+    );
+    // Truncated quotients are rendered in full by default.
+    assert_eq!(
+        engine.eval_rule("data.test.share_str".to_string())?,
+        Value::from(format!("33.{}", "3".repeat(98)))
+    );
+
+    engine.set_exact_arithmetic(true);
+    assert_eq!(
+        engine.eval_rule("data.test.allow".to_string())?,
+        Value::from(true)
+    );
+    let Value::Number(total) = engine.eval_rule("data.test.total".to_string())? else {
+        bail!("total must be a number");
+    };
+    assert!(total.is_exact());
+    assert_eq!(total.to_rational(), Some((1003, 10)));
+
+    let Value::Number(share) = engine.eval_rule("data.test.share".to_string())? else {
+        bail!("share must be a number");
+    };
+    assert!(!share.is_exact());
+    assert_eq!(share.to_rational(), Some((100, 3)));
+    // Fractions are converted to floats on output.
+    assert_eq!(
+        engine.eval_rule("data.test.share_str".to_string())?,
+        Value::from("33.333333333333336")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn builtin_introspection() -> Result<()> {
+    let mut engine = Engine::new();
+
+    let names = engine.builtin_names();
+    assert!(names.windows(2).all(|w| w[0] < w[1]));
+    assert!(names.contains(&"count".to_string()));
+
+    let count = engine.builtin_signature("count").expect("count");
+    assert_eq!(count.arity, 1);
+    assert_eq!(count.arg_names, ["arg1"]);
+
+    let substring = engine.builtin_signature("substring").expect("substring");
+    assert_eq!(substring.arity, 3);
+    assert!(!substring.requires_std);
+
+    #[cfg(feature = "std")]
+    assert!(
+        engine
+            .builtin_signature("rand.intn")
+            .expect("rand.intn")
+            .requires_std
+    );
+
+    assert!(engine.builtin_signature("no.such.builtin").is_none());
+
+    engine.set_capabilities_from_json(r#"{ "builtins": [ { "name": "count" } ] }"#)?;
+    assert_eq!(engine.builtin_names(), ["count"]);
+    assert!(engine.builtin_signature("substring").is_none());
+
+    Ok(())
+}
+
+#[test]
+fn dependencies_to_dot() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "policy.rego".to_string(),
+        r#"
+        package test
+        import rego.v1
+
+        allow if {
+          input.user in data.lib.admins
+          not blocked
+        }
+
+        blocked if input.user == "mallory"
+        "#
+        .to_string(),
+    )?;
+    engine.add_policy(
+        "lib.rego".to_string(),
+        "package lib\nadmins := {\"alice\"}".to_string(),
+    )?;
+
+    let dot = engine.dependencies_to_dot()?;
+    assert!(dot.starts_with("digraph dependencies {\n"));
+    assert!(dot.contains("subgraph \"cluster_data.test\" {\n    label = \"data.test\";\n"));
+    assert!(dot.contains("subgraph \"cluster_data.lib\" {\n    label = \"data.lib\";\n"));
+
+    let edges: Vec<&str> = dot.lines().filter(|l| l.contains(" -> ")).collect();
+    assert_eq!(
+        edges,
+        [
+            r#"  "data.test.allow" -> "data.lib.admins";"#,
+            r#"  "data.test.allow" -> "data.test.blocked";"#,
+        ]
+    );
+
+    // The engine is not modified.
+    assert_eq!(
+        engine.eval_rule("data.test.allow".to_string())?,
+        Value::Undefined
+    );
+    Ok(())
+}
+
+#[test]
+fn cache_input_independent_rules() -> Result<()> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let names: Vec<Value> = (0..500).map(|i| Value::from(format!("user{i}"))).collect();
+    let mut data = Value::new_object();
+    data.as_object_mut()?
+        .insert(Value::from("names"), Value::from(names));
+
+    let mut engine = Engine::new();
+    engine.add_data(data)?;
+    engine.add_policy(
+        "policy.rego".to_string(),
+        r#"
+        package test
+        import rego.v1
+
+        table := {name: upper(name) | some name in data.names}
+
+        allow if table[input.name] == upper(input.name)
+
+        others := {name | some name in data.names; name != input.name}
+        num_others := count(others)
+
+        alt if table["x"] with data.names as ["x"]
+        "#
+        .to_string(),
+    )?;
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = calls.clone();
+    engine.set_builtin_call_logger(Box::new(move |name: &str, _: &[Value]| {
+        if name == "upper" {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+    }));
+
+    let inputs = Value::from(
+        (0..200)
+            .map(|i| Value::from_json_str(&format!(r#"{{"name": "user{}"}}"#, i * 3)))
+            .collect::<Result<Vec<_>>>()?,
+    );
+    let expected: Vec<Value> = (0..200)
+        .map(|i| match i * 3 < 500 {
+            true => Value::from(true),
+            false => Value::Undefined,
+        })
+        .collect();
+
+    // Without caching, table is recomputed for every input.
+    let results = engine.eval_rule_over_array("data.test.allow".to_string(), inputs.clone())?;
+    assert_eq!(results, expected);
+    assert_eq!(calls.swap(0, Ordering::Relaxed), 200 * 501);
+
+    // With caching, table is computed only once.
+    engine.set_cache_input_independent_rules(true);
+    let results = engine.eval_rule_over_array("data.test.allow".to_string(), inputs.clone())?;
+    assert_eq!(results, expected);
+    assert_eq!(calls.swap(0, Ordering::Relaxed), 500 + 200);
+
+    // The cached value is also used by queries.
+    let r = engine.eval_query("data.test.table.user7".to_string(), false)?;
+    assert_eq!(r.result[0].expressions[0].value, Value::from("USER7"));
+    assert_eq!(calls.swap(0, Ordering::Relaxed), 0);
+
+    // Rules that indirectly depend on input are recomputed.
+    let results = engine.eval_rule_over_array(
+        "data.test.num_others".to_string(),
+        Value::from_json_str(r#"[{"name": "user1"}, {"name": "nobody"}]"#)?,
+    )?;
+    assert_eq!(results, vec![Value::from(499), Value::from(500)]);
+
+    // Cached values are not used within with modifiers.
+    assert_eq!(
+        engine.eval_rule("data.test.alt".to_string())?,
+        Value::from(true)
+    );
+
+    // Changes to data invalidate the cache.
+    engine.add_data(Value::from_json_str(r#"{"extra": 1}"#)?)?;
+    calls.store(0, Ordering::Relaxed);
+    engine.set_input(Value::from_json_str(r#"{"name": "user1"}"#)?);
+    assert_eq!(
+        engine.eval_rule("data.test.allow".to_string())?,
+        Value::from(true)
+    );
+    assert_eq!(calls.swap(0, Ordering::Relaxed), 501);
+
+    Ok(())
+}
+
+#[test]
+fn print_value_formatter() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "policy.rego".to_string(),
+        r#"
+        package test
+        import rego.v1
+
+        x if {
+          print("values:", {"a": {1, 2}}, [3], 4, input.missing)
+        }
+        "#
+        .to_string(),
+    )?;
+    engine.set_gather_prints(true);
+
+    // Values are printed in Rego notation by default.
+    engine.eval_rule("data.test.x".to_string())?;
+    assert_eq!(
+        engine.take_prints()?,
+        vec![r#"policy.rego:6: values: {"a": {1, 2}} [3] 4 <undefined>"#]
+    );
+
+    // Strings and undefined values are not passed to the formatter.
+    engine.set_print_value_formatter(Box::new(|v: &Value| {
+        format!("json({})", serde_json::to_string(v).unwrap_or_default())
+    }));
+    engine.eval_rule("data.test.x".to_string())?;
+    assert_eq!(
+        engine.take_prints()?,
+        vec![r#"policy.rego:6: values: json({"a":[1,2]}) json([3]) json(4) <undefined>"#]
+    );
+
+    engine.clear_print_value_formatter();
+    engine.eval_query(r#"print({"b"})"#.to_string(), false)?;
+    assert_eq!(engine.take_prints()?, vec![r#"<query.rego>:1: {"b"}"#]);
+
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "arrow")]
+fn eval_rule_over_array_arrow() -> Result<()> {
+    use arrow_array::{Array, BooleanArray, Float64Array, Int64Array, StringArray};
+    use arrow_schema::DataType;
+
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "policy.rego".to_string(),
+        r#"
+        package test
+        import rego.v1
+
+        decision := {"allow": input.n > 1, "score": input.n / 2, "user": input.user, "tags": {input.user}} if input.n > 0
+        decision := {"allow": false, "score": 0, "count": input.n} if input.n < 0
+
+        flag := input.n > 1
+        "#
+        .to_string(),
+    )?;
+
+    let inputs = Value::from_json_str(
+        r#"[{"n": 1, "user": "a"}, {"n": 4, "user": "b"}, {"n": 0}, {"n": 3, "user": "c"}]"#,
+    )?;
+    let batch = engine.eval_rule_over_array_arrow("data.test.decision".to_string(), inputs)?;
+    assert_eq!(batch.num_rows(), 4);
+
+    let schema = batch.schema();
+    let columns: Vec<(&str, &DataType)> = schema
+        .fields()
+        .iter()
+        .map(|f| (f.name().as_str(), f.data_type()))
+        .collect();
+    assert_eq!(
+        columns,
+        vec![
+            ("allow", &DataType::Boolean),
+            ("score", &DataType::Float64),
+            ("tags", &DataType::Utf8),
+            ("user", &DataType::Utf8)
+        ]
+    );
+
+    let allow = batch
+        .column(0)
+        .as_any()
+        .downcast_ref::<BooleanArray>()
+        .unwrap();
+    assert!(!allow.value(0) && allow.value(1) && allow.is_null(2) && allow.value(3));
+    let score = batch
+        .column(1)
+        .as_any()
+        .downcast_ref::<Float64Array>()
+        .unwrap();
+    assert_eq!((score.value(0), score.value(1)), (0.5, 2.0));
+    let tags = batch
+        .column(2)
+        .as_any()
+        .downcast_ref::<StringArray>()
+        .unwrap();
+    assert_eq!(tags.value(1), r#"["b"]"#);
+    let user = batch
+        .column(3)
+        .as_any()
+        .downcast_ref::<StringArray>()
+        .unwrap();
+    assert_eq!(user.value(3), "c");
+
+    // Integer columns.
+    let inputs = Value::from_json_str(r#"[{"n": -1}, {"n": -7}]"#)?;
+    let batch = engine.eval_rule_over_array_arrow("data.test.decision".to_string(), inputs)?;
+    let count = batch
+        .column_by_name("count")
+        .unwrap()
+        .as_any()
+        .downcast_ref::<Int64Array>()
+        .unwrap();
+    assert_eq!(count.values().to_vec(), vec![-1, -7]);
+
+    // Fields missing in a result are null.
+    let inputs = Value::from_json_str(r#"[{"n": 2, "user": "a"}, {"n": -1}]"#)?;
+    let batch = engine.eval_rule_over_array_arrow("data.test.decision".to_string(), inputs)?;
+    assert_eq!(batch.num_columns(), 5);
+    assert!(batch.column_by_name("count").unwrap().is_null(0));
+    assert!(batch.column_by_name("user").unwrap().is_null(1));
+
+    // Results with fields or types not matching the inferred schema are errors.
+    let mut inputs: Vec<Value> = (0..100)
+        .map(|_| Value::from_json_str(r#"{"n": 2, "user": "a"}"#))
+        .collect::<Result<_>>()?;
+    inputs.push(Value::from_json_str(r#"{"n": 2, "user": 5}"#)?);
+    let err = engine
+        .eval_rule_over_array_arrow("data.test.decision".to_string(), Value::from(inputs))
+        .unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("result 100: field `user` has value 5"),
+        "{err}"
+    );
+
+    // Results must be objects.
+    let err = engine
+        .eval_rule_over_array_arrow(
+            "data.test.flag".to_string(),
+            Value::from_json_str(r#"[{"n": 2}]"#)?,
+        )
+        .unwrap_err();
+    assert!(err.to_string().contains("expected an object"), "{err}");
+
+    Ok(())
+}
+
+#[test]
+fn eval_query_project() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_data(Value::from_json_str(
+        r#"{"users": [{"name": "alice", "age": 30}, {"name": "bob", "age": 17}]}"#,
+    )?)?;
+
+    let rows = engine.eval_query_project(
+        "u := data.users[i]; name := u.name; adult := u.age >= 18".to_string(),
+        &["name", "adult", "missing", "i"],
+    )?;
+    assert_eq!(
+        rows,
+        vec![
+            vec![
+                Value::from("alice"),
+                Value::from(true),
+                Value::Undefined,
+                Value::from(0)
+            ],
+            vec![
+                Value::from("bob"),
+                Value::from(false),
+                Value::Undefined,
+                Value::from(1)
+            ],
+        ]
+    );
+
+    // No results yield no rows.
+    assert!(engine
+        .eval_query_project("x := 1; x > 2".to_string(), &["x"])?
+        .is_empty());
+    Ok(())
+}
+
+#[test]
+fn error_codes() -> Result<()> {
+    // Errors can also be retrieved after conversion to anyhow::Error.
+    fn error(r: Result<impl core::fmt::Debug, RegorusError>) -> RegorusError {
+        let err = anyhow::Error::from(r.unwrap_err());
+        err.downcast_ref::<RegorusError>().unwrap().clone()
+    }
+
+    // Syntax errors.
+    let mut engine = Engine::new();
+    let e = error(engine.add_policy("test.rego".to_string(), "package test\nx := ".to_string()));
+    assert_eq!(e.code(), "rego_parse_error");
+    let loc = e.location().unwrap();
+    assert_eq!((loc.file.as_str(), loc.line), ("test.rego", 2));
+    assert_eq!(
+        error(engine.eval_query("x := ".to_string(), false)).code(),
+        "rego_parse_error"
+    );
+
+    // Compile errors.
+    engine.add_policy("test.rego".to_string(), "package test\nx := y".to_string())?;
+    let e = error(engine.eval_query("data.test.x".to_string(), false));
+    assert_eq!(e.code(), "rego_compile_error");
+    assert_eq!(e.location().map(|l| (l.line, l.col)), Some((2, 6)));
+
+    // Recursive rules.
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "test.rego".to_string(),
+        "package test\nx := y\ny := x".to_string(),
+    )?;
+    let e = error(engine.eval_query("data.test.x".to_string(), false));
+    assert_eq!(e.code(), "rego_recursion_error");
+    assert!(e.message().contains("recursion detected"));
+
+    // Conflicting outputs.
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "test.rego".to_string(),
+        "package test\nx = 1 { true }\nx = 2 { true }".to_string(),
+    )?;
+    let e = error(engine.eval_query("data.test.x".to_string(), false));
+    assert_eq!(e.code(), "eval_conflict_error");
+    assert!(e.location().is_some());
+
+    // Builtin errors are raised only in strict mode.
+    let mut engine = Engine::new();
+    engine.set_strict_builtin_errors(true);
+    let e = error(engine.eval_query("x := upper(1)".to_string(), false));
+    assert_eq!(e.code(), "eval_type_error");
+    assert_eq!(e.location().map(|l| l.col), Some(12));
+    let e = error(engine.eval_query("x := json.unmarshal(\"{\")".to_string(), false));
+    assert_eq!(e.code(), "eval_builtin_error");
+
+    // Classification survives terse formatting.
+    engine.set_error_format(ErrorFormat::Terse);
+    let e = error(engine.eval_query("x := upper(1)".to_string(), false));
+    assert_eq!(e.code(), "eval_type_error");
+    assert!(!e.message().contains('\n'));
+
+    // Display is unchanged and errors from extensions are generic.
+    let mut engine = Engine::new();
+    engine.add_extension(
+        "fail".to_string(),
+        Some(0),
+        Box::new(|_| bail!("extension failed")),
+    )?;
+    let e = error(engine.eval_query("x := fail()".to_string(), false));
+    assert_eq!(e.code(), "eval_error");
+    assert!(e.to_string().contains("extension failed"));
+
+    // Errors that are not specific to evaluation.
+    let e = error(engine.add_data(Value::from(1)));
+    assert_eq!(e.code(), "eval_error");
+    assert_eq!(e.to_string(), "data must be object");
+    Ok(())
+}
+
+#[test]
+fn max_eval_depth() -> Result<()> {
+    let mut policy = "package test\n".to_string();
+    for i in 0..20 {
+        policy += &format!("f{i}(x) := f{}(x) + 1\n", i + 1);
+    }
+    policy += "f20(x) := x\n";
+
+    let mut engine = Engine::new();
+    engine.add_policy("test.rego".to_string(), policy)?;
+    engine.set_max_eval_depth(100);
+    let results = engine.eval_query("data.test.f0(1)".to_string(), false)?;
+    assert_eq!(results.result[0].expressions[0].value, Value::from(21));
+
+    // Nested function calls exceed the limit.
+    engine.set_max_eval_depth(20);
+    let e = engine
+        .eval_query("data.test.f0(1)".to_string(), false)
+        .unwrap_err();
+    assert_eq!(e.code(), "eval_recursion_limit");
+    assert!(e.message().contains("maximum evaluation depth 20 exceeded"));
+    assert_eq!(e.location().map(|l| l.file.as_str()), Some("test.rego"));
+
+    // The engine remains usable after the limit is hit.
+    engine.set_max_eval_depth(100);
+    let results = engine.eval_query("data.test.f10(1)".to_string(), false)?;
+    assert_eq!(results.result[0].expressions[0].value, Value::from(11));
+
+    // Nested expressions are limited too.
+    engine.set_max_eval_depth(5);
+    let e = engine
+        .eval_query("x := [[[[[[1]]]]]]".to_string(), false)
+        .unwrap_err();
+    assert_eq!(e.code(), "eval_recursion_limit");
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "stacker")]
+fn max_eval_depth_default() -> Result<()> {
+    // Test threads have small stacks; evaluation up to the default limit must not overflow them,
+    // even in debug builds.
+    let mut policy = "package test\n".to_string();
+    for i in 0..1000 {
+        policy += &format!("f{i}(x) := f{}(x) + 1\n", i + 1);
+    }
+    policy += "f1000(x) := x\n";
+
+    let mut engine = Engine::new();
+    engine.add_policy("test.rego".to_string(), policy)?;
+    let results = engine.eval_query("data.test.f900(1)".to_string(), false)?;
+    assert_eq!(results.result[0].expressions[0].value, Value::from(101));
+
+    let e = engine
+        .eval_query("data.test.f0(1)".to_string(), false)
+        .unwrap_err();
+    assert_eq!(e.code(), "eval_recursion_limit");
+    assert!(e
+        .message()
+        .contains("maximum evaluation depth 1000 exceeded"));
+    Ok(())
+}
+
+#[test]
+fn rule_dependency_graph() -> Result<()> {
+    let mut engine = Engine::new();
+    engine.add_policy(
+        "lib.rego".to_string(),
+        r#"
+        package lib
+        import rego.v1
+
+        roles := {"admin": ["alice"]}
+        "#
+        .to_string(),
+    )?;
+    engine.add_policy(
+        "test.rego".to_string(),
+        r#"
+        package test
+        import rego.v1
+
+        allow if is_admin(input.user)
+        is_admin(user) if user in data.lib.roles.admin
+        mock_roles := {"admin": ["bob"]}
+        test_allow if allow with data.lib.roles as mock_roles
+        "#
+        .to_string(),
+    )?;
+
+    let graph = engine.rule_dependency_graph()?;
+    let edge = |source: &str, target: &str, kind| DependencyEdge {
+        source: source.to_string(),
+        target: target.to_string(),
+        kind,
+    };
+    assert_eq!(
+        graph.edges,
+        [
+            edge(
+                "data.test.allow",
+                "data.test.is_admin",
+                DependencyKind::Call
+            ),
+            edge(
+                "data.test.is_admin",
+                "data.lib.roles",
+                DependencyKind::DataRef
+            ),
+            edge(
+                "data.test.test_allow",
+                "data.lib.roles",
+                DependencyKind::With
+            ),
+            edge(
+                "data.test.test_allow",
+                "data.test.allow",
+                DependencyKind::DataRef
+            ),
+            edge(
+                "data.test.test_allow",
+                "data.test.mock_roles",
+                DependencyKind::With
+            ),
+        ]
+    );
+
+    // Each rule comes after the rules it depends on.
+    let position = |path: &str| graph.rules.iter().position(|r| r == path).unwrap();
+    assert_eq!(graph.rules.len(), 5);
+    for e in &graph.edges {
+        assert!(position(&e.target) < position(&e.source));
+    }
+    Ok(())
+}
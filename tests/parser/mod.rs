@@ -622,6 +622,7 @@ struct TestCase {
     imports: Option<Vec<Value>>,
     policy: Option<Vec<Value>>,
     error: Option<String>,
+    max_literal_elements: Option<usize>,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
@@ -639,6 +640,9 @@ fn yaml_test_impl(file: &str) -> Result<()> {
         print!("\ncase {} ", case.note);
         let source = Source::from_contents("case.rego".to_string(), case.rego.clone())?;
         let mut parser = Parser::new(&source)?;
+        if let Some(n) = case.max_literal_elements {
+            parser.set_max_literal_elements(n);
+        }
         match parser.parse() {
             Ok(module) => {
                 if let Some(e) = &case.error {
@@ -3,6 +3,8 @@
 
 #![cfg(test)]
 
+use std::collections::BTreeSet;
+
 use anyhow::Result;
 use regorus::*;
 
@@ -155,3 +157,742 @@ fn api() -> Result<()> {
     assert!(Value::from(5.6).as_bool_mut().is_err());
     Ok(())
 }
+
+#[test]
+fn set_array_conversions() -> Result<()> {
+    let array = Value::from_json_str(r#"[3, "a", 1, 3, null]"#)?;
+
+    let set = array.clone().into_set()?;
+    assert_eq!(set.as_set()?.len(), 4);
+    assert_eq!(set.clone().into_set()?, set);
+
+    // Elements are sorted and deduplicated.
+    assert_eq!(
+        set.into_array()?,
+        Value::from_json_str(r#"[null, 1, 3, "a"]"#)?
+    );
+    assert_eq!(array.clone().into_array()?, array);
+
+    for v in [Value::Null, Value::from(1), Value::new_object()] {
+        assert!(v.clone().into_set().is_err());
+        assert!(v.into_array().is_err());
+    }
+    assert_eq!(
+        Value::from("a").into_set().unwrap_err().to_string(),
+        "cannot convert string to set"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn try_get() -> Result<()> {
+    let v = Value::from_json_str(r#"{ "a": [1, null], "b": { "c": 2 } }"#)?;
+
+    assert_eq!(
+        v.try_get(&Value::from("b"))?.map(|b| &b["c"]),
+        Some(&Value::from(2))
+    );
+    assert_eq!(v.try_get(&Value::from("z"))?, None);
+
+    // Null values are distinct from missing values.
+    let a = &v["a"];
+    assert_eq!(a.try_get(&Value::from(1))?, Some(&Value::Null));
+    assert_eq!(a.try_get(&Value::from(2))?, None);
+    assert_eq!(a.try_get(&Value::from(-1))?, None);
+    assert_eq!(a.try_get(&Value::from(0.5))?, None);
+    assert_eq!(
+        a.try_get(&Value::from("0")).unwrap_err().to_string(),
+        "array cannot be indexed by string"
+    );
+
+    let set = a.clone().into_set()?;
+    assert_eq!(set.try_get(&Value::Null)?, Some(&Value::Null));
+    assert_eq!(set.try_get(&Value::from(2))?, None);
+
+    for scalar in [
+        Value::Null,
+        Value::from(true),
+        Value::from(1),
+        Value::from("s"),
+    ] {
+        assert!(scalar.try_get(&Value::from(0)).is_err());
+    }
+    assert_eq!(
+        Value::from(1)
+            .try_get(&Value::from(0))
+            .unwrap_err()
+            .to_string(),
+        "number cannot be indexed"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn stable_hash() -> Result<()> {
+    // Equal values built differently have equal hashes.
+    let a = Value::from_json_str(r#"{ "b": [1, 2.5, "x"], "a": { "c": null, "d": true } }"#)?;
+    let mut b = Value::new_object();
+    b.as_object_mut()?.insert(
+        Value::from("a"),
+        Value::from_json_str(r#"{ "d": true, "c": null }"#)?,
+    );
+    b.as_object_mut()?.insert(
+        Value::from("b"),
+        Value::from(vec![Value::from(1u64), Value::from(2.5), Value::from("x")]),
+    );
+    assert_eq!(a, b);
+    assert_eq!(a.stable_hash(), b.stable_hash());
+
+    // Sets are hashed in canonical order.
+    let s1 = Value::from_json_str("[3, 1, 2]")?.into_set()?;
+    let s2 = Value::from_json_str("[2, 3, 1]")?.into_set()?;
+    assert_eq!(s1.stable_hash(), s2.stable_hash());
+
+    // Numbers are hashed by value.
+    assert_eq!(Value::from(1).stable_hash(), Value::from(1.0).stable_hash());
+    assert_eq!(
+        Value::from(100).stable_hash(),
+        Value::from_json_str("1e2")?.stable_hash()
+    );
+
+    // Different values have different hashes.
+    let values = [
+        Value::Null,
+        Value::Undefined,
+        Value::from(false),
+        Value::from(true),
+        Value::from(0),
+        Value::from("0"),
+        Value::from(""),
+        Value::new_array(),
+        Value::new_set(),
+        Value::new_object(),
+        Value::from_json_str(r#"["a", "b"]"#)?,
+        Value::from_json_str(r#"["ab"]"#)?,
+        Value::from_json_str(r#"["a", "b"]"#)?.into_set()?,
+        Value::from_json_str(r#"{"a": "b"}"#)?,
+        Value::from_json_str(r#"[[], []]"#)?,
+        Value::from_json_str(r#"[[[]]]"#)?,
+    ];
+    for (i, v1) in values.iter().enumerate() {
+        for v2 in &values[i + 1..] {
+            assert_ne!(v1.stable_hash(), v2.stable_hash(), "{v1} {v2}");
+        }
+    }
+
+    // The hash is stable across runs and platforms.
+    assert_eq!(Value::Null.stable_hash(), 0xaf63bd4c8601b7df);
+
+    Ok(())
+}
+
+#[test]
+fn retain() -> Result<()> {
+    let mut v = Value::from_json_str(
+        r#"{
+  "a": [1, 2, 3, 4],
+  "b": { "c": 1, "d": "x", "e": [5, "y"] },
+  "f": "z"
+}"#,
+    )?;
+    v.as_object_mut()?.insert(
+        Value::from("s"),
+        Value::from_json_str(r#"[1, "w", [6, "v"]]"#)?.into_set()?,
+    );
+
+    // Drop all strings.
+    let mut paths = vec![];
+    v.retain(&mut |path, v| {
+        paths.push(path.to_vec());
+        !matches!(v, Value::String(_))
+    });
+    let mut expected = Value::from_json_str(
+        r#"{
+  "a": [1, 2, 3, 4],
+  "b": { "c": 1, "e": [5] }
+}"#,
+    )?;
+    expected.as_object_mut()?.insert(
+        Value::from("s"),
+        Value::from_json_str("[1, [6]]")?.into_set()?,
+    );
+    assert_eq!(v, expected);
+
+    // Paths use array indices of the original array.
+    assert!(paths.contains(&vec![Value::from("b"), Value::from("e"), Value::from(1)]));
+
+    // Drop odd numbers; arrays are re-indexed.
+    v.retain(&mut |_, v| !matches!(v.as_u64(), Ok(n) if n % 2 == 1));
+    assert_eq!(v["a"], Value::from_json_str("[2, 4]")?);
+    assert_eq!(v["b"], Value::from_json_str(r#"{ "e": [] }"#)?);
+
+    // Empty collections are retained unless pruned by the predicate.
+    v.retain(&mut |_, v| !matches!(v, Value::Array(a) if a.is_empty()));
+    assert_eq!(v["b"], Value::new_object());
+    v.retain(&mut |_, v| v != &Value::new_object());
+    assert_eq!(v["b"], Value::Undefined);
+
+    // Scalars are unaffected.
+    let mut n = Value::from(1);
+    n.retain(&mut |_, _| false);
+    assert_eq!(n, Value::from(1));
+
+    Ok(())
+}
+
+#[test]
+fn get_set_path() -> Result<()> {
+    let path = |keys: &[&str]| -> Vec<Value> { keys.iter().map(|k| Value::from(*k)).collect() };
+
+    // Build a nested structure.
+    let mut v = Value::Undefined;
+    v.set_path(
+        &path(&["users", "alice", "roles"]),
+        Value::from_json_str(r#"["admin"]"#)?,
+    )?;
+    v.set_path(&path(&["users", "alice", "age"]), Value::from(30))?;
+    v.set_path(&path(&["users", "bob"]), Value::new_object())?;
+    v.set_path(&path(&["users", "bob", "age"]), Value::from(40))?;
+    v.set_path(&path(&["version"]), Value::from("1"))?;
+    assert_eq!(
+        v,
+        Value::from_json_str(
+            r#"{
+  "users": { "alice": { "roles": ["admin"], "age": 30 }, "bob": { "age": 40 } },
+  "version": "1"
+}"#
+        )?
+    );
+
+    // Overwrite an existing value.
+    v.set_path(&path(&["users", "alice", "age"]), Value::from(31))?;
+    assert_eq!(
+        v.get_path(&path(&["users", "alice", "age"])),
+        &Value::from(31)
+    );
+
+    // Non-string keys.
+    v.set_path(&[Value::from("ids"), Value::from(1)], Value::from(true))?;
+    assert_eq!(v["ids"][&Value::from(1)], Value::from(true));
+
+    // Lookups through arrays and sets.
+    let mut roles = path(&["users", "alice", "roles"]);
+    roles.push(Value::from(0));
+    assert_eq!(v.get_path(&roles), &Value::from("admin"));
+    let mut s = Value::new_object();
+    s.set_path(&path(&["s"]), Value::from_json_str(r#"["x"]"#)?.into_set()?)?;
+    assert_eq!(s.get_path(&path(&["s", "x"])), &Value::from("x"));
+
+    // Missing paths.
+    assert_eq!(
+        v.get_path(&path(&["users", "carol", "age"])),
+        &Value::Undefined
+    );
+    assert_eq!(v.get_path(&path(&["version", "x"])), &Value::Undefined);
+
+    // Setting through non-objects is an error and leaves the value unchanged.
+    let before = v.clone();
+    assert!(v
+        .set_path(&path(&["version", "major"]), Value::from(1))
+        .is_err());
+    assert!(v.set_path(&roles, Value::from("user")).is_err());
+    assert_eq!(v, before);
+
+    // An empty path replaces the value.
+    v.set_path(&[], Value::Null)?;
+    assert_eq!(v, Value::Null);
+    assert_eq!(v.get_path(&[]), &Value::Null);
+
+    Ok(())
+}
+
+#[test]
+fn bytes() -> Result<()> {
+    let bytes = [0u8, 1, 127, 128, 255];
+    let v = Value::from_bytes(&bytes);
+    assert_eq!(v, Value::from_json_str("[0, 1, 127, 128, 255]")?);
+    assert_eq!(v.as_bytes()?, bytes);
+
+    assert_eq!(Value::from_bytes(&[]), Value::new_array());
+    assert!(Value::new_array().as_bytes()?.is_empty());
+
+    for (json, msg) in [
+        ("[1, 256]", "element 1 is not a byte: 256"),
+        ("[-1]", "element 0 is not a byte: -1"),
+        ("[1.5]", "element 0 is not a byte: 1.5"),
+        (r#"[1, "2"]"#, "element 1 is not a number"),
+        (r#"{"a": 1}"#, "not an array"),
+    ] {
+        let err = Value::from_json_str(json)?
+            .as_bytes()
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, msg, "{json}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn eq_json_semantics() -> Result<()> {
+    let set = |items: &[Value]| Value::from(items.iter().cloned().collect::<BTreeSet<Value>>());
+    let json = |s: &str| Value::from_json_str(s);
+
+    let s = set(&[Value::from("b"), Value::from("a")]);
+    assert!(s.eq_json_semantics(&json(r#"["a", "b"]"#)?));
+    assert!(json(r#"["a", "b"]"#)?.eq_json_semantics(&s));
+    assert!(!s.eq_json_semantics(&json(r#"["b", "a"]"#)?));
+    assert!(!s.eq_json_semantics(&json(r#"["a"]"#)?));
+    assert!(s.eq_json_semantics(&s));
+
+    // Numbers.
+    assert!(Value::from(2).eq_json_semantics(&Value::from(2.0)));
+    assert!(!Value::from(2).eq_json_semantics(&Value::from("2")));
+
+    // Nested sets within objects and arrays.
+    let mut v = Value::new_object();
+    v.as_object_mut()?.insert(
+        Value::from("roles"),
+        Value::from(vec![set(&[Value::from(1), Value::from(0.5)]), Value::Null]),
+    );
+    assert!(v.eq_json_semantics(&json(r#"{"roles": [[0.5, 1], null]}"#)?));
+    assert!(!v.eq_json_semantics(&json(r#"{"roles": [[0.5, 1]]}"#)?));
+    assert!(!v.eq_json_semantics(&json(r#"{"role": [[0.5, 1], null]}"#)?));
+
+    // Set elements are compared recursively.
+    let nested = set(&[set(&[Value::from(1)])]);
+    assert!(nested.eq_json_semantics(&json("[[1]]")?));
+
+    assert!(Value::Undefined.eq_json_semantics(&Value::Undefined));
+    assert!(!Value::Undefined.eq_json_semantics(&Value::Null));
+    Ok(())
+}
+
+#[test]
+fn entries_and_elements() -> Result<()> {
+    let v = Value::from_json_str(r#"{"b": [3, 1], "a": 2}"#)?;
+    let keys: Vec<&Value> = v.entries().map(|(k, _)| k).collect();
+    assert_eq!(keys, [&Value::from("a"), &Value::from("b")]);
+    assert_eq!(
+        v["b"].elements().cloned().collect::<Vec<_>>(),
+        [Value::from(3), Value::from(1)]
+    );
+
+    let s = Value::from(BTreeSet::from([Value::from(2), Value::from(1)]));
+    assert_eq!(
+        s.elements().cloned().collect::<Vec<_>>(),
+        [Value::from(1), Value::from(2)]
+    );
+
+    // Mismatched kinds yield nothing.
+    assert_eq!(v.elements().count(), 0);
+    assert_eq!(s.entries().count(), 0);
+    assert_eq!(Value::Undefined.entries().count(), 0);
+    assert_eq!(Value::Null.elements().count(), 0);
+    Ok(())
+}
+
+#[test]
+fn describe() -> Result<()> {
+    use std::collections::BTreeMap;
+
+    // Nested objects.
+    let v = Value::from_json_str(
+        r#"{"user": {"name": "a", "age": 30, "admin": false, "manager": null}, "score": 2.5}"#,
+    )?;
+    let user = StructuralType::Object(BTreeMap::from([
+        (Value::from("admin"), StructuralType::Boolean),
+        (Value::from("age"), StructuralType::Integer),
+        (Value::from("manager"), StructuralType::Null),
+        (Value::from("name"), StructuralType::String),
+    ]));
+    assert_eq!(
+        v.describe(),
+        StructuralType::Object(BTreeMap::from([
+            (Value::from("score"), StructuralType::Number),
+            (Value::from("user"), user),
+        ]))
+    );
+    assert_eq!(
+        v.describe().to_string(),
+        r#"{"score": number, "user": {"admin": boolean, "age": integer, "manager": null, "name": string}}"#
+    );
+
+    // Heterogeneous arrays produce unions in order of first occurrence.
+    let v = Value::from_json_str(r#"["a", 1, "b", {"x": 1}, {"x": "y"}, [], null]"#)?;
+    assert_eq!(
+        v.describe().to_string(),
+        r#"array[string | integer | {"x": integer} | {"x": string} | array[any] | null]"#
+    );
+
+    // Integers are subsumed by numbers.
+    let v = Value::from_json_str("[1, 2.5, 3]")?;
+    assert_eq!(
+        v.describe(),
+        StructuralType::Array(Box::new(StructuralType::Number))
+    );
+
+    // Sets and other kinds.
+    let s = Value::from(BTreeSet::from([Value::from(true), Value::from("t")]));
+    assert_eq!(s.describe().to_string(), "set[boolean | string]");
+    assert_eq!(Value::new_set().describe().to_string(), "set[any]");
+    assert_eq!(Value::Undefined.describe(), StructuralType::Undefined);
+    Ok(())
+}
+
+#[test]
+fn deserialize_into() -> Result<()> {
+    #[derive(serde::Deserialize, Debug, PartialEq)]
+    enum Role {
+        Admin,
+        Custom(String),
+        Scoped { scope: String },
+    }
+
+    #[derive(serde::Deserialize, Debug, PartialEq)]
+    struct User {
+        name: String,
+        age: u8,
+        score: f64,
+        manager: Option<String>,
+        tags: Vec<String>,
+        roles: Vec<Role>,
+        limits: std::collections::BTreeMap<String, i64>,
+    }
+
+    let mut v = Value::from_json_str(
+        r#"{
+          "name": "alice",
+          "age": 30,
+          "score": 0.1,
+          "manager": null,
+          "roles": ["Admin", {"Custom": "auditor"}, {"Scoped": {"scope": "ns1"}}],
+          "limits": {"cpu": -2, "memory": 1024}
+        }"#,
+    )?;
+    // Sets are deserialized as sequences.
+    v.as_object_mut()?.insert(
+        Value::from("tags"),
+        Value::from(BTreeSet::from([Value::from("b"), Value::from("a")])),
+    );
+
+    let user: User = v.deserialize_into()?;
+    assert_eq!(
+        user,
+        User {
+            name: "alice".to_string(),
+            age: 30,
+            score: 0.1,
+            manager: None,
+            tags: vec!["a".to_string(), "b".to_string()],
+            roles: vec![
+                Role::Admin,
+                Role::Custom("auditor".to_string()),
+                Role::Scoped {
+                    scope: "ns1".to_string()
+                }
+            ],
+            limits: [("cpu".to_string(), -2), ("memory".to_string(), 1024)].into(),
+        }
+    );
+
+    // Integers beyond 64 bits.
+    let v = Value::from(vec![Value::from(-5), Value::from(i128::MAX)]);
+    assert_eq!(v.deserialize_into::<Vec<i128>>()?, vec![-5, i128::MAX]);
+
+    // Missing fields are undefined and hence None.
+    #[derive(serde::Deserialize)]
+    struct Partial {
+        name: Option<String>,
+    }
+    let p: Partial = Value::new_object().deserialize_into()?;
+    assert!(p.name.is_none());
+
+    // Type mismatches are errors.
+    assert!(Value::from("x").deserialize_into::<Vec<String>>().is_err());
+    assert!(Value::from(300).deserialize_into::<u8>().is_err());
+    assert!(Value::from(2.5).deserialize_into::<i64>().is_err());
+    assert!(Value::Undefined.deserialize_into::<String>().is_err());
+    Ok(())
+}
+
+#[test]
+fn from_serde() -> Result<()> {
+    use std::collections::BTreeMap;
+
+    #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+    enum Action {
+        Allow,
+        Redirect(String),
+        Limit { rate: u32 },
+    }
+
+    #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+    struct Rule {
+        id: u64,
+        weight: f64,
+        ports: BTreeMap<u16, String>,
+        pairs: Vec<(String, bool)>,
+        actions: Vec<Action>,
+        owner: Option<String>,
+    }
+
+    let rule = Rule {
+        id: 7,
+        weight: 0.5,
+        ports: BTreeMap::from([(80, "http".to_string()), (443, "https".to_string())]),
+        pairs: vec![("a".to_string(), true)],
+        actions: vec![
+            Action::Allow,
+            Action::Redirect("/login".to_string()),
+            Action::Limit { rate: 10 },
+        ],
+        owner: None,
+    };
+
+    let v = Value::from_serde(&rule)?;
+    // Non-string keys are retained.
+    assert_eq!(v["ports"][&Value::from(443)], Value::from("https"));
+    assert_eq!(
+        v,
+        Value::from(BTreeMap::from([
+            (Value::from("id"), Value::from(7)),
+            (Value::from("weight"), Value::from(0.5)),
+            (Value::from("ports"), v["ports"].clone()),
+            (
+                Value::from("pairs"),
+                Value::from_json_str(r#"[["a", true]]"#)?
+            ),
+            (
+                Value::from("actions"),
+                Value::from_json_str(
+                    r#"["Allow", {"Redirect": "/login"}, {"Limit": {"rate": 10}}]"#
+                )?
+            ),
+            (Value::from("owner"), Value::Null),
+        ]))
+    );
+
+    // Round trip.
+    assert_eq!(v.deserialize_into::<Rule>()?, rule);
+
+    // Values can be produced by any serde format.
+    let json = r#"{"id": 7, "tags": ["x", "y"], "nested": {"ok": true, "n": null}}"#;
+    let from_json: Value = serde_json::from_str(json)?;
+    assert_eq!(from_json, Value::from_json_str(json)?);
+    assert_eq!(Value::from_serde(&from_json)?, from_json);
+
+    let yaml = "id: 7\ntags:\n  - x\n  - y\nnested:\n  ok: true\n  n: null\n";
+    let from_yaml: Value = serde_yaml::from_str(yaml)?;
+    assert_eq!(from_yaml, from_json);
+
+    // Sets are serialized as arrays.
+    let set = Value::from(BTreeSet::from([Value::from(2), Value::from(1)]));
+    assert_eq!(Value::from_serde(&set)?, Value::from_json_str("[1, 2]")?);
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "yaml")]
+fn from_yaml() -> Result<()> {
+    let v = Value::from_yaml_str(
+        r#"
+        name: app
+        replicas: 0x10
+        ratio: 1.5
+        owner: ~
+        labels: &labels { tier: web }
+        copy: *labels
+        port: !!str 80
+        "#,
+    )?;
+    assert_eq!(
+        v,
+        Value::from_json_str(
+            r#"{
+              "name": "app",
+              "replicas": 16,
+              "ratio": 1.5,
+              "owner": null,
+              "labels": { "tier": "web" },
+              "copy": { "tier": "web" },
+              "port": "80"
+            }"#
+        )?
+    );
+    assert_eq!(Value::from_yaml_str("")?, Value::Null);
+
+    let err = |yaml: &str| Value::from_yaml_str(yaml).unwrap_err().to_string();
+    assert!(err("a: 1\nb:\n  c: 2\n  c: 3").contains("duplicate entry with key \"c\""));
+    assert_eq!(
+        err("items:\n  - x\n  - !ref y"),
+        "unsupported YAML tag `!ref` at `items[1]`"
+    );
+    assert_eq!(
+        err("%TAG !e! tag:example.com,2000:\n---\na: !e!x 1"),
+        "YAML %TAG directives are not supported"
+    );
+    assert_eq!(
+        err("!config {a: 1}"),
+        "unsupported YAML tag `!config` at document root"
+    );
+    assert_eq!(
+        err("limit: .inf"),
+        "unsupported YAML number `.inf` at `limit`"
+    );
+    assert!(err("---\na: 1\n---\nb: 2").contains("more than one document"));
+    Ok(())
+}
+
+#[test]
+fn merge() -> Result<()> {
+    let base = Value::from_json_str(
+        r#"{
+          "users": { "alice": { "role": "admin", "groups": ["a"] } },
+          "limit": 5,
+          "tags": ["x"]
+        }"#,
+    )?;
+    let update = Value::from_json_str(
+        r#"{
+          "users": { "alice": { "role": "viewer", "groups": ["b"] }, "bob": { "role": "dev" } },
+          "limit": 10,
+          "tags": ["y"],
+          "extra": null
+        }"#,
+    )?;
+
+    // Errors leave the value unmodified.
+    let mut v = base.clone();
+    let err = v.merge(update.clone(), MergeStrategy::Error).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "conflicting values for `limit`: `5` and `10`"
+    );
+    assert_eq!(v, base);
+
+    let mut v = base.clone();
+    v.merge(update.clone(), MergeStrategy::OverwriteWithRhs)?;
+    assert_eq!(
+        v,
+        Value::from_json_str(
+            r#"{
+              "users": { "alice": { "role": "viewer", "groups": ["b"] }, "bob": { "role": "dev" } },
+              "limit": 10,
+              "tags": ["y"],
+              "extra": null
+            }"#
+        )?
+    );
+
+    let mut v = base.clone();
+    v.merge(update.clone(), MergeStrategy::KeepLhs)?;
+    assert_eq!(
+        v,
+        Value::from_json_str(
+            r#"{
+              "users": { "alice": { "role": "admin", "groups": ["a"] }, "bob": { "role": "dev" } },
+              "limit": 5,
+              "tags": ["x"],
+              "extra": null
+            }"#
+        )?
+    );
+
+    // Arrays are concatenated at any depth; scalar conflicts are errors.
+    let mut v = base.clone();
+    let err = v
+        .merge(update.clone(), MergeStrategy::DeepArrayConcat)
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "conflicting values for `limit`: `5` and `10`"
+    );
+    v.merge(
+        Value::from_json_str(r#"{ "users": { "alice": { "groups": ["b"] } }, "tags": ["x"] }"#)?,
+        MergeStrategy::DeepArrayConcat,
+    )?;
+    assert_eq!(
+        v["users"]["alice"]["groups"],
+        Value::from_json_str(r#"["a", "b"]"#)?
+    );
+    assert_eq!(v["tags"], Value::from_json_str(r#"["x", "x"]"#)?);
+
+    // Equal values and undefined values never conflict.
+    let mut v = base.clone();
+    v.merge(base.clone(), MergeStrategy::Error)?;
+    assert_eq!(v, base);
+    let mut v = Value::Undefined;
+    v.merge(base.clone(), MergeStrategy::KeepLhs)?;
+    assert_eq!(v, base);
+
+    // Paths of non-identifier keys are bracketed.
+    let mut v = Value::from_json_str(r#"{ "a-b": { "1": true } }"#)?;
+    let err = v
+        .merge(
+            Value::from_json_str(r#"{ "a-b": { "1": false } }"#)?,
+            MergeStrategy::Error,
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"conflicting values for `["a-b"]["1"]`: `true` and `false`"#
+    );
+
+    let err = Value::from(1)
+        .merge(Value::from(2), MergeStrategy::Error)
+        .unwrap_err();
+    assert_eq!(err.to_string(), "conflicting values: `1` and `2`");
+    Ok(())
+}
+
+#[test]
+fn pointer() -> Result<()> {
+    let mut v = Value::from_json_str(
+        r#"{ "a": { "b": [10, 20, { "c": true }], "x/y": 1, "m~n": 2, "": 3 }, "01": 4 }"#,
+    )?;
+
+    assert_eq!(v.pointer(""), Some(&v));
+    assert_eq!(v.pointer("/a/b/0"), Some(&Value::from(10)));
+    assert_eq!(v.pointer("/a/b/2/c"), Some(&Value::from(true)));
+    assert_eq!(v.pointer("/a/x~1y"), Some(&Value::from(1)));
+    assert_eq!(v.pointer("/a/m~0n"), Some(&Value::from(2)));
+    assert_eq!(v.pointer("/a/"), Some(&Value::from(3)));
+    assert_eq!(v.pointer("/01"), Some(&Value::from(4)));
+
+    // Missing values and malformed pointers.
+    assert_eq!(v.pointer("a"), None);
+    assert_eq!(v.pointer("/z"), None);
+    assert_eq!(v.pointer("/a/b/3"), None);
+    assert_eq!(v.pointer("/a/b/-"), None);
+    assert_eq!(v.pointer("/a/b/01"), None);
+    assert_eq!(v.pointer("/a/b/+1"), None);
+    assert_eq!(v.pointer("/a/b/0/c"), None);
+
+    // Set elements are not keyed.
+    let mut set = BTreeSet::new();
+    set.insert(Value::from(1));
+    let s = Value::from(set);
+    assert_eq!(s.pointer("/0"), None);
+    assert_eq!(s.pointer("/1"), None);
+
+    // Modify in place.
+    *v.pointer_mut("/a/b/2/c").unwrap() = Value::from(false);
+    *v.pointer_mut("/a/x~1y").unwrap() = Value::Null;
+    assert!(v.pointer_mut("/a/b/5").is_none());
+    assert_eq!(
+        v,
+        Value::from_json_str(
+            r#"{ "a": { "b": [10, 20, { "c": false }], "x/y": null, "m~n": 2, "": 3 }, "01": 4 }"#
+        )?
+    );
+
+    // Copies are not affected by modifications.
+    let copy = v.clone();
+    *v.pointer_mut("/a/b/0").unwrap() = Value::from(0);
+    assert_eq!(copy.pointer("/a/b/0"), Some(&Value::from(10)));
+    assert_eq!(v.pointer("/a/b/0"), Some(&Value::from(0)));
+    Ok(())
+}